@@ -0,0 +1,291 @@
+//! Assemblage des lots de factures en archive ZIP
+//!
+//! Chaque PDF est écrit dans l'archive dès sa génération. Avec
+//! [`BatchArchive::streaming`], chaque entrée terminée est transmise à la
+//! sortie (corps de la réponse HTTP) : seule l'entrée en cours reste en
+//! mémoire, quelle que soit la taille du lot. Les factures refusées ne
+//! bloquent pas le lot, leurs erreurs sont consignées dans le manifeste
+//! `manifest.json`.
+
+use crate::models::error::FieldError;
+use serde::Serialize;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+/// Nom du manifeste JSON ajouté en fin d'archive
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// Résultat d'une facture du lot
+#[derive(Debug, Serialize)]
+pub struct BatchEntry {
+    /// Position de la facture dans la requête (à partir de 0)
+    pub index: usize,
+    pub invoice_number: String,
+    /// Nom du PDF dans l'archive (absent si la facture est refusée)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub errors: Vec<FieldError>,
+    /// Écarts de conformité PDF/A-3 tolérés (`allow_non_conformant`) et
+    /// montants suspects (plafond `max_amount`, décimales)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Manifeste du lot : compteurs et détail par facture
+#[derive(Debug, Default, Serialize)]
+pub struct BatchManifest {
+    pub generated: usize,
+    pub failed: usize,
+    pub invoices: Vec<BatchEntry>,
+}
+
+/// Sortie ZIP au fil de l'eau vers un flux non positionnable
+///
+/// `ZipWriter` revient en arrière pour compléter l'en-tête local de l'entrée
+/// en cours (taille, CRC) : seule cette entrée est tamponnée. Elle est
+/// transmise à `sink` et libérée au `flush` que `ZipWriter` effectue à la fin
+/// de chaque entrée (`set_flush_on_finish_file`).
+pub struct StreamingZipOutput<S: Write> {
+    sink: S,
+    /// Octets déjà transmis à `sink`
+    flushed: u64,
+    /// Octets de l'entrée en cours, à partir de la position `flushed`
+    pending: Cursor<Vec<u8>>,
+}
+
+impl<S: Write> StreamingZipOutput<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            flushed: 0,
+            pending: Cursor::new(Vec::new()),
+        }
+    }
+
+    /// Flux de sortie (octets transmis jusqu'au dernier `flush`)
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: Write> Write for StreamingZipOutput<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let pending = std::mem::take(self.pending.get_mut());
+        self.pending.set_position(0);
+        self.sink.write_all(&pending)?;
+        self.flushed += pending.len() as u64;
+        self.sink.flush()
+    }
+}
+
+/// Exigé par `set_flush_on_finish_file` : seule l'entrée en cours est relisible
+impl<S: Write> Read for StreamingZipOutput<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.pending.read(buf)
+    }
+}
+
+impl<S: Write> Seek for StreamingZipOutput<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => {
+                (self.flushed + self.pending.position()).checked_add_signed(delta)
+            }
+            SeekFrom::End(delta) => {
+                (self.flushed + self.pending.get_ref().len() as u64).checked_add_signed(delta)
+            }
+        };
+        match target {
+            Some(target) if target >= self.flushed => {
+                self.pending.set_position(target - self.flushed);
+                Ok(target)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "position deja transmise dans le flux ZIP",
+            )),
+        }
+    }
+}
+
+/// Archive ZIP d'un lot de factures en cours d'assemblage
+pub struct BatchArchive<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    manifest: BatchManifest,
+}
+
+impl<W: Write + Seek> BatchArchive<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            zip: ZipWriter::new(writer),
+            manifest: BatchManifest::default(),
+        }
+    }
+
+    /// Ajoute le PDF d'une facture générée, avec ses avertissements éventuels
+    pub fn add_invoice(
+        &mut self,
+        index: usize,
+        invoice_number: &str,
+        filename: &str,
+        pdf: &[u8],
+        warnings: Vec<String>,
+    ) -> io::Result<()> {
+        self.zip
+            .start_file(filename, SimpleFileOptions::default())
+            .map_err(io::Error::other)?;
+        self.zip.write_all(pdf)?;
+
+        self.manifest.generated += 1;
+        self.manifest.invoices.push(BatchEntry {
+            index,
+            invoice_number: invoice_number.to_string(),
+            file: Some(filename.to_string()),
+            errors: Vec::new(),
+            warnings,
+        });
+        Ok(())
+    }
+
+    /// Consigne une facture refusée dans le manifeste
+    pub fn add_failure(&mut self, index: usize, invoice_number: &str, errors: Vec<FieldError>) {
+        self.manifest.failed += 1;
+        self.manifest.invoices.push(BatchEntry {
+            index,
+            invoice_number: invoice_number.to_string(),
+            file: None,
+            errors,
+            warnings: Vec::new(),
+        });
+    }
+
+    /// Écrit le manifeste et termine l'archive
+    pub fn finish(mut self) -> io::Result<W> {
+        let manifest = serde_json::to_vec_pretty(&self.manifest).map_err(io::Error::other)?;
+        self.zip
+            .start_file(MANIFEST_FILENAME, SimpleFileOptions::default())
+            .map_err(io::Error::other)?;
+        self.zip.write_all(&manifest)?;
+        let mut writer = self.zip.finish().map_err(io::Error::other)?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<S: Write> BatchArchive<StreamingZipOutput<S>> {
+    /// Archive écrite au fil de l'eau dans `sink`, entrée par entrée
+    pub fn streaming(sink: S) -> Self {
+        let mut archive = Self::new(StreamingZipOutput::new(sink));
+        archive.zip.set_flush_on_finish_file(true);
+        archive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_archive_contains_pdfs_and_manifest() {
+        let mut archive = BatchArchive::new(Cursor::new(Vec::new()));
+        archive
+            .add_invoice(0, "F-001", "facture_F-001.pdf", b"%PDF-1.7", Vec::new())
+            .unwrap();
+        archive.add_failure(
+            1,
+            "F-002",
+            vec![FieldError::new(
+                "lines",
+                "La facture doit contenir au moins une ligne",
+            )],
+        );
+        let bytes = archive.finish().unwrap().into_inner();
+
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(zip.len(), 2);
+
+        let mut pdf = Vec::new();
+        zip.by_name("facture_F-001.pdf")
+            .unwrap()
+            .read_to_end(&mut pdf)
+            .unwrap();
+        assert_eq!(pdf, b"%PDF-1.7");
+
+        let mut manifest = String::new();
+        zip.by_name(MANIFEST_FILENAME)
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        assert!(manifest.contains("\"generated\": 1"));
+        assert!(manifest.contains("\"failed\": 1"));
+        assert!(manifest.contains("au moins une ligne"));
+        assert!(!manifest.contains("warnings"));
+    }
+
+    /// Flux de sortie partagé qui consigne la taille de chaque transmission
+    #[derive(Clone, Default)]
+    struct ChunkSink {
+        bytes: Rc<RefCell<Vec<u8>>>,
+        chunks: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl Write for ChunkSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.borrow_mut().extend_from_slice(buf);
+            self.chunks.borrow_mut().push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streaming_archive_releases_each_invoice() {
+        const INVOICES: usize = 100;
+        const PDF_SIZE: usize = 4096;
+
+        let sink = ChunkSink::default();
+        let mut archive = BatchArchive::streaming(sink.clone());
+        let mut seed = 0x2545_f491_u32;
+        for index in 0..INVOICES {
+            // Contenu peu compressible : la taille d'une entrée reste proche du PDF
+            let pdf: Vec<u8> = (0..PDF_SIZE)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    seed as u8
+                })
+                .collect();
+            let number = format!("F-{:03}", index);
+            archive
+                .add_invoice(index, &number, &format!("{}.pdf", number), &pdf, Vec::new())
+                .unwrap();
+            // Les entrées précédentes sont transmises : seule celle-ci est tamponnée
+            assert_eq!(sink.chunks.borrow().len(), index);
+            assert!(sink.bytes.borrow().len() >= index * PDF_SIZE);
+        }
+        archive.finish().unwrap();
+
+        assert!(sink
+            .chunks
+            .borrow()
+            .iter()
+            .all(|&chunk| chunk < 2 * PDF_SIZE));
+        assert!(sink.bytes.borrow().len() > INVOICES * PDF_SIZE);
+
+        let zip = ZipArchive::new(Cursor::new(sink.bytes.take())).unwrap();
+        assert_eq!(zip.len(), INVOICES + 1);
+    }
+}
@@ -1,10 +1,11 @@
 //! Test de génération PDF/A-3
 
-use facturx_create::facturx::generate_invoice_pdf;
-use facturx_create::models::invoice::InvoiceForm;
+use facturx_create::facturx::{generate_invoice_pdf, GenerationOptions};
+use facturx_create::models::invoice::{InvoiceForm, InvoiceTotals, InvoiceTypeCode};
 use facturx_create::models::line::InvoiceLine;
 use facturx_create::EmitterConfig;
 use std::fs;
+use std::time::Instant;
 
 fn main() {
     println!("Test de génération PDF/A-3 avec krilla...");
@@ -13,63 +14,119 @@ fn main() {
     let emitter = EmitterConfig {
         siren: Some("123456789".to_string()),
         siret: "12345678901234".to_string(),
+        siren_as_legal_id: false,
         name: "Test Company".to_string(),
         address: "123 Test Street, 75001 Paris".to_string(),
         bic: Some("BNPAFRPP".to_string()),
+        iban: Some("FR7630006000011234567890189".to_string()),
         num_tva: Some("FR12345678901".to_string()),
+        gln: None,
+        phone: None,
+        email: None,
+        website: None,
+        vat_on_payments: false,
+        vat_exempt_franchise: false,
         logo: None,
         xml_storage: None,
         pdf_storage: None,
+        legal_form: None,
+        capital: None,
+        rcs: None,
+        invoice_number_regex: None,
     };
 
     // Facture de test
     let invoice = InvoiceForm {
         invoice_number: "TEST-KRILLA-001".to_string(),
-        type_code: 380,
+        type_code: InvoiceTypeCode::Invoice,
         issue_date: "2024-01-31".to_string(),
         due_date: Some("2024-02-28".to_string()),
+        tax_point_date: None,
         currency_code: "EUR".to_string(),
         recipient_name: "Client Test SARL".to_string(),
         recipient_siret: "98765432109876".to_string(),
         recipient_address: "456 Client Avenue, 69001 Lyon".to_string(),
         recipient_country_code: "FR".to_string(),
+        recipient_public_sector: false,
+        recipient_service_code: None,
         recipient_vat_number: Some("FR98765432109".to_string()),
+        recipient_gln: None,
         payment_terms: Some("Paiement à 30 jours".to_string()),
         buyer_reference: None,
         purchase_order_reference: None,
+        tax_type_code: None,
+        tax_currency_code: None,
+        tax_exchange_rate: None,
         lines: vec![
             InvoiceLine {
                 description: "Développement logiciel".to_string(),
+                detail: None,
+                seller_item_id: None,
+                product_code: None,
+                standard_item_id: None,
+                standard_item_scheme: None,
+                order_line_reference: None,
+                section: Some("Prestations".to_string()),
+                period_start: None,
+                period_end: None,
                 quantity: 10.0,
+                unit_code: None,
                 unit_price_ht: 150.0,
                 vat_rate: 20.0,
+                vat_category: None,
                 discount_value: None,
                 discount_type: None,
+                discountable: true,
+                position: None,
                 total_ht: None,
+                gross_ht: None,
                 total_ttc: None,
                 total_vat: None,
                 discount_amount: None,
             },
             InvoiceLine {
                 description: "Maintenance mensuelle".to_string(),
+                detail: None,
+                seller_item_id: None,
+                product_code: None,
+                standard_item_id: None,
+                standard_item_scheme: None,
+                order_line_reference: None,
+                section: Some("Prestations".to_string()),
+                period_start: None,
+                period_end: None,
                 quantity: 1.0,
+                unit_code: None,
                 unit_price_ht: 500.0,
                 vat_rate: 20.0,
+                vat_category: None,
                 discount_value: None,
                 discount_type: None,
+                discountable: true,
+                position: None,
                 total_ht: None,
+                gross_ht: None,
                 total_ttc: None,
                 total_vat: None,
                 discount_amount: None,
             },
         ],
+        installments: Vec::new(),
+        global_discount_percent: None,
+        additional_references: Vec::new(),
+        ship_from_address: None,
+        invoiced_object_id: None,
+        invoiced_object_scheme: None,
+        prepaid_amount: None,
+        paid: false,
+        payment_date: None,
     };
 
     // Calcul des totaux
     let total_ht: f64 = invoice.lines.iter().map(|l| l.quantity * l.unit_price_ht).sum();
     let total_vat: f64 = invoice.lines.iter().map(|l| l.quantity * l.unit_price_ht * l.vat_rate / 100.0).sum();
     let total_ttc = total_ht + total_vat;
-    let totals = (total_ht, total_vat, total_ttc);
+    let totals = InvoiceTotals { total_ht, total_vat, total_ttc, allowance_total: 0.0, prepaid_total: 0.0 };
 
     println!("Total HT: {:.2} EUR", total_ht);
     println!("Total TVA: {:.2} EUR", total_vat);
@@ -85,8 +142,29 @@ fn main() {
   </rsm:ExchangedDocumentContext>
 </rsm:CrossIndustryInvoice>"#;
 
+    // Comparaison des deux chemins de métadonnées (avec / sans post-traitement XMP lopdf)
+    for xmp_post_processing in [true, false] {
+        let options = GenerationOptions {
+            xmp_post_processing,
+            ..Default::default()
+        };
+        let iterations = 10;
+        let start = Instant::now();
+        for _ in 0..iterations {
+            if let Err(e) = generate_invoice_pdf(&invoice, &emitter, totals, xml_content, None, &options) {
+                eprintln!("ERREUR: {}", e);
+                std::process::exit(1);
+            }
+        }
+        println!(
+            "Post-traitement XMP {}: {:.1} ms/PDF",
+            if xmp_post_processing { "actif" } else { "inactif" },
+            start.elapsed().as_secs_f64() * 1000.0 / iterations as f64
+        );
+    }
+
     // Génération du PDF
-    match generate_invoice_pdf(&invoice, &emitter, totals, xml_content, None) {
+    match generate_invoice_pdf(&invoice, &emitter, totals, xml_content, None, &GenerationOptions::default()) {
         Ok(pdf_bytes) => {
             let output_path = "data/factures-pdf/test-krilla.pdf";
             fs::write(output_path, &pdf_bytes).expect("Erreur écriture fichier");
@@ -0,0 +1,167 @@
+//! Génération à blanc : validation, totaux et XML sans PDF
+//!
+//! Permet de contrôler le XML CII d'une facture sans charger les polices ni
+//! rendre le PDF/A-3 (diagnostic, intégration continue des modèles).
+
+use super::error::FacturXError;
+use super::options::GenerationOptions;
+use super::xml_generator::generate_facturx_xml_with_options;
+use super::xmp_metadata::FacturXProfile;
+use crate::models::invoice::{InvoiceForm, InvoiceTotals};
+use crate::models::rules::{failed_errors, invoice_number_rule};
+use crate::EmitterConfig;
+
+/// Valide la facture, calcule ses totaux et génère son XML dans `profile`,
+/// avec les options par défaut (voir [`dry_run_with_options`])
+pub fn dry_run(
+    form: &mut InvoiceForm,
+    emitter: &EmitterConfig,
+    profile: FacturXProfile,
+) -> Result<(String, InvoiceTotals), FacturXError> {
+    let options = GenerationOptions {
+        profile,
+        ..Default::default()
+    };
+    dry_run_with_options(form, emitter, &options)
+}
+
+/// Valide la facture, calcule ses totaux et génère son XML avec `options`
+///
+/// Applique les mêmes étapes que la génération complète (franchise en base,
+/// échéancier) mais s'arrête avant le PDF. Le XML est celui qui serait
+/// embarqué avec ces options (profil, référence acheteur par défaut, catégorie
+/// des taux nuls, fragments XML additionnels...). La facture est validée
+/// avant le calcul des totaux : une facture sans ligne est refusée plutôt que
+/// de produire une facture à zéro.
+///
+/// # Returns
+/// Le XML Factur-X et les totaux de la facture
+pub fn dry_run_with_options(
+    form: &mut InvoiceForm,
+    emitter: &EmitterConfig,
+    options: &GenerationOptions,
+) -> Result<(String, InvoiceTotals), FacturXError> {
+    let invoice_number_pattern = emitter
+        .invoice_number_pattern()
+        .map_err(|e| FacturXError::Validation(vec![e]))?;
+    let mut rules = form.validate_verbose();
+    rules.push(invoice_number_rule(
+        &form.invoice_number,
+        invoice_number_pattern.as_ref(),
+    ));
+    let errors = failed_errors(rules);
+    if !errors.is_empty() {
+        return Err(FacturXError::Validation(errors));
+    }
+
+    form.sort_lines();
+    if emitter.vat_exempt_franchise {
+        form.apply_vat_franchise();
+    }
+    let totals = form.compute_totals();
+
+    let mut errors = form.validate_prepaid_total(totals);
+    errors.extend(form.validate_installments(totals.due_payable()));
+    if !errors.is_empty() {
+        return Err(FacturXError::Validation(errors));
+    }
+
+    let xml = generate_facturx_xml_with_options(form, emitter, totals, options)
+        .map_err(FacturXError::Xml)?;
+    Ok((xml, totals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_emitter() -> EmitterConfig {
+        toml::from_str(
+            r#"
+            siren = "123456789"
+            siret = "12345678900012"
+            name = "Emetteur"
+            address = "1 rue du Test"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn test_form(recipient_siret: &str) -> InvoiceForm {
+        form_with_lines(
+            recipient_siret,
+            r#"[{"description": "Audit", "quantity": 2, "unit_price_ht": 50, "vat_rate": 20}]"#,
+        )
+    }
+
+    fn form_with_lines(recipient_siret: &str, lines: &str) -> InvoiceForm {
+        let mut form = InvoiceForm::sample(serde_json::from_str(lines).unwrap());
+        form.recipient_siret = recipient_siret.to_string();
+        form
+    }
+
+    #[test]
+    fn test_dry_run_returns_xml_and_totals() {
+        let mut form = test_form("98765432100019");
+        let (xml, totals) = dry_run(&mut form, &test_emitter(), FacturXProfile::Basic).unwrap();
+
+        assert_eq!(totals.total_ht, 100.0);
+        assert_eq!(totals.total_ttc, 120.0);
+        assert!(xml.contains("<ram:ID>FA-2024-001</ram:ID>"));
+        assert!(xml.contains("<ram:ID>urn:factur-x.eu:1p0:basic</ram:ID>"));
+        assert!(xml.contains("<ram:IncludedSupplyChainTradeLineItem>"));
+    }
+
+    #[test]
+    fn test_dry_run_with_options() {
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            default_buyer_reference: Some("ACHATS".to_string()),
+            ..Default::default()
+        };
+        let public_form = || {
+            let mut form = test_form("98765432100019");
+            form.recipient_public_sector = true;
+            form
+        };
+        let (xml, _) = dry_run_with_options(&mut public_form(), &test_emitter(), &options).unwrap();
+        assert!(xml.contains("<ram:BuyerReference>ACHATS</ram:BuyerReference>"));
+
+        let (xml, _) = dry_run(&mut public_form(), &test_emitter(), FacturXProfile::Basic).unwrap();
+        assert!(!xml.contains("ACHATS"));
+    }
+
+    #[test]
+    fn test_dry_run_rejects_invalid_invoice() {
+        let mut form = test_form("123");
+        match dry_run(&mut form, &test_emitter(), FacturXProfile::Minimum) {
+            Err(FacturXError::Validation(errors)) => {
+                assert_eq!(errors[0].field, "recipient_siret");
+            }
+            other => panic!("erreur de validation attendue: {:?}", other.map(|(_, t)| t)),
+        }
+    }
+
+    #[test]
+    fn test_dry_run_rejects_prepaid_above_total() {
+        let mut form = test_form("98765432100019");
+        form.prepaid_amount = Some(150.0);
+        match dry_run(&mut form, &test_emitter(), FacturXProfile::Basic) {
+            Err(FacturXError::Validation(errors)) => {
+                assert_eq!(errors[0].field, "prepaid_amount");
+            }
+            other => panic!("erreur de validation attendue: {:?}", other.map(|(_, t)| t)),
+        }
+    }
+
+    #[test]
+    fn test_dry_run_rejects_invoice_without_lines() {
+        let mut form = form_with_lines("98765432100019", "[]");
+        match dry_run(&mut form, &test_emitter(), FacturXProfile::Basic) {
+            Err(FacturXError::Validation(errors)) => {
+                assert!(errors.iter().any(|e| e.field == "lines"));
+            }
+            other => panic!("erreur de validation attendue: {:?}", other.map(|(_, t)| t)),
+        }
+    }
+}
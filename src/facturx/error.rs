@@ -0,0 +1,33 @@
+//! Erreurs de l'API de génération Factur-X
+
+use crate::models::error::FieldError;
+use std::fmt;
+
+/// Erreur retournée par les points d'entrée de la bibliothèque
+#[derive(Debug)]
+pub enum FacturXError {
+    /// Données de facture invalides
+    Validation(Vec<FieldError>),
+    /// Échec de génération du XML CII
+    Xml(String),
+    /// Échec de génération du PDF/A-3
+    Pdf(String),
+    /// Échec du rendu de l'aperçu
+    Render(String),
+}
+
+impl fmt::Display for FacturXError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FacturXError::Validation(errors) => {
+                let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+                write!(f, "Donnees invalides: {}", messages.join("; "))
+            }
+            FacturXError::Xml(e) => write!(f, "Erreur generation XML: {}", e),
+            FacturXError::Pdf(e) => write!(f, "Erreur generation PDF: {}", e),
+            FacturXError::Render(e) => write!(f, "Erreur rendu apercu: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FacturXError {}
@@ -0,0 +1,418 @@
+//! Profil ICC de l'output intent PDF/A
+//!
+//! krilla déclare par défaut un output intent sRGB. Les chaînes d'impression
+//! peuvent fournir leur propre profil (CMJN ou sRGB spécifique) : il remplace
+//! alors celui de krilla lors du post-traitement lopdf du PDF.
+//!
+//! L'output intent de krilla ne déclare pas de `RegistryName` et son
+//! `OutputConditionIdentifier` n'est pas celui du registre ICC, ce que les
+//! validateurs PDF/A stricts signalent : le post-traitement les complète
+//! d'après [`OutputCondition`].
+
+use lopdf::{Dictionary, Object, Stream};
+use serde::Deserialize;
+
+/// Taille de l'en-tête d'un profil ICC
+const ICC_HEADER_LEN: usize = 128;
+
+/// Identifiant de la condition sRGB dans le registre de l'ICC
+pub const SRGB_CONDITION_IDENTIFIER: &str = "sRGB IEC61966-2.1";
+
+/// Registre des conditions de caractérisation (`RegistryName`)
+pub const ICC_REGISTRY_NAME: &str = "http://www.color.org";
+
+/// Espace colorimétrique d'un profil ICC (champ `colorSpace` de l'en-tête)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IccColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+impl IccColorSpace {
+    fn from_signature(signature: &[u8]) -> Option<Self> {
+        match signature {
+            b"GRAY" => Some(IccColorSpace::Gray),
+            b"RGB " => Some(IccColorSpace::Rgb),
+            b"CMYK" => Some(IccColorSpace::Cmyk),
+            _ => None,
+        }
+    }
+
+    /// Nombre de composantes (`/N` du flux ICC)
+    pub fn components(&self) -> i64 {
+        match self {
+            IccColorSpace::Gray => 1,
+            IccColorSpace::Rgb => 3,
+            IccColorSpace::Cmyk => 4,
+        }
+    }
+}
+
+/// Profil ICC de sortie avec son identifiant de condition d'impression
+///
+/// En configuration, le profil se lit depuis un fichier :
+/// `output_intent = { identifier = "FOGRA39", path = "assets/icc/coated.icc" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "IccProfileConfig")]
+pub struct IccProfile {
+    identifier: String,
+    data: Vec<u8>,
+    color_space: IccColorSpace,
+}
+
+impl IccProfile {
+    /// Construit un profil après contrôle de l'en-tête ICC
+    ///
+    /// PDF/A n'accepte en output intent que des profils de classe sortie
+    /// (`prtr`) ou écran (`mntr`), en niveaux de gris, RVB ou CMJN.
+    pub fn new(identifier: impl Into<String>, data: Vec<u8>) -> Result<Self, String> {
+        let identifier = identifier.into();
+        if identifier.trim().is_empty() {
+            return Err("L'identifiant du profil ICC est obligatoire".to_string());
+        }
+        if data.len() < ICC_HEADER_LEN || &data[36..40] != b"acsp" {
+            return Err(format!(
+                "Le profil ICC '{}' n'est pas un profil ICC valide",
+                identifier
+            ));
+        }
+        let declared_len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if declared_len != data.len() {
+            return Err(format!(
+                "Le profil ICC '{}' est tronque ({} octets declares, {} lus)",
+                identifier,
+                declared_len,
+                data.len()
+            ));
+        }
+        if !matches!(&data[12..16], b"prtr" | b"mntr") {
+            return Err(format!(
+                "Le profil ICC '{}' doit etre un profil de sortie ou d'ecran",
+                identifier
+            ));
+        }
+        let color_space = IccColorSpace::from_signature(&data[16..20]).ok_or_else(|| {
+            format!(
+                "Espace colorimetrique du profil ICC '{}' non supporte (gris, RVB ou CMJN)",
+                identifier
+            )
+        })?;
+
+        Ok(Self {
+            identifier,
+            data,
+            color_space,
+        })
+    }
+
+    /// Lit un profil ICC depuis un fichier
+    pub fn from_file(identifier: impl Into<String>, path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Impossible de lire le profil ICC {}: {}", path, e))?;
+        Self::new(identifier, data)
+    }
+
+    /// Identifiant de la condition d'impression (`OutputConditionIdentifier`)
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn color_space(&self) -> IccColorSpace {
+        self.color_space
+    }
+
+    /// Remplace les output intents du catalogue par un output intent PDF/A
+    /// unique portant ce profil
+    ///
+    /// Les anciens objets (output intent et profil sRGB de krilla) ne sont
+    /// plus référencés et sont retirés du document.
+    pub(super) fn replace_output_intent(
+        &self,
+        doc: &mut lopdf::Document,
+        registry_name: &str,
+    ) -> Result<(), String> {
+        let mut profile_dict = Dictionary::new();
+        profile_dict.set("N", Object::Integer(self.color_space.components()));
+        let profile_id = doc.add_object(Stream::new(profile_dict, self.data.clone()));
+
+        let identifier = || Object::string_literal(self.identifier.as_str());
+        let mut intent = Dictionary::new();
+        intent.set("Type", Object::Name(b"OutputIntent".to_vec()));
+        intent.set("S", Object::Name(b"GTS_PDFA1".to_vec()));
+        intent.set("OutputConditionIdentifier", identifier());
+        intent.set("Info", identifier());
+        intent.set("RegistryName", Object::string_literal(registry_name));
+        intent.set("DestOutputProfile", Object::Reference(profile_id));
+        let intent_id = doc.add_object(intent);
+
+        let catalog_id = doc
+            .trailer
+            .get(b"Root")
+            .and_then(Object::as_reference)
+            .map_err(|_| "Pas de reference /Root dans le trailer")?;
+        doc.get_object_mut(catalog_id)
+            .and_then(Object::as_dict_mut)
+            .map_err(|e| format!("Erreur acces catalogue: {:?}", e))?
+            .set(
+                "OutputIntents",
+                Object::Array(vec![Object::Reference(intent_id)]),
+            );
+
+        doc.prune_objects();
+        Ok(())
+    }
+}
+
+/// Condition de sortie déclarée par les output intents du PDF
+///
+/// En configuration : `output_condition = { identifier = "sRGB IEC61966-2.1",
+/// registry_name = "http://www.color.org" }` (valeurs par défaut). Avec un
+/// profil `output_intent`, l'identifiant reste celui du profil et seul le
+/// registre est repris.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OutputCondition {
+    /// `OutputConditionIdentifier` de l'output intent sRGB de krilla
+    pub identifier: String,
+    /// `RegistryName` des output intents
+    pub registry_name: String,
+}
+
+impl Default for OutputCondition {
+    fn default() -> Self {
+        Self {
+            identifier: SRGB_CONDITION_IDENTIFIER.to_string(),
+            registry_name: ICC_REGISTRY_NAME.to_string(),
+        }
+    }
+}
+
+impl OutputCondition {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.identifier.trim().is_empty() || self.registry_name.trim().is_empty() {
+            return Err(
+                "Condition de sortie invalide: identifiant et registre obligatoires".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Renseigne `OutputConditionIdentifier` et `RegistryName` sur chaque
+    /// output intent du catalogue (directs ou référencés)
+    ///
+    /// Un PDF sans output intent (généré sans validation PDF/A) est laissé
+    /// tel quel.
+    pub(super) fn apply(&self, doc: &mut lopdf::Document) -> Result<(), String> {
+        let catalog = doc
+            .catalog()
+            .map_err(|e| format!("Erreur acces catalogue: {:?}", e))?;
+        let intents = match catalog.get(b"OutputIntents") {
+            Ok(Object::Array(intents)) => intents.clone(),
+            Ok(Object::Reference(id)) => doc
+                .get_object(*id)
+                .and_then(Object::as_array)
+                .map_err(|e| format!("Erreur acces output intents: {:?}", e))?
+                .clone(),
+            _ => return Ok(()),
+        };
+
+        let update = |intent: &mut Dictionary| {
+            intent.set(
+                "OutputConditionIdentifier",
+                Object::string_literal(self.identifier.as_str()),
+            );
+            intent.set(
+                "RegistryName",
+                Object::string_literal(self.registry_name.as_str()),
+            );
+        };
+        let mut inline = Vec::with_capacity(intents.len());
+        for intent in intents {
+            match intent {
+                Object::Reference(id) => {
+                    let dict = doc
+                        .get_object_mut(id)
+                        .and_then(Object::as_dict_mut)
+                        .map_err(|e| format!("Erreur acces output intent: {:?}", e))?;
+                    update(dict);
+                    inline.push(Object::Reference(id));
+                }
+                Object::Dictionary(mut dict) => {
+                    update(&mut dict);
+                    inline.push(Object::Dictionary(dict));
+                }
+                other => inline.push(other),
+            }
+        }
+
+        let catalog_id = doc
+            .trailer
+            .get(b"Root")
+            .and_then(Object::as_reference)
+            .map_err(|_| "Pas de reference /Root dans le trailer")?;
+        doc.get_object_mut(catalog_id)
+            .and_then(Object::as_dict_mut)
+            .map_err(|e| format!("Erreur acces catalogue: {:?}", e))?
+            .set("OutputIntents", Object::Array(inline));
+        Ok(())
+    }
+}
+
+/// Forme du profil dans la section `[generation]`
+#[derive(Deserialize)]
+struct IccProfileConfig {
+    identifier: String,
+    path: String,
+}
+
+impl TryFrom<IccProfileConfig> for IccProfile {
+    type Error = String;
+
+    fn try_from(config: IccProfileConfig) -> Result<Self, String> {
+        IccProfile::from_file(config.identifier, &config.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// En-tête ICC minimal (sans table de tags) de la classe et de l'espace donnés
+    fn icc_header(class: &[u8; 4], color_space: &[u8; 4]) -> Vec<u8> {
+        let mut data = vec![0u8; ICC_HEADER_LEN];
+        data[0..4].copy_from_slice(&(ICC_HEADER_LEN as u32).to_be_bytes());
+        data[12..16].copy_from_slice(class);
+        data[16..20].copy_from_slice(color_space);
+        data[36..40].copy_from_slice(b"acsp");
+        data
+    }
+
+    #[test]
+    fn test_icc_profile_header() {
+        let profile = IccProfile::new("FOGRA39", icc_header(b"prtr", b"CMYK")).unwrap();
+        assert_eq!(profile.identifier(), "FOGRA39");
+        assert_eq!(profile.color_space(), IccColorSpace::Cmyk);
+        assert_eq!(profile.color_space().components(), 4);
+
+        let rgb = IccProfile::new("sRGB IEC61966-2.1", icc_header(b"mntr", b"RGB ")).unwrap();
+        assert_eq!(rgb.color_space().components(), 3);
+    }
+
+    #[test]
+    fn test_icc_profile_rejects_invalid_data() {
+        assert!(IccProfile::new("FOGRA39", b"not an icc profile".to_vec()).is_err());
+        assert!(IccProfile::new(" ", icc_header(b"prtr", b"CMYK")).is_err());
+        assert!(IccProfile::new("Scanner", icc_header(b"scnr", b"RGB ")).is_err());
+        assert!(IccProfile::new("Lab", icc_header(b"prtr", b"Lab ")).is_err());
+
+        let mut truncated = icc_header(b"prtr", b"CMYK");
+        truncated[0..4].copy_from_slice(&512u32.to_be_bytes());
+        assert!(IccProfile::new("FOGRA39", truncated).is_err());
+    }
+
+    #[test]
+    fn test_replace_output_intent() {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let srgb_id = doc.add_object(Stream::new(Dictionary::new(), b"sRGB".to_vec()));
+        let mut old_intent = Dictionary::new();
+        old_intent.set("DestOutputProfile", Object::Reference(srgb_id));
+        let old_intent_id = doc.add_object(old_intent);
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set(
+            "OutputIntents",
+            Object::Array(vec![Object::Reference(old_intent_id)]),
+        );
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        let profile = IccProfile::new("FOGRA39", icc_header(b"prtr", b"CMYK")).unwrap();
+        profile
+            .replace_output_intent(&mut doc, ICC_REGISTRY_NAME)
+            .unwrap();
+
+        let intents = doc
+            .catalog()
+            .unwrap()
+            .get(b"OutputIntents")
+            .and_then(Object::as_array)
+            .unwrap();
+        assert_eq!(intents.len(), 1);
+        let intent = doc
+            .get_dictionary(intents[0].as_reference().unwrap())
+            .unwrap();
+        assert_eq!(
+            intent
+                .get(b"OutputConditionIdentifier")
+                .unwrap()
+                .as_str()
+                .unwrap(),
+            b"FOGRA39"
+        );
+        assert_eq!(
+            intent.get(b"RegistryName").unwrap().as_str().unwrap(),
+            ICC_REGISTRY_NAME.as_bytes()
+        );
+        let stream = doc
+            .get_object(
+                intent
+                    .get(b"DestOutputProfile")
+                    .unwrap()
+                    .as_reference()
+                    .unwrap(),
+            )
+            .and_then(Object::as_stream)
+            .unwrap();
+        assert_eq!(stream.dict.get(b"N").unwrap().as_i64().unwrap(), 4);
+        assert_eq!(stream.content, profile.data());
+        assert!(doc.get_object(srgb_id).is_err());
+    }
+
+    #[test]
+    fn test_output_condition_completes_krilla_intent() {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let mut krilla_intent = Dictionary::new();
+        krilla_intent.set("S", Object::Name(b"GTS_PDFA1".to_vec()));
+        krilla_intent.set("OutputConditionIdentifier", Object::string_literal("sRGB"));
+        let intent_id = doc.add_object(krilla_intent);
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set(
+            "OutputIntents",
+            Object::Array(vec![Object::Reference(intent_id)]),
+        );
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        OutputCondition::default().apply(&mut doc).unwrap();
+
+        let intent = doc.get_dictionary(intent_id).unwrap();
+        assert_eq!(
+            intent
+                .get(b"OutputConditionIdentifier")
+                .unwrap()
+                .as_str()
+                .unwrap(),
+            SRGB_CONDITION_IDENTIFIER.as_bytes()
+        );
+        assert_eq!(
+            intent.get(b"RegistryName").unwrap().as_str().unwrap(),
+            ICC_REGISTRY_NAME.as_bytes()
+        );
+
+        let condition: OutputCondition =
+            toml::from_str(r#"registry_name = "https://registry.example""#).unwrap();
+        assert_eq!(condition.identifier, SRGB_CONDITION_IDENTIFIER);
+        assert!(condition.validate().is_ok());
+        let blank = OutputCondition {
+            identifier: " ".to_string(),
+            ..Default::default()
+        };
+        assert!(blank.validate().is_err());
+    }
+}
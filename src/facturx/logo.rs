@@ -0,0 +1,84 @@
+//! Source du logo de l'émetteur pour le PDF
+//!
+//! Le logo peut venir d'un fichier (configuration `logo` de l'émetteur) ou
+//! d'octets déjà en mémoire, pour les usages bibliothèque sans système de fichiers.
+
+use std::borrow::Cow;
+
+/// Formats d'image acceptés pour le logo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    /// Détecte le format à partir de la signature des données
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else {
+            None
+        }
+    }
+}
+
+/// Logo à dessiner en tête du PDF
+#[derive(Debug, Clone)]
+pub enum LogoSource {
+    /// Chemin d'un fichier PNG ou JPEG (format détecté à la lecture)
+    Path(String),
+    /// Image PNG ou JPEG déjà chargée
+    Bytes(Vec<u8>, ImageFormat),
+}
+
+impl LogoSource {
+    /// Retourne les octets de l'image et leur format
+    pub(super) fn load(&self) -> Result<(Cow<'_, [u8]>, ImageFormat), String> {
+        match self {
+            LogoSource::Path(path) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|e| format!("Impossible de lire le logo {}: {}", path, e))?;
+                let format = ImageFormat::detect(&bytes).ok_or_else(|| {
+                    format!("Format du logo {} non supporte (PNG ou JPEG attendu)", path)
+                })?;
+                Ok((Cow::Owned(bytes), format))
+            }
+            LogoSource::Bytes(bytes, format) => Ok((Cow::Borrowed(bytes), *format)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_image_format() {
+        assert_eq!(
+            ImageFormat::detect(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::detect(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(ImageFormat::detect(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn test_load_bytes_keeps_format() {
+        let logo = LogoSource::Bytes(vec![1, 2, 3], ImageFormat::Png);
+        let (bytes, format) = logo.load().unwrap();
+        assert_eq!(&*bytes, &[1, 2, 3]);
+        assert_eq!(format, ImageFormat::Png);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let logo = LogoSource::Path("assets/absent.png".to_string());
+        assert!(logo.load().is_err());
+    }
+}
@@ -3,11 +3,46 @@
 //! Ce module fournit les fonctions pour générer des factures conformes
 //! au standard Factur-X (profil MINIMUM et BASIC) avec :
 //! - XML CII (Cross Industry Invoice) embarqué
+//! - XML UBL 2.1 équivalent, pour l'envoi PEPPOL
 //! - PDF/A-3 avec métadonnées XMP
+//! - Aperçu PNG de la première page
+//! - Génération à blanc (XML et totaux sans PDF)
+//! - Remplacement du XML embarqué d'un PDF existant
+//! - Contrôle des invariants PDF/A-3 après post-traitement
+//! - Comparaison de deux XML (maintenance des fichiers de référence)
 
+mod dry_run;
+mod error;
+mod icc;
+mod logo;
+mod options;
 mod pdf_generator;
+mod pdfa;
+mod preview;
+mod profiles;
+mod reembed;
+mod ubl_generator;
+mod vat_breakdown;
+mod xml_diff;
 mod xml_generator;
 pub mod xmp_metadata;
 
-pub use pdf_generator::generate_invoice_pdf;
-pub use xml_generator::generate_facturx_xml;
+pub use dry_run::{dry_run, dry_run_with_options};
+pub use error::FacturXError;
+pub use icc::{IccColorSpace, IccProfile, OutputCondition};
+pub use logo::{ImageFormat, LogoSource};
+pub use options::{
+    GenerationOptions, Language, LayoutConfig, PageOrientation, Template, ZeroRateCategory,
+};
+pub use pdf_generator::{
+    generate_invoice_pdf, generate_invoice_pdf_with_warnings, page_orientation, FontSet,
+    GeneratedPdf,
+};
+pub use pdfa::verify_pdfa3;
+pub use preview::{preview_dimensions, render_preview_png};
+pub use profiles::{describe_profiles, BusinessTerm, ProfileDescription};
+pub use reembed::{extract_facturx_xml, reembed_facturx_xml};
+pub use ubl_generator::generate_ubl_xml;
+pub use vat_breakdown::{vat_breakdown, VatBreakdown};
+pub use xml_diff::diff_xml;
+pub use xml_generator::{generate_facturx_xml, generate_facturx_xml_with_options};
@@ -0,0 +1,472 @@
+//! Options de génération Factur-X
+//!
+//! Regroupe les réglages facultatifs de la génération PDF et XML, avec des
+//! valeurs par défaut reproduisant le comportement historique. Les options se
+//! lisent aussi depuis la section `[generation]` de la configuration.
+
+use super::icc::{IccProfile, OutputCondition};
+use super::pdf_generator::{PAGE_HEIGHT_PT, PAGE_WIDTH_PT};
+use super::xml_generator::validate_xml_fragment;
+use super::xmp_metadata::{
+    FacturXProfile, PdfaConformance, DEFAULT_PRODUCER, FACTURX_XML_FILENAME,
+};
+use crate::models::catalog::Catalog;
+use serde::Deserialize;
+
+/// Plafond par défaut des totaux : assez haut pour ne jamais se déclencher
+pub const DEFAULT_MAX_AMOUNT: f64 = 1_000_000_000_000.0;
+
+/// Nombre maximal de lignes par défaut : large pour un usage normal
+pub const DEFAULT_MAX_LINES: usize = 1000;
+
+/// Langue du PDF : détermine le format des dates affichées
+///
+/// Le XML conserve toujours le format 102 (AAAAMMJJ).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// JJ/MM/AAAA (défaut)
+    #[default]
+    Fr,
+    /// MM/JJ/AAAA
+    En,
+    /// AAAA-MM-JJ (format neutre ISO 8601)
+    Iso,
+}
+
+/// Orientation de la page A4 du PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageOrientation {
+    /// Portrait, ou paysage si les colonnes du tableau des lignes dépassent
+    /// la largeur imprimable du portrait (défaut)
+    #[default]
+    Auto,
+    Portrait,
+    Landscape,
+}
+
+/// Modèle de mise en page du PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Template {
+    /// Émetteur et titre centré en noir, totaux alignés sous le tableau (défaut)
+    #[default]
+    Classic,
+    /// Bandeau coloré en tête de la première page (émetteur et titre) et
+    /// totaux dans un encadré aligné à droite
+    Modern,
+}
+
+/// Catégorie de TVA (UNTDID 5305) des lignes au taux de 0 %
+///
+/// Un taux positif est toujours en catégorie S (taux normal) ; la franchise
+/// en base de TVA impose E à toutes les lignes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ZeroRateCategory {
+    /// Taux zéro (défaut), sans motif d'exonération
+    #[default]
+    Z,
+    /// Exonéré de TVA, avec un motif d'exonération (BT-120)
+    E,
+    /// Hors champ de la TVA : motif VATEX-EU-O et aucun taux émis
+    O,
+}
+
+impl ZeroRateCategory {
+    /// Code UNTDID 5305 (BT-118, BT-151)
+    pub fn code(self) -> &'static str {
+        match self {
+            ZeroRateCategory::Z => "Z",
+            ZeroRateCategory::E => "E",
+            ZeroRateCategory::O => "O",
+        }
+    }
+
+    /// Motif d'exonération par défaut (BT-120), exigé pour E et O
+    pub fn default_exemption_reason(self) -> Option<&'static str> {
+        match self {
+            ZeroRateCategory::Z => None,
+            ZeroRateCategory::E => Some("Exonération de TVA"),
+            ZeroRateCategory::O => Some("Non soumis à la TVA"),
+        }
+    }
+
+    /// Code de motif d'exonération VATEX (BT-121)
+    pub fn exemption_reason_code(self) -> Option<&'static str> {
+        match self {
+            ZeroRateCategory::O => Some("VATEX-EU-O"),
+            _ => None,
+        }
+    }
+}
+
+/// Largeur imprimable minimale du portrait (colonnes obligatoires du tableau)
+const MIN_CONTENT_WIDTH: f32 = 300.0;
+/// Hauteur minimale entre la marge haute et le pied de page
+const MIN_CONTENT_HEIGHT: f32 = 400.0;
+
+/// Marges, corps de texte et interligne du PDF, en points (1pt = 1/72 pouce)
+///
+/// Les valeurs par défaut reproduisent la mise en page historique. Se lit
+/// depuis la section `[generation.layout]`, chaque valeur étant facultative.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub margin_left: f32,
+    pub margin_right: f32,
+    pub margin_top: f32,
+    /// Titre du document et nom de l'émetteur
+    pub font_size_title: f32,
+    /// Numéro de facture, titres de blocs et total TTC
+    pub font_size_header: f32,
+    pub font_size_normal: f32,
+    /// Tableau des lignes, récapitulatif TVA et pied de page
+    pub font_size_small: f32,
+    /// Détail sous la description d'une ligne
+    pub font_size_detail: f32,
+    /// Interligne des blocs de texte et hauteur d'une ligne du tableau
+    pub line_height: f32,
+    /// Montants affichés avec le symbole de la devise (€, $, £, ¥) plutôt
+    /// que son code ISO 4217 ; le XML garde toujours le code
+    pub currency_symbol: bool,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            margin_left: 57.0, // ~20mm
+            margin_right: 57.0,
+            margin_top: 57.0,
+            font_size_title: 18.0,
+            font_size_header: 12.0,
+            font_size_normal: 10.0,
+            font_size_small: 8.0,
+            font_size_detail: 7.0,
+            line_height: 14.0,
+            currency_symbol: false,
+        }
+    }
+}
+
+impl LayoutConfig {
+    /// Vérifie que les valeurs sont positives et que les marges laissent une
+    /// zone de contenu utilisable sur une page A4 portrait
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("margin_left", self.margin_left),
+            ("margin_right", self.margin_right),
+            ("margin_top", self.margin_top),
+            ("font_size_title", self.font_size_title),
+            ("font_size_header", self.font_size_header),
+            ("font_size_normal", self.font_size_normal),
+            ("font_size_small", self.font_size_small),
+            ("font_size_detail", self.font_size_detail),
+            ("line_height", self.line_height),
+        ] {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(format!(
+                    "Mise en page invalide: {} = {} (valeur positive attendue)",
+                    name, value
+                ));
+            }
+        }
+
+        let content_width = PAGE_WIDTH_PT - self.margin_left - self.margin_right;
+        if content_width < MIN_CONTENT_WIDTH {
+            return Err(format!(
+                "Mise en page invalide: les marges gauche et droite ne laissent que {} pt de largeur utile (minimum {} pt)",
+                content_width, MIN_CONTENT_WIDTH
+            ));
+        }
+        if PAGE_HEIGHT_PT - self.margin_top < MIN_CONTENT_HEIGHT {
+            return Err(format!(
+                "Mise en page invalide: la marge haute ({} pt) ne laisse pas {} pt de hauteur utile",
+                self.margin_top, MIN_CONTENT_HEIGHT
+            ));
+        }
+        Ok(())
+    }
+
+    /// Largeur imprimable d'une page de largeur `page_width`
+    pub fn content_width(&self, page_width: f32) -> f32 {
+        page_width - self.margin_left - self.margin_right
+    }
+}
+
+/// Options de génération du PDF Factur-X
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GenerationOptions {
+    /// Remplace le XMP produit par krilla par le XMP Factur-X complet
+    /// (schéma d'extension `fx:`) via un aller-retour lopdf.
+    ///
+    /// - `true` (défaut) : XMP conforme Factur-X, requis pour qu'un lecteur
+    ///   identifie le profil et le fichier XML embarqué.
+    /// - `false` : seules les métadonnées natives de krilla (titre, auteur,
+    ///   sujet) sont écrites ; le PDF n'est ni rechargé ni réécrit, ce qui
+    ///   est plus rapide sur les gros documents et préserve l'ordre des
+    ///   objets produit par krilla. A réserver aux usages qui n'exigent pas
+    ///   l'extension `fx:` (aperçus, archivage interne).
+    pub xmp_post_processing: bool,
+
+    /// Profil Factur-X du XML embarqué (MINIMUM par défaut)
+    ///
+    /// MINIMUM et BASIC WL ne décrivent que l'en-tête et les totaux ; BASIC
+    /// ajoute les lignes de facture (`IncludedSupplyChainTradeLineItem`).
+    pub profile: FacturXProfile,
+
+    /// Niveau PDF/A-3 validé par krilla et déclaré dans le XMP
+    /// (`pdfaid:conformance`) : `a3b` par défaut, `a3u` quand l'archivage
+    /// exige que tout le texte soit extractible en Unicode
+    pub pdfa_conformance: PdfaConformance,
+
+    /// Mode strict : refuse la génération si une règle métier EN 16931
+    /// (BR-CO-10, BR-CO-13, BR-CO-14, BR-CO-15, BR-CO-25, BR-S-08) n'est pas
+    /// respectée, plutôt que de laisser la plateforme de réception rejeter
+    /// la facture (désactivé par défaut).
+    pub strict: bool,
+
+    /// BT-10 : Référence acheteur par défaut des destinataires publics, émise
+    /// quand ni la référence saisie ni le code service ne sont renseignés
+    pub default_buyer_reference: Option<String>,
+
+    /// Mention de copie (ex. "DUPLICATA") imprimée en filigrane diagonal sur
+    /// la page, pour distinguer une réimpression de l'original. Le XML
+    /// embarqué n'est pas modifié.
+    pub copy_label: Option<String>,
+
+    /// XML compact : sans indentation ni retours à la ligne, pour réduire la
+    /// taille du fichier embarqué. Désactivé par défaut (XML indenté, plus
+    /// lisible à l'inspection).
+    pub compact_xml: bool,
+
+    /// Déclare `xmlns:xsi` et `xsi:schemaLocation` (schéma CII D16B) sur
+    /// l'élément racine, pour les validateurs qui l'exigent. Désactivé par
+    /// défaut : le reste du document est identique.
+    pub schema_location: bool,
+
+    /// Préfixe le `factur-x.xml` embarqué du BOM UTF-8 (EF BB BF), exigé par
+    /// certains logiciels de réception anciens et refusé par d'autres.
+    /// Désactivé par défaut ; le XML enregistré à côté du PDF n'est pas
+    /// concerné.
+    pub xml_bom: bool,
+
+    /// Langue du PDF (format des dates), français par défaut
+    pub language: Language,
+
+    /// Orientation de la page ; `Portrait` ou `Landscape` imposent le format,
+    /// `Auto` (défaut) bascule en paysage pour les tableaux trop larges
+    /// (voir [`page_orientation`](super::page_orientation))
+    pub orientation: PageOrientation,
+
+    /// Profil ICC de l'output intent PDF/A (ex. CMJN FOGRA39 pour
+    /// l'impression), à la place du sRGB déclaré par krilla (défaut). Les
+    /// couleurs sont alors toutes écrites en espaces ICC, indépendants de
+    /// l'output intent, et le PDF passe par le post-traitement lopdf.
+    pub output_intent: Option<IccProfile>,
+
+    /// `OutputConditionIdentifier` et `RegistryName` écrits sur l'output
+    /// intent lors du post-traitement (sRGB IEC61966-2.1 du registre ICC par
+    /// défaut), exigés par les validateurs PDF/A stricts. Une valeur autre
+    /// que celle par défaut impose le post-traitement, même avec
+    /// `xmp_post_processing = false`.
+    pub output_condition: OutputCondition,
+
+    /// Tolère un PDF non conforme PDF/A-3 : si la validation krilla échoue,
+    /// le PDF est regénéré sans validation, avec le XML embarqué, et les
+    /// erreurs de validation sont retournées en avertissements. Désactivé par
+    /// défaut (échec de la génération).
+    pub allow_non_conformant: bool,
+
+    /// Cadre "Bon pour accord / Date et signature" en bas de page, pour les
+    /// devis et factures à faire signer (désactivé par défaut)
+    pub signature_box: bool,
+
+    /// Nom du fichier XML embarqué, repris tel quel dans le XMP
+    /// (`fx:DocumentFileName`) : `factur-x.xml` par défaut, seul nom reconnu
+    /// par les lecteurs Factur-X. Un autre nom produit un avertissement de
+    /// validation XMP.
+    pub xml_filename: String,
+
+    /// Outil producteur déclaré dans le PDF (`/Producer` et `pdf:Producer`
+    /// du XMP), pour les intégrateurs qui signent avec leur propre nom
+    pub producer: String,
+
+    /// Plafond des totaux de la facture, au-delà duquel un avertissement est
+    /// retourné (certaines plateformes refusent les montants trop élevés).
+    /// Très élevé par défaut, donc sans effet.
+    pub max_amount: f64,
+
+    /// Nombre maximal de lignes d'une facture : au-delà, la facture est
+    /// refusée à la validation plutôt que de prolonger indéfiniment la
+    /// génération du PDF (1000 par défaut)
+    pub max_lines: usize,
+
+    /// Catalogue produits : les lignes avec un `product_code` et sans prix
+    /// sont complétées depuis ce catalogue (aucun par défaut)
+    pub catalog: Option<Catalog>,
+
+    /// Marges, corps de texte et interligne du PDF (section
+    /// `[generation.layout]`)
+    pub layout: LayoutConfig,
+
+    /// Modèle de mise en page du PDF (`classic` par défaut) ; le XML n'est
+    /// pas modifié
+    pub template: Template,
+
+    /// Rend cliquables l'e-mail (`mailto:`) et le site web de l'émetteur
+    /// imprimés dans l'en-tête, par des annotations de lien (activé par
+    /// défaut). Sans elles, le texte reste imprimé à l'identique.
+    pub link_annotations: bool,
+
+    /// Reprend les mentions légales de l'émetteur (forme juridique, capital,
+    /// RCS) dans une note d'en-tête du XML (code sujet `ABL`). Désactivé par
+    /// défaut : les mentions ne sont imprimées que sur le PDF.
+    pub legal_mentions_note: bool,
+
+    /// Catégorie de TVA des lignes au taux de 0 % (`Z` par défaut, `E` ou `O`)
+    pub zero_rate_category: ZeroRateCategory,
+
+    /// Motif d'exonération (BT-120) des lignes à 0 % en catégorie E ou O, à
+    /// la place du motif générique (ex. "Exonération art. 261 du CGI")
+    pub zero_rate_exemption_reason: Option<String>,
+
+    /// Fragment XML libre ajouté en fin d'`ApplicableHeaderTradeAgreement`,
+    /// pour les champs (souvent EXTENDED) que la bibliothèque ne modélise
+    /// pas. Contrôlé à la validation : XML bien formé, éléments `ram:` au
+    /// premier niveau. L'ordre du schéma reste à la charge de l'appelant.
+    pub extra_agreement_xml: Option<String>,
+
+    /// Fragment XML libre ajouté en fin d'`ApplicableHeaderTradeDelivery`
+    /// (voir `extra_agreement_xml`)
+    pub extra_delivery_xml: Option<String>,
+
+    /// Fragment XML libre ajouté en fin d'`ApplicableHeaderTradeSettlement`,
+    /// après les totaux (voir `extra_agreement_xml`)
+    pub extra_settlement_xml: Option<String>,
+}
+
+impl Default for GenerationOptions {
+    fn default() -> Self {
+        Self {
+            xmp_post_processing: true,
+            profile: FacturXProfile::Minimum,
+            pdfa_conformance: PdfaConformance::A3B,
+            strict: false,
+            default_buyer_reference: None,
+            copy_label: None,
+            compact_xml: false,
+            schema_location: false,
+            xml_bom: false,
+            language: Language::Fr,
+            orientation: PageOrientation::Auto,
+            output_intent: None,
+            output_condition: OutputCondition::default(),
+            allow_non_conformant: false,
+            signature_box: false,
+            xml_filename: FACTURX_XML_FILENAME.to_string(),
+            producer: DEFAULT_PRODUCER.to_string(),
+            max_amount: DEFAULT_MAX_AMOUNT,
+            max_lines: DEFAULT_MAX_LINES,
+            catalog: None,
+            layout: LayoutConfig::default(),
+            template: Template::Classic,
+            link_annotations: true,
+            legal_mentions_note: false,
+            zero_rate_category: ZeroRateCategory::Z,
+            zero_rate_exemption_reason: None,
+            extra_agreement_xml: None,
+            extra_delivery_xml: None,
+            extra_settlement_xml: None,
+        }
+    }
+}
+
+impl GenerationOptions {
+    /// Vérifie que le nom du fichier XML embarqué est utilisable, ainsi que
+    /// les plafonds, la mise en page et les fragments XML libres
+    pub fn validate(&self) -> Result<(), String> {
+        if self.xml_filename.len() <= ".xml".len()
+            || !self.xml_filename.ends_with(".xml")
+            || self.xml_filename.contains(['/', '\\'])
+        {
+            return Err(format!(
+                "Nom du fichier XML embarque invalide: '{}' (nom simple en .xml attendu)",
+                self.xml_filename
+            ));
+        }
+        if self.max_amount.is_nan() || self.max_amount <= 0.0 {
+            return Err(format!(
+                "Plafond des montants invalide: {} (montant positif attendu)",
+                self.max_amount
+            ));
+        }
+        if self.max_lines == 0 {
+            return Err("Nombre maximal de lignes invalide: 0 (au moins 1 attendu)".to_string());
+        }
+        self.layout.validate()?;
+        self.output_condition.validate()?;
+        for (option, fragment) in [
+            ("extra_agreement_xml", &self.extra_agreement_xml),
+            ("extra_delivery_xml", &self.extra_delivery_xml),
+            ("extra_settlement_xml", &self.extra_settlement_xml),
+        ] {
+            if let Some(fragment) = fragment {
+                validate_xml_fragment(option, fragment)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_from_config() {
+        assert_eq!(GenerationOptions::default().template, Template::Classic);
+        let options: GenerationOptions = toml::from_str(r#"template = "modern""#).unwrap();
+        assert_eq!(options.template, Template::Modern);
+        assert!(toml::from_str::<GenerationOptions>(r#"template = "fancy""#).is_err());
+    }
+
+    #[test]
+    fn test_layout_validation() {
+        assert!(LayoutConfig::default().validate().is_ok());
+        assert!(GenerationOptions::default().validate().is_ok());
+
+        let layout = LayoutConfig {
+            font_size_small: 0.0,
+            ..Default::default()
+        };
+        assert!(layout.validate().unwrap_err().contains("font_size_small"));
+
+        let layout = LayoutConfig {
+            margin_left: 150.0,
+            margin_right: 150.0,
+            ..Default::default()
+        };
+        assert!(layout.validate().unwrap_err().contains("largeur utile"));
+
+        let layout = LayoutConfig {
+            margin_top: 500.0,
+            ..Default::default()
+        };
+        assert!(layout.validate().unwrap_err().contains("marge haute"));
+
+        let options: GenerationOptions = toml::from_str(
+            r#"
+            [layout]
+            margin_left = 40.0
+            font_size_normal = 12.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(options.layout.margin_left, 40.0);
+        assert_eq!(options.layout.margin_right, 57.0);
+        assert_eq!(options.layout.font_size_normal, 12.0);
+        assert_eq!(options.layout.content_width(PAGE_WIDTH_PT), 498.0);
+    }
+}
@@ -2,129 +2,535 @@
 //!
 //! Utilise krilla pour generer un PDF/A-3 conforme avec :
 //! - Polices embarquees (Liberation Sans)
-//! - Profil ICC sRGB pour les couleurs
+//! - Profil ICC sRGB pour les couleurs (ou profil de sortie configure)
 //! - XML Factur-X en piece jointe
 //! - Metadonnees XMP Factur-X injectees via lopdf
 
-use super::xmp_metadata::{generate_xmp_metadata, FacturXProfile, XmpMetadata};
-use crate::models::invoice::InvoiceForm;
-use crate::EmitterConfig;
+use super::icc::OutputCondition;
+use super::logo::{ImageFormat, LogoSource};
+use super::options::{GenerationOptions, Language, LayoutConfig, PageOrientation, Template};
+use super::pdfa::verify_pdfa3;
+use super::reembed::UTF8_BOM;
+use super::vat_breakdown::vat_breakdown;
+use super::xmp_metadata::{generate_xmp_metadata, FacturXProfile, PdfaConformance, XmpMetadata};
+use crate::models::invoice::{InvoiceForm, InvoiceTotals, Prepayment, VatRateKey};
+use crate::models::line::{format_quantity, InvoiceLine};
+use crate::{
+    EmitterConfig, FREE_OF_CHARGE_MENTION, VAT_FRANCHISE_MENTION, VAT_ON_PAYMENTS_MENTION,
+};
+use krilla::action::LinkAction;
+use krilla::annotation::{Annotation, LinkAnnotation, Target};
 use krilla::color::rgb;
 use krilla::configure::{Configuration, Validator};
 use krilla::embed::{AssociationKind, EmbeddedFile, MimeType};
 use krilla::error::KrillaError;
-use krilla::geom::{PathBuilder, Point};
-use krilla::metadata::DateTime;
+use krilla::geom::{PathBuilder, Point, Rect, Size, Transform};
+use krilla::image::Image;
+use krilla::metadata::{DateTime, Metadata};
+use krilla::num::NormalizedF32;
 use krilla::page::PageSettings;
 use krilla::paint::{Fill, Paint, Stroke};
 use krilla::surface::Surface;
 use krilla::text::{Font, TextDirection};
 use krilla::{Document, SerializeSettings};
 use lopdf::{Dictionary, Object, Stream};
-use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 
 /// Constantes de mise en page (en points, 1pt = 1/72 inch)
-const PAGE_WIDTH_PT: f32 = 595.0; // A4 width
-const PAGE_HEIGHT_PT: f32 = 842.0; // A4 height
-const MARGIN_LEFT: f32 = 57.0; // ~20mm
-const MARGIN_RIGHT: f32 = 57.0;
-const MARGIN_TOP: f32 = 57.0;
-const FONT_SIZE_TITLE: f32 = 18.0;
-const FONT_SIZE_HEADER: f32 = 12.0;
-const FONT_SIZE_NORMAL: f32 = 10.0;
-const FONT_SIZE_SMALL: f32 = 8.0;
-const LINE_HEIGHT: f32 = 14.0;
-
-/// Structure pour les polices chargees
-struct FontSet {
+pub(super) const PAGE_WIDTH_PT: f32 = 595.0; // A4 width
+pub(super) const PAGE_HEIGHT_PT: f32 = 842.0; // A4 height
+const DETAIL_WRAP_CHARS: usize = 100;
+const LOGO_MAX_WIDTH: f32 = 150.0;
+const LOGO_MAX_HEIGHT: f32 = 50.0;
+const WATERMARK_MAX_FONT_SIZE: f32 = 96.0;
+const WATERMARK_OPACITY: f32 = 0.15;
+/// Largeur moyenne d'une majuscule Liberation Sans Bold, en fraction de la taille
+const WATERMARK_CHAR_WIDTH: f32 = 0.7;
+/// Type MIME du XML Factur-X embarque
+const XML_MIME_TYPE: &str = "text/xml";
+/// Largeur moyenne d'un caractere Liberation Sans, en fraction de la taille
+/// (zone cliquable des liens de contact)
+const LINK_CHAR_WIDTH: f32 = 0.55;
+/// Tampon "PAYE" : corps, inclinaison (degres) et decalage a gauche des totaux
+const PAID_STAMP_FONT_SIZE: f32 = 14.0;
+const PAID_STAMP_ANGLE: f32 = -12.0;
+const PAID_STAMP_OFFSET: f32 = 110.0;
+const SIGNATURE_BOX_WIDTH: f32 = 220.0;
+const SIGNATURE_BOX_HEIGHT: f32 = 80.0;
+/// Hauteur minimale du cadre : libelles et une zone de signature lisible
+const SIGNATURE_BOX_MIN_HEIGHT: f32 = 50.0;
+/// Espace libre au-dessus et au-dessous du cadre de signature
+const SIGNATURE_BOX_GAP: f32 = 10.0;
+/// Haut du pied de page, compte depuis le bas de la page (mention TVA comprise)
+const FOOTER_TOP_OFFSET: f32 = 52.0;
+/// Largeur reservee au numero de page, aligne sur la marge droite du pied de page
+const PAGE_NUMBER_WIDTH: f32 = 50.0;
+/// Modele "modern" : couleur du bandeau et du total TTC, fond de l'encadre
+/// des totaux et marge interieure de l'encadre
+const MODERN_ACCENT: (u8, u8, u8) = (31, 78, 121);
+const MODERN_CARD_BACKGROUND: (u8, u8, u8) = (238, 242, 247);
+const MODERN_CARD_PADDING: f32 = 10.0;
+
+/// Polices Liberation Sans embarquees dans les PDF
+pub struct FontSet {
     regular: Font,
     bold: Font,
 }
 
 impl FontSet {
+    /// Verifie une fois pour toutes que les polices existent et se chargent
+    ///
+    /// A appeler au demarrage : sans cela, une police manquante n'est
+    /// detectee qu'a la premiere generation, une fois la requete acceptee.
+    pub fn verify() -> Result<(), String> {
+        Self::load().map(|_| ())
+    }
+
     fn load() -> Result<Self, String> {
-        let fonts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/fonts");
+        Self::load_from(&Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/fonts"))
+    }
 
-        let regular_path = fonts_dir.join("LiberationSans-Regular.ttf");
-        let bold_path = fonts_dir.join("LiberationSans-Bold.ttf");
+    fn load_from(fonts_dir: &Path) -> Result<Self, String> {
+        Ok(FontSet {
+            regular: load_font(fonts_dir, "LiberationSans-Regular.ttf", "regular")?,
+            bold: load_font(fonts_dir, "LiberationSans-Bold.ttf", "bold")?,
+        })
+    }
+}
 
-        let regular_bytes = std::fs::read(&regular_path).map_err(|e| {
-            format!(
-                "Erreur lecture police regular: {} - {}",
-                regular_path.display(),
-                e
-            )
-        })?;
-        let bold_bytes = std::fs::read(&bold_path).map_err(|e| {
+/// Lit et analyse une police, avec le chemin et la marche a suivre en cas d'echec
+fn load_font(fonts_dir: &Path, file_name: &str, style: &str) -> Result<Font, String> {
+    let path = fonts_dir.join(file_name);
+    let bytes = std::fs::read(&path).map_err(|e| {
+        format!(
+            "Erreur lecture police {}: {} - {} (copiez {} dans {})",
+            style,
+            path.display(),
+            e,
+            file_name,
+            fonts_dir.display()
+        )
+    })?;
+    Font::new(Arc::new(bytes).into(), 0).ok_or_else(|| {
+        format!(
+            "Erreur creation police {}: {} n'est pas une police TrueType valide",
+            style,
+            path.display()
+        )
+    })
+}
+
+/// PDF genere et ecarts de conformite PDF/A-3 toleres
+#[derive(Debug)]
+pub struct GeneratedPdf {
+    pub bytes: Vec<u8>,
+    /// Erreurs de validation PDF/A-3 ignorees grace a `allow_non_conformant`
+    /// (vide si le PDF est conforme)
+    pub warnings: Vec<String>,
+}
+
+/// Genere le PDF/A-3 de la facture avec le XML Factur-X embarque
+///
+/// Voir [`GenerationOptions`] pour les reglages facultatifs (post-traitement XMP, ...).
+/// Les avertissements de conformite d'un PDF produit avec
+/// `allow_non_conformant` ne sont exposes que par
+/// [`generate_invoice_pdf_with_warnings`].
+pub fn generate_invoice_pdf(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    xml_content: &str,
+    logo: Option<&LogoSource>,
+    options: &GenerationOptions,
+) -> Result<Vec<u8>, String> {
+    generate_invoice_pdf_with_warnings(invoice, emitter, totals, xml_content, logo, options)
+        .map(|pdf| pdf.bytes)
+}
+
+/// Genere le PDF de la facture en retournant les ecarts de conformite toleres
+///
+/// En cas d'echec de la validation PDF/A-3, et si `allow_non_conformant` est
+/// actif, le PDF est regenere sans validation : le XML reste embarque, les
+/// erreurs de validation sont retournees en avertissements et le XMP ne
+/// declare plus la conformite PDF/A (`pdfaid`).
+pub fn generate_invoice_pdf_with_warnings(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    xml_content: &str,
+    logo: Option<&LogoSource>,
+    options: &GenerationOptions,
+) -> Result<GeneratedPdf, String> {
+    options.layout.validate()?;
+    totals.ensure_finite()?;
+    let mut xmp_metadata = xmp_metadata_for(invoice, emitter, options);
+
+    let render = |validator| {
+        render_pdf(
+            invoice,
+            emitter,
+            totals,
+            xml_content,
+            logo,
+            options,
+            &xmp_metadata,
+            validator,
+        )
+    };
+    let (pdf_bytes, warnings) = match render(pdfa_validator(options.pdfa_conformance)) {
+        Ok(bytes) => (bytes, Vec::new()),
+        Err(RenderError::Validation(errors)) if options.allow_non_conformant => {
+            let bytes = render(Validator::None).map_err(RenderError::into_message)?;
+            (bytes, errors)
+        }
+        Err(e) => return Err(e.into_message()),
+    };
+    xmp_metadata.pdfa_identification = warnings.is_empty();
+
+    // Sans post-traitement, le PDF de krilla est conserve tel quel
+    if !needs_post_processing(options) {
+        return Ok(GeneratedPdf {
+            bytes: pdf_bytes,
+            warnings,
+        });
+    }
+
+    let mut pdf = lopdf::Document::load_mem(&pdf_bytes)
+        .map_err(|e| format!("Erreur chargement PDF: {:?}", e))?;
+
+    if options.xmp_post_processing {
+        // Generer les metadonnees XMP Factur-X
+        let xmp_string = generate_xmp_metadata(&xmp_metadata)
+            .map_err(|e| format!("Erreur generation XMP: {}", e))?;
+
+        // Utiliser lopdf pour remplacer le stream XMP
+        replace_xmp_metadata(&mut pdf, xmp_string.as_bytes())
+            .map_err(|e| format!("Erreur remplacement XMP: {}", e))?;
+    }
+
+    // L'output intent de krilla est complete (identifiant ICC, registre), ou
+    // remplace par le profil configure
+    match &options.output_intent {
+        Some(profile) => profile
+            .replace_output_intent(&mut pdf, &options.output_condition.registry_name)
+            .map_err(|e| format!("Erreur remplacement output intent: {}", e))?,
+        None => options
+            .output_condition
+            .apply(&mut pdf)
+            .map_err(|e| format!("Erreur mise a jour output intent: {}", e))?,
+    }
+
+    // Sauvegarder le PDF modifie en memoire
+    let mut output = Vec::new();
+    pdf.save_to(&mut output)
+        .map_err(|e| format!("Erreur sauvegarde PDF: {:?}", e))?;
+
+    // Le re-enregistrement lopdf intervient apres la validation krilla : en mode
+    // strict et en debug, les invariants PDF/A-3 sont reverifies sur le resultat
+    if warnings.is_empty() && (options.strict || cfg!(debug_assertions)) {
+        verify_pdfa3(&output, options.pdfa_conformance).map_err(|errors| {
             format!(
-                "Erreur lecture police bold: {} - {}",
-                bold_path.display(),
-                e
+                "PDF/A-3 invalide apres post-traitement: {}",
+                errors.join("; ")
             )
         })?;
+    }
+
+    Ok(GeneratedPdf {
+        bytes: output,
+        warnings,
+    })
+}
 
-        let regular =
-            Font::new(Arc::new(regular_bytes).into(), 0).ok_or("Erreur creation police regular")?;
-        let bold =
-            Font::new(Arc::new(bold_bytes).into(), 0).ok_or("Erreur creation police bold")?;
+/// Indique si le PDF de krilla doit etre recharge par lopdf : XMP Factur-X,
+/// output intent personnalise ou condition de sortie configuree (les valeurs
+/// par defaut de `output_condition` ne sont ecrites qu'avec le XMP)
+fn needs_post_processing(options: &GenerationOptions) -> bool {
+    options.xmp_post_processing
+        || options.output_intent.is_some()
+        || options.output_condition != OutputCondition::default()
+}
 
-        Ok(FontSet { regular, bold })
+/// Validateur krilla du niveau PDF/A-3 demande
+///
+/// Le texte est toujours dessine a partir de chaines Unicode : krilla ecrit
+/// la `ToUnicode` de chaque glyphe utilise. En A-3u, un glyphe sans
+/// correspondance (caractere de controle, glyphe `.notdef`) fait echouer la
+/// validation au lieu d'etre tolere comme en A-3b.
+fn pdfa_validator(conformance: PdfaConformance) -> Validator {
+    match conformance {
+        PdfaConformance::A3B => Validator::A3_B,
+        PdfaConformance::A3U => Validator::A3_U,
     }
 }
 
-/// Genere le PDF/A-3 de la facture avec le XML Factur-X embarque
-pub fn generate_invoice_pdf(
+/// Metadonnees XMP de la facture
+///
+/// `xml_filename` vient des options : c'est aussi le nom de la piece jointe
+/// (voir [`embedded_xml_file`]), le XMP et le PDF designent donc le meme fichier.
+fn xmp_metadata_for(
     invoice: &InvoiceForm,
     emitter: &EmitterConfig,
-    totals: (f64, f64, f64),
+    options: &GenerationOptions,
+) -> XmpMetadata {
+    let invoice_type_label = invoice.type_code.label();
+
+    XmpMetadata {
+        title: format!("{} {}", invoice_type_label, invoice.invoice_number),
+        author: emitter.name.clone(),
+        subject: format!(
+            "{} Factur-X pour {}",
+            invoice_type_label, invoice.recipient_name
+        ),
+        profile: options.profile,
+        xml_filename: options.xml_filename.clone(),
+        facturx_version: "1.0".to_string(),
+        producer: options.producer.clone(),
+        pdfa_identification: true,
+        pdfa_conformance: options.pdfa_conformance,
+    }
+}
+
+/// Piece jointe du XML Factur-X, nommee comme le `fx:DocumentFileName` du XMP
+fn embedded_xml_file(
     xml_content: &str,
-    _logo_path: Option<&str>,
-) -> Result<Vec<u8>, String> {
-    let (total_ht, total_vat, total_ttc) = totals;
+    xmp_metadata: &XmpMetadata,
+    profile: FacturXProfile,
+    xml_bom: bool,
+    modification_date: DateTime,
+) -> Result<EmbeddedFile, String> {
+    let mime_type = attachment_mime_type(&xmp_metadata.xml_filename, XML_MIME_TYPE)?;
+    Ok(EmbeddedFile {
+        path: xmp_metadata.xml_filename.clone(),
+        mime_type: Some(mime_type),
+        description: Some("Factur-X XML invoice data".to_string()),
+        association_kind: association_kind_for(profile),
+        data: embedded_xml_bytes(xml_content, xml_bom).into(),
+        modification_date: Some(modification_date),
+        compress: Some(true),
+        location: None,
+    })
+}
+
+/// Types MIME de premier niveau enregistres aupres de l'IANA (RFC 6838)
+const MIME_TOP_LEVEL_TYPES: [&str; 9] = [
+    "application",
+    "audio",
+    "font",
+    "image",
+    "message",
+    "model",
+    "multipart",
+    "text",
+    "video",
+];
+
+/// Type MIME d'une piece jointe (`/Subtype` du fichier embarque, exige par
+/// PDF/A-3)
+///
+/// La syntaxe RFC 6838 (`type/sous-type`, type de premier niveau enregistre,
+/// noms de 127 caracteres au plus) est controlee avant krilla, dont
+/// `MimeType::new` ne renvoie qu'un `None` sans explication ; l'erreur nomme
+/// la piece jointe et le type refuse.
+fn attachment_mime_type(attachment: &str, mime: &str) -> Result<MimeType, String> {
+    let restricted_name = |name: &str| {
+        name.len() <= 127
+            && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    let valid = match mime.split_once('/') {
+        Some((top_level, subtype)) => {
+            MIME_TOP_LEVEL_TYPES.contains(&top_level.to_ascii_lowercase().as_str())
+                && restricted_name(subtype)
+        }
+        None => false,
+    };
+    let invalid = || {
+        format!(
+            "Piece jointe '{}' : type MIME '{}' invalide (type/sous-type attendu, ex. {})",
+            attachment, mime, XML_MIME_TYPE
+        )
+    };
+    if !valid {
+        return Err(invalid());
+    }
+    MimeType::new(mime).ok_or_else(invalid)
+}
+
+/// Octets du XML embarque, precedes du BOM UTF-8 si demande
+fn embedded_xml_bytes(xml_content: &str, xml_bom: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(UTF8_BOM.len() + xml_content.len());
+    if xml_bom {
+        bytes.extend_from_slice(UTF8_BOM);
+    }
+    bytes.extend_from_slice(xml_content.as_bytes());
+    bytes
+}
+
+/// Echec du rendu krilla
+enum RenderError {
+    /// Erreurs de validation PDF/A-3
+    Validation(Vec<String>),
+    Other(String),
+}
+
+impl RenderError {
+    fn into_message(self) -> String {
+        match self {
+            RenderError::Validation(errors) => {
+                format!("Erreurs de validation PDF/A-3: {}", errors.join("; "))
+            }
+            RenderError::Other(message) => message,
+        }
+    }
+}
+
+impl From<String> for RenderError {
+    fn from(message: String) -> Self {
+        RenderError::Other(message)
+    }
+}
+
+impl From<&str> for RenderError {
+    fn from(message: &str) -> Self {
+        RenderError::Other(message.to_string())
+    }
+}
 
+/// Dessine la facture et embarque le XML avec le validateur krilla donne
+#[allow(clippy::too_many_arguments)]
+fn render_pdf(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    xml_content: &str,
+    logo: Option<&LogoSource>,
+    options: &GenerationOptions,
+    xmp_metadata: &XmpMetadata,
+    validator: Validator,
+) -> Result<Vec<u8>, RenderError> {
     // Charger les polices
     let fonts = FontSet::load()?;
 
     // Configurer les parametres de serialisation pour PDF/A-3
-    let config = Configuration::new_with_validator(Validator::A3_B);
+    let config = Configuration::new_with_validator(validator);
+    // Avec un output intent personnalise, aucune couleur ne doit dependre
+    // de l'espace de l'output intent (DeviceRGB interdit face a un profil CMJN)
     let settings = SerializeSettings {
         configuration: config,
+        no_device_cs: options.output_intent.is_some(),
         ..Default::default()
     };
 
     // Creer le document avec validation PDF/A-3
     let mut doc = Document::new_with(settings);
 
-    // Preparer les metadonnees XMP
-    let invoice_type_label = match invoice.type_code {
-        380 => "Facture",
-        381 => "Avoir",
-        384 => "Facture rectificative",
-        389 => "Facture d'acompte",
-        _ => "Facture",
-    };
+    // Metadonnees natives krilla (dictionnaire Info + XMP de base)
+    doc.set_metadata(
+        Metadata::new()
+            .title(xmp_metadata.title.clone())
+            .authors(vec![xmp_metadata.author.clone()])
+            .subject(xmp_metadata.subject.clone())
+            .producer(xmp_metadata.producer.clone()),
+    );
 
-    let xmp_metadata = XmpMetadata {
-        title: format!("{} {}", invoice_type_label, invoice.invoice_number),
-        author: emitter.name.clone(),
-        subject: format!(
-            "{} Factur-X pour {}",
-            invoice_type_label, invoice.recipient_name
-        ),
-        profile: FacturXProfile::Minimum,
-        xml_filename: "factur-x.xml".to_string(),
-        facturx_version: "1.0".to_string(),
-    };
+    // Premiere passe sans rien conserver pour compter les pages, puis
+    // dessin definitif avec le total dans le pied de page ("Page X / Y")
+    let page_count = draw_invoice_pages(
+        &mut Document::new(),
+        invoice,
+        emitter,
+        totals,
+        logo,
+        options,
+        &fonts,
+        None,
+    )?;
+    draw_invoice_pages(
+        &mut doc,
+        invoice,
+        emitter,
+        totals,
+        logo,
+        options,
+        &fonts,
+        Some(page_count),
+    )?;
+
+    // === EMBARQUER LE XML FACTUR-X ===
+    // Créer la date de modification (requise pour PDF/A-3)
+    let now = chrono::Utc::now();
+    let mod_date = DateTime::new(now.format("%Y").to_string().parse().unwrap_or(2024))
+        .month(now.format("%m").to_string().parse().unwrap_or(1))
+        .day(now.format("%d").to_string().parse().unwrap_or(1))
+        .hour(now.format("%H").to_string().parse().unwrap_or(0))
+        .minute(now.format("%M").to_string().parse().unwrap_or(0))
+        .second(now.format("%S").to_string().parse().unwrap_or(0));
+
+    let embedded_xml = embedded_xml_file(
+        xml_content,
+        xmp_metadata,
+        options.profile,
+        options.xml_bom,
+        mod_date,
+    )?;
+    doc.embed_file(embedded_xml);
+
+    // Finaliser et exporter le PDF avec Krilla
+    match doc.finish() {
+        Ok(bytes) => Ok(bytes),
+        Err(KrillaError::Validation(errors)) => Err(RenderError::Validation(
+            errors.iter().map(|e| format!("{:?}", e)).collect(),
+        )),
+        Err(e) => Err(RenderError::Other(format!(
+            "Erreur generation PDF: {:?}",
+            e
+        ))),
+    }
+}
 
-    // Creer la page A4
-    let page_settings = PageSettings::from_wh(PAGE_WIDTH_PT, PAGE_HEIGHT_PT)
-        .ok_or("Erreur creation taille page")?;
-    let mut page = doc.start_page_with(page_settings);
+/// Dessine les pages de la facture dans `doc` et retourne leur nombre
+///
+/// krilla ecrit les pages l'une apres l'autre : le total n'est connu qu'a la
+/// fin. `render_pdf` appelle donc cette fonction deux fois, d'abord sans
+/// total (`page_count` a `None`, pied de page sans numero) sur un document
+/// jete pour mesurer, puis avec le total mesure. Le numero etant dessine
+/// dans le pied de page, hors de la zone de contenu, les sauts de page sont
+/// identiques d'une passe a l'autre.
+#[allow(clippy::too_many_arguments)]
+fn draw_invoice_pages(
+    doc: &mut Document,
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    logo: Option<&LogoSource>,
+    options: &GenerationOptions,
+    fonts: &FontSet,
+    page_count: Option<usize>,
+) -> Result<usize, RenderError> {
+    let layout = &options.layout;
+    let InvoiceTotals {
+        total_ht,
+        total_vat,
+        total_ttc,
+        allowance_total,
+        ..
+    } = totals;
+
+    // Creer la page A4, en paysage si le tableau des lignes l'exige
+    let show_vat = !emitter.vat_exempt_franchise;
+    let columns = table_columns(invoice, show_vat);
+    let orientation = resolve_orientation(options.orientation, table_min_width(&columns), layout);
+    let (page_width, page_height) = page_size(orientation);
+    let page_settings =
+        PageSettings::from_wh(page_width, page_height).ok_or("Erreur creation taille page")?;
+    let mut page = doc.start_page_with(page_settings.clone());
     let mut surface = page.surface();
 
-    let mut y_pos = MARGIN_TOP;
+    let mut y_pos = layout.margin_top;
 
     // Couleur noire pour le texte
     let black = rgb::Color::new(0, 0, 0);
@@ -132,38 +538,148 @@ pub fn generate_invoice_pdf(
         paint: Paint::from(black),
         ..Default::default()
     };
-    surface.set_fill(Some(black_fill.clone()));
+    start_page(&mut surface, fonts, options, page_width, page_height);
+
+    // Le modele "modern" porte l'emetteur et le titre dans un bandeau
+    let modern = options.template == Template::Modern;
+    if modern {
+        y_pos = draw_header_band(
+            &mut surface,
+            fonts,
+            layout,
+            &emitter.name,
+            invoice.type_code.title_uppercase(),
+            page_width,
+        );
+        surface.set_fill(Some(black_fill.clone()));
+    }
+
+    // Les lignes et blocs qui ne tiennent plus au-dessus du pied de page
+    // passent sur une nouvelle page
+    let content_limit = page_height - FOOTER_TOP_OFFSET - SIGNATURE_BOX_GAP;
+    let mut page_number = 1;
+    // Annotations de la page en cours, ajoutees une fois sa surface terminee
+    let mut annotations: Vec<Annotation> = Vec::new();
+    macro_rules! new_page {
+        () => {
+            draw_footer(
+                &mut surface,
+                fonts,
+                layout,
+                emitter,
+                page_height,
+                page_width,
+                page_label(page_number, page_count).as_deref(),
+            );
+            page_number += 1;
+            drop(surface);
+            for annotation in annotations.drain(..) {
+                page.add_annotation(annotation);
+            }
+            page.finish();
+            page = doc.start_page_with(page_settings.clone());
+            surface = page.surface();
+            start_page(&mut surface, fonts, options, page_width, page_height);
+            y_pos = layout.margin_top;
+        };
+    }
+
+    // === LOGO ===
+    if let Some(logo) = logo {
+        let image = load_logo_image(logo)?;
+        let (width_px, height_px) = image.size();
+        let (width, height) = fit_logo(width_px, height_px);
+        let size = Size::from_wh(width, height).ok_or("Taille du logo invalide")?;
+
+        // Le haut du logo s'aligne sur le haut de la premiere ligne de texte
+        surface.push_transform(&Transform::from_translate(
+            layout.margin_left,
+            y_pos - layout.font_size_title,
+        ));
+        surface.draw_image(image, size);
+        surface.pop();
+        y_pos += height + 8.0;
+    }
 
     // === EN-TETE : Emetteur ===
-    draw_text(
-        &mut surface,
-        &emitter.name,
-        &fonts.bold,
-        FONT_SIZE_TITLE,
-        MARGIN_LEFT,
-        y_pos,
-    );
-    y_pos += FONT_SIZE_TITLE + 4.0;
+    if !modern {
+        draw_text(
+            &mut surface,
+            &emitter.name,
+            &fonts.bold,
+            layout.font_size_title,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.font_size_title + 4.0;
+    }
 
     draw_text(
         &mut surface,
         &emitter.address,
         &fonts.regular,
-        FONT_SIZE_NORMAL,
-        MARGIN_LEFT,
+        layout.font_size_normal,
+        layout.margin_left,
         y_pos,
     );
-    y_pos += LINE_HEIGHT;
+    y_pos += layout.line_height;
+
+    if let Some(phone) = emitter.normalized_phone() {
+        draw_text(
+            &mut surface,
+            &format!("Tel: {}", phone),
+            &fonts.regular,
+            layout.font_size_small,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+    }
+
+    // E-mail et site web, cliquables si `link_annotations`
+    for (text, uri) in emitter.contact_links() {
+        draw_text(
+            &mut surface,
+            &text,
+            &fonts.regular,
+            layout.font_size_small,
+            layout.margin_left,
+            y_pos,
+        );
+        if options.link_annotations {
+            annotations.extend(contact_link_annotation(
+                &text,
+                uri,
+                layout.margin_left,
+                y_pos,
+                layout.font_size_small,
+            ));
+        }
+        y_pos += layout.line_height;
+    }
 
     draw_text(
         &mut surface,
-        &format!("SIRET: {}", emitter.siret),
+        &format!("SIRET: {}", format_siret(&emitter.siret)),
         &fonts.regular,
-        FONT_SIZE_SMALL,
-        MARGIN_LEFT,
+        layout.font_size_small,
+        layout.margin_left,
         y_pos,
     );
-    y_pos += LINE_HEIGHT;
+    y_pos += layout.line_height;
+
+    // Forme juridique, capital social et RCS
+    if let Some(mentions) = emitter.legal_mentions() {
+        draw_text(
+            &mut surface,
+            &mentions,
+            &fonts.regular,
+            layout.font_size_small,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+    }
 
     if let Some(ref num_tva) = emitter.num_tva {
         if !num_tva.is_empty() {
@@ -171,72 +687,102 @@ pub fn generate_invoice_pdf(
                 &mut surface,
                 &format!("TVA: {}", num_tva),
                 &fonts.regular,
-                FONT_SIZE_SMALL,
-                MARGIN_LEFT,
+                layout.font_size_small,
+                layout.margin_left,
                 y_pos,
             );
-            y_pos += LINE_HEIGHT;
+            y_pos += layout.line_height;
         }
     }
 
     y_pos += 20.0;
 
     // === TITRE FACTURE ===
-    let invoice_type = match invoice.type_code {
-        380 => "FACTURE",
-        381 => "AVOIR",
-        384 => "FACTURE RECTIFICATIVE",
-        389 => "FACTURE D'ACOMPTE",
-        _ => "FACTURE",
-    };
-
-    draw_text(
-        &mut surface,
-        invoice_type,
-        &fonts.bold,
-        FONT_SIZE_TITLE,
-        PAGE_WIDTH_PT / 2.0 - 40.0,
-        y_pos,
-    );
-    y_pos += FONT_SIZE_TITLE + 8.0;
+    if !modern {
+        draw_text(
+            &mut surface,
+            invoice.type_code.title_uppercase(),
+            &fonts.bold,
+            layout.font_size_title,
+            page_width / 2.0 - 40.0,
+            y_pos,
+        );
+        y_pos += layout.font_size_title + 8.0;
+    }
 
     // Numero de facture
     draw_text(
         &mut surface,
         &format!("N {}", invoice.invoice_number),
         &fonts.bold,
-        FONT_SIZE_HEADER,
-        MARGIN_LEFT,
+        layout.font_size_header,
+        layout.margin_left,
         y_pos,
     );
 
     // Date
-    let date_display = format_date_display(&invoice.issue_date);
+    let date_display = format_date_display(&invoice.issue_date, options.language);
     draw_text(
         &mut surface,
         &format!("Date: {}", date_display),
         &fonts.regular,
-        FONT_SIZE_NORMAL,
-        PAGE_WIDTH_PT - MARGIN_RIGHT - 120.0,
+        layout.font_size_normal,
+        page_width - layout.margin_right - 120.0,
         y_pos,
     );
-    y_pos += LINE_HEIGHT;
+    y_pos += layout.line_height;
 
     if let Some(ref due_date) = invoice.due_date {
         if !due_date.is_empty() {
-            let due_date_display = format_date_display(due_date);
+            let due_date_display = format_date_display(due_date, options.language);
             draw_text(
                 &mut surface,
                 &format!("Echeance: {}", due_date_display),
                 &fonts.regular,
-                FONT_SIZE_NORMAL,
-                PAGE_WIDTH_PT - MARGIN_RIGHT - 120.0,
+                layout.font_size_normal,
+                page_width - layout.margin_right - 120.0,
                 y_pos,
             );
-            y_pos += LINE_HEIGHT;
+            y_pos += layout.line_height;
         }
     }
 
+    // Date d'exigibilite de la TVA (BT-7), si differente de la date d'emission
+    if let Some(ref tax_point_date) = invoice.tax_point_date {
+        if !tax_point_date.is_empty() {
+            draw_text(
+                &mut surface,
+                &format!(
+                    "Date TVA: {}",
+                    format_date_display(tax_point_date, options.language)
+                ),
+                &fonts.regular,
+                layout.font_size_normal,
+                page_width - layout.margin_right - 120.0,
+                y_pos,
+            );
+            y_pos += layout.line_height;
+        }
+    }
+
+    // Objet facture (BT-18) : contrat, compteur ou point de livraison, sous
+    // le numero (la colonne des dates est trop etroite)
+    if let Some((id, scheme)) = invoice.invoiced_object() {
+        let label = match scheme {
+            Some(scheme) => format!("Objet: {} ({})", id, scheme),
+            None => format!("Objet: {}", id),
+        };
+        draw_text(
+            &mut surface,
+            &label,
+            &fonts.regular,
+            layout.font_size_normal,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+    }
+
     y_pos += 20.0;
 
     // === CLIENT ===
@@ -244,43 +790,45 @@ pub fn generate_invoice_pdf(
         &mut surface,
         "CLIENT",
         &fonts.bold,
-        FONT_SIZE_HEADER,
-        MARGIN_LEFT,
+        layout.font_size_header,
+        layout.margin_left,
         y_pos,
     );
-    y_pos += LINE_HEIGHT + 4.0;
+    y_pos += layout.line_height + 4.0;
 
     draw_text(
         &mut surface,
         &invoice.recipient_name,
         &fonts.regular,
-        FONT_SIZE_NORMAL,
-        MARGIN_LEFT,
+        layout.font_size_normal,
+        layout.margin_left,
         y_pos,
     );
-    y_pos += LINE_HEIGHT;
+    y_pos += layout.line_height;
 
     if !invoice.recipient_address.is_empty() {
         draw_text(
             &mut surface,
             &invoice.recipient_address,
             &fonts.regular,
-            FONT_SIZE_NORMAL,
-            MARGIN_LEFT,
+            layout.font_size_normal,
+            layout.margin_left,
             y_pos,
         );
-        y_pos += LINE_HEIGHT;
+        y_pos += layout.line_height;
     }
 
-    draw_text(
-        &mut surface,
-        &format!("SIRET: {}", invoice.recipient_siret),
-        &fonts.regular,
-        FONT_SIZE_SMALL,
-        MARGIN_LEFT,
-        y_pos,
-    );
-    y_pos += LINE_HEIGHT;
+    if let Some((siret, _)) = invoice.buyer_legal_id() {
+        draw_text(
+            &mut surface,
+            &format!("SIRET: {}", format_siret(siret)),
+            &fonts.regular,
+            layout.font_size_small,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+    }
 
     if let Some(ref vat_number) = invoice.recipient_vat_number {
         if !vat_number.is_empty() {
@@ -288,11 +836,11 @@ pub fn generate_invoice_pdf(
                 &mut surface,
                 &format!("N TVA: {}", vat_number),
                 &fonts.regular,
-                FONT_SIZE_SMALL,
-                MARGIN_LEFT,
+                layout.font_size_small,
+                layout.margin_left,
                 y_pos,
             );
-            y_pos += LINE_HEIGHT;
+            y_pos += layout.line_height;
         }
     }
 
@@ -300,306 +848,561 @@ pub fn generate_invoice_pdf(
         &mut surface,
         &format!("Pays: {}", invoice.recipient_country_code),
         &fonts.regular,
-        FONT_SIZE_SMALL,
-        MARGIN_LEFT,
+        layout.font_size_small,
+        layout.margin_left,
         y_pos,
     );
-    y_pos += LINE_HEIGHT;
+    y_pos += layout.line_height;
+
+    // === REFERENCES ===
+    // Sous le bloc client plutot qu'a cote : une reference longue est coupee
+    // sur plusieurs lignes sans jamais chevaucher l'adresse du client
+    let references = reference_lines(invoice, options);
+    if !references.is_empty() {
+        y_pos += 10.0;
+        draw_text(
+            &mut surface,
+            "REFERENCES",
+            &fonts.bold,
+            layout.font_size_header,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.line_height + 4.0;
+
+        for reference in &references {
+            for text in wrap_text(reference, DETAIL_WRAP_CHARS) {
+                draw_text(
+                    &mut surface,
+                    &text,
+                    &fonts.regular,
+                    layout.font_size_normal,
+                    layout.margin_left,
+                    y_pos,
+                );
+                y_pos += layout.line_height;
+            }
+        }
+    }
 
     y_pos += 30.0;
 
     // === TABLEAU DES LIGNES ===
-    let col_desc = MARGIN_LEFT;
-    let col_qty = 280.0;
-    let col_price = 340.0;
-    let col_vat = 410.0;
-    let col_total = 480.0;
-
-    // En-tete du tableau
-    draw_text(
-        &mut surface,
-        "Description",
-        &fonts.bold,
-        FONT_SIZE_SMALL,
-        col_desc,
-        y_pos,
-    );
-    draw_text(
-        &mut surface,
-        "Qte",
-        &fonts.bold,
-        FONT_SIZE_SMALL,
-        col_qty,
-        y_pos,
-    );
-    draw_text(
-        &mut surface,
-        "PU HT",
-        &fonts.bold,
-        FONT_SIZE_SMALL,
-        col_price,
-        y_pos,
-    );
-    draw_text(
-        &mut surface,
-        "TVA",
-        &fonts.bold,
-        FONT_SIZE_SMALL,
-        col_vat,
-        y_pos,
-    );
-    draw_text(
-        &mut surface,
-        "Total HT",
-        &fonts.bold,
-        FONT_SIZE_SMALL,
-        col_total,
-        y_pos,
-    );
+    let column_x = column_positions(&columns, layout, page_width);
+    let col_desc = layout.margin_left;
+    let col_total = column_x[columns.len() - 1];
 
-    y_pos += 4.0;
-    draw_horizontal_line(
+    // En-tete du tableau (en franchise en base, pas de colonne TVA)
+    y_pos = draw_table_header(
         &mut surface,
-        MARGIN_LEFT,
+        fonts,
+        layout,
+        &columns,
+        &column_x,
         y_pos,
-        PAGE_WIDTH_PT - MARGIN_RIGHT,
+        page_width,
     );
-    y_pos += LINE_HEIGHT;
 
-    // Lignes de facturation
-    for line in &invoice.lines {
-        if !line.is_valid() {
-            continue;
+    // Lignes de facturation, regroupees par section avec un sous-total
+    let lines: Vec<&InvoiceLine> = invoice.lines.iter().filter(|l| l.is_valid()).collect();
+    let mut section_total = 0.0;
+    for (index, line) in lines.iter().enumerate() {
+        let section = line.section_text();
+        let (starts_section, ends_section) = section_bounds(&lines, index);
+
+        // Le titre de section reste avec sa premiere ligne, le sous-total
+        // avec la derniere : jamais de titre seul en bas de page
+        let mut row_height = line_row_height(line, layout);
+        if starts_section {
+            row_height += layout.line_height;
+        }
+        if ends_section {
+            row_height += layout.line_height;
+        }
+        if y_pos + row_height > content_limit {
+            new_page!();
+            y_pos = draw_table_header(
+                &mut surface,
+                fonts,
+                layout,
+                &columns,
+                &column_x,
+                y_pos,
+                page_width,
+            );
+            if let (Some(section), false) = (section, starts_section) {
+                draw_text(
+                    &mut surface,
+                    &format!("{} (suite)", section),
+                    &fonts.bold,
+                    layout.font_size_small,
+                    col_desc,
+                    y_pos,
+                );
+                y_pos += layout.line_height;
+            }
         }
 
-        let desc = if line.description.len() > 40 {
-            format!("{}...", &line.description[..37])
-        } else {
-            line.description.clone()
-        };
-
-        draw_text(
-            &mut surface,
-            &desc,
-            &fonts.regular,
-            FONT_SIZE_SMALL,
-            col_desc,
-            y_pos,
-        );
-        draw_text(
-            &mut surface,
-            &format!("{:.2}", line.quantity),
-            &fonts.regular,
-            FONT_SIZE_SMALL,
-            col_qty,
-            y_pos,
-        );
-        draw_text(
-            &mut surface,
-            &format!("{:.2}", line.unit_price_ht),
-            &fonts.regular,
-            FONT_SIZE_SMALL,
-            col_price,
-            y_pos,
-        );
-        draw_text(
-            &mut surface,
-            &format!("{:.1}%", line.vat_rate),
-            &fonts.regular,
-            FONT_SIZE_SMALL,
-            col_vat,
-            y_pos,
-        );
-        draw_text(
-            &mut surface,
-            &format!("{:.2}", line.total_ht_value()),
-            &fonts.regular,
-            FONT_SIZE_SMALL,
-            col_total,
-            y_pos,
-        );
-
-        y_pos += LINE_HEIGHT;
+        if let (Some(section), true) = (section, starts_section) {
+            section_total = 0.0;
+            draw_text(
+                &mut surface,
+                section,
+                &fonts.bold,
+                layout.font_size_small,
+                col_desc,
+                y_pos,
+            );
+            y_pos += layout.line_height;
+        }
 
-        if let Some(discount) = line.discount_amount {
-            if discount > 0.0 {
-                let short_desc = if line.description.len() > 25 {
-                    format!("{}...", &line.description[..22])
-                } else {
-                    line.description.clone()
-                };
+        for (column, x) in columns.iter().zip(&column_x) {
+            let text = match column {
+                TableColumn::Description => line_description(line),
+                TableColumn::Quantity => format_quantity(line.quantity),
+                TableColumn::UnitPrice => format!("{:.2}", line.unit_price_ht),
+                TableColumn::Vat => format!("{}%", VatRateKey::new(line.vat_rate)),
+                // Rabais applique : ecart entre montant brut et montant net
+                TableColumn::Discount => match line.discount_amount {
+                    Some(discount) if discount > 0.0 => {
+                        format!("-{:.2}", line.gross_ht_value() - line.total_ht_value())
+                    }
+                    _ => String::new(),
+                },
+                // Periode de facturation : (01/01→31/01)
+                TableColumn::Period => match line.billing_period() {
+                    Some((start, end)) => format!(
+                        "{}→{}",
+                        start
+                            .map(|date| format_day_month(date, options.language))
+                            .unwrap_or_default(),
+                        end.map(|date| format_day_month(date, options.language))
+                            .unwrap_or_default()
+                    ),
+                    None => String::new(),
+                },
+                TableColumn::Total => format!("{:.2}", line.total_ht_value()),
+            };
+            draw_text(
+                &mut surface,
+                &text,
+                &fonts.regular,
+                layout.font_size_small,
+                *x,
+                y_pos,
+            );
+        }
+
+        y_pos += layout.line_height;
+
+        // Detail de la ligne, en retrait et en plus petit
+        if let Some(detail) = line.detail_text() {
+            for detail_line in wrap_text(detail, DETAIL_WRAP_CHARS) {
                 draw_text(
                     &mut surface,
-                    &format!(
-                        "  - Rabais sur {}: -{:.2} {}",
-                        short_desc, discount, invoice.currency_code
-                    ),
+                    &detail_line,
                     &fonts.regular,
-                    FONT_SIZE_SMALL,
-                    col_desc,
-                    y_pos,
+                    layout.font_size_detail,
+                    col_desc + 10.0,
+                    y_pos - 3.0,
                 );
-                y_pos += LINE_HEIGHT;
+                y_pos += layout.font_size_detail + 3.0;
             }
         }
+
+        // Ligne du bon de commande (BT-132), discrete sous la description
+        if let Some(reference) = line.order_line_reference_text() {
+            draw_text(
+                &mut surface,
+                &format!("Ligne de commande {}", reference),
+                &fonts.regular,
+                layout.font_size_detail,
+                col_desc + 10.0,
+                y_pos - 3.0,
+            );
+            y_pos += layout.font_size_detail + 3.0;
+        }
+
+        section_total += line.total_ht_value();
+        if let (Some(section), true) = (section, ends_section) {
+            draw_text(
+                &mut surface,
+                &format!("Sous-total {}", section),
+                &fonts.bold,
+                layout.font_size_small,
+                col_desc + 10.0,
+                y_pos,
+            );
+            draw_text(
+                &mut surface,
+                &format!("{:.2}", section_total),
+                &fonts.bold,
+                layout.font_size_small,
+                col_total,
+                y_pos,
+            );
+            y_pos += layout.line_height;
+        }
     }
 
     y_pos += 8.0;
     draw_horizontal_line(
         &mut surface,
-        MARGIN_LEFT,
+        layout.margin_left,
         y_pos,
-        PAGE_WIDTH_PT - MARGIN_RIGHT,
+        page_width - layout.margin_right,
     );
     y_pos += 20.0;
 
     // === RECAPITULATIF TVA ===
-    let vat_breakdown = calculate_vat_breakdown(invoice);
-    if !vat_breakdown.is_empty() {
+    // Le recapitulatif et les totaux ne sont pas separes par un saut de page
+    let deposit = matches!(invoice.prepayment(), Prepayment::Deposit(_));
+    let vat_breakdown = vat_breakdown(invoice, emitter.vat_exempt_franchise, options);
+    let summary_height = summary_block_height(
+        layout,
+        options.template,
+        if show_vat { vat_breakdown.len() } else { 0 },
+        invoice.tax_currency_vat_total(total_vat).is_some(),
+        totals.is_free_of_charge(),
+        allowance_total > 0.0,
+        deposit,
+    );
+    if y_pos + summary_height > content_limit {
+        new_page!();
+    }
+    if show_vat && !vat_breakdown.is_empty() {
         draw_text(
             &mut surface,
             "Recapitulatif TVA",
             &fonts.bold,
-            FONT_SIZE_SMALL,
-            MARGIN_LEFT,
+            layout.font_size_small,
+            layout.margin_left,
             y_pos,
         );
-        y_pos += LINE_HEIGHT;
+        y_pos += layout.line_height;
 
-        for (rate, (base_ht, vat_amount)) in &vat_breakdown {
+        for group in &vat_breakdown {
             draw_text(
                 &mut surface,
                 &format!(
-                    "TVA {:.1}% : Base {:.2} {} - TVA {:.2} {}",
-                    rate, base_ht, invoice.currency_code, vat_amount, invoice.currency_code
+                    "TVA {}% ({}) : Base {} - TVA {}",
+                    group.rate,
+                    group.category,
+                    format_money(group.basis, &invoice.currency_code, layout),
+                    format_money(group.vat_amount, &invoice.currency_code, layout)
                 ),
                 &fonts.regular,
-                FONT_SIZE_SMALL,
-                MARGIN_LEFT + 10.0,
+                layout.font_size_small,
+                layout.margin_left + 10.0,
                 y_pos,
             );
-            y_pos += LINE_HEIGHT;
+            y_pos += layout.line_height;
         }
         y_pos += 10.0;
     }
 
     // === TOTAUX ===
-    let totals_x = PAGE_WIDTH_PT - MARGIN_RIGHT - 150.0;
+    let totals_x = page_width - layout.margin_right - 150.0;
+    if modern {
+        // Encadre aligne sur la marge droite, dessine sous les montants
+        y_pos += MODERN_CARD_PADDING;
+        let card_x = totals_x - MODERN_CARD_PADDING;
+        fill_rect(
+            &mut surface,
+            card_x,
+            y_pos - layout.font_size_normal - MODERN_CARD_PADDING,
+            page_width - layout.margin_right - card_x,
+            totals_block_height(
+                layout,
+                invoice.tax_currency_vat_total(total_vat).is_some(),
+                totals.is_free_of_charge(),
+                allowance_total > 0.0,
+                deposit,
+            ) + layout.font_size_normal
+                + 2.0 * MODERN_CARD_PADDING,
+            MODERN_CARD_BACKGROUND,
+        );
+        surface.set_fill(Some(black_fill.clone()));
+    }
+    let totals_top = y_pos;
 
     draw_text(
         &mut surface,
-        &format!("Total HT: {:.2} {}", total_ht, invoice.currency_code),
+        &format!(
+            "Total HT: {}",
+            format_money(total_ht, &invoice.currency_code, layout)
+        ),
         &fonts.regular,
-        FONT_SIZE_NORMAL,
+        layout.font_size_normal,
         totals_x,
         y_pos,
     );
-    y_pos += LINE_HEIGHT;
+    y_pos += layout.line_height;
+
+    // Remise globale (hors lignes exclues) et base HT remisee
+    if allowance_total > 0.0 {
+        draw_text(
+            &mut surface,
+            &format!(
+                "Remise {}%: -{}",
+                format_quantity(invoice.global_discount_percent.unwrap_or_default()),
+                format_money(allowance_total, &invoice.currency_code, layout)
+            ),
+            &fonts.regular,
+            layout.font_size_normal,
+            totals_x,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+        draw_text(
+            &mut surface,
+            &format!(
+                "Base HT: {}",
+                format_money(totals.tax_basis_total(), &invoice.currency_code, layout)
+            ),
+            &fonts.regular,
+            layout.font_size_normal,
+            totals_x,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+    }
 
     draw_text(
         &mut surface,
-        &format!("Total TVA: {:.2} {}", total_vat, invoice.currency_code),
+        &format!(
+            "Total TVA: {}",
+            format_money(total_vat, &invoice.currency_code, layout)
+        ),
         &fonts.regular,
-        FONT_SIZE_NORMAL,
+        layout.font_size_normal,
         totals_x,
         y_pos,
     );
-    y_pos += LINE_HEIGHT + 4.0;
+    y_pos += layout.line_height;
+
+    // Total TVA dans la devise de comptabilisation de la TVA
+    if let Some((tax_currency, tax_total)) = invoice.tax_currency_vat_total(total_vat) {
+        draw_text(
+            &mut surface,
+            &format!(
+                "soit TVA: {}",
+                format_money(tax_total, tax_currency, layout)
+            ),
+            &fonts.regular,
+            layout.font_size_small,
+            totals_x,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+    }
+    y_pos += 4.0;
 
+    if modern {
+        let (r, g, b) = MODERN_ACCENT;
+        surface.set_fill(Some(Fill {
+            paint: Paint::from(rgb::Color::new(r, g, b)),
+            ..Default::default()
+        }));
+    }
     draw_text(
         &mut surface,
-        &format!("Total TTC: {:.2} {}", total_ttc, invoice.currency_code),
+        &format!(
+            "Total TTC: {}",
+            format_money(total_ttc, &invoice.currency_code, layout)
+        ),
         &fonts.bold,
-        FONT_SIZE_HEADER,
+        layout.font_size_header,
         totals_x,
         y_pos,
     );
+    surface.set_fill(Some(black_fill.clone()));
+    if totals.is_free_of_charge() {
+        y_pos += layout.line_height;
+        draw_text(
+            &mut surface,
+            FREE_OF_CHARGE_MENTION,
+            &fonts.bold,
+            layout.font_size_normal,
+            totals_x,
+            y_pos,
+        );
+    }
+
+    // Acompte deja verse (BT-113) et montant restant a payer (BT-115)
+    if deposit {
+        y_pos += layout.line_height;
+        draw_text(
+            &mut surface,
+            &format!(
+                "Acompte verse: -{}",
+                format_money(totals.prepaid_total, &invoice.currency_code, layout)
+            ),
+            &fonts.regular,
+            layout.font_size_normal,
+            totals_x,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+        draw_text(
+            &mut surface,
+            &format!(
+                "Net a payer: {}",
+                format_money(totals.due_payable(), &invoice.currency_code, layout)
+            ),
+            &fonts.bold,
+            layout.font_size_normal,
+            totals_x,
+            y_pos,
+        );
+    }
+
+    // Facture acquittee : tampon "PAYE le ..." a gauche des totaux
+    if let Some(date) = invoice.paid_on() {
+        let label = format!("PAYÉ le {}", format_date_display(date, options.language));
+        draw_paid_stamp(
+            &mut surface,
+            &label,
+            &fonts.bold,
+            totals_x - PAID_STAMP_OFFSET,
+            totals_top + layout.line_height,
+        );
+        surface.set_fill(Some(black_fill.clone()));
+    }
     y_pos += 30.0;
 
+    // === ECHEANCIER ===
+    if !invoice.installments.is_empty() {
+        if y_pos + 2.0 * layout.line_height > content_limit {
+            new_page!();
+        }
+        draw_text(
+            &mut surface,
+            "Echeancier",
+            &fonts.bold,
+            layout.font_size_small,
+            layout.margin_left,
+            y_pos,
+        );
+        y_pos += layout.line_height;
+
+        for installment in &invoice.installments {
+            if y_pos + layout.line_height > content_limit {
+                new_page!();
+            }
+            draw_text(
+                &mut surface,
+                &format_date_display(&installment.due_date, options.language),
+                &fonts.regular,
+                layout.font_size_small,
+                layout.margin_left + 10.0,
+                y_pos,
+            );
+            draw_text(
+                &mut surface,
+                installment.description.as_deref().unwrap_or(""),
+                &fonts.regular,
+                layout.font_size_small,
+                layout.margin_left + 80.0,
+                y_pos,
+            );
+            draw_text(
+                &mut surface,
+                &format_money(installment.amount, &invoice.currency_code, layout),
+                &fonts.regular,
+                layout.font_size_small,
+                col_total,
+                y_pos,
+            );
+            y_pos += layout.line_height;
+        }
+        y_pos += 10.0;
+    }
+
     // === CONDITIONS DE PAIEMENT ===
     if let Some(ref payment_terms) = invoice.payment_terms {
         if !payment_terms.is_empty() {
+            if y_pos + layout.line_height > content_limit {
+                new_page!();
+            }
             draw_text(
                 &mut surface,
                 &format!("Conditions: {}", payment_terms),
                 &fonts.regular,
-                FONT_SIZE_SMALL,
-                MARGIN_LEFT,
+                layout.font_size_small,
+                layout.margin_left,
                 y_pos,
             );
+            y_pos += layout.line_height;
         }
     }
 
+    // === BON POUR ACCORD ===
+    if options.signature_box {
+        let box_layout = match signature_box_layout(y_pos, page_height) {
+            Some(box_layout) => box_layout,
+            None => {
+                new_page!();
+                signature_box_layout(y_pos, page_height)
+                    .ok_or("Pas assez de place en bas de page pour le cadre \"Bon pour accord\"")?
+            }
+        };
+        let (top, height) = box_layout;
+        draw_signature_box(
+            &mut surface,
+            fonts,
+            layout,
+            page_width - layout.margin_right - SIGNATURE_BOX_WIDTH,
+            top,
+            height,
+        );
+        surface.set_fill(Some(black_fill.clone()));
+    }
+
     // === PIED DE PAGE ===
-    draw_text(
+    draw_footer(
         &mut surface,
-        "Facture conforme Factur-X - XML embarque",
-        &fonts.regular,
-        FONT_SIZE_SMALL,
-        MARGIN_LEFT,
-        PAGE_HEIGHT_PT - 30.0,
+        fonts,
+        layout,
+        emitter,
+        page_height,
+        page_width,
+        page_label(page_number, page_count).as_deref(),
     );
 
     // Terminer la surface et la page
     drop(surface);
+    for annotation in annotations {
+        page.add_annotation(annotation);
+    }
     page.finish();
 
-    // === EMBARQUER LE XML FACTUR-X ===
-    // Créer la date de modification (requise pour PDF/A-3)
-    let now = chrono::Utc::now();
-    let mod_date = DateTime::new(now.format("%Y").to_string().parse().unwrap_or(2024))
-        .month(now.format("%m").to_string().parse().unwrap_or(1))
-        .day(now.format("%d").to_string().parse().unwrap_or(1))
-        .hour(now.format("%H").to_string().parse().unwrap_or(0))
-        .minute(now.format("%M").to_string().parse().unwrap_or(0))
-        .second(now.format("%S").to_string().parse().unwrap_or(0));
-
-    let mime_type = MimeType::new("text/xml").ok_or("Erreur creation MimeType")?;
-    let embedded_xml = EmbeddedFile {
-        path: "factur-x.xml".to_string(),
-        mime_type: Some(mime_type),
-        description: Some("Factur-X XML invoice data".to_string()),
-        association_kind: AssociationKind::Data,
-        data: xml_content.as_bytes().to_vec().into(),
-        modification_date: Some(mod_date),
-        compress: Some(true),
-        location: None,
-    };
-    doc.embed_file(embedded_xml);
-
-    // Finaliser et exporter le PDF avec Krilla
-    let pdf_bytes = match doc.finish() {
-        Ok(bytes) => bytes,
-        Err(KrillaError::Validation(errors)) => {
-            let error_msgs: Vec<String> = errors.iter().map(|e| format!("{:?}", e)).collect();
-            return Err(format!(
-                "Erreurs de validation PDF/A-3: {}",
-                error_msgs.join("; ")
-            ));
-        }
-        Err(e) => return Err(format!("Erreur generation PDF: {:?}", e)),
-    };
-
-    // Generer les metadonnees XMP Factur-X
-    let xmp_string = generate_xmp_metadata(&xmp_metadata)
-        .map_err(|e| format!("Erreur generation XMP: {}", e))?;
-    let xmp_bytes = xmp_string.as_bytes();
-
-    // Utiliser lopdf pour remplacer le stream XMP
-    let pdf_with_xmp = replace_xmp_metadata(&pdf_bytes, xmp_bytes)
-        .map_err(|e| format!("Erreur remplacement XMP: {}", e))?;
-
-    Ok(pdf_with_xmp)
+    Ok(page_number)
 }
 
-/// Remplace les metadonnees XMP dans un PDF existant
-fn replace_xmp_metadata(pdf_bytes: &[u8], xmp_bytes: &[u8]) -> Result<Vec<u8>, String> {
-    use lopdf::Document;
+/// Annotation de lien couvrant un texte imprime a la position `(x, y)` (ligne
+/// de base), vers `uri` ; le texte sert de texte alternatif
+fn contact_link_annotation(
+    text: &str,
+    uri: String,
+    x: f32,
+    y: f32,
+    font_size: f32,
+) -> Option<Annotation> {
+    let width = text.chars().count() as f32 * font_size * LINK_CHAR_WIDTH;
+    let rect = Rect::from_xywh(x, y - font_size, width, font_size * 1.25)?;
+    let link = LinkAnnotation::new(rect, Target::Action(LinkAction::new(uri).into()));
+    Some(Annotation::new_link(link, Some(text.to_string())))
+}
 
-    // Charger le PDF depuis les bytes
-    let mut doc =
-        Document::load_mem(pdf_bytes).map_err(|e| format!("Erreur chargement PDF: {:?}", e))?;
+/// Numero de page du pied de page ("Page 2 / 3"), absent tant que le total
+/// n'est pas connu
+fn page_label(page_number: usize, page_count: Option<usize>) -> Option<String> {
+    page_count.map(|count| format!("Page {} / {}", page_number, count))
+}
 
+/// Remplace les metadonnees XMP dans un PDF charge par lopdf
+fn replace_xmp_metadata(doc: &mut lopdf::Document, xmp_bytes: &[u8]) -> Result<(), String> {
     // Acceder au catalogue (retourne directement un &Dictionary dans lopdf 0.34)
     let catalog = doc
         .catalog()
@@ -623,12 +1426,187 @@ fn replace_xmp_metadata(pdf_bytes: &[u8], xmp_bytes: &[u8]) -> Result<Vec<u8>, S
     // Remplacer l'objet XMP existant
     doc.objects.insert(metadata_ref, Object::Stream(xmp_stream));
 
-    // Sauvegarder le PDF modifie en memoire
-    let mut output = Vec::new();
-    doc.save_to(&mut output)
-        .map_err(|e| format!("Erreur sauvegarde PDF: {:?}", e))?;
+    Ok(())
+}
+
+/// Prepare une page : filigrane de copie, dessine en premier pour rester sous
+/// le contenu (le XML embarque n'est pas modifie), puis texte en noir
+fn start_page(
+    surface: &mut Surface,
+    fonts: &FontSet,
+    options: &GenerationOptions,
+    page_width: f32,
+    page_height: f32,
+) {
+    if let Some(watermark) = options.copy_label.as_deref().and_then(watermark_for) {
+        draw_watermark(surface, &watermark, &fonts.bold, page_width, page_height);
+    }
+    surface.set_fill(Some(Fill {
+        paint: Paint::from(rgb::Color::new(0, 0, 0)),
+        ..Default::default()
+    }));
+}
+
+/// Pied de page repete sur chaque page : mention TVA eventuelle, conformite
+/// et numero de page aligne a droite
+fn draw_footer(
+    surface: &mut Surface,
+    fonts: &FontSet,
+    layout: &LayoutConfig,
+    emitter: &EmitterConfig,
+    page_height: f32,
+    page_width: f32,
+    page_label: Option<&str>,
+) {
+    if emitter.vat_exempt_franchise {
+        draw_text(
+            surface,
+            VAT_FRANCHISE_MENTION,
+            &fonts.bold,
+            layout.font_size_small,
+            layout.margin_left,
+            page_height - 42.0,
+        );
+    } else if emitter.vat_on_payments {
+        draw_text(
+            surface,
+            VAT_ON_PAYMENTS_MENTION,
+            &fonts.bold,
+            layout.font_size_small,
+            layout.margin_left,
+            page_height - 42.0,
+        );
+    }
+    draw_text(
+        surface,
+        "Facture conforme Factur-X - XML embarque",
+        &fonts.regular,
+        layout.font_size_small,
+        layout.margin_left,
+        page_height - 30.0,
+    );
+    if let Some(label) = page_label {
+        draw_text(
+            surface,
+            label,
+            &fonts.regular,
+            layout.font_size_small,
+            page_width - layout.margin_right - PAGE_NUMBER_WIDTH,
+            page_height - 30.0,
+        );
+    }
+}
+
+/// Dessine l'en-tete du tableau des lignes et retourne la position de la
+/// premiere ligne
+#[allow(clippy::too_many_arguments)]
+fn draw_table_header(
+    surface: &mut Surface,
+    fonts: &FontSet,
+    layout: &LayoutConfig,
+    columns: &[TableColumn],
+    column_x: &[f32],
+    y_pos: f32,
+    page_width: f32,
+) -> f32 {
+    for (column, x) in columns.iter().zip(column_x) {
+        draw_text(
+            surface,
+            column.title(),
+            &fonts.bold,
+            layout.font_size_small,
+            *x,
+            y_pos,
+        );
+    }
+
+    draw_horizontal_line(
+        surface,
+        layout.margin_left,
+        y_pos + 4.0,
+        page_width - layout.margin_right,
+    );
+    y_pos + 4.0 + layout.line_height
+}
+
+/// La ligne `index` ouvre-t-elle et/ou ferme-t-elle une section ?
+///
+/// Une section regroupe des lignes consecutives de meme section ; les lignes
+/// sans section n'ont ni titre ni sous-total.
+fn section_bounds(lines: &[&InvoiceLine], index: usize) -> (bool, bool) {
+    let section = lines[index].section_text();
+    if section.is_none() {
+        return (false, false);
+    }
+    let starts = index == 0 || lines[index - 1].section_text() != section;
+    let ends = lines
+        .get(index + 1)
+        .is_none_or(|next| next.section_text() != section);
+    (starts, ends)
+}
+
+/// Hauteur d'une ligne du tableau, detail compris
+fn line_row_height(line: &InvoiceLine, layout: &LayoutConfig) -> f32 {
+    let detail_lines = line
+        .detail_text()
+        .map_or(0, |detail| wrap_text(detail, DETAIL_WRAP_CHARS).len())
+        + usize::from(line.order_line_reference_text().is_some());
+    layout.line_height + detail_lines as f32 * (layout.font_size_detail + 3.0)
+}
+
+/// Hauteur du recapitulatif TVA et des totaux, gardes sur une meme page
+fn summary_block_height(
+    layout: &LayoutConfig,
+    template: Template,
+    vat_rates: usize,
+    tax_currency: bool,
+    free_of_charge: bool,
+    global_discount: bool,
+    deposit: bool,
+) -> f32 {
+    let mut height = 0.0;
+    if vat_rates > 0 {
+        height += (vat_rates + 1) as f32 * layout.line_height + 10.0;
+    }
+    height += totals_block_height(
+        layout,
+        tax_currency,
+        free_of_charge,
+        global_discount,
+        deposit,
+    );
+    // Encadre du modele "modern" : marges interieures haute et basse
+    if template == Template::Modern {
+        height += 2.0 * MODERN_CARD_PADDING;
+    }
+    height
+}
 
-    Ok(output)
+/// Hauteur des totaux, de la premiere ligne de base a la derniere
+fn totals_block_height(
+    layout: &LayoutConfig,
+    tax_currency: bool,
+    free_of_charge: bool,
+    global_discount: bool,
+    deposit: bool,
+) -> f32 {
+    // Total HT et Total TVA, puis Total TTC en plus grand
+    let mut height = 2.0 * layout.line_height + 4.0 + layout.font_size_header;
+    if tax_currency {
+        height += layout.line_height;
+    }
+    // Remise globale puis base HT remisee
+    if global_discount {
+        height += 2.0 * layout.line_height;
+    }
+    if free_of_charge {
+        height += layout.line_height;
+    }
+    // Acompte verse puis net a payer
+    if deposit {
+        height += 2.0 * layout.line_height;
+    }
+    height
 }
 
 /// Dessine du texte sur la surface
@@ -643,6 +1621,75 @@ fn draw_text(surface: &mut Surface, text: &str, font: &Font, size: f32, x: f32,
     );
 }
 
+/// Remplit un rectangle (haut gauche en `x`, `y`) de la couleur donnee
+fn fill_rect(
+    surface: &mut Surface,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    (r, g, b): (u8, u8, u8),
+) {
+    let mut builder = PathBuilder::new();
+    builder.move_to(x, y);
+    builder.line_to(x + width, y);
+    builder.line_to(x + width, y + height);
+    builder.line_to(x, y + height);
+    builder.close();
+    if let Some(path) = builder.finish() {
+        surface.set_stroke(None);
+        surface.set_fill(Some(Fill {
+            paint: Paint::from(rgb::Color::new(r, g, b)),
+            ..Default::default()
+        }));
+        surface.draw_path(&path);
+    }
+}
+
+/// Bandeau du modele "modern" sur toute la largeur du haut de la premiere
+/// page : nom de l'emetteur a gauche et titre du document a droite, en blanc.
+/// Retourne la ligne de base du premier bloc sous le bandeau.
+fn draw_header_band(
+    surface: &mut Surface,
+    fonts: &FontSet,
+    layout: &LayoutConfig,
+    emitter_name: &str,
+    title: &str,
+    page_width: f32,
+) -> f32 {
+    let band_height = header_band_height(layout);
+    fill_rect(surface, 0.0, 0.0, page_width, band_height, MODERN_ACCENT);
+
+    surface.set_fill(Some(Fill {
+        paint: Paint::from(rgb::Color::new(255, 255, 255)),
+        ..Default::default()
+    }));
+    draw_text(
+        surface,
+        emitter_name,
+        &fonts.bold,
+        layout.font_size_title,
+        layout.margin_left,
+        layout.margin_top,
+    );
+    let title_width = title.chars().count() as f32 * layout.font_size_title * WATERMARK_CHAR_WIDTH;
+    draw_text(
+        surface,
+        title,
+        &fonts.bold,
+        layout.font_size_title,
+        page_width - layout.margin_right - title_width,
+        layout.margin_top,
+    );
+
+    band_height + layout.font_size_title + 8.0
+}
+
+/// Hauteur du bandeau : marge haute et une demi-ligne sous le texte
+fn header_band_height(layout: &LayoutConfig) -> f32 {
+    layout.margin_top + layout.line_height
+}
+
 /// Dessine une ligne horizontale
 fn draw_horizontal_line(surface: &mut Surface, x1: f32, y: f32, x2: f32) {
     let mut builder = PathBuilder::new();
@@ -659,33 +1706,991 @@ fn draw_horizontal_line(surface: &mut Surface, x1: f32, y: f32, x2: f32) {
     }
 }
 
-/// Convertit une date YYYY-MM-DD en DD/MM/YYYY
-fn format_date_display(date: &str) -> String {
-    if date.len() == 10 && date.contains('-') {
-        let parts: Vec<&str> = date.split('-').collect();
-        if parts.len() == 3 {
-            return format!("{}/{}/{}", parts[2], parts[1], parts[0]);
+/// Position (haut) et hauteur du cadre "Bon pour accord"
+///
+/// Le cadre se place en bas de page, juste au-dessus du pied de page. Si le
+/// contenu descend trop bas, il est reduit et suit directement le contenu ;
+/// `None` quand meme la hauteur minimale ne tient pas sans chevauchement.
+fn signature_box_layout(content_bottom: f32, page_height: f32) -> Option<(f32, f32)> {
+    let bottom = page_height - FOOTER_TOP_OFFSET - SIGNATURE_BOX_GAP;
+    let available = bottom - (content_bottom + SIGNATURE_BOX_GAP);
+    if available >= SIGNATURE_BOX_HEIGHT {
+        Some((bottom - SIGNATURE_BOX_HEIGHT, SIGNATURE_BOX_HEIGHT))
+    } else if available >= SIGNATURE_BOX_MIN_HEIGHT {
+        Some((content_bottom + SIGNATURE_BOX_GAP, available))
+    } else {
+        None
+    }
+}
+
+/// Dessine le cadre "Bon pour accord" et ses libelles
+fn draw_signature_box(
+    surface: &mut Surface,
+    fonts: &FontSet,
+    layout: &LayoutConfig,
+    x: f32,
+    top: f32,
+    height: f32,
+) {
+    let mut builder = PathBuilder::new();
+    builder.move_to(x, top);
+    builder.line_to(x + SIGNATURE_BOX_WIDTH, top);
+    builder.line_to(x + SIGNATURE_BOX_WIDTH, top + height);
+    builder.line_to(x, top + height);
+    builder.close();
+    if let Some(path) = builder.finish() {
+        surface.set_fill(None);
+        surface.set_stroke(Some(Stroke {
+            paint: Paint::from(rgb::Color::new(0, 0, 0)),
+            width: 0.75,
+            ..Default::default()
+        }));
+        surface.draw_path(&path);
+        surface.set_stroke(None);
+    }
+
+    surface.set_fill(Some(Fill {
+        paint: Paint::from(rgb::Color::new(0, 0, 0)),
+        ..Default::default()
+    }));
+    draw_text(
+        surface,
+        "Bon pour accord",
+        &fonts.bold,
+        layout.font_size_normal,
+        x + 8.0,
+        top + 16.0,
+    );
+    draw_text(
+        surface,
+        "Date et signature",
+        &fonts.regular,
+        layout.font_size_small,
+        x + 8.0,
+        top + 28.0,
+    );
+}
+
+/// Filigrane diagonal d'une copie : texte en majuscules et taille de police
+/// choisie pour que le texte occupe au plus 70% de la diagonale de la page
+struct Watermark {
+    text: String,
+    font_size: f32,
+    /// Largeur estimee du texte en points (pour le centrage)
+    width: f32,
+}
+
+fn watermark_for(label: &str) -> Option<Watermark> {
+    let text = label.trim().to_uppercase();
+    let chars = text.chars().count();
+    if chars == 0 {
+        return None;
+    }
+
+    let diagonal = PAGE_WIDTH_PT.hypot(PAGE_HEIGHT_PT);
+    let font_size =
+        (diagonal * 0.7 / (chars as f32 * WATERMARK_CHAR_WIDTH)).min(WATERMARK_MAX_FONT_SIZE);
+    Some(Watermark {
+        width: chars as f32 * font_size * WATERMARK_CHAR_WIDTH,
+        text,
+        font_size,
+    })
+}
+
+/// Dessine le filigrane en gris clair semi-transparent, du coin bas gauche au
+/// coin haut droit. PDF/A-3 autorise la transparence (contrairement a PDF/A-1),
+/// krilla l'ecrit via un ExtGState et le validateur A3_B l'accepte.
+fn draw_watermark(
+    surface: &mut Surface,
+    watermark: &Watermark,
+    font: &Font,
+    page_width: f32,
+    page_height: f32,
+) {
+    let angle = -(page_height / page_width).atan().to_degrees();
+    surface.push_transform(
+        &Transform::from_translate(page_width / 2.0, page_height / 2.0)
+            .pre_concat(Transform::from_rotate(angle)),
+    );
+    surface.set_fill(Some(Fill {
+        paint: Paint::from(rgb::Color::new(160, 160, 160)),
+        opacity: NormalizedF32::new(WATERMARK_OPACITY).unwrap_or(NormalizedF32::ONE),
+        ..Default::default()
+    }));
+    // Centre le texte sur l'origine (ligne de base abaissee d'un tiers de corps)
+    draw_text(
+        surface,
+        &watermark.text,
+        font,
+        watermark.font_size,
+        -watermark.width / 2.0,
+        watermark.font_size / 3.0,
+    );
+    surface.pop();
+}
+
+/// Dessine le tampon vert encadre et legerement incline d'une facture acquittee,
+/// centre sur (`x`, `y`)
+fn draw_paid_stamp(surface: &mut Surface, label: &str, font: &Font, x: f32, y: f32) {
+    let green = rgb::Color::new(0, 128, 0);
+    let width = label.chars().count() as f32 * PAID_STAMP_FONT_SIZE * WATERMARK_CHAR_WIDTH;
+    let half_width = width / 2.0 + 6.0;
+    let half_height = PAID_STAMP_FONT_SIZE * 0.8;
+
+    surface.push_transform(
+        &Transform::from_translate(x, y).pre_concat(Transform::from_rotate(PAID_STAMP_ANGLE)),
+    );
+    let mut builder = PathBuilder::new();
+    builder.move_to(-half_width, -half_height);
+    builder.line_to(half_width, -half_height);
+    builder.line_to(half_width, half_height);
+    builder.line_to(-half_width, half_height);
+    builder.close();
+    if let Some(path) = builder.finish() {
+        surface.set_fill(None);
+        surface.set_stroke(Some(Stroke {
+            paint: Paint::from(green),
+            width: 1.5,
+            ..Default::default()
+        }));
+        surface.draw_path(&path);
+        surface.set_stroke(None);
+    }
+    surface.set_fill(Some(Fill {
+        paint: Paint::from(green),
+        ..Default::default()
+    }));
+    draw_text(
+        surface,
+        label,
+        font,
+        PAID_STAMP_FONT_SIZE,
+        -width / 2.0,
+        PAID_STAMP_FONT_SIZE / 3.0,
+    );
+    surface.pop();
+}
+
+/// Colonnes du tableau des lignes, dans l'ordre d'affichage
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TableColumn {
+    Description,
+    Quantity,
+    UnitPrice,
+    Vat,
+    Discount,
+    Period,
+    Total,
+}
+
+impl TableColumn {
+    fn title(self) -> &'static str {
+        match self {
+            TableColumn::Description => "Description",
+            TableColumn::Quantity => "Qte",
+            TableColumn::UnitPrice => "PU HT",
+            TableColumn::Vat => "TVA",
+            TableColumn::Discount => "Rabais",
+            TableColumn::Period => "Periode",
+            TableColumn::Total => "Total HT",
+        }
+    }
+
+    /// Largeur minimale en points ; la description occupe la largeur restante
+    fn min_width(self) -> f32 {
+        match self {
+            TableColumn::Description => 150.0,
+            TableColumn::Quantity => 60.0,
+            TableColumn::UnitPrice | TableColumn::Vat | TableColumn::Discount => 70.0,
+            TableColumn::Period => 80.0,
+            TableColumn::Total => 58.0,
+        }
+    }
+}
+
+/// Colonnes affichees : TVA hors franchise, rabais et periode si au moins une
+/// ligne en comporte
+fn table_columns(invoice: &InvoiceForm, show_vat: bool) -> Vec<TableColumn> {
+    let lines: Vec<_> = invoice.lines.iter().filter(|l| l.is_valid()).collect();
+    let has_discount = lines
+        .iter()
+        .any(|l| l.discount_value.is_some_and(|v| v > 0.0));
+    let has_period = lines.iter().any(|l| l.billing_period().is_some());
+
+    let mut columns = vec![
+        TableColumn::Description,
+        TableColumn::Quantity,
+        TableColumn::UnitPrice,
+    ];
+    if show_vat {
+        columns.push(TableColumn::Vat);
+    }
+    if has_discount {
+        columns.push(TableColumn::Discount);
+    }
+    if has_period {
+        columns.push(TableColumn::Period);
+    }
+    columns.push(TableColumn::Total);
+    columns
+}
+
+fn table_min_width(columns: &[TableColumn]) -> f32 {
+    columns.iter().map(|c| c.min_width()).sum()
+}
+
+/// Abscisse de chaque colonne : les colonnes numeriques gardent leur largeur
+/// minimale, la description s'elargit jusqu'a remplir la largeur imprimable
+fn column_positions(columns: &[TableColumn], layout: &LayoutConfig, page_width: f32) -> Vec<f32> {
+    let extra = (layout.content_width(page_width) - table_min_width(columns)).max(0.0);
+    let mut x = layout.margin_left;
+    columns
+        .iter()
+        .map(|column| {
+            let position = x;
+            x += column.min_width();
+            if *column == TableColumn::Description {
+                x += extra;
+            }
+            position
+        })
+        .collect()
+}
+
+/// Orientation retenue : en mode automatique, paysage si le tableau des
+/// lignes depasse la largeur imprimable du portrait
+fn resolve_orientation(
+    requested: PageOrientation,
+    table_width: f32,
+    layout: &LayoutConfig,
+) -> PageOrientation {
+    match requested {
+        PageOrientation::Auto if table_width > layout.content_width(PAGE_WIDTH_PT) => {
+            PageOrientation::Landscape
+        }
+        PageOrientation::Auto => PageOrientation::Portrait,
+        forced => forced,
+    }
+}
+
+/// Dimensions (largeur, hauteur) de la page A4 dans l'orientation donnee
+pub(super) fn page_size(orientation: PageOrientation) -> (f32, f32) {
+    match orientation {
+        PageOrientation::Landscape => (PAGE_HEIGHT_PT, PAGE_WIDTH_PT),
+        _ => (PAGE_WIDTH_PT, PAGE_HEIGHT_PT),
+    }
+}
+
+/// Orientation de la page que retiendra `generate_invoice_pdf` (portrait ou
+/// paysage), selon `options.orientation` et les colonnes du tableau des lignes
+pub fn page_orientation(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    options: &GenerationOptions,
+) -> PageOrientation {
+    let columns = table_columns(invoice, !emitter.vat_exempt_franchise);
+    resolve_orientation(
+        options.orientation,
+        table_min_width(&columns),
+        &options.layout,
+    )
+}
+
+/// Description affichee : code article en tete (reference vendeur, sinon GTIN),
+/// tronquee a 40 caracteres
+fn line_description(line: &InvoiceLine) -> String {
+    let desc = if line.description.chars().count() > 40 {
+        format!(
+            "{}...",
+            line.description.chars().take(37).collect::<String>()
+        )
+    } else {
+        line.description.clone()
+    };
+    let item_code = line
+        .seller_item_id_text()
+        .or_else(|| line.standard_item().map(|(id, _)| id));
+    match item_code {
+        Some(code) => format!("[{}] {}", code, desc),
+        None => desc,
+    }
+}
+
+/// Relation du XML embarque (AFRelationship) selon le profil :
+/// `Data` pour MINIMUM et BASIC WL, `Alternative` pour les profils avec lignes
+fn association_kind_for(profile: FacturXProfile) -> AssociationKind {
+    if profile.has_line_items() {
+        AssociationKind::Alternative
+    } else {
+        AssociationKind::Data
+    }
+}
+
+/// Lignes du bloc "References" : reference acheteur (BT-10, telle qu'emise
+/// dans le XML) et bon de commande (BT-13), chacune seulement si renseignee
+fn reference_lines(invoice: &InvoiceForm, options: &GenerationOptions) -> Vec<String> {
+    let purchase_order = invoice
+        .purchase_order_reference
+        .as_deref()
+        .map(str::trim)
+        .filter(|reference| !reference.is_empty());
+    [
+        (
+            "Reference acheteur",
+            invoice.effective_buyer_reference(options.default_buyer_reference.as_deref()),
+        ),
+        ("Bon de commande", purchase_order),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| value.map(|value| format!("{}: {}", label, value)))
+    .collect()
+}
+
+/// Decoupe un texte en lignes d'au plus `max_chars` caracteres, aux espaces
+/// (un mot plus long que la limite reste entier sur sa ligne)
+fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let current_len = current.chars().count();
+        if current_len > 0 && current_len + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
         }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+/// Charge le logo sous forme d'image krilla
+fn load_logo_image(logo: &LogoSource) -> Result<Image, String> {
+    let (bytes, format) = logo.load()?;
+    let data = Arc::new(bytes.into_owned()).into();
+    match format {
+        ImageFormat::Png => Image::from_png(data, true),
+        ImageFormat::Jpeg => Image::from_jpeg(data, true),
+    }
+    .map_err(|e| format!("Logo illisible: {}", e))
+}
+
+/// Dimensions du logo en points, reduit pour tenir dans le cadre reserve
+/// en conservant ses proportions (jamais agrandi)
+fn fit_logo(width_px: u32, height_px: u32) -> (f32, f32) {
+    let width = width_px.max(1) as f32;
+    let height = height_px.max(1) as f32;
+    let scale = (LOGO_MAX_WIDTH / width)
+        .min(LOGO_MAX_HEIGHT / height)
+        .min(1.0);
+    (width * scale, height * scale)
+}
+
+/// Montant a 2 decimales suivi du code de la devise, ou de son symbole si
+/// `layout.currency_symbol` est actif
+fn format_money(amount: f64, currency_code: &str, layout: &LayoutConfig) -> String {
+    let currency = if layout.currency_symbol {
+        currency_symbol(currency_code)
+    } else {
+        currency_code
+    };
+    format!("{:.2} {}", amount, currency)
+}
+
+/// SIRET groupe pour l'affichage : SIREN par groupes de 3 chiffres puis NIC
+/// ("123 456 789 01234"). Une valeur qui n'a pas 14 chiffres est affichee
+/// telle quelle ; le XML garde toujours le SIRET sans espaces.
+fn format_siret(siret: &str) -> String {
+    let digits: String = siret.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.len() != 14 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return siret.trim().to_string();
+    }
+    format!(
+        "{} {} {} {}",
+        &digits[..3],
+        &digits[3..6],
+        &digits[6..9],
+        &digits[9..]
+    )
+}
+
+/// Symbole usuel d'une devise ISO 4217, ou le code lui-meme a defaut
+fn currency_symbol(code: &str) -> &str {
+    match code {
+        "EUR" => "€",
+        "USD" => "$",
+        "GBP" => "£",
+        "JPY" | "CNY" => "¥",
+        _ => code,
+    }
+}
+
+/// Convertit une date YYYY-MM-DD selon la langue du PDF :
+/// DD/MM/YYYY (fr), MM/DD/YYYY (en) ou YYYY-MM-DD (iso)
+fn format_date_display(date: &str, language: Language) -> String {
+    let pattern = match language {
+        Language::Fr => "%d/%m/%Y",
+        Language::En => "%m/%d/%Y",
+        Language::Iso => "%Y-%m-%d",
+    };
+    format_date_with(date, pattern)
+}
+
+/// Jour et mois d'une date YYYY-MM-DD (periodes de facturation)
+fn format_day_month(date: &str, language: Language) -> String {
+    let pattern = match language {
+        Language::Fr => "%d/%m",
+        Language::En => "%m/%d",
+        Language::Iso => "%m-%d",
+    };
+    format_date_with(date, pattern)
+}
+
+/// Reformate une date YYYY-MM-DD, ou la retourne telle quelle si elle est illisible
+fn format_date_with(date: &str, pattern: &str) -> String {
+    match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(parsed) => parsed.format(pattern).to_string(),
+        Err(_) => date.to_string(),
     }
-    date.to_string()
 }
 
-/// Calcule le recapitulatif TVA par taux
-fn calculate_vat_breakdown(invoice: &InvoiceForm) -> HashMap<String, (f64, f64)> {
-    let mut vat_by_rate: HashMap<String, (f64, f64)> = HashMap::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_font_reported_with_path() {
+        let error = FontSet::load_from(Path::new("/introuvable/fonts"))
+            .err()
+            .unwrap();
+        assert!(error.starts_with(
+            "Erreur lecture police regular: /introuvable/fonts/LiberationSans-Regular.ttf"
+        ));
+        assert!(error.contains("copiez LiberationSans-Regular.ttf dans /introuvable/fonts"));
+    }
+
+    #[test]
+    fn test_bundled_fonts_verify() {
+        assert_eq!(FontSet::verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_currency_symbol() {
+        assert_eq!(currency_symbol("EUR"), "€");
+        assert_eq!(currency_symbol("CHF"), "CHF");
+
+        let mut layout = LayoutConfig::default();
+        assert_eq!(format_money(1200.0, "EUR", &layout), "1200.00 EUR");
+        layout.currency_symbol = true;
+        assert_eq!(format_money(1200.0, "EUR", &layout), "1200.00 €");
+        assert_eq!(format_money(99.5, "SEK", &layout), "99.50 SEK");
+    }
+
+    #[test]
+    fn test_reference_lines() {
+        let mut invoice = invoice_with_line(line());
+        let options = GenerationOptions::default();
+        assert!(reference_lines(&invoice, &options).is_empty());
+
+        invoice.buyer_reference = Some(" REF-42 ".to_string());
+        invoice.purchase_order_reference = Some("  ".to_string());
+        assert_eq!(
+            reference_lines(&invoice, &options),
+            vec!["Reference acheteur: REF-42"]
+        );
+
+        invoice.buyer_reference = None;
+        invoice.purchase_order_reference = Some("PO-2024-118".to_string());
+        assert_eq!(
+            reference_lines(&invoice, &options),
+            vec!["Bon de commande: PO-2024-118"]
+        );
 
-    for line in &invoice.lines {
-        if !line.is_valid() {
-            continue;
+        invoice.recipient_public_sector = true;
+        invoice.recipient_service_code = Some("SERV-01".to_string());
+        assert_eq!(
+            reference_lines(&invoice, &options),
+            vec![
+                "Reference acheteur: SERV-01",
+                "Bon de commande: PO-2024-118"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(
+            wrap_text("Audit complet du systeme d'information", 15),
+            vec!["Audit complet", "du systeme", "d'information"]
+        );
+        assert_eq!(wrap_text("  ", 15), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_fit_logo_keeps_ratio() {
+        assert_eq!(fit_logo(600, 200), (150.0, 50.0));
+        assert_eq!(fit_logo(100, 400), (12.5, 50.0));
+        assert_eq!(fit_logo(40, 20), (40.0, 20.0));
+    }
+
+    #[test]
+    fn test_watermark_label_fits_diagonal() {
+        let watermark = watermark_for(" duplicata ").unwrap();
+        assert_eq!(watermark.text, "DUPLICATA");
+        assert_eq!(watermark.font_size, WATERMARK_MAX_FONT_SIZE);
+        assert!(watermark.width < PAGE_WIDTH_PT.hypot(PAGE_HEIGHT_PT));
+
+        let long = watermark_for("Copie conforme a l'original").unwrap();
+        assert!(long.font_size < WATERMARK_MAX_FONT_SIZE);
+        assert!(long.width <= PAGE_WIDTH_PT.hypot(PAGE_HEIGHT_PT) * 0.7 + 0.01);
+
+        assert!(watermark_for("   ").is_none());
+    }
+
+    #[test]
+    fn test_copy_label_drawn_on_page() {
+        let line = InvoiceLine {
+            description: "Audit".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            ..Default::default()
+        };
+        let mut invoice = invoice_with_line(line);
+        let emitter: EmitterConfig = toml::from_str("name = \"Mon Entreprise\"").unwrap();
+        let totals = invoice.compute_totals();
+        let page_text = |options: &GenerationOptions| {
+            let pdf =
+                generate_invoice_pdf(&invoice, &emitter, totals, "<xml/>", None, options).unwrap();
+            lopdf::Document::load_mem(&pdf)
+                .unwrap()
+                .extract_text(&[1])
+                .unwrap()
+        };
+
+        let options = GenerationOptions {
+            copy_label: Some("Duplicata".to_string()),
+            ..Default::default()
+        };
+        assert!(page_text(&options).contains("DUPLICATA"));
+        assert!(!page_text(&GenerationOptions::default()).contains("DUPLICATA"));
+    }
+
+    #[test]
+    fn test_format_siret() {
+        assert_eq!(format_siret("12345678901234"), "123 456 789 01234");
+        assert_eq!(format_siret("123 456 789 01234"), "123 456 789 01234");
+        assert_eq!(format_siret(" 123456789 01234 "), "123 456 789 01234");
+        assert_eq!(format_siret("1234567890"), "1234567890");
+    }
+
+    #[test]
+    fn test_format_day_month() {
+        assert_eq!(format_day_month("2024-01-31", Language::Fr), "31/01");
+        assert_eq!(format_day_month("2024-01-31", Language::En), "01/31");
+        assert_eq!(format_day_month("2024-01-31", Language::Iso), "01-31");
+        assert_eq!(format_day_month("janvier", Language::Fr), "janvier");
+    }
+
+    #[test]
+    fn test_format_date_display_by_language() {
+        assert_eq!(
+            format_date_display("2024-03-15", Language::Fr),
+            "15/03/2024"
+        );
+        assert_eq!(
+            format_date_display("2024-03-15", Language::En),
+            "03/15/2024"
+        );
+        assert_eq!(
+            format_date_display("2024-03-15", Language::Iso),
+            "2024-03-15"
+        );
+        assert_eq!(format_date_display("15 mars", Language::En), "15 mars");
+    }
+
+    fn invoice_with_line(line: InvoiceLine) -> InvoiceForm {
+        serde_json::from_value::<InvoiceForm>(serde_json::json!({
+            "invoice_number": "FA-1",
+            "issue_date": "2024-01-15",
+            "type_code": 380,
+            "currency_code": "EUR",
+            "due_date": null,
+            "payment_terms": null,
+            "buyer_reference": null,
+            "purchase_order_reference": null,
+            "tax_type_code": null,
+            "tax_currency_code": null,
+            "tax_exchange_rate": null,
+            "recipient_name": "Client",
+            "recipient_siret": "12345678900012",
+            "recipient_vat_number": null,
+            "recipient_gln": null,
+            "recipient_address": "",
+            "recipient_country_code": "FR",
+            "lines": [line],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_page_label() {
+        assert_eq!(page_label(2, Some(3)).as_deref(), Some("Page 2 / 3"));
+        assert_eq!(page_label(1, None), None);
+    }
+
+    /// URI des annotations de lien de la premiere page d'un PDF
+    fn link_uris(pdf: &[u8]) -> Vec<String> {
+        let pdf = lopdf::Document::load_mem(pdf).unwrap();
+        let page_id = pdf.get_pages()[&1];
+        pdf.get_page_annotations(page_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|annotation| {
+                annotation.get(b"Subtype").and_then(|s| s.as_name()).ok()
+                    == Some(b"Link".as_slice())
+            })
+            .filter_map(|annotation| {
+                let action = annotation.get(b"A").ok()?;
+                let action = match action {
+                    lopdf::Object::Reference(id) => pdf.get_dictionary(*id).ok()?,
+                    other => other.as_dict().ok()?,
+                };
+                let uri = action.get(b"URI").ok()?.as_str().ok()?;
+                Some(String::from_utf8_lossy(uri).into_owned())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_contact_link_annotations() {
+        let line = InvoiceLine {
+            description: "Audit".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            ..Default::default()
+        };
+        let mut invoice = invoice_with_line(line);
+        let emitter: EmitterConfig = toml::from_str(
+            r#"
+            name = "Mon Entreprise"
+            email = "contact@entreprise.fr"
+            website = "www.entreprise.fr"
+            "#,
+        )
+        .unwrap();
+        let totals = invoice.compute_totals();
+
+        let options = GenerationOptions::default();
+        let pdf =
+            generate_invoice_pdf(&invoice, &emitter, totals, "<xml/>", None, &options).unwrap();
+        assert_eq!(
+            link_uris(&pdf),
+            ["mailto:contact@entreprise.fr", "https://www.entreprise.fr"]
+        );
+
+        let options = GenerationOptions {
+            link_annotations: false,
+            ..Default::default()
+        };
+        let pdf =
+            generate_invoice_pdf(&invoice, &emitter, totals, "<xml/>", None, &options).unwrap();
+        assert!(link_uris(&pdf).is_empty());
+    }
+
+    #[test]
+    fn test_page_numbers_on_three_pages() {
+        let line = InvoiceLine {
+            description: "Abonnement".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 10.0,
+            vat_rate: 20.0,
+            ..Default::default()
+        };
+        let mut invoice = invoice_with_line(line.clone());
+        let emitter: EmitterConfig = toml::from_str("name = \"Mon Entreprise\"").unwrap();
+        let options = GenerationOptions::default();
+        let fonts = FontSet::load().unwrap();
+
+        // Ajoute des lignes jusqu'a deborder sur une troisieme page
+        let measure = |invoice: &mut InvoiceForm| {
+            let totals = invoice.compute_totals();
+            let pages = draw_invoice_pages(
+                &mut Document::new(),
+                invoice,
+                &emitter,
+                totals,
+                None,
+                &options,
+                &fonts,
+                None,
+            );
+            pages.map_err(RenderError::into_message).unwrap()
+        };
+        while measure(&mut invoice) < 3 {
+            invoice.lines.push(line.clone());
+        }
+        assert_eq!(measure(&mut invoice), 3);
+
+        let totals = invoice.compute_totals();
+        let pdf =
+            generate_invoice_pdf(&invoice, &emitter, totals, "<xml/>", None, &options).unwrap();
+        let pdf = lopdf::Document::load_mem(&pdf).unwrap();
+        assert_eq!(pdf.get_pages().len(), 3);
+        for page in 1..=3 {
+            let text = pdf.extract_text(&[page]).unwrap();
+            assert!(
+                text.contains(&format!("Page {} / 3", page)),
+                "pied de page {} : {}",
+                page,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_vat_breakdown_fractional_rates() {
+        let rated = |vat_rate: f64| InvoiceLine { vat_rate, ..line() };
+        let mut invoice = invoice_with_line(rated(2.1));
+        invoice.lines.extend([rated(2.10), rated(8.25), rated(8.2)]);
+        invoice.compute_totals();
+
+        let breakdown = vat_breakdown(&invoice, false, &GenerationOptions::default());
+        let rates: Vec<String> = breakdown.iter().map(|g| g.rate.to_string()).collect();
+        assert_eq!(rates, vec!["2.1", "8.2", "8.25"]);
+        assert_eq!(breakdown[0].category, "S");
+        assert_eq!(breakdown[0].basis, 200.0);
+        assert!((breakdown[0].vat_amount - 4.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_portrait_layout_matches_fixed_columns() {
+        let columns = table_columns(&invoice_with_line(line()), true);
+        assert_eq!(
+            column_positions(&columns, &LayoutConfig::default(), PAGE_WIDTH_PT),
+            vec![57.0, 280.0, 340.0, 410.0, 480.0]
+        );
+    }
+
+    #[test]
+    fn test_auto_orientation_for_wide_tables() {
+        let mut line = line();
+        line.discount_value = Some(10.0);
+        let invoice = invoice_with_line(line.clone());
+        let width = table_min_width(&table_columns(&invoice, true));
+        assert_eq!(
+            resolve_orientation(PageOrientation::Auto, width, &LayoutConfig::default()),
+            PageOrientation::Portrait
+        );
+
+        line.period_start = Some("2024-01-01".to_string());
+        let invoice = invoice_with_line(line);
+        let width = table_min_width(&table_columns(&invoice, true));
+        assert_eq!(
+            resolve_orientation(PageOrientation::Auto, width, &LayoutConfig::default()),
+            PageOrientation::Landscape
+        );
+        assert_eq!(
+            resolve_orientation(PageOrientation::Portrait, width, &LayoutConfig::default()),
+            PageOrientation::Portrait
+        );
+        assert_eq!(page_size(PageOrientation::Landscape), (842.0, 595.0));
+    }
+
+    fn line() -> InvoiceLine {
+        InvoiceLine {
+            description: "Abonnement".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_signature_box_layout() {
+        // Contenu court : cadre pleine hauteur au-dessus du pied de page
+        let (top, height) = signature_box_layout(400.0, PAGE_HEIGHT_PT).unwrap();
+        assert_eq!(height, SIGNATURE_BOX_HEIGHT);
+        assert_eq!(
+            top + height,
+            PAGE_HEIGHT_PT - FOOTER_TOP_OFFSET - SIGNATURE_BOX_GAP
+        );
+
+        // Contenu long : cadre reduit, place sous le contenu
+        let content_bottom = PAGE_HEIGHT_PT - 130.0;
+        let (top, height) = signature_box_layout(content_bottom, PAGE_HEIGHT_PT).unwrap();
+        assert!((SIGNATURE_BOX_MIN_HEIGHT..SIGNATURE_BOX_HEIGHT).contains(&height));
+        assert_eq!(top, content_bottom + SIGNATURE_BOX_GAP);
+        assert!(top + height <= PAGE_HEIGHT_PT - FOOTER_TOP_OFFSET);
+
+        // Plus de place sans chevaucher le pied de page
+        assert!(signature_box_layout(PAGE_HEIGHT_PT - 100.0, PAGE_HEIGHT_PT).is_none());
+    }
+
+    #[test]
+    fn test_modern_template_geometry() {
+        let layout = LayoutConfig::default();
+        let classic =
+            summary_block_height(&layout, Template::Classic, 2, false, false, false, false);
+        let modern = summary_block_height(&layout, Template::Modern, 2, false, false, false, false);
+        assert_eq!(modern - classic, 2.0 * MODERN_CARD_PADDING);
+        assert_eq!(
+            classic,
+            3.0 * layout.line_height
+                + 10.0
+                + totals_block_height(&layout, false, false, false, false)
+        );
+
+        // Le bandeau couvre la premiere ligne de base de la mise en page
+        assert!(header_band_height(&layout) > layout.margin_top);
+    }
+
+    #[test]
+    fn test_custom_output_condition_forces_post_processing() {
+        let mut options = GenerationOptions {
+            xmp_post_processing: false,
+            ..Default::default()
+        };
+        assert!(!needs_post_processing(&options));
+
+        options.output_condition.registry_name = "https://registre.example".to_string();
+        assert!(needs_post_processing(&options));
+
+        options.output_condition = OutputCondition::default();
+        options.xmp_post_processing = true;
+        assert!(needs_post_processing(&options));
+    }
+
+    #[test]
+    fn test_attachment_mime_type() {
+        assert!(attachment_mime_type("factur-x.xml", "text/xml").is_ok());
+        assert!(attachment_mime_type("devis.pdf", "application/pdf").is_ok());
+        assert!(attachment_mime_type("plan.svg", "image/svg+xml").is_ok());
+
+        for mime in ["textxml", "text/", "txt/xml", "text/x ml", "/xml"] {
+            let error = attachment_mime_type("annexe.xml", mime).err().unwrap();
+            assert_eq!(
+                error,
+                format!(
+                    "Piece jointe 'annexe.xml' : type MIME '{}' invalide \
+                     (type/sous-type attendu, ex. text/xml)",
+                    mime
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_embedded_xml_filename_matches_xmp() {
+        let invoice = invoice_with_line(InvoiceLine {
+            description: "Audit".to_string(),
+            ..Default::default()
+        });
+        let emitter: EmitterConfig = toml::from_str("name = \"Mon Entreprise\"").unwrap();
+        let options = GenerationOptions {
+            xml_filename: "facture.xml".to_string(),
+            ..Default::default()
+        };
+        let xmp_metadata = xmp_metadata_for(&invoice, &emitter, &options);
+        let embedded = embedded_xml_file(
+            "<xml/>",
+            &xmp_metadata,
+            options.profile,
+            options.xml_bom,
+            DateTime::new(2024),
+        )
+        .unwrap();
+
+        assert_eq!(embedded.path, "facture.xml");
+        let xmp = generate_xmp_metadata(&xmp_metadata).unwrap();
+        assert!(xmp.contains("<fx:DocumentFileName>facture.xml</fx:DocumentFileName>"));
+    }
+
+    #[test]
+    fn test_saved_pdf_declares_pdfa_conformance() {
+        let line = InvoiceLine {
+            description: "Audit".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            ..Default::default()
+        };
+        let mut invoice = invoice_with_line(line);
+        let emitter: EmitterConfig = toml::from_str("name = \"Mon Entreprise\"").unwrap();
+        let totals = invoice.compute_totals();
+
+        for (conformance, other) in [
+            (PdfaConformance::A3B, PdfaConformance::A3U),
+            (PdfaConformance::A3U, PdfaConformance::A3B),
+        ] {
+            let options = GenerationOptions {
+                pdfa_conformance: conformance,
+                ..Default::default()
+            };
+            let pdf =
+                generate_invoice_pdf(&invoice, &emitter, totals, "<xml/>", None, &options).unwrap();
+            assert_eq!(verify_pdfa3(&pdf, conformance), Ok(()));
+            assert_eq!(
+                verify_pdfa3(&pdf, other),
+                Err(vec![format!(
+                    "Le XMP ne declare pas pdfaid:conformance = {}",
+                    other.letter()
+                )])
+            );
         }
-        let rate_key = format!("{:.1}", line.vat_rate);
-        let base_ht = line.total_ht_value();
-        let vat_amount = line.total_vat_value();
+    }
 
-        let entry = vat_by_rate.entry(rate_key).or_insert((0.0, 0.0));
-        entry.0 += base_ht;
-        entry.1 += vat_amount;
+    #[test]
+    fn test_embedded_xml_bom() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><a/>";
+        assert_eq!(embedded_xml_bytes(xml, false), xml.as_bytes());
+
+        let with_bom = embedded_xml_bytes(xml, true);
+        assert_eq!(&with_bom[..3], [0xEF, 0xBB, 0xBF]);
+        assert_eq!(&with_bom[3..], xml.as_bytes());
     }
 
-    vat_by_rate
+    #[test]
+    fn test_section_bounds() {
+        let line = |section: Option<&str>| InvoiceLine {
+            description: "Ligne".to_string(),
+            section: section.map(str::to_string),
+            ..Default::default()
+        };
+        let lines = [
+            line(Some("Prestations")),
+            line(Some("Prestations")),
+            line(None),
+            line(Some(" Materiel ")),
+            line(Some("Prestations")),
+        ];
+        let lines: Vec<&InvoiceLine> = lines.iter().collect();
+
+        assert_eq!(section_bounds(&lines, 0), (true, false));
+        assert_eq!(section_bounds(&lines, 1), (false, true));
+        assert_eq!(section_bounds(&lines, 2), (false, false));
+        assert_eq!(section_bounds(&lines, 3), (true, true));
+        assert_eq!(section_bounds(&lines, 4), (true, true));
+    }
+
+    #[test]
+    fn test_line_row_height_includes_detail() {
+        let mut line = InvoiceLine {
+            description: "Audit".to_string(),
+            ..Default::default()
+        };
+        let layout = LayoutConfig::default();
+        assert_eq!(line_row_height(&line, &layout), layout.line_height);
+
+        line.detail = Some("Analyse ".repeat(20));
+        let detail_lines = wrap_text(line.detail_text().unwrap(), DETAIL_WRAP_CHARS).len();
+        assert_eq!(detail_lines, 2);
+        assert_eq!(
+            line_row_height(&line, &layout),
+            layout.line_height + 2.0 * (layout.font_size_detail + 3.0)
+        );
+    }
 }
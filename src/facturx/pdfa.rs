@@ -0,0 +1,316 @@
+//! Contrôle des invariants PDF/A-3 d'un PDF déjà produit
+//!
+//! krilla valide le PDF/A-3 au moment du rendu, mais le post-traitement lopdf
+//! (`replace_xmp_metadata`, remplacement de l'output intent) réécrit le
+//! fichier après cette validation. `verify_pdfa3` relit le PDF final et
+//! vérifie les points que ce ré-enregistrement pourrait casser.
+
+use super::xmp_metadata::PdfaConformance;
+use lopdf::{Dictionary, Document, Object};
+
+/// Vérifie les invariants PDF/A-3 d'un PDF produit au niveau `conformance`
+///
+/// - le XMP déclare `pdfaid:part` 3 et la lettre `pdfaid:conformance` du
+///   niveau demandé (B ou U) ;
+/// - le catalogue référence au moins un `OutputIntent` GTS_PDFA1, avec
+///   `OutputConditionIdentifier`, `RegistryName` et profil ICC de destination ;
+/// - le flux `/Metadata` est présent et non compressé ;
+/// - toutes les polices sont embarquées.
+///
+/// # Returns
+/// La liste des écarts constatés (vide si le PDF est conforme)
+pub fn verify_pdfa3(pdf_bytes: &[u8], conformance: PdfaConformance) -> Result<(), Vec<String>> {
+    let doc = Document::load_mem(pdf_bytes).map_err(|e| vec![format!("PDF illisible: {}", e)])?;
+    let catalog = doc
+        .catalog()
+        .map_err(|_| vec!["Catalogue PDF introuvable".to_string()])?;
+
+    let mut errors = Vec::new();
+    check_metadata(&doc, catalog, conformance, &mut errors);
+    check_output_intents(&doc, catalog, &mut errors);
+    check_fonts_embedded(&doc, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Flux XMP non compressé déclarant PDF/A-3 au niveau `conformance`
+fn check_metadata(
+    doc: &Document,
+    catalog: &Dictionary,
+    conformance: PdfaConformance,
+    errors: &mut Vec<String>,
+) {
+    let stream = match catalog
+        .get(b"Metadata")
+        .and_then(|metadata| doc.dereference(metadata))
+        .and_then(|(_, object)| object.as_stream())
+    {
+        Ok(stream) => stream,
+        Err(_) => {
+            errors.push("Flux /Metadata absent du catalogue".to_string());
+            return;
+        }
+    };
+
+    if stream.dict.has(b"Filter") {
+        errors.push("Le flux /Metadata ne doit pas etre compresse".to_string());
+    }
+
+    let xmp = String::from_utf8_lossy(&stream.content);
+    if xmp_property(&xmp, "pdfaid:part").as_deref() != Some("3") {
+        errors.push("Le XMP ne declare pas pdfaid:part = 3".to_string());
+    }
+    if xmp_property(&xmp, "pdfaid:conformance").as_deref() != Some(conformance.letter()) {
+        errors.push(format!(
+            "Le XMP ne declare pas pdfaid:conformance = {}",
+            conformance.letter()
+        ));
+    }
+}
+
+/// Valeur d'une propriété XMP, en élément (`<p>v</p>`) ou en attribut (`p="v"`)
+fn xmp_property(xmp: &str, name: &str) -> Option<String> {
+    let element = format!("<{}>", name);
+    if let Some(start) = xmp.find(&element).map(|i| i + element.len()) {
+        let end = xmp[start..].find('<')?;
+        return Some(xmp[start..start + end].trim().to_string());
+    }
+    for quote in ['"', '\''] {
+        let attribute = format!("{}={}", name, quote);
+        if let Some(start) = xmp.find(&attribute).map(|i| i + attribute.len()) {
+            let end = xmp[start..].find(quote)?;
+            return Some(xmp[start..start + end].trim().to_string());
+        }
+    }
+    None
+}
+
+/// Au moins un output intent dans le catalogue, chacun complet
+fn check_output_intents(doc: &Document, catalog: &Dictionary, errors: &mut Vec<String>) {
+    let intents = catalog
+        .get(b"OutputIntents")
+        .and_then(|intents| doc.dereference(intents))
+        .and_then(|(_, object)| object.as_array())
+        .map(|intents| intents.as_slice())
+        .unwrap_or_default();
+    if intents.is_empty() {
+        errors.push("Aucun OutputIntent dans le catalogue".to_string());
+    }
+
+    for intent in intents {
+        let Ok(intent) = doc
+            .dereference(intent)
+            .and_then(|(_, object)| object.as_dict())
+        else {
+            errors.push("OutputIntent illisible".to_string());
+            continue;
+        };
+        if !intent
+            .get(b"S")
+            .and_then(Object::as_name)
+            .is_ok_and(|s| s == b"GTS_PDFA1")
+        {
+            errors.push("OutputIntent sans /S GTS_PDFA1".to_string());
+        }
+        for key in ["OutputConditionIdentifier", "RegistryName"] {
+            let filled = intent
+                .get(key.as_bytes())
+                .and_then(Object::as_str)
+                .is_ok_and(|value| !value.iter().all(u8::is_ascii_whitespace));
+            if !filled {
+                errors.push(format!("OutputIntent sans /{}", key));
+            }
+        }
+        let has_profile = intent
+            .get(b"DestOutputProfile")
+            .and_then(|profile| doc.dereference(profile))
+            .and_then(|(_, object)| object.as_stream())
+            .is_ok();
+        if !has_profile {
+            errors.push("OutputIntent sans profil /DestOutputProfile".to_string());
+        }
+    }
+}
+
+/// Chaque police (hors Type0, dont les descendantes sont contrôlées, et
+/// Type3, dessinée par le PDF lui-même) a un descripteur avec fichier embarqué
+fn check_fonts_embedded(doc: &Document, errors: &mut Vec<String>) {
+    for object in doc.objects.values() {
+        let Ok(font) = object.as_dict() else {
+            continue;
+        };
+        if !font
+            .get(b"Type")
+            .and_then(Object::as_name)
+            .is_ok_and(|t| t == b"Font")
+        {
+            continue;
+        }
+        let subtype = font.get(b"Subtype").and_then(Object::as_name).ok();
+        if matches!(subtype, Some(b"Type0") | Some(b"Type3")) {
+            continue;
+        }
+
+        let embedded = font
+            .get(b"FontDescriptor")
+            .and_then(|descriptor| doc.dereference(descriptor))
+            .and_then(|(_, object)| object.as_dict())
+            .is_ok_and(|descriptor| {
+                [b"FontFile".as_slice(), b"FontFile2", b"FontFile3"]
+                    .iter()
+                    .any(|key| descriptor.has(key))
+            });
+        if !embedded {
+            let name = font
+                .get(b"BaseFont")
+                .and_then(Object::as_name)
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .unwrap_or_else(|_| "sans nom".to_string());
+            errors.push(format!("Police non embarquee: {}", name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facturx::xmp_metadata::{generate_xmp_metadata, XmpMetadata};
+    use lopdf::Stream;
+
+    /// PDF minimal tel que produit par le post-traitement : XMP généré,
+    /// output intent et police TrueType embarquée
+    fn post_processed_pdf(
+        xmp: &str,
+        compress_metadata: bool,
+        embed_font: bool,
+        complete_intent: bool,
+    ) -> Vec<u8> {
+        let mut doc = Document::with_version("1.7");
+
+        let mut metadata = Stream::new(Dictionary::new(), xmp.as_bytes().to_vec());
+        if compress_metadata {
+            metadata.compress().unwrap();
+        }
+        let metadata_id = doc.add_object(metadata);
+
+        let mut intent = Dictionary::new();
+        intent.set("Type", Object::Name(b"OutputIntent".to_vec()));
+        intent.set("S", Object::Name(b"GTS_PDFA1".to_vec()));
+        if complete_intent {
+            let profile = doc.add_object(Stream::new(Dictionary::new(), b"icc".to_vec()));
+            intent.set(
+                "OutputConditionIdentifier",
+                Object::string_literal("sRGB IEC61966-2.1"),
+            );
+            intent.set(
+                "RegistryName",
+                Object::string_literal("http://www.color.org"),
+            );
+            intent.set("DestOutputProfile", Object::Reference(profile));
+        }
+
+        let mut descriptor = Dictionary::new();
+        descriptor.set("Type", Object::Name(b"FontDescriptor".to_vec()));
+        if embed_font {
+            let font_file = doc.add_object(Stream::new(Dictionary::new(), b"font".to_vec()));
+            descriptor.set("FontFile2", Object::Reference(font_file));
+        }
+        let descriptor_id = doc.add_object(descriptor);
+
+        let mut font = Dictionary::new();
+        font.set("Type", Object::Name(b"Font".to_vec()));
+        font.set("Subtype", Object::Name(b"CIDFontType2".to_vec()));
+        font.set("BaseFont", Object::Name(b"LiberationSans".to_vec()));
+        font.set("FontDescriptor", Object::Reference(descriptor_id));
+        let font_id = doc.add_object(font);
+
+        let mut type0 = Dictionary::new();
+        type0.set("Type", Object::Name(b"Font".to_vec()));
+        type0.set("Subtype", Object::Name(b"Type0".to_vec()));
+        type0.set("DescendantFonts", Object::Array(vec![font_id.into()]));
+        doc.add_object(type0);
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set("Metadata", Object::Reference(metadata_id));
+        catalog.set("OutputIntents", Object::Array(vec![intent.into()]));
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn generated_xmp(pdfa_identification: bool) -> String {
+        generate_xmp_metadata(&XmpMetadata {
+            title: "Facture FA-2024-001".to_string(),
+            author: "Ma Societe".to_string(),
+            pdfa_identification,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_pdfa3_generated_pdf() {
+        let pdf = post_processed_pdf(&generated_xmp(true), false, true, true);
+        assert_eq!(verify_pdfa3(&pdf, PdfaConformance::A3B), Ok(()));
+
+        // Un XMP en B ne satisfait pas une demande A-3u
+        assert_eq!(
+            verify_pdfa3(&pdf, PdfaConformance::A3U),
+            Err(vec![
+                "Le XMP ne declare pas pdfaid:conformance = U".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_verify_pdfa3_reports_broken_invariants() {
+        let errors = verify_pdfa3(
+            &post_processed_pdf(&generated_xmp(false), true, false, true),
+            PdfaConformance::A3B,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert!(errors[0].contains("compresse"));
+        assert!(errors[1].contains("pdfaid:part"));
+        assert!(errors[2].contains("pdfaid:conformance"));
+        assert_eq!(errors[3], "Police non embarquee: LiberationSans");
+
+        assert!(verify_pdfa3(b"pas un PDF", PdfaConformance::A3B).is_err());
+    }
+
+    #[test]
+    fn test_verify_pdfa3_reports_incomplete_output_intent() {
+        let errors = verify_pdfa3(
+            &post_processed_pdf(&generated_xmp(true), false, true, false),
+            PdfaConformance::A3B,
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                "OutputIntent sans /OutputConditionIdentifier",
+                "OutputIntent sans /RegistryName",
+                "OutputIntent sans profil /DestOutputProfile",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_xmp_property() {
+        let xmp = r#"<rdf:Description pdfaid:part="3"><pdfaid:conformance>B</pdfaid:conformance>"#;
+        assert_eq!(xmp_property(xmp, "pdfaid:part").as_deref(), Some("3"));
+        assert_eq!(
+            xmp_property(xmp, "pdfaid:conformance").as_deref(),
+            Some("B")
+        );
+        assert_eq!(xmp_property(xmp, "pdfaid:amd"), None);
+    }
+}
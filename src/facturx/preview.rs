@@ -0,0 +1,145 @@
+//! Aperçu PNG de la facture
+//!
+//! Rasterise la première page du PDF généré avec hayro : l'aperçu reprend
+//! exactement la mise en page de `generate_invoice_pdf` pour les mêmes options
+//! et le même logo, orientation comprise.
+
+use super::error::FacturXError;
+use super::logo::LogoSource;
+use super::options::GenerationOptions;
+use super::pdf_generator::{generate_invoice_pdf, page_orientation, page_size};
+use super::xml_generator::generate_facturx_xml_with_options;
+use crate::models::invoice::{InvoiceForm, InvoiceTotals};
+use crate::EmitterConfig;
+use hayro::{InterpreterSettings, Pdf, RenderSettings};
+use std::sync::Arc;
+
+/// Résolution maximale acceptée pour un aperçu (au-delà, l'image devient démesurée)
+const MAX_PREVIEW_DPI: u32 = 600;
+
+/// Rend la première page de la facture en PNG
+///
+/// # Arguments
+/// * `logo` - Logo dessiné dans l'en-tête, comme pour `generate_invoice_pdf`
+/// * `options` - Options de génération de la facture (modèle, langue, mise
+///   en page, orientation, filigrane...)
+/// * `dpi` - Résolution de sortie (72 = 1 pixel par point PDF), entre 1 et 600
+///
+/// # Returns
+/// Les octets PNG, de dimensions [`preview_dimensions`]
+pub fn render_preview_png(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    logo: Option<&LogoSource>,
+    options: &GenerationOptions,
+    dpi: u32,
+) -> Result<Vec<u8>, FacturXError> {
+    if dpi == 0 || dpi > MAX_PREVIEW_DPI {
+        return Err(FacturXError::Render(format!(
+            "resolution {} dpi hors limites (1 a {})",
+            dpi, MAX_PREVIEW_DPI
+        )));
+    }
+
+    let xml_content = generate_facturx_xml_with_options(invoice, emitter, totals, options)
+        .map_err(FacturXError::Xml)?;
+
+    // L'aperçu n'a pas besoin du XMP Factur-X : pas d'aller-retour lopdf
+    let options = GenerationOptions {
+        xmp_post_processing: false,
+        ..options.clone()
+    };
+    let pdf_bytes = generate_invoice_pdf(invoice, emitter, totals, &xml_content, logo, &options)
+        .map_err(FacturXError::Pdf)?;
+
+    let pdf = Pdf::new(Arc::new(pdf_bytes))
+        .map_err(|e| FacturXError::Render(format!("chargement PDF: {:?}", e)))?;
+    let page = pdf
+        .pages()
+        .first()
+        .ok_or_else(|| FacturXError::Render("PDF sans page".to_string()))?;
+
+    let scale = dpi as f32 / 72.0;
+    let render_settings = RenderSettings {
+        x_scale: scale,
+        y_scale: scale,
+        ..Default::default()
+    };
+    let pixmap = hayro::render(page, &InterpreterSettings::default(), &render_settings);
+
+    Ok(pixmap.take_png())
+}
+
+/// Dimensions en pixels (largeur, hauteur) de l'aperçu de la facture : A4
+/// dans l'orientation que retient [`page_orientation`](super::page_orientation)
+/// pour ces options (le mode automatique peut passer en paysage)
+pub fn preview_dimensions(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    options: &GenerationOptions,
+    dpi: u32,
+) -> (u32, u32) {
+    let (width, height) = page_size(page_orientation(invoice, emitter, options));
+    let scale = dpi as f32 / 72.0;
+    (
+        (width * scale).round() as u32,
+        (height * scale).round() as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facturx::options::PageOrientation;
+    use crate::models::line::InvoiceLine;
+
+    #[test]
+    fn test_preview_dimensions() {
+        let line = InvoiceLine {
+            description: "Abonnement".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            ..Default::default()
+        };
+        let mut invoice = serde_json::from_value::<InvoiceForm>(serde_json::json!({
+            "invoice_number": "FA-1",
+            "issue_date": "2024-01-15",
+            "type_code": 380,
+            "currency_code": "EUR",
+            "recipient_name": "Client",
+            "recipient_siret": "12345678900012",
+            "recipient_address": "",
+            "recipient_country_code": "FR",
+            "lines": [line],
+        }))
+        .unwrap();
+        let emitter: EmitterConfig = toml::from_str("name = \"Mon Entreprise\"").unwrap();
+        let options = GenerationOptions::default();
+        assert_eq!(
+            preview_dimensions(&invoice, &emitter, &options, 72),
+            (595, 842)
+        );
+        assert_eq!(
+            preview_dimensions(&invoice, &emitter, &options, 150),
+            (1240, 1754)
+        );
+
+        // Rabais et période : le mode automatique passe en paysage
+        invoice.lines[0].discount_value = Some(10.0);
+        invoice.lines[0].period_start = Some("2024-01-01".to_string());
+        assert_eq!(
+            preview_dimensions(&invoice, &emitter, &options, 72),
+            (842, 595)
+        );
+
+        let portrait = GenerationOptions {
+            orientation: PageOrientation::Portrait,
+            ..Default::default()
+        };
+        assert_eq!(
+            preview_dimensions(&invoice, &emitter, &portrait, 72),
+            (595, 842)
+        );
+    }
+}
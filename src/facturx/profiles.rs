@@ -0,0 +1,141 @@
+//! Description des profils Factur-X et de leurs termes métier obligatoires
+//!
+//! Sert à l'endpoint de découverte `GET /api/profiles` : pour chaque profil,
+//! les termes métier (BT) exigés et le champ du formulaire ou de la
+//! configuration émetteur qui les renseigne. Les montants calculés
+//! renvoient au champ `lines` dont ils sont dérivés.
+
+use super::xmp_metadata::FacturXProfile;
+use serde::Serialize;
+
+/// Terme métier EN 16931 exigé par un profil
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BusinessTerm {
+    /// Identifiant du terme (ex: "BT-1")
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Champ de `InvoiceForm`, de `InvoiceLine` (`lines[][...]`) ou de
+    /// l'émetteur (`emitter.`) qui renseigne le terme
+    pub field: &'static str,
+}
+
+const fn term(id: &'static str, name: &'static str, field: &'static str) -> BusinessTerm {
+    BusinessTerm { id, name, field }
+}
+
+/// Termes obligatoires du profil MINIMUM
+const MINIMUM_TERMS: &[BusinessTerm] = &[
+    term("BT-1", "Numero de facture", "invoice_number"),
+    term("BT-2", "Date d'emission", "issue_date"),
+    term("BT-3", "Type de document", "type_code"),
+    term("BT-5", "Code devise", "currency_code"),
+    term("BT-27", "Nom du vendeur", "emitter.name"),
+    term("BT-30", "Identifiant legal du vendeur", "emitter.siret"),
+    term("BT-44", "Nom de l'acheteur", "recipient_name"),
+    term("BT-109", "Total HT", "lines"),
+    term("BT-110", "Total TVA", "lines"),
+    term("BT-112", "Total TTC", "lines"),
+    term("BT-115", "Montant a payer", "lines"),
+];
+
+/// Termes ajoutés par le profil BASIC WL
+const BASIC_WL_TERMS: &[BusinessTerm] = &[
+    term("BT-55", "Code pays de l'acheteur", "recipient_country_code"),
+    term("BT-116", "Base HT par taux de TVA", "lines"),
+    term("BT-117", "Montant de TVA par taux", "lines"),
+    term("BT-118", "Categorie de TVA", "lines[][vat_rate]"),
+];
+
+/// Termes ajoutés par le profil BASIC (lignes de facture)
+const BASIC_TERMS: &[BusinessTerm] = &[
+    term("BT-126", "Identifiant de ligne", "lines"),
+    term("BT-129", "Quantite facturee", "lines[][quantity]"),
+    term("BT-131", "Montant net de la ligne", "lines"),
+    term("BT-146", "Prix unitaire net", "lines[][unit_price_ht]"),
+    term(
+        "BT-151",
+        "Categorie de TVA de la ligne",
+        "lines[][vat_rate]",
+    ),
+    term("BT-153", "Designation de l'article", "lines[][description]"),
+];
+
+impl FacturXProfile {
+    /// Termes métier obligatoires du profil
+    ///
+    /// Chaque profil reprend les exigences du profil inférieur.
+    pub fn required_terms(&self) -> Vec<BusinessTerm> {
+        let levels: &[&[BusinessTerm]] = match self {
+            FacturXProfile::Minimum => &[MINIMUM_TERMS],
+            FacturXProfile::BasicWL => &[MINIMUM_TERMS, BASIC_WL_TERMS],
+            // EN 16931 et EXTENDED n'ajoutent que des termes facultatifs à BASIC
+            FacturXProfile::Basic | FacturXProfile::EN16931 | FacturXProfile::Extended => {
+                &[MINIMUM_TERMS, BASIC_WL_TERMS, BASIC_TERMS]
+            }
+        };
+        levels
+            .iter()
+            .flat_map(|terms| terms.iter().copied())
+            .collect()
+    }
+}
+
+/// Description d'un profil pour l'endpoint de découverte
+#[derive(Debug, Serialize)]
+pub struct ProfileDescription {
+    /// Valeur de `profile` dans la section `[generation]`
+    pub profile: FacturXProfile,
+    pub name: &'static str,
+    pub urn: &'static str,
+    pub required_terms: Vec<BusinessTerm>,
+}
+
+/// Décrit tous les profils Factur-X, du plus simple au plus complet
+pub fn describe_profiles() -> Vec<ProfileDescription> {
+    FacturXProfile::ALL
+        .iter()
+        .map(|profile| ProfileDescription {
+            profile: *profile,
+            name: profile.name(),
+            urn: profile.urn(),
+            required_terms: profile.required_terms(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_terms_are_cumulative() {
+        let minimum = FacturXProfile::Minimum.required_terms();
+        let basic_wl = FacturXProfile::BasicWL.required_terms();
+        let basic = FacturXProfile::Basic.required_terms();
+
+        assert!(minimum.iter().all(|t| basic_wl.contains(t)));
+        assert!(basic_wl.iter().all(|t| basic.contains(t)));
+        assert!(minimum
+            .iter()
+            .any(|t| t.id == "BT-1" && t.field == "invoice_number"));
+        assert!(!basic_wl.iter().any(|t| t.id == "BT-153"));
+        assert!(basic
+            .iter()
+            .any(|t| t.id == "BT-153" && t.field == "lines[][description]"));
+        assert_eq!(FacturXProfile::EN16931.required_terms(), basic);
+        assert_eq!(FacturXProfile::Extended.required_terms(), basic);
+    }
+
+    #[test]
+    fn test_describe_profiles_json() {
+        let profiles = describe_profiles();
+        assert_eq!(profiles.len(), 5);
+
+        let json = serde_json::to_value(&profiles).unwrap();
+        assert_eq!(json[0]["profile"], "minimum");
+        assert_eq!(json[0]["urn"], "urn:factur-x.eu:1p0:minimum");
+        assert_eq!(json[1]["profile"], "basicwl");
+        assert_eq!(json[1]["name"], "BASIC WL");
+        assert_eq!(json[0]["required_terms"][0]["id"], "BT-1");
+    }
+}
@@ -0,0 +1,413 @@
+//! Lecture et remplacement du XML Factur-X embarqué dans un PDF existant
+//!
+//! Quand seul le XML est regénéré (correction d'un champ de TVA par l'ERP),
+//! le nouveau XML remplace le flux de la pièce jointe sans redessiner la
+//! page : le PDF est chargé avec lopdf, comme pour `replace_xmp_metadata`.
+//! La pièce jointe remplacée est celle que désigne `fx:DocumentFileName` dans
+//! le XMP, pour que le XMP et le fichier embarqué restent cohérents.
+
+use super::error::FacturXError;
+use crate::models::error::FieldError;
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+use xml::name::OwnedName;
+use xml::reader::{ParserConfig, XmlEvent};
+
+/// Espace de noms de l'élément racine d'un XML Factur-X (CII)
+const CII_NAMESPACE: &str = "urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100";
+
+/// Nom local de l'élément racine d'un XML Factur-X (CII)
+const CII_ROOT_ELEMENT: &str = "CrossIndustryInvoice";
+
+/// Marque d'ordre des octets UTF-8, optionnelle en tête du XML embarqué
+pub(super) const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Extrait le XML Factur-X embarqué dans `pdf`
+///
+/// La pièce jointe lue est celle que désigne `fx:DocumentFileName` dans le
+/// XMP. Un BOM UTF-8 en tête du fichier (option `xml_bom`) est retiré.
+pub fn extract_facturx_xml(pdf: &[u8]) -> Result<String, FacturXError> {
+    let invalid = |message: String| FacturXError::Validation(vec![FieldError::new("pdf", message)]);
+
+    let doc = Document::load_mem(pdf).map_err(|e| invalid(format!("PDF illisible: {:?}", e)))?;
+    let xml_filename = xmp_document_filename(&doc).ok_or_else(|| {
+        invalid("Le XMP du PDF ne declare pas de XML Factur-X (fx:DocumentFileName)".to_string())
+    })?;
+    let stream_id = find_embedded_file(&doc, &xml_filename).ok_or_else(|| {
+        invalid(format!(
+            "Le PDF n'embarque pas le fichier '{}' declare par le XMP",
+            xml_filename
+        ))
+    })?;
+
+    let content = doc
+        .get_object(stream_id)
+        .and_then(Object::as_stream)
+        .map_err(|_| FacturXError::Pdf("La piece jointe XML n'est pas un flux".to_string()))?
+        .get_plain_content()
+        .map_err(|e| FacturXError::Pdf(format!("Erreur decompression XML: {:?}", e)))?;
+    let content = content.strip_prefix(UTF8_BOM).unwrap_or(&content);
+    String::from_utf8(content.to_vec())
+        .map_err(|_| invalid(format!("Le fichier '{}' n'est pas en UTF-8", xml_filename)))
+}
+
+/// Remplace le XML Factur-X embarqué dans `pdf` par `xml_content`
+///
+/// Le XML doit être bien formé, de racine `CrossIndustryInvoice` dans
+/// l'espace de noms CII (quel que soit son préfixe), et le PDF doit
+/// embarquer une pièce jointe du nom déclaré par le XMP. La taille et la date
+/// de modification de la pièce jointe sont mises à jour.
+///
+/// # Returns
+/// Les octets du PDF modifié
+pub fn reembed_facturx_xml(pdf: &[u8], xml_content: &str) -> Result<Vec<u8>, FacturXError> {
+    let invalid = |field: &str, message: String| {
+        FacturXError::Validation(vec![FieldError::new(field, message)])
+    };
+
+    let root = xml_root_element(xml_content)
+        .map_err(|e| invalid("xml", format!("XML mal forme: {}", e)))?;
+    if root.namespace.as_deref() != Some(CII_NAMESPACE) || root.local_name != CII_ROOT_ELEMENT {
+        return Err(invalid(
+            "xml",
+            format!(
+                "L'element racine du XML est '{}' ({} de l'espace de noms {} attendu)",
+                root, CII_ROOT_ELEMENT, CII_NAMESPACE
+            ),
+        ));
+    }
+
+    let mut doc =
+        Document::load_mem(pdf).map_err(|e| invalid("pdf", format!("PDF illisible: {:?}", e)))?;
+    let xml_filename = xmp_document_filename(&doc).ok_or_else(|| {
+        invalid(
+            "pdf",
+            "Le XMP du PDF ne declare pas de XML Factur-X (fx:DocumentFileName)".to_string(),
+        )
+    })?;
+    let stream_id = find_embedded_file(&doc, &xml_filename).ok_or_else(|| {
+        invalid(
+            "pdf",
+            format!(
+                "Le PDF n'embarque pas le fichier '{}' declare par le XMP",
+                xml_filename
+            ),
+        )
+    })?;
+
+    replace_embedded_stream(&mut doc, stream_id, xml_content.as_bytes())
+        .map_err(FacturXError::Pdf)?;
+
+    let mut output = Vec::new();
+    doc.save_to(&mut output)
+        .map_err(|e| FacturXError::Pdf(format!("Erreur sauvegarde PDF: {:?}", e)))?;
+    Ok(output)
+}
+
+/// Vérifie que le XML est bien formé et retourne le nom de son élément racine
+///
+/// Le document est lu en entier par l'`EventReader` de xml-rs (imbrication,
+/// entités, caractères interdits, racine unique) ; les DTD sont refusées.
+fn xml_root_element(xml: &str) -> Result<OwnedName, String> {
+    let mut reader = ParserConfig::new()
+        .allow_multiple_root_elements(false)
+        .create_reader(xml.trim_start_matches('\u{feff}').as_bytes());
+    let mut root = None;
+
+    loop {
+        match reader.next().map_err(|e| e.to_string())? {
+            XmlEvent::StartElement { name, .. } if root.is_none() => {
+                if reader.doctype().is_some() {
+                    return Err("declaration DOCTYPE non autorisee".to_string());
+                }
+                root = Some(name);
+            }
+            XmlEvent::EndDocument => break,
+            _ => {}
+        }
+    }
+    root.ok_or_else(|| "aucun element racine".to_string())
+}
+
+/// Suit une référence indirecte
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    doc.dereference(object).ok().map(|(_, object)| object)
+}
+
+/// Nom du fichier XML déclaré dans le XMP du catalogue (`fx:DocumentFileName`)
+fn xmp_document_filename(doc: &Document) -> Option<String> {
+    let metadata = doc.catalog().ok()?.get(b"Metadata").ok()?;
+    let stream = resolve(doc, metadata)?.as_stream().ok()?;
+    let xmp = stream.get_plain_content().ok()?;
+    let xmp = String::from_utf8_lossy(&xmp);
+
+    // Forme élément (générateur) ou attribut (autres producteurs)
+    let value = if let Some((_, after)) = xmp.split_once("<fx:DocumentFileName>") {
+        after.split_once("</fx:DocumentFileName>")?.0
+    } else {
+        let (_, after) = xmp.split_once("fx:DocumentFileName=\"")?;
+        after.split_once('"')?.0
+    };
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Flux de la pièce jointe `filename` (arbre `/EmbeddedFiles` du catalogue)
+fn find_embedded_file(doc: &Document, filename: &str) -> Option<ObjectId> {
+    let names = resolve(doc, doc.catalog().ok()?.get(b"Names").ok()?)?
+        .as_dict()
+        .ok()?;
+    let tree = resolve(doc, names.get(b"EmbeddedFiles").ok()?)?
+        .as_dict()
+        .ok()?;
+    find_in_name_tree(doc, tree, filename, 0)
+}
+
+/// Profondeur maximale parcourue dans un arbre de noms (protège des cycles)
+const MAX_NAME_TREE_DEPTH: usize = 16;
+
+fn find_in_name_tree(
+    doc: &Document,
+    node: &Dictionary,
+    filename: &str,
+    depth: usize,
+) -> Option<ObjectId> {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return None;
+    }
+
+    if let Some(entries) = node
+        .get(b"Names")
+        .ok()
+        .and_then(|names| resolve(doc, names))
+        .and_then(|names| names.as_array().ok())
+    {
+        for pair in entries.chunks(2) {
+            let [name, filespec] = pair else { continue };
+            let Some(filespec) = resolve(doc, filespec).and_then(|f| f.as_dict().ok()) else {
+                continue;
+            };
+            let matches = [
+                Some(name),
+                filespec.get(b"UF").ok(),
+                filespec.get(b"F").ok(),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|n| lopdf::decode_text_string(n).is_ok_and(|n| n == filename));
+            if !matches {
+                continue;
+            }
+            let stream_ref = resolve(doc, filespec.get(b"EF").ok()?)?
+                .as_dict()
+                .ok()?
+                .get(b"F")
+                .ok()?;
+            return stream_ref.as_reference().ok();
+        }
+    }
+
+    let kids = resolve(doc, node.get(b"Kids").ok()?)?.as_array().ok()?;
+    kids.iter()
+        .filter_map(|kid| resolve(doc, kid)?.as_dict().ok())
+        .find_map(|kid| find_in_name_tree(doc, kid, filename, depth + 1))
+}
+
+/// Remplace le contenu du flux de la pièce jointe, compressé, en mettant à
+/// jour `/Params` (taille, date de modification ; l'empreinte MD5 n'étant
+/// plus valable, elle est retirée)
+fn replace_embedded_stream(
+    doc: &mut Document,
+    stream_id: ObjectId,
+    content: &[u8],
+) -> Result<(), String> {
+    let mut params = doc
+        .get_object(stream_id)
+        .and_then(Object::as_stream)
+        .map_err(|_| "La piece jointe XML n'est pas un flux")?
+        .dict
+        .get(b"Params")
+        .ok()
+        .and_then(|params| resolve(doc, params))
+        .and_then(|params| params.as_dict().ok())
+        .cloned()
+        .unwrap_or_default();
+    params.set("Size", Object::Integer(content.len() as i64));
+    params.set(
+        "ModDate",
+        Object::String(
+            chrono::Utc::now()
+                .format("D:%Y%m%d%H%M%SZ")
+                .to_string()
+                .into_bytes(),
+            StringFormat::Literal,
+        ),
+    );
+    params.remove(b"CheckSum");
+
+    let stream = doc
+        .get_object_mut(stream_id)
+        .and_then(Object::as_stream_mut)
+        .map_err(|_| "La piece jointe XML n'est pas un flux")?;
+    stream.set_plain_content(content.to_vec());
+    stream.dict.set("Params", params);
+    stream
+        .compress()
+        .map_err(|e| format!("Erreur compression XML: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::Stream;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100"
+    xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100">
+    <ram:ID>FA &amp; 001</ram:ID><ram:Empty/>
+</rsm:CrossIndustryInvoice>"#;
+
+    /// PDF minimal : XMP Factur-X et pièce jointe `factur-x.xml`
+    fn pdf_with_attachment(xmp_filename: &str) -> Vec<u8> {
+        let mut doc = Document::with_version("1.7");
+        let xmp = format!(
+            "<x:xmpmeta><fx:DocumentFileName>{}</fx:DocumentFileName></x:xmpmeta>",
+            xmp_filename
+        );
+        let metadata_id = doc.add_object(Stream::new(Dictionary::new(), xmp.into_bytes()));
+
+        let mut params = Dictionary::new();
+        params.set("Size", Object::Integer(6));
+        params.set("CheckSum", Object::string_literal("old"));
+        let mut file_dict = Dictionary::new();
+        file_dict.set("Type", Object::Name(b"EmbeddedFile".to_vec()));
+        file_dict.set("Params", params);
+        let file_id = doc.add_object(Stream::new(file_dict, b"<old/>".to_vec()));
+
+        let mut ef = Dictionary::new();
+        ef.set("F", Object::Reference(file_id));
+        let mut filespec = Dictionary::new();
+        filespec.set("Type", Object::Name(b"Filespec".to_vec()));
+        filespec.set("UF", Object::string_literal("factur-x.xml"));
+        filespec.set("EF", ef);
+        let filespec_id = doc.add_object(filespec);
+
+        let mut embedded_files = Dictionary::new();
+        embedded_files.set(
+            "Names",
+            Object::Array(vec![
+                Object::string_literal("factur-x.xml"),
+                Object::Reference(filespec_id),
+            ]),
+        );
+        let mut names = Dictionary::new();
+        names.set("EmbeddedFiles", embedded_files);
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set("Metadata", Object::Reference(metadata_id));
+        catalog.set("Names", names);
+        catalog.set("AF", Object::Array(vec![Object::Reference(filespec_id)]));
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn validation_field(error: FacturXError) -> String {
+        match error {
+            FacturXError::Validation(errors) => errors[0].field.clone(),
+            other => panic!("erreur inattendue: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_reembed_replaces_attachment() {
+        let pdf = reembed_facturx_xml(&pdf_with_attachment("factur-x.xml"), XML).unwrap();
+
+        let doc = Document::load_mem(&pdf).unwrap();
+        let stream_id = find_embedded_file(&doc, "factur-x.xml").unwrap();
+        let stream = doc.get_object(stream_id).unwrap().as_stream().unwrap();
+        assert_eq!(stream.get_plain_content().unwrap(), XML.as_bytes());
+
+        let params = stream.dict.get(b"Params").unwrap().as_dict().unwrap();
+        assert_eq!(
+            params.get(b"Size").unwrap().as_i64().unwrap(),
+            XML.len() as i64
+        );
+        assert!(params.get(b"CheckSum").is_err());
+        assert!(params.get(b"ModDate").is_ok());
+    }
+
+    #[test]
+    fn test_extract_strips_bom() {
+        let pdf = pdf_with_attachment("factur-x.xml");
+        let with_bom = format!("\u{feff}{}", XML);
+        for xml in [XML, with_bom.as_str()] {
+            let pdf = reembed_facturx_xml(&pdf, xml).unwrap();
+            assert_eq!(extract_facturx_xml(&pdf).unwrap(), XML);
+        }
+
+        let error = extract_facturx_xml(&pdf_with_attachment("zugferd.xml")).unwrap_err();
+        assert_eq!(validation_field(error), "pdf");
+    }
+
+    #[test]
+    fn test_reembed_rejects_mismatch_and_malformed_xml() {
+        let pdf = pdf_with_attachment("factur-x.xml");
+        let ns = format!("xmlns:rsm=\"{}\"", CII_NAMESPACE);
+        for xml in [
+            format!(
+                "<rsm:CrossIndustryInvoice {}><a></b></rsm:CrossIndustryInvoice>",
+                ns
+            ),
+            format!(
+                "<rsm:CrossIndustryInvoice {}>&nbsp;</rsm:CrossIndustryInvoice>",
+                ns
+            ),
+            format!(
+                "<rsm:CrossIndustryInvoice {}>&#0;</rsm:CrossIndustryInvoice>",
+                ns
+            ),
+            format!("<rsm:CrossIndustryInvoice {}>", ns),
+            format!("<rsm:Invoice {}/>", ns),
+            // Bon nom local, mauvais espace de noms ou espace de noms absent
+            "<rsm:CrossIndustryInvoice xmlns:rsm=\"urn:autre\"/>".to_string(),
+            "<CrossIndustryInvoice/>".to_string(),
+        ] {
+            let xml = xml.as_str();
+            let error = reembed_facturx_xml(&pdf, xml).unwrap_err();
+            assert_eq!(validation_field(error), "xml", "{}", xml);
+        }
+
+        // Le XMP désigne un fichier absent des pièces jointes
+        let error = reembed_facturx_xml(&pdf_with_attachment("zugferd.xml"), XML).unwrap_err();
+        assert_eq!(validation_field(error), "pdf");
+
+        let error = reembed_facturx_xml(b"not a pdf", XML).unwrap_err();
+        assert_eq!(validation_field(error), "pdf");
+    }
+
+    #[test]
+    fn test_xml_root_element() {
+        let root = xml_root_element(XML).unwrap();
+        assert_eq!(root.namespace.as_deref(), Some(CII_NAMESPACE));
+        assert_eq!(root.local_name, CII_ROOT_ELEMENT);
+
+        // Préfixe par défaut : seul l'espace de noms compte
+        let xml = format!("<CrossIndustryInvoice xmlns=\"{}\"/>", CII_NAMESPACE);
+        let pdf = pdf_with_attachment("factur-x.xml");
+        assert!(reembed_facturx_xml(&pdf, &xml).is_ok());
+
+        assert_eq!(
+            xml_root_element("<!-- note --><a x='1 > 0'><![CDATA[<b>]]></a>")
+                .unwrap()
+                .local_name,
+            "a"
+        );
+        assert!(xml_root_element("<a/><b/>").is_err());
+        assert!(xml_root_element("<!DOCTYPE a><a/>").is_err());
+        assert!(xml_root_element("texte <a/>").is_err());
+    }
+}
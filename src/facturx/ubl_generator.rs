@@ -0,0 +1,867 @@
+//! Générateur XML UBL 2.1 (EN 16931)
+//!
+//! Produit la même facture que le XML CII Factur-X, au format OASIS UBL 2.1
+//! attendu par les acheteurs qui reçoivent leurs factures par PEPPOL. Un avoir
+//! (type 381) est émis en `CreditNote`, les autres types en `Invoice`.
+
+use super::error::FacturXError;
+use super::options::GenerationOptions;
+use super::vat_breakdown::{vat_breakdown, TaxCategory};
+use super::xml_generator::{escape_xml, seller_legal_id};
+use crate::models::invoice::{
+    InvoiceForm, InvoiceTotals, InvoiceTypeCode, VatGroupKey, VatRateKey,
+};
+use crate::models::line::format_quantity;
+use crate::{EmitterConfig, VAT_FRANCHISE_MENTION, VAT_ON_PAYMENTS_MENTION};
+use chrono::NaiveDate;
+
+/// Spécification EN 16931 déclarée dans `cbc:CustomizationID` (BT-24)
+const EN16931_CUSTOMIZATION_ID: &str = "urn:cen.eu:en16931:2017";
+
+/// Éléments propres au type de document UBL : facture ou avoir
+struct UblDocument {
+    root: &'static str,
+    type_code: &'static str,
+    line: &'static str,
+    quantity: &'static str,
+}
+
+impl UblDocument {
+    fn for_type(type_code: InvoiceTypeCode) -> Self {
+        match type_code {
+            InvoiceTypeCode::CreditNote => UblDocument {
+                root: "CreditNote",
+                type_code: "CreditNoteTypeCode",
+                line: "CreditNoteLine",
+                quantity: "CreditedQuantity",
+            },
+            _ => UblDocument {
+                root: "Invoice",
+                type_code: "InvoiceTypeCode",
+                line: "InvoiceLine",
+                quantity: "InvoicedQuantity",
+            },
+        }
+    }
+
+    fn is_credit_note(&self) -> bool {
+        self.root == "CreditNote"
+    }
+}
+
+/// Génère le XML UBL 2.1 d'une facture
+///
+/// Reprend les données du XML CII (profil BASIC) : en-tête, vendeur et
+/// acheteur, ventilation de la TVA, remise globale, totaux et lignes. Les
+/// catégories de TVA suivent les options de génération par défaut (taux
+/// zéro en catégorie Z).
+///
+/// # Arguments
+/// * `invoice` - Les données de la facture
+/// * `emitter` - Les informations de l'émetteur
+/// * `totals` - Totaux HT, TVA et TTC de la facture
+///
+/// # Returns
+/// Le XML UBL (`Invoice`, ou `CreditNote` pour un avoir)
+pub fn generate_ubl_xml(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+) -> Result<String, FacturXError> {
+    totals.ensure_finite().map_err(FacturXError::Xml)?;
+    let options = GenerationOptions::default();
+    let document = UblDocument::for_type(invoice.type_code);
+    let currency = escape_xml(&invoice.currency_code);
+    let franchise = emitter.vat_exempt_franchise;
+
+    // En-tête : dates, notes, devises et références
+    let mut header_xml = format!(
+        r#"
+    <cbc:IssueDate>{}</cbc:IssueDate>"#,
+        format_date_for_ubl(&invoice.issue_date)?
+    );
+    // L'avoir n'a pas de date d'échéance d'en-tête en UBL 2.1
+    let due_date = invoice
+        .due_date
+        .as_deref()
+        .or_else(|| invoice.installments.first().map(|i| i.due_date.as_str()))
+        .map(str::trim)
+        .filter(|date| !date.is_empty());
+    if let (Some(due_date), false) = (due_date, document.is_credit_note()) {
+        header_xml.push_str(&format!(
+            r#"
+    <cbc:DueDate>{}</cbc:DueDate>"#,
+            format_date_for_ubl(due_date)?
+        ));
+    }
+    let tax_point_date = match invoice.tax_point_date.as_deref().map(str::trim) {
+        Some(date) if !date.is_empty() => format!(
+            r#"
+    <cbc:TaxPointDate>{}</cbc:TaxPointDate>"#,
+            format_date_for_ubl(date)?
+        ),
+        _ => String::new(),
+    };
+    // L'ordre du schéma diffère : la date d'exigibilité précède le type dans
+    // un avoir et le suit (après les notes) dans une facture
+    if document.is_credit_note() {
+        header_xml.push_str(&tax_point_date);
+    }
+    header_xml.push_str(&format!(
+        r#"
+    <cbc:{tag}>{code}</cbc:{tag}>"#,
+        tag = document.type_code,
+        code = invoice.type_code.code()
+    ));
+    let mut notes = Vec::new();
+    if emitter.vat_on_payments {
+        notes.push(VAT_ON_PAYMENTS_MENTION.to_string());
+    }
+    if franchise {
+        notes.push(VAT_FRANCHISE_MENTION.to_string());
+    }
+    for note in notes {
+        header_xml.push_str(&format!(
+            r#"
+    <cbc:Note>{}</cbc:Note>"#,
+            escape_xml(&note)
+        ));
+    }
+    if !document.is_credit_note() {
+        header_xml.push_str(&tax_point_date);
+    }
+    header_xml.push_str(&format!(
+        r#"
+    <cbc:DocumentCurrencyCode>{}</cbc:DocumentCurrencyCode>"#,
+        currency
+    ));
+    let tax_currency_total = invoice.tax_currency_vat_total(totals.total_vat);
+    if let Some((tax_currency, _)) = tax_currency_total {
+        header_xml.push_str(&format!(
+            r#"
+    <cbc:TaxCurrencyCode>{}</cbc:TaxCurrencyCode>"#,
+            escape_xml(tax_currency)
+        ));
+    }
+    if let Some(reference) = invoice.effective_buyer_reference(None) {
+        header_xml.push_str(&format!(
+            r#"
+    <cbc:BuyerReference>{}</cbc:BuyerReference>"#,
+            escape_xml(reference)
+        ));
+    }
+    match invoice.purchase_order_reference.as_deref().map(str::trim) {
+        Some(order) if !order.is_empty() => header_xml.push_str(&format!(
+            r#"
+    <cac:OrderReference>
+        <cbc:ID>{}</cbc:ID>
+    </cac:OrderReference>"#,
+            escape_xml(order)
+        )),
+        _ => {}
+    }
+
+    // Vendeur (BG-4) et acheteur (BG-7)
+    let seller_vat = emitter
+        .num_tva
+        .as_deref()
+        .map(str::trim)
+        .filter(|vat| !vat.is_empty());
+    let seller_contact = contact_xml(
+        emitter.normalized_phone().as_deref(),
+        emitter
+            .email
+            .as_deref()
+            .map(str::trim)
+            .filter(|email| !email.is_empty()),
+    );
+    let seller_xml = party_xml(
+        emitter.gln.as_deref(),
+        &emitter.address,
+        "FR",
+        seller_vat,
+        &emitter.name,
+        Some((seller_legal_id(emitter), "0002")),
+        &seller_contact,
+    );
+    let buyer_vat = invoice
+        .recipient_vat_number
+        .as_deref()
+        .map(str::trim)
+        .filter(|vat| !vat.is_empty());
+    let buyer_xml = party_xml(
+        invoice.recipient_gln.as_deref(),
+        &invoice.recipient_address,
+        &invoice.recipient_country_code,
+        buyer_vat,
+        &invoice.recipient_name,
+        invoice.buyer_legal_id(),
+        "",
+    );
+
+    // Paiement : virement (BG-16) et conditions (BT-20)
+    let mut payment_xml = String::new();
+    if let Some(iban) = emitter.normalized_iban() {
+        payment_xml.push_str(&format!(
+            r#"
+    <cac:PaymentMeans>
+        <cbc:PaymentMeansCode>58</cbc:PaymentMeansCode>
+        <cac:PayeeFinancialAccount>
+            <cbc:ID>{}</cbc:ID>
+        </cac:PayeeFinancialAccount>
+    </cac:PaymentMeans>"#,
+            escape_xml(&iban)
+        ));
+    }
+    match invoice.payment_terms.as_deref().map(str::trim) {
+        Some(terms) if !terms.is_empty() => payment_xml.push_str(&format!(
+            r#"
+    <cac:PaymentTerms>
+        <cbc:Note>{}</cbc:Note>
+    </cac:PaymentTerms>"#,
+            escape_xml(terms)
+        )),
+        _ => {}
+    }
+
+    // Remise globale par taux (BG-20)
+    let percent = invoice.global_discount_percent.unwrap_or_default();
+    let mut allowances_xml = String::new();
+    for allowance in invoice.global_discount_allowances() {
+        let group = VatGroupKey {
+            rate: VatRateKey::new(allowance.vat_rate),
+            category: allowance.vat_category,
+        };
+        let category = TaxCategory::for_group(group, franchise, &options);
+        allowances_xml.push_str(&format!(
+            r#"
+    <cac:AllowanceCharge>
+        <cbc:ChargeIndicator>false</cbc:ChargeIndicator>
+        <cbc:AllowanceChargeReasonCode>95</cbc:AllowanceChargeReasonCode>
+        <cbc:AllowanceChargeReason>Remise globale</cbc:AllowanceChargeReason>
+        <cbc:MultiplierFactorNumeric>{percent:.2}</cbc:MultiplierFactorNumeric>
+        {amount}
+        {basis}{tax_category}
+    </cac:AllowanceCharge>"#,
+            percent = percent,
+            amount = amount_xml("Amount", allowance.amount, &currency),
+            basis = amount_xml("BaseAmount", allowance.basis, &currency),
+            tax_category = tax_category_xml(
+                "TaxCategory",
+                &category,
+                allowance.vat_rate,
+                false,
+                "        "
+            ),
+        ));
+    }
+
+    // Ventilation de la TVA (BG-23), puis total converti dans la devise de TVA
+    let mut subtotals_xml = String::new();
+    for group in vat_breakdown(invoice, franchise, &options) {
+        subtotals_xml.push_str(&format!(
+            r#"
+        <cac:TaxSubtotal>
+            {basis}
+            {vat}{tax_category}
+        </cac:TaxSubtotal>"#,
+            basis = amount_xml("TaxableAmount", group.basis, &currency),
+            vat = amount_xml("TaxAmount", group.vat_amount, &currency),
+            tax_category = tax_category_xml(
+                "TaxCategory",
+                &group.tax_category(),
+                group.rate.rate(),
+                true,
+                "            "
+            ),
+        ));
+    }
+    let mut tax_total_xml = format!(
+        r#"
+    <cac:TaxTotal>
+        {}{}
+    </cac:TaxTotal>"#,
+        amount_xml("TaxAmount", totals.total_vat, &currency),
+        subtotals_xml
+    );
+    if let Some((tax_currency, tax_total)) = tax_currency_total {
+        tax_total_xml.push_str(&format!(
+            r#"
+    <cac:TaxTotal>
+        {}
+    </cac:TaxTotal>"#,
+            amount_xml("TaxAmount", tax_total, &escape_xml(tax_currency))
+        ));
+    }
+
+    // Totaux (BG-22)
+    let mut monetary_total_xml = [
+        amount_xml("LineExtensionAmount", totals.total_ht, &currency),
+        amount_xml("TaxExclusiveAmount", totals.tax_basis_total(), &currency),
+        amount_xml("TaxInclusiveAmount", totals.total_ttc, &currency),
+    ]
+    .join("\n        ");
+    if totals.allowance_total > 0.0 {
+        monetary_total_xml.push_str("\n        ");
+        monetary_total_xml.push_str(&amount_xml(
+            "AllowanceTotalAmount",
+            totals.allowance_total,
+            &currency,
+        ));
+    }
+    if totals.prepaid_total > 0.0 {
+        monetary_total_xml.push_str("\n        ");
+        monetary_total_xml.push_str(&amount_xml(
+            "PrepaidAmount",
+            totals.prepaid_total,
+            &currency,
+        ));
+    }
+    monetary_total_xml.push_str("\n        ");
+    monetary_total_xml.push_str(&amount_xml(
+        "PayableAmount",
+        totals.due_payable(),
+        &currency,
+    ));
+
+    let lines_xml = generate_lines_xml(invoice, &document, franchise, &options, &currency)?;
+
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<{root} xmlns="urn:oasis:names:specification:ubl:schema:xsd:{root}-2"
+    xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2"
+    xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">
+    <cbc:CustomizationID>{customization_id}</cbc:CustomizationID>
+    <cbc:ID>{invoice_number}</cbc:ID>{header}
+    <cac:AccountingSupplierParty>{seller}
+    </cac:AccountingSupplierParty>
+    <cac:AccountingCustomerParty>{buyer}
+    </cac:AccountingCustomerParty>{payment}{allowances}{tax_total}
+    <cac:LegalMonetaryTotal>
+        {monetary_total}
+    </cac:LegalMonetaryTotal>{lines}
+</{root}>"#,
+        root = document.root,
+        customization_id = EN16931_CUSTOMIZATION_ID,
+        invoice_number = escape_xml(&invoice.invoice_number),
+        header = header_xml,
+        seller = seller_xml,
+        buyer = buyer_xml,
+        payment = payment_xml,
+        allowances = allowances_xml,
+        tax_total = tax_total_xml,
+        monetary_total = monetary_total_xml,
+        lines = lines_xml,
+    ))
+}
+
+/// Génère les lignes de la facture (BG-25) : `InvoiceLine` ou `CreditNoteLine`
+fn generate_lines_xml(
+    invoice: &InvoiceForm,
+    document: &UblDocument,
+    franchise: bool,
+    options: &GenerationOptions,
+    currency: &str,
+) -> Result<String, FacturXError> {
+    let mut xml = String::new();
+    for (index, line) in invoice.lines.iter().filter(|l| l.is_valid()).enumerate() {
+        let category = TaxCategory::for_group(VatGroupKey::of_line(line), franchise, options);
+
+        let note_xml = match line.detail_text() {
+            Some(detail) => format!(
+                r#"
+        <cbc:Note>{}</cbc:Note>"#,
+                escape_xml(detail)
+            ),
+            None => String::new(),
+        };
+
+        // Période de facturation (BG-26) et ligne du bon de commande (BT-132)
+        let mut references_xml = String::new();
+        if let Some((start, end)) = line.billing_period() {
+            references_xml.push_str(
+                r#"
+        <cac:InvoicePeriod>"#,
+            );
+            for (tag, date) in [("StartDate", start), ("EndDate", end)] {
+                if let Some(date) = date {
+                    references_xml.push_str(&format!(
+                        r#"
+            <cbc:{tag}>{date}</cbc:{tag}>"#,
+                        tag = tag,
+                        date = format_date_for_ubl(date)?
+                    ));
+                }
+            }
+            references_xml.push_str(
+                r#"
+        </cac:InvoicePeriod>"#,
+            );
+        }
+        if let Some(reference) = line.order_line_reference_text() {
+            references_xml.push_str(&format!(
+                r#"
+        <cac:OrderLineReference>
+            <cbc:LineID>{}</cbc:LineID>
+        </cac:OrderLineReference>"#,
+                escape_xml(reference)
+            ));
+        }
+
+        // Rabais de la ligne (BG-27)
+        let discount = line.discount_amount.unwrap_or(0.0);
+        let allowance_xml = if discount > 0.0 {
+            format!(
+                r#"
+        <cac:AllowanceCharge>
+            <cbc:ChargeIndicator>false</cbc:ChargeIndicator>
+            <cbc:AllowanceChargeReasonCode>95</cbc:AllowanceChargeReasonCode>
+            <cbc:AllowanceChargeReason>Rabais</cbc:AllowanceChargeReason>
+            {}
+        </cac:AllowanceCharge>"#,
+                amount_xml("Amount", discount, currency)
+            )
+        } else {
+            String::new()
+        };
+
+        // Identifiants article (BT-155 puis BT-157, ordre du schéma UBL)
+        let mut item_ids_xml = String::new();
+        if let Some(id) = line.seller_item_id_text() {
+            item_ids_xml.push_str(&format!(
+                r#"
+            <cac:SellersItemIdentification>
+                <cbc:ID>{}</cbc:ID>
+            </cac:SellersItemIdentification>"#,
+                escape_xml(id)
+            ));
+        }
+        if let Some((id, scheme)) = line.standard_item() {
+            item_ids_xml.push_str(&format!(
+                r#"
+            <cac:StandardItemIdentification>
+                <cbc:ID schemeID="{}">{}</cbc:ID>
+            </cac:StandardItemIdentification>"#,
+                escape_xml(scheme),
+                escape_xml(id)
+            ));
+        }
+
+        xml.push_str(&format!(
+            r#"
+    <cac:{line_tag}>
+        <cbc:ID>{line_id}</cbc:ID>{note}
+        <cbc:{quantity_tag} unitCode="{unit_code}">{quantity}</cbc:{quantity_tag}>
+        {total_ht}{references}{allowance}
+        <cac:Item>
+            <cbc:Name>{name}</cbc:Name>{item_ids}{tax_category}
+        </cac:Item>
+        <cac:Price>
+            {price}
+        </cac:Price>
+    </cac:{line_tag}>"#,
+            line_tag = document.line,
+            line_id = index + 1,
+            note = note_xml,
+            quantity_tag = document.quantity,
+            unit_code = escape_xml(line.unit_code()),
+            quantity = format_quantity(line.quantity),
+            total_ht = amount_xml("LineExtensionAmount", line.total_ht_value(), currency),
+            references = references_xml,
+            allowance = allowance_xml,
+            name = escape_xml(&line.description),
+            item_ids = item_ids_xml,
+            tax_category = tax_category_xml(
+                "ClassifiedTaxCategory",
+                &category,
+                line.vat_rate,
+                false,
+                "            "
+            ),
+            price = amount_xml("PriceAmount", line.unit_price_ht, currency),
+        ));
+    }
+    Ok(xml)
+}
+
+/// Partie (vendeur ou acheteur) : GLN, adresse, TVA, raison sociale,
+/// identifiant légal et contact, dans l'ordre du schéma `cac:Party`
+fn party_xml(
+    gln: Option<&str>,
+    address: &str,
+    country_code: &str,
+    vat_number: Option<&str>,
+    name: &str,
+    legal_id: Option<(&str, &str)>,
+    contact: &str,
+) -> String {
+    let mut xml = String::from(
+        r#"
+        <cac:Party>"#,
+    );
+    match gln.map(str::trim) {
+        Some(gln) if !gln.is_empty() => xml.push_str(&format!(
+            r#"
+            <cac:PartyIdentification>
+                <cbc:ID schemeID="0088">{}</cbc:ID>
+            </cac:PartyIdentification>"#,
+            escape_xml(gln)
+        )),
+        _ => {}
+    }
+    xml.push_str(&format!(
+        r#"
+            <cac:PostalAddress>
+                <cbc:StreetName>{}</cbc:StreetName>
+                <cac:Country>
+                    <cbc:IdentificationCode>{}</cbc:IdentificationCode>
+                </cac:Country>
+            </cac:PostalAddress>"#,
+        escape_xml(address),
+        escape_xml(country_code.trim())
+    ));
+    if let Some(vat_number) = vat_number {
+        xml.push_str(&format!(
+            r#"
+            <cac:PartyTaxScheme>
+                <cbc:CompanyID>{}</cbc:CompanyID>
+                <cac:TaxScheme>
+                    <cbc:ID>VAT</cbc:ID>
+                </cac:TaxScheme>
+            </cac:PartyTaxScheme>"#,
+            escape_xml(vat_number)
+        ));
+    }
+    let legal_id_xml = match legal_id {
+        Some((id, scheme)) => format!(
+            r#"
+                <cbc:CompanyID schemeID="{}">{}</cbc:CompanyID>"#,
+            scheme,
+            escape_xml(id)
+        ),
+        None => String::new(),
+    };
+    xml.push_str(&format!(
+        r#"
+            <cac:PartyLegalEntity>
+                <cbc:RegistrationName>{}</cbc:RegistrationName>{}
+            </cac:PartyLegalEntity>{}
+        </cac:Party>"#,
+        escape_xml(name),
+        legal_id_xml,
+        contact
+    ));
+    xml
+}
+
+/// Contact du vendeur (BG-6) : téléphone et e-mail, s'ils sont renseignés
+fn contact_xml(phone: Option<&str>, email: Option<&str>) -> String {
+    if phone.is_none() && email.is_none() {
+        return String::new();
+    }
+    let mut xml = String::from(
+        r#"
+            <cac:Contact>"#,
+    );
+    if let Some(phone) = phone {
+        xml.push_str(&format!(
+            r#"
+                <cbc:Telephone>{}</cbc:Telephone>"#,
+            escape_xml(phone)
+        ));
+    }
+    if let Some(email) = email {
+        xml.push_str(&format!(
+            r#"
+                <cbc:ElectronicMail>{}</cbc:ElectronicMail>"#,
+            escape_xml(email)
+        ));
+    }
+    xml.push_str(
+        r#"
+            </cac:Contact>"#,
+    );
+    xml
+}
+
+/// Catégorie de TVA (`TaxCategory` ou `ClassifiedTaxCategory`) indentée de
+/// `indent` ; le motif d'exonération n'est émis que dans la ventilation, et
+/// la catégorie O (hors champ) ne porte pas de taux
+fn tax_category_xml(
+    element: &str,
+    category: &TaxCategory,
+    rate: f64,
+    with_exemption_reason: bool,
+    indent: &str,
+) -> String {
+    let mut xml = format!(
+        "\n{indent}<cac:{element}>\n{indent}    <cbc:ID>{code}</cbc:ID>",
+        indent = indent,
+        element = element,
+        code = category.code
+    );
+    if category.code != "O" {
+        xml.push_str(&format!(
+            "\n{}    <cbc:Percent>{:.2}</cbc:Percent>",
+            indent, rate
+        ));
+    }
+    if with_exemption_reason {
+        if let Some(code) = category.exemption_reason_code {
+            xml.push_str(&format!(
+                "\n{}    <cbc:TaxExemptionReasonCode>{}</cbc:TaxExemptionReasonCode>",
+                indent, code
+            ));
+        }
+        if let Some(reason) = &category.exemption_reason {
+            xml.push_str(&format!(
+                "\n{}    <cbc:TaxExemptionReason>{}</cbc:TaxExemptionReason>",
+                indent,
+                escape_xml(reason)
+            ));
+        }
+    }
+    xml.push_str(&format!(
+        "\n{indent}    <cac:TaxScheme>\n{indent}        <cbc:ID>VAT</cbc:ID>\n{indent}    </cac:TaxScheme>\n{indent}</cac:{element}>",
+        indent = indent,
+        element = element
+    ));
+    xml
+}
+
+/// Montant UBL : tous les montants portent leur devise (`currencyID`)
+fn amount_xml(tag: &str, amount: f64, currency: &str) -> String {
+    format!(
+        r#"<cbc:{tag} currencyID="{currency}">{amount:.2}</cbc:{tag}>"#,
+        tag = tag,
+        currency = currency,
+        amount = amount
+    )
+}
+
+/// Date UBL (YYYY-MM-DD), contrôlée
+fn format_date_for_ubl(date: &str) -> Result<String, FacturXError> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .map_err(|_| FacturXError::Xml(format!("Format de date invalide: {}", date)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xml::reader::{EventReader, XmlEvent};
+
+    fn test_emitter() -> EmitterConfig {
+        toml::from_str(
+            r#"
+            siren = "123456789"
+            siret = "12345678900012"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            num_tva = "FR40303265045"
+            iban = "FR7630006000011234567890189"
+            email = "contact@entreprise.fr"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn test_invoice(type_code: u16) -> InvoiceForm {
+        InvoiceForm::from_json(&format!(
+            r#"{{
+                "invoice_number": "FA-2024-001",
+                "issue_date": "2024-01-15",
+                "due_date": "2024-02-14",
+                "type_code": {},
+                "currency_code": "EUR",
+                "purchase_order_reference": "PO-17",
+                "recipient_name": "Client SA",
+                "recipient_siret": "98765432100019",
+                "recipient_address": "1 avenue du Client, 69001 Lyon",
+                "recipient_country_code": "FR",
+                "global_discount_percent": 10,
+                "lines": [
+                    {{"description": "Audit", "quantity": 2, "unit_price_ht": 100, "vat_rate": 20,
+                      "discount_value": 10, "discount_type": "amount"}},
+                    {{"description": "Livre", "quantity": 1, "unit_price_ht": 50, "vat_rate": 5.5}}
+                ]
+            }}"#,
+            type_code
+        ))
+        .unwrap()
+    }
+
+    /// Chemins des éléments du XML (`Invoice/cac:TaxTotal/cbc:TaxAmount`)
+    /// avec leur texte ; échoue si le XML est mal formé
+    fn elements(xml: &str) -> Vec<(String, String)> {
+        let mut path: Vec<String> = Vec::new();
+        let mut elements = Vec::new();
+        for event in EventReader::from_str(xml) {
+            match event.unwrap() {
+                XmlEvent::StartElement { name, .. } => {
+                    path.push(match name.prefix {
+                        Some(prefix) => format!("{}:{}", prefix, name.local_name),
+                        None => name.local_name,
+                    });
+                    elements.push((path.join("/"), String::new()));
+                }
+                XmlEvent::Characters(text) => elements.last_mut().unwrap().1 = text,
+                XmlEvent::EndElement { .. } => {
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+        elements
+    }
+
+    fn text<'a>(elements: &'a [(String, String)], path: &str) -> Vec<&'a str> {
+        elements
+            .iter()
+            .filter(|(p, _)| p == path)
+            .map(|(_, text)| text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_ubl_invoice() {
+        let mut invoice = test_invoice(380);
+        let totals = invoice.compute_totals();
+        let xml = generate_ubl_xml(&invoice, &test_emitter(), totals).unwrap();
+        let elements = elements(&xml);
+
+        assert!(xml.contains(
+            r#"<Invoice xmlns="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2""#
+        ));
+        assert_eq!(text(&elements, "Invoice/cbc:ID"), ["FA-2024-001"]);
+        assert_eq!(text(&elements, "Invoice/cbc:IssueDate"), ["2024-01-15"]);
+        assert_eq!(text(&elements, "Invoice/cbc:DueDate"), ["2024-02-14"]);
+        assert_eq!(text(&elements, "Invoice/cbc:InvoiceTypeCode"), ["380"]);
+        assert_eq!(
+            text(&elements, "Invoice/cac:OrderReference/cbc:ID"),
+            ["PO-17"]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:AccountingSupplierParty/cac:Party/cac:PartyTaxScheme/cbc:CompanyID"
+            ),
+            ["FR40303265045"]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:AccountingCustomerParty/cac:Party/cac:PartyLegalEntity/cbc:CompanyID"
+            ),
+            ["98765432100019"]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:PaymentMeans/cac:PayeeFinancialAccount/cbc:ID"
+            ),
+            ["FR7630006000011234567890189"]
+        );
+
+        // Une remise globale et un sous-total de TVA par taux
+        assert_eq!(
+            text(&elements, "Invoice/cac:AllowanceCharge/cbc:Amount"),
+            ["5.00", "19.00"]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:TaxTotal/cac:TaxSubtotal/cac:TaxCategory/cbc:Percent"
+            ),
+            ["5.50", "20.00"]
+        );
+        assert_eq!(
+            text(&elements, "Invoice/cac:TaxTotal/cbc:TaxAmount"),
+            [format!("{:.2}", totals.total_vat).as_str()]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:LegalMonetaryTotal/cbc:PayableAmount"
+            ),
+            [format!("{:.2}", totals.due_payable()).as_str()]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:LegalMonetaryTotal/cbc:LineExtensionAmount"
+            ),
+            ["240.00"]
+        );
+
+        // Lignes : quantité, rabais, montant net et prix
+        assert_eq!(
+            text(&elements, "Invoice/cac:InvoiceLine/cbc:ID"),
+            ["1", "2"]
+        );
+        assert_eq!(
+            text(&elements, "Invoice/cac:InvoiceLine/cbc:LineExtensionAmount"),
+            ["190.00", "50.00"]
+        );
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:InvoiceLine/cac:AllowanceCharge/cbc:Amount"
+            ),
+            ["10.00"]
+        );
+        assert_eq!(
+            text(&elements, "Invoice/cac:InvoiceLine/cac:Item/cbc:Name"),
+            ["Audit", "Livre"]
+        );
+    }
+
+    #[test]
+    fn test_ubl_credit_note() {
+        let mut invoice = test_invoice(381);
+        let totals = invoice.compute_totals();
+        let xml = generate_ubl_xml(&invoice, &test_emitter(), totals).unwrap();
+        let elements = elements(&xml);
+
+        assert_eq!(
+            text(&elements, "CreditNote/cbc:CreditNoteTypeCode"),
+            ["381"]
+        );
+        assert!(text(&elements, "CreditNote/cbc:DueDate").is_empty());
+        assert_eq!(
+            text(
+                &elements,
+                "CreditNote/cac:CreditNoteLine/cbc:CreditedQuantity"
+            ),
+            ["2", "1"]
+        );
+    }
+
+    #[test]
+    fn test_ubl_vat_franchise() {
+        let mut invoice = test_invoice(380);
+        invoice.apply_vat_franchise();
+        let mut emitter = test_emitter();
+        emitter.num_tva = None;
+        emitter.vat_exempt_franchise = true;
+        let totals = invoice.compute_totals();
+        let xml = generate_ubl_xml(&invoice, &emitter, totals).unwrap();
+        let elements = elements(&xml);
+
+        assert_eq!(text(&elements, "Invoice/cbc:Note"), [VAT_FRANCHISE_MENTION]);
+        assert!(!xml.contains("<cac:PartyTaxScheme>"));
+        assert_eq!(
+            text(
+                &elements,
+                "Invoice/cac:TaxTotal/cac:TaxSubtotal/cac:TaxCategory/cbc:TaxExemptionReasonCode"
+            ),
+            ["VATEX-FR-FRANCHISE"]
+        );
+
+        invoice.issue_date = "15/01/2024".to_string();
+        assert!(matches!(
+            generate_ubl_xml(&invoice, &emitter, totals),
+            Err(FacturXError::Xml(_))
+        ));
+    }
+}
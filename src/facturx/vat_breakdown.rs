@@ -0,0 +1,221 @@
+//! Ventilation de la TVA (BG-23) partagée par le XML et le PDF
+//!
+//! Les lignes sont regroupées par catégorie, taux et motif d'exonération : deux
+//! lignes à 20 % de catégories différentes (taux normal, autoliquidation)
+//! donnent deux groupes distincts, dans le XML comme dans le récapitulatif
+//! imprimé.
+
+use super::options::{GenerationOptions, ZeroRateCategory};
+use crate::models::invoice::{InvoiceForm, VatGroupKey, VatRateKey};
+use crate::VAT_FRANCHISE_MENTION;
+use std::collections::BTreeMap;
+
+/// Catégorie de TVA d'un taux (BT-118, BT-151) et son motif d'exonération
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct TaxCategory {
+    pub(super) code: &'static str,
+    /// BT-120 : motif d'exonération (catégories E, O, AE, K et G)
+    pub(super) exemption_reason: Option<String>,
+    /// BT-121 : code VATEX du motif
+    pub(super) exemption_reason_code: Option<&'static str>,
+}
+
+impl TaxCategory {
+    /// Catégorie d'un groupe de TVA : E en franchise en base, la catégorie
+    /// imposée par la ligne (BT-151) s'il y en a une, `zero_rate_category`
+    /// pour un taux nul, S (taux normal) sinon
+    pub(super) fn for_group(
+        group: VatGroupKey,
+        vat_exempt_franchise: bool,
+        options: &GenerationOptions,
+    ) -> Self {
+        if vat_exempt_franchise {
+            return TaxCategory {
+                code: "E",
+                exemption_reason: Some(VAT_FRANCHISE_MENTION.to_string()),
+                exemption_reason_code: Some("VATEX-FR-FRANCHISE"),
+            };
+        }
+        let zero_rate_category = match group.category {
+            Some("Z") => ZeroRateCategory::Z,
+            Some("E") => ZeroRateCategory::E,
+            Some("O") => ZeroRateCategory::O,
+            Some(code) => return Self::imposed(code),
+            None if group.rate.rate() != 0.0 => return Self::imposed("S"),
+            None => options.zero_rate_category,
+        };
+        let exemption_reason = zero_rate_category
+            .default_exemption_reason()
+            .map(|default| {
+                options
+                    .zero_rate_exemption_reason
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|reason| !reason.is_empty())
+                    .unwrap_or(default)
+                    .to_string()
+            });
+        TaxCategory {
+            code: zero_rate_category.code(),
+            exemption_reason,
+            exemption_reason_code: zero_rate_category.exemption_reason_code(),
+        }
+    }
+
+    /// Catégories sans motif configurable : S, L et M n'en portent pas, AE,
+    /// K et G ont leur motif VATEX
+    fn imposed(code: &'static str) -> Self {
+        let (exemption_reason, exemption_reason_code) = match code {
+            "AE" => (Some("Autoliquidation"), Some("VATEX-EU-AE")),
+            "K" => (
+                Some("Livraison intracommunautaire exonérée"),
+                Some("VATEX-EU-IC"),
+            ),
+            "G" => (Some("Exportation hors UE"), Some("VATEX-EU-G")),
+            _ => (None, None),
+        };
+        TaxCategory {
+            code,
+            exemption_reason: exemption_reason.map(str::to_string),
+            exemption_reason_code,
+        }
+    }
+
+    /// Taux (BT-119, BT-152) indenté de `indent` ; la catégorie O (hors
+    /// champ) n'en porte pas (règles BR-O)
+    pub(super) fn rate_xml(&self, rate: f64, indent: &str) -> String {
+        if self.code == "O" {
+            return String::new();
+        }
+        format!(
+            "\n{}<ram:RateApplicablePercent>{:.2}</ram:RateApplicablePercent>",
+            indent, rate
+        )
+    }
+}
+
+/// Groupe de la ventilation TVA (BG-23)
+#[derive(Debug, Clone, PartialEq)]
+pub struct VatBreakdown {
+    /// BT-118 : Code de catégorie de TVA
+    pub category: &'static str,
+    /// BT-119 : Taux de TVA
+    pub rate: VatRateKey,
+    /// BT-120 : Motif d'exonération
+    pub exemption_reason: Option<String>,
+    /// BT-121 : Code VATEX du motif
+    pub exemption_reason_code: Option<&'static str>,
+    /// BT-116 : Base imposable, remise globale déduite
+    pub basis: f64,
+    /// BT-117 : Montant de TVA
+    pub vat_amount: f64,
+}
+
+impl VatBreakdown {
+    pub(super) fn tax_category(&self) -> TaxCategory {
+        TaxCategory {
+            code: self.category,
+            exemption_reason: self.exemption_reason.clone(),
+            exemption_reason_code: self.exemption_reason_code,
+        }
+    }
+}
+
+/// Ventilation de la TVA d'une facture, par taux croissant puis catégorie
+///
+/// Chaque groupe de lignes (taux et catégorie imposée, voir [`VatGroupKey`])
+/// est résolu en catégorie (franchise en base, `zero_rate_category`), puis
+/// les groupes de même catégorie, taux et motif sont cumulés. La remise
+/// globale réduit la base et la TVA du groupe auquel elle est imputée. À
+/// appeler après le calcul des totaux.
+pub fn vat_breakdown(
+    invoice: &InvoiceForm,
+    vat_exempt_franchise: bool,
+    options: &GenerationOptions,
+) -> Vec<VatBreakdown> {
+    let mut groups: BTreeMap<(VatRateKey, &'static str, Option<String>), VatBreakdown> =
+        BTreeMap::new();
+    let mut add = |group: VatGroupKey, basis: f64, vat_amount: f64| {
+        let category = TaxCategory::for_group(group, vat_exempt_franchise, options);
+        let entry = groups
+            .entry((group.rate, category.code, category.exemption_reason.clone()))
+            .or_insert_with(|| VatBreakdown {
+                category: category.code,
+                rate: group.rate,
+                exemption_reason: category.exemption_reason,
+                exemption_reason_code: category.exemption_reason_code,
+                basis: 0.0,
+                vat_amount: 0.0,
+            });
+        entry.basis += basis;
+        entry.vat_amount += vat_amount;
+    };
+
+    for line in invoice.lines.iter().filter(|l| l.is_valid()) {
+        add(
+            VatGroupKey::of_line(line),
+            line.total_ht_value(),
+            line.total_vat_value(),
+        );
+    }
+    for allowance in invoice.global_discount_allowances() {
+        let group = VatGroupKey {
+            rate: VatRateKey::new(allowance.vat_rate),
+            category: allowance.vat_category,
+        };
+        add(group, -allowance.amount, -allowance.vat_amount());
+    }
+
+    groups.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::line::InvoiceLine;
+
+    fn line(vat_rate: f64, vat_category: Option<&str>) -> InvoiceLine {
+        InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            vat_rate,
+            vat_category: vat_category.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_vat_breakdown_groups_by_category() {
+        let mut invoice = InvoiceForm::sample(vec![
+            line(20.0, None),
+            line(20.0, Some("s")),
+            line(0.0, None),
+            line(0.0, Some("Z")),
+            line(0.0, Some("AE")),
+            line(0.0, Some("K")),
+        ]);
+        invoice.compute_totals();
+
+        let breakdown = vat_breakdown(&invoice, false, &GenerationOptions::default());
+        let groups: Vec<(&str, String, f64)> = breakdown
+            .iter()
+            .map(|group| (group.category, group.rate.to_string(), group.basis))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("AE", "0.0".to_string(), 100.0),
+                ("K", "0.0".to_string(), 100.0),
+                ("Z", "0.0".to_string(), 200.0),
+                ("S", "20.0".to_string(), 200.0),
+            ]
+        );
+        assert_eq!(breakdown[0].exemption_reason_code, Some("VATEX-EU-AE"));
+        assert_eq!(breakdown[1].exemption_reason_code, Some("VATEX-EU-IC"));
+
+        let franchise = vat_breakdown(&invoice, true, &GenerationOptions::default());
+        assert_eq!(franchise.len(), 2);
+        assert!(franchise.iter().all(|group| group.category == "E"));
+    }
+}
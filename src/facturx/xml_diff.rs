@@ -0,0 +1,190 @@
+//! Comparaison de deux XML élément par élément
+//!
+//! Outil de test : après une modification du générateur, `diff_xml` liste ce
+//! qui a changé entre deux XML Factur-X (fichiers de référence, deux
+//! exécutions) sans tenir compte de l'indentation.
+
+use std::collections::BTreeMap;
+use xml::name::OwnedName;
+use xml::reader::{ParserConfig, XmlEvent};
+
+/// Élément XML simplifié : nom qualifié, attributs, texte et enfants
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: BTreeMap<String, String>,
+    text: String,
+    children: Vec<Element>,
+}
+
+/// Compare deux XML et liste leurs différences
+///
+/// Les espaces non significatifs (indentation, retours à la ligne) et les
+/// commentaires sont ignorés, l'ordre des attributs aussi. Les éléments de
+/// même nom sont appariés dans l'ordre d'apparition ; chaque différence est
+/// préfixée du chemin de l'élément (ex. `/rsm:CrossIndustryInvoice/
+/// rsm:ExchangedDocument/ram:ID`, avec `[n]` pour les éléments répétés).
+///
+/// # Returns
+/// Les différences constatées (vide si les deux XML sont équivalents), ou
+/// l'erreur d'analyse si l'un des deux n'est pas un XML bien formé
+pub fn diff_xml(a: &str, b: &str) -> Vec<String> {
+    let (a, b) = match (parse(a), parse(b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (a, b) => {
+            return [("A", a.err()), ("B", b.err())]
+                .into_iter()
+                .filter_map(|(side, error)| error.map(|e| format!("XML {} illisible: {}", side, e)))
+                .collect();
+        }
+    };
+
+    let mut differences = Vec::new();
+    if a.name != b.name {
+        differences.push(format!("/: racine '{}' -> '{}'", a.name, b.name));
+    } else {
+        diff_element(&format!("/{}", a.name), &a, &b, &mut differences);
+    }
+    differences
+}
+
+fn diff_element(path: &str, a: &Element, b: &Element, differences: &mut Vec<String>) {
+    for (name, value) in &a.attributes {
+        match b.attributes.get(name) {
+            None => differences.push(format!("{}/@{}: attribut supprime", path, name)),
+            Some(other) if other != value => {
+                differences.push(format!("{}/@{}: '{}' -> '{}'", path, name, value, other))
+            }
+            Some(_) => {}
+        }
+    }
+    for name in b.attributes.keys() {
+        if !a.attributes.contains_key(name) {
+            differences.push(format!("{}/@{}: attribut ajoute", path, name));
+        }
+    }
+
+    if a.text != b.text {
+        differences.push(format!("{}: '{}' -> '{}'", path, a.text, b.text));
+    }
+
+    // Appariement des enfants par nom, dans l'ordre d'apparition
+    let mut names: Vec<&str> = Vec::new();
+    for child in a.children.iter().chain(&b.children) {
+        if !names.contains(&child.name.as_str()) {
+            names.push(&child.name);
+        }
+    }
+    for name in names {
+        let a_children: Vec<&Element> = a.children.iter().filter(|c| c.name == name).collect();
+        let b_children: Vec<&Element> = b.children.iter().filter(|c| c.name == name).collect();
+        let repeated = a_children.len().max(b_children.len()) > 1;
+        for index in 0..a_children.len().max(b_children.len()) {
+            let child_path = if repeated {
+                format!("{}/{}[{}]", path, name, index + 1)
+            } else {
+                format!("{}/{}", path, name)
+            };
+            match (a_children.get(index), b_children.get(index)) {
+                (Some(a), Some(b)) => diff_element(&child_path, a, b, differences),
+                (Some(_), None) => differences.push(format!("{}: element supprime", child_path)),
+                (None, Some(_)) => differences.push(format!("{}: element ajoute", child_path)),
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// Nom tel qu'écrit dans le document (`ram:ID`)
+fn qualified_name(name: &OwnedName) -> String {
+    match &name.prefix {
+        Some(prefix) => format!("{}:{}", prefix, name.local_name),
+        None => name.local_name.clone(),
+    }
+}
+
+fn parse(xml: &str) -> Result<Element, String> {
+    let reader = ParserConfig::new()
+        .trim_whitespace(true)
+        .ignore_comments(true)
+        .cdata_to_characters(true)
+        .coalesce_characters(true)
+        .create_reader(xml.as_bytes());
+
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+    for event in reader {
+        match event.map_err(|e| e.to_string())? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: qualified_name(&name),
+                attributes: attributes
+                    .into_iter()
+                    .map(|attribute| (qualified_name(&attribute.name), attribute.value))
+                    .collect(),
+                ..Default::default()
+            }),
+            XmlEvent::Characters(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or("Balise fermante inattendue")?;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            _ => {}
+        }
+    }
+    root.ok_or_else(|| "Aucun element racine".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFERENCE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rsm:Invoice xmlns:rsm="urn:rsm" xmlns:ram="urn:ram">
+    <ram:ID>FA-001</ram:ID>
+    <ram:Amount currencyID="EUR">100.00</ram:Amount>
+    <ram:Note>Premiere</ram:Note>
+    <ram:Note>Seconde</ram:Note>
+</rsm:Invoice>"#;
+
+    #[test]
+    fn test_diff_xml_ignores_whitespace_and_attribute_order() {
+        let compact = r#"<rsm:Invoice xmlns:ram="urn:ram" xmlns:rsm="urn:rsm"><ram:ID>FA-001</ram:ID><!-- total --><ram:Amount currencyID="EUR">100.00</ram:Amount><ram:Note>Premiere</ram:Note><ram:Note>Seconde</ram:Note></rsm:Invoice>"#;
+        assert_eq!(diff_xml(REFERENCE, compact), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_diff_xml_reports_differences() {
+        let changed = r#"<rsm:Invoice xmlns:rsm="urn:rsm" xmlns:ram="urn:ram">
+            <ram:ID>FA-002</ram:ID>
+            <ram:Amount currencyID="USD" schemeID="X">100.00</ram:Amount>
+            <ram:Note>Premiere</ram:Note>
+            <ram:DueDate>2024-02-15</ram:DueDate>
+        </rsm:Invoice>"#;
+        assert_eq!(
+            diff_xml(REFERENCE, changed),
+            vec![
+                "/rsm:Invoice/ram:ID: 'FA-001' -> 'FA-002'",
+                "/rsm:Invoice/ram:Amount/@currencyID: 'EUR' -> 'USD'",
+                "/rsm:Invoice/ram:Amount/@schemeID: attribut ajoute",
+                "/rsm:Invoice/ram:Note[2]: element supprime",
+                "/rsm:Invoice/ram:DueDate: element ajoute",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_xml_reports_malformed_input() {
+        let differences = diff_xml(REFERENCE, "<rsm:Invoice><ram:ID>");
+        assert_eq!(differences.len(), 1);
+        assert!(differences[0].starts_with("XML B illisible"));
+    }
+}
@@ -1,64 +1,155 @@
 //! Générateur XML Factur-X conforme au standard CII UN/CEFACT
 //!
-//! Génère un document XML conforme au profil MINIMUM de Factur-X.
+//! Génère un document XML conforme au profil MINIMUM de Factur-X, ou BASIC WL
+//! et BASIC selon les [`GenerationOptions`].
 
-use crate::models::invoice::InvoiceForm;
-use crate::EmitterConfig;
+use super::options::GenerationOptions;
+use super::vat_breakdown::{vat_breakdown, TaxCategory};
+use super::xmp_metadata::FacturXProfile;
+use crate::models::invoice::{InvoiceForm, InvoiceTotals, VatGroupKey, VatRateKey};
+use crate::models::line::format_quantity;
+use crate::models::validation::is_xml_char;
+use crate::{
+    EmitterConfig, FREE_OF_CHARGE_MENTION, PAID_MENTION, VAT_FRANCHISE_MENTION,
+    VAT_ON_PAYMENTS_MENTION,
+};
+
+/// Espace de noms `ram:` des agrégats CII
+const RAM_NAMESPACE: &str =
+    "urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100";
+
+/// Emplacement du schéma CII D16B déclaré par `xsi:schemaLocation`
+const CII_SCHEMA_LOCATION: &str =
+    "urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100 CrossIndustryInvoice_100pD16B.xsd";
 
 /// Génère le XML Factur-X (profil MINIMUM) pour une facture
 ///
 /// # Arguments
 /// * `invoice` - Les données de la facture
 /// * `emitter` - Les informations de l'émetteur
-/// * `totals` - Tuple (total_ht, total_vat, total_ttc)
+/// * `totals` - Totaux HT, TVA et TTC de la facture
 ///
 /// # Returns
 /// Le XML Factur-X en tant que String
 pub fn generate_facturx_xml(
     invoice: &InvoiceForm,
     emitter: &EmitterConfig,
-    totals: (f64, f64, f64),
+    totals: InvoiceTotals,
 ) -> Result<String, String> {
-    let (total_ht, total_vat, total_ttc) = totals;
+    generate_facturx_xml_with_options(invoice, emitter, totals, &GenerationOptions::default())
+}
+
+/// Génère le XML Factur-X dans le profil demandé par `options.profile`
+///
+/// Les lignes de facture sont émises à partir de BASIC, l'objet facturé
+/// (BT-18) à partir d'EN 16931, les documents référencés et le lieu
+/// d'expédition en EXTENDED.
+pub fn generate_facturx_xml_with_options(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    options: &GenerationOptions,
+) -> Result<String, String> {
+    options.validate()?;
+    totals.ensure_finite()?;
+    let profile = options.profile;
+
+    let InvoiceTotals {
+        total_ht,
+        total_vat,
+        total_ttc,
+        allowance_total,
+        prepaid_total,
+    } = totals;
 
     // Formater la date d'émission (YYYYMMDD pour Factur-X)
     let issue_date_formatted = format_date_for_facturx(&invoice.issue_date)?;
 
-    // Formater la date d'échéance si présente
-    let due_date_xml = if let Some(ref due_date) = invoice.due_date {
-        if !due_date.is_empty() {
-            let due_date_formatted = format_date_for_facturx(due_date)?;
-            format!(
+    // Conditions de paiement : en EXTENDED, une échéance par versement si un
+    // échéancier est fourni (les échéances multiples et PartialPaymentAmount
+    // n'existent que dans ce profil) ; sinon la date d'échéance unique, à défaut
+    // celle du premier versement
+    let due_date_xml = if profile == FacturXProfile::Extended && !invoice.installments.is_empty() {
+        let mut terms = String::new();
+        for installment in &invoice.installments {
+            let description_xml = match installment.description {
+                Some(ref description) if !description.is_empty() => format!(
+                    r#"
+                        <ram:Description>{}</ram:Description>"#,
+                    escape_xml(description)
+                ),
+                _ => String::new(),
+            };
+            terms.push_str(&format!(
+                r#"
+                    <ram:SpecifiedTradePaymentTerms>{description}
+                        <ram:DueDateDateTime>
+                            <udt:DateTimeString format="102">{due_date}</udt:DateTimeString>
+                        </ram:DueDateDateTime>
+                        <ram:PartialPaymentAmount>{amount:.2}</ram:PartialPaymentAmount>
+                    </ram:SpecifiedTradePaymentTerms>"#,
+                description = description_xml,
+                due_date = format_date_for_facturx(&installment.due_date)?,
+                amount = installment.amount,
+            ));
+        }
+        terms
+    } else {
+        let due_date = invoice
+            .due_date
+            .as_deref()
+            .filter(|date| !date.is_empty())
+            .or_else(|| {
+                invoice
+                    .installments
+                    .first()
+                    .map(|installment| installment.due_date.as_str())
+            });
+        match due_date {
+            Some(due_date) => format!(
                 r#"
                     <ram:SpecifiedTradePaymentTerms>
                         <ram:DueDateDateTime>
                             <udt:DateTimeString format="102">{}</udt:DateTimeString>
                         </ram:DueDateDateTime>
                     </ram:SpecifiedTradePaymentTerms>"#,
-                due_date_formatted
-            )
-        } else {
-            String::new()
+                format_date_for_facturx(due_date)?
+            ),
+            None => String::new(),
         }
-    } else {
-        String::new()
     };
 
-    // Numéro TVA de l'émetteur
-    let seller_vat_xml = if let Some(ref num_tva) = emitter.num_tva {
-        if !num_tva.is_empty() {
-            format!(
-                r#"
+    let seller_legal_id = seller_legal_id(emitter);
+
+    // Contact du vendeur (téléphone normalisé)
+    let seller_contact_xml = match emitter.normalized_phone() {
+        Some(phone) => format!(
+            r#"
+                <ram:DefinedTradeContact>
+                    <ram:TelephoneUniversalCommunication>
+                        <ram:CompleteNumber>{}</ram:CompleteNumber>
+                    </ram:TelephoneUniversalCommunication>
+                </ram:DefinedTradeContact>"#,
+            phone
+        ),
+        None => String::new(),
+    };
+
+    // GLN de l'émetteur et du destinataire (schéma 0088)
+    let seller_gln_xml = global_id_xml(emitter.gln.as_deref());
+    let buyer_gln_xml = global_id_xml(invoice.recipient_gln.as_deref());
+
+    // Numéro TVA de l'émetteur ; un numéro blanc (émetteur en franchise en
+    // base sans numéro) n'émet aucun enregistrement fiscal
+    let seller_vat_xml = match emitter.num_tva.as_deref().map(str::trim) {
+        Some(num_tva) if !num_tva.is_empty() => format!(
+            r#"
                         <ram:SpecifiedTaxRegistration>
                             <ram:ID schemeID="VA">{}</ram:ID>
                         </ram:SpecifiedTaxRegistration>"#,
-                escape_xml(num_tva)
-            )
-        } else {
-            String::new()
-        }
-    } else {
-        String::new()
+            escape_xml(num_tva)
+        ),
+        _ => String::new(),
     };
 
     // Numéro TVA du destinataire
@@ -78,17 +169,28 @@ pub fn generate_facturx_xml(
         String::new()
     };
 
-    // Référence acheteur
-    let buyer_reference_xml = if let Some(ref buyer_ref) = invoice.buyer_reference {
-        if !buyer_ref.is_empty() {
-            format!(
-                r#"
+    // Identifiant légal de l'acheteur : SIRET pour un acheteur français uniquement
+    let buyer_legal_org_xml = match invoice.buyer_legal_id() {
+        Some((id, scheme)) => format!(
+            r#"
+                <ram:SpecifiedLegalOrganization>
+                    <ram:ID schemeID="{}">{}</ram:ID>
+                </ram:SpecifiedLegalOrganization>"#,
+            scheme,
+            escape_xml(id)
+        ),
+        None => String::new(),
+    };
+
+    // Référence acheteur (code service ou défaut configuré pour un destinataire public)
+    let buyer_reference_xml = if let Some(buyer_ref) =
+        invoice.effective_buyer_reference(options.default_buyer_reference.as_deref())
+    {
+        format!(
+            r#"
                     <ram:BuyerReference>{}</ram:BuyerReference>"#,
-                escape_xml(buyer_ref)
-            )
-        } else {
-            String::new()
-        }
+            escape_xml(buyer_ref)
+        )
     } else {
         String::new()
     };
@@ -110,19 +212,100 @@ pub fn generate_facturx_xml(
         String::new()
     };
 
+    let additional_references_xml = generate_additional_references_xml(invoice, profile);
+
+    let notes_xml = generate_notes_xml(invoice, emitter, totals, options);
+
+    // Montant déjà payé (BT-113), émis seulement s'il est non nul
+    let prepaid_xml = if prepaid_total > 0.0 {
+        format!(
+            r#"
+                <ram:TotalPrepaidAmount>{:.2}</ram:TotalPrepaidAmount>"#,
+            prepaid_total
+        )
+    } else {
+        String::new()
+    };
+
+    // Lignes de facture (BASIC)
+    let line_items_xml = if profile.has_line_items() {
+        generate_line_items_xml(invoice, emitter.vat_exempt_franchise, options)?
+    } else {
+        String::new()
+    };
+
+    // Devise de TVA (BT-6) et total TVA converti (BT-111)
+    let (tax_currency_xml, tax_currency_total_xml) = match invoice.tax_currency_vat_total(total_vat)
+    {
+        Some((tax_currency, tax_total)) => (
+            format!(
+                r#"
+            <ram:TaxCurrencyCode>{}</ram:TaxCurrencyCode>"#,
+                escape_xml(tax_currency)
+            ),
+            format!(
+                r#"
+                <ram:TaxTotalAmount currencyID="{}">{:.2}</ram:TaxTotalAmount>"#,
+                escape_xml(tax_currency),
+                tax_total
+            ),
+        ),
+        None => (String::new(), String::new()),
+    };
+
     // Générer le récapitulatif TVA par taux
-    let vat_breakdown_xml = generate_vat_breakdown_xml(invoice, &invoice.currency_code);
+    // Date d'exigibilité de la TVA (BT-7), absente du profil MINIMUM
+    let tax_point_date = match invoice.tax_point_date.as_deref().map(str::trim) {
+        Some(date) if !date.is_empty() && profile != FacturXProfile::Minimum => {
+            Some(format_date_for_facturx(date)?)
+        }
+        _ => None,
+    };
+    let vat_breakdown_xml = generate_vat_breakdown_xml(
+        invoice,
+        emitter.vat_exempt_franchise,
+        options,
+        tax_point_date.as_deref(),
+    );
+
+    // Remise globale par taux (BG-20) et total des remises (BT-107)
+    let global_discount_xml =
+        generate_global_discount_xml(invoice, emitter.vat_exempt_franchise, options);
+    let allowance_total_xml = if allowance_total > 0.0 {
+        format!(
+            r#"
+                <ram:AllowanceTotalAmount>{:.2}</ram:AllowanceTotalAmount>"#,
+            allowance_total
+        )
+    } else {
+        String::new()
+    };
+
+    // Attributs xsi de l'élément racine (option `schema_location`)
+    let schema_location_xml = if options.schema_location {
+        format!(
+            r#"
+    xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+    xsi:schemaLocation="{}""#,
+            CII_SCHEMA_LOCATION
+        )
+    } else {
+        String::new()
+    };
 
     // Construction du XML complet
+    // Seul TaxTotalAmount porte l'attribut currencyID (obligatoire : il distingue
+    // BT-110 de BT-111) ; les règles CII l'interdisent sur les autres montants,
+    // exprimés dans la devise de facturation BT-5.
     let xml = format!(
         r#"<?xml version="1.0" encoding="UTF-8"?>
 <rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100"
-    xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100"
+    xmlns:ram="{ram_namespace}"
     xmlns:udt="urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100"
-    xmlns:qdt="urn:un:unece:uncefact:data:standard:QualifiedDataType:100">
+    xmlns:qdt="urn:un:unece:uncefact:data:standard:QualifiedDataType:100"{schema_location}>
     <rsm:ExchangedDocumentContext>
         <ram:GuidelineSpecifiedDocumentContextParameter>
-            <ram:ID>urn:factur-x.eu:1p0:minimum</ram:ID>
+            <ram:ID>{guideline_id}</ram:ID>
         </ram:GuidelineSpecifiedDocumentContextParameter>
     </rsm:ExchangedDocumentContext>
     <rsm:ExchangedDocument>
@@ -130,105 +313,709 @@ pub fn generate_facturx_xml(
         <ram:TypeCode>{type_code}</ram:TypeCode>
         <ram:IssueDateTime>
             <udt:DateTimeString format="102">{issue_date}</udt:DateTimeString>
-        </ram:IssueDateTime>
+        </ram:IssueDateTime>{notes}
     </rsm:ExchangedDocument>
-    <rsm:SupplyChainTradeTransaction>
+    <rsm:SupplyChainTradeTransaction>{line_items}
         <ram:ApplicableHeaderTradeAgreement>{buyer_reference}
-            <ram:SellerTradeParty>
+            <ram:SellerTradeParty>{seller_gln}
                 <ram:Name>{seller_name}</ram:Name>
                 <ram:SpecifiedLegalOrganization>
-                    <ram:ID schemeID="0002">{seller_siret}</ram:ID>
-                </ram:SpecifiedLegalOrganization>
+                    <ram:ID schemeID="0002">{seller_legal_id}</ram:ID>
+                </ram:SpecifiedLegalOrganization>{seller_contact}
                 <ram:PostalTradeAddress>
                     <ram:LineOne>{seller_address}</ram:LineOne>
                     <ram:CountryID>FR</ram:CountryID>
                 </ram:PostalTradeAddress>{seller_vat}
             </ram:SellerTradeParty>
-            <ram:BuyerTradeParty>
-                <ram:Name>{buyer_name}</ram:Name>
-                <ram:SpecifiedLegalOrganization>
-                    <ram:ID schemeID="0002">{buyer_siret}</ram:ID>
-                </ram:SpecifiedLegalOrganization>
+            <ram:BuyerTradeParty>{buyer_gln}
+                <ram:Name>{buyer_name}</ram:Name>{buyer_legal_org}
                 <ram:PostalTradeAddress>
                     <ram:LineOne>{buyer_address}</ram:LineOne>
                     <ram:CountryID>{buyer_country}</ram:CountryID>
                 </ram:PostalTradeAddress>{buyer_vat}
-            </ram:BuyerTradeParty>{order_reference}
+            </ram:BuyerTradeParty>{order_reference}{additional_references}{extra_agreement}
         </ram:ApplicableHeaderTradeAgreement>
-        <ram:ApplicableHeaderTradeDelivery/>
-        <ram:ApplicableHeaderTradeSettlement>
-            <ram:InvoiceCurrencyCode>{currency}</ram:InvoiceCurrencyCode>{due_date}{vat_breakdown}
+        {delivery}
+        <ram:ApplicableHeaderTradeSettlement>{tax_currency}
+            <ram:InvoiceCurrencyCode>{currency}</ram:InvoiceCurrencyCode>{payment_means}{vat_breakdown}{global_discount}{due_date}
             <ram:SpecifiedTradeSettlementHeaderMonetarySummation>
-                <ram:LineTotalAmount>{total_ht:.2}</ram:LineTotalAmount>
-                <ram:TaxBasisTotalAmount>{total_ht:.2}</ram:TaxBasisTotalAmount>
-                <ram:TaxTotalAmount currencyID="{currency}">{total_vat:.2}</ram:TaxTotalAmount>
-                <ram:GrandTotalAmount>{total_ttc:.2}</ram:GrandTotalAmount>
-                <ram:DuePayableAmount>{total_ttc:.2}</ram:DuePayableAmount>
-            </ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+                <ram:LineTotalAmount>{total_ht:.2}</ram:LineTotalAmount>{allowance_total}
+                <ram:TaxBasisTotalAmount>{tax_basis_total:.2}</ram:TaxBasisTotalAmount>
+                <ram:TaxTotalAmount currencyID="{currency}">{total_vat:.2}</ram:TaxTotalAmount>{tax_currency_total}
+                <ram:GrandTotalAmount>{total_ttc:.2}</ram:GrandTotalAmount>{prepaid}
+                <ram:DuePayableAmount>{due_payable:.2}</ram:DuePayableAmount>
+            </ram:SpecifiedTradeSettlementHeaderMonetarySummation>{extra_settlement}
         </ram:ApplicableHeaderTradeSettlement>
     </rsm:SupplyChainTradeTransaction>
 </rsm:CrossIndustryInvoice>"#,
+        ram_namespace = RAM_NAMESPACE,
+        schema_location = schema_location_xml,
+        guideline_id = profile.urn(),
         invoice_number = escape_xml(&invoice.invoice_number),
-        type_code = invoice.type_code,
+        type_code = invoice.type_code.code(),
         issue_date = issue_date_formatted,
+        notes = notes_xml,
+        line_items = line_items_xml,
         buyer_reference = buyer_reference_xml,
+        seller_gln = seller_gln_xml,
         seller_name = escape_xml(&emitter.name),
-        seller_siret = escape_xml(&emitter.siret),
+        seller_legal_id = escape_xml(seller_legal_id),
+        seller_contact = seller_contact_xml,
         seller_address = escape_xml(&emitter.address),
         seller_vat = seller_vat_xml,
+        buyer_gln = buyer_gln_xml,
         buyer_name = escape_xml(&invoice.recipient_name),
-        buyer_siret = escape_xml(&invoice.recipient_siret),
+        buyer_legal_org = buyer_legal_org_xml,
         buyer_address = escape_xml(&invoice.recipient_address),
         buyer_country = escape_xml(&invoice.recipient_country_code),
         buyer_vat = buyer_vat_xml,
         order_reference = order_reference_xml,
+        additional_references = additional_references_xml,
+        extra_agreement = extra_xml(options.extra_agreement_xml.as_deref(), "            "),
+        delivery = generate_delivery_xml(invoice, profile, options),
+        tax_currency = tax_currency_xml,
         currency = escape_xml(&invoice.currency_code),
+        payment_means = generate_payment_means_xml(emitter, profile),
         due_date = due_date_xml,
         vat_breakdown = vat_breakdown_xml,
         total_ht = total_ht,
+        global_discount = global_discount_xml,
+        allowance_total = allowance_total_xml,
+        tax_basis_total = totals.tax_basis_total(),
         total_vat = total_vat,
+        tax_currency_total = tax_currency_total_xml,
         total_ttc = total_ttc,
+        prepaid = prepaid_xml,
+        due_payable = totals.due_payable(),
+        extra_settlement = extra_xml(options.extra_settlement_xml.as_deref(), "            "),
     );
 
+    if options.compact_xml {
+        return Ok(compact_xml(&xml));
+    }
     Ok(xml)
 }
 
-/// Génère le récapitulatif TVA par taux pour le XML
-fn generate_vat_breakdown_xml(invoice: &InvoiceForm, _currency: &str) -> String {
-    use std::collections::HashMap;
+/// Génère les notes du document (IncludedNote) : TVA sur les encaissements,
+/// franchise en base, mentions légales, gratuité et paiement
+///
+/// Le profil MINIMUM ne prévoit pas de note dans `ExchangedDocument` : rien
+/// n'est émis.
+fn generate_notes_xml(
+    invoice: &InvoiceForm,
+    emitter: &EmitterConfig,
+    totals: InvoiceTotals,
+    options: &GenerationOptions,
+) -> String {
+    if options.profile == FacturXProfile::Minimum {
+        return String::new();
+    }
+
+    // Mention de TVA sur les encaissements (note réglementaire)
+    let mut notes_xml = if emitter.vat_on_payments {
+        format!(
+            r#"
+        <ram:IncludedNote>
+            <ram:Content>{}</ram:Content>
+            <ram:SubjectCode>REG</ram:SubjectCode>
+        </ram:IncludedNote>"#,
+            VAT_ON_PAYMENTS_MENTION
+        )
+    } else {
+        String::new()
+    };
+
+    // Franchise en base : la mention de l'article 293 B est reprise en note
+    // réglementaire du document, en plus du motif d'exonération de la TVA
+    if emitter.vat_exempt_franchise {
+        notes_xml.push_str(&format!(
+            r#"
+        <ram:IncludedNote>
+            <ram:Content>{}</ram:Content>
+            <ram:SubjectCode>REG</ram:SubjectCode>
+        </ram:IncludedNote>"#,
+            VAT_FRANCHISE_MENTION
+        ));
+    }
+
+    // Mentions légales de la société (forme juridique, capital, RCS)
+    if options.legal_mentions_note {
+        if let Some(mentions) = emitter.legal_mentions() {
+            notes_xml.push_str(&format!(
+                r#"
+        <ram:IncludedNote>
+            <ram:Content>{}</ram:Content>
+            <ram:SubjectCode>ABL</ram:SubjectCode>
+        </ram:IncludedNote>"#,
+                escape_xml(&mentions)
+            ));
+        }
+    }
+
+    // Montant à payer nul : la note explique l'absence de paiement attendu
+    if totals.is_free_of_charge() {
+        notes_xml.push_str(&format!(
+            r#"
+        <ram:IncludedNote>
+            <ram:Content>{}</ram:Content>
+            <ram:SubjectCode>AAI</ram:SubjectCode>
+        </ram:IncludedNote>"#,
+            FREE_OF_CHARGE_MENTION
+        ));
+    }
+
+    // Facture acquittée à l'émission : note de paiement
+    if let Some(date) = invoice.paid_on() {
+        let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|d| d.format("%d/%m/%Y").to_string())
+            .unwrap_or_else(|_| date.to_string());
+        notes_xml.push_str(&format!(
+            r#"
+        <ram:IncludedNote>
+            <ram:Content>{} {}</ram:Content>
+            <ram:SubjectCode>PMT</ram:SubjectCode>
+        </ram:IncludedNote>"#,
+            PAID_MENTION,
+            escape_xml(&date)
+        ));
+    }
+
+    notes_xml
+}
+
+/// Supprime l'indentation du XML : les noeuds texte composés uniquement
+/// d'espaces sont retirés et les espaces entre attributs réduits à un seul.
+///
+/// Le contenu des éléments et des attributs est conservé tel quel (les
+/// valeurs saisies sont déjà échappées par `escape_xml`).
+fn compact_xml(xml: &str) -> String {
+    let mut output = String::with_capacity(xml.len());
+    let mut text = String::new();
+    let mut in_tag = false;
+    let mut quote: Option<char> = None;
+
+    for c in xml.chars() {
+        if in_tag {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if c == '>' => in_tag = false,
+                None if c.is_whitespace() => {
+                    if !output.ends_with(' ') {
+                        output.push(' ');
+                    }
+                    continue;
+                }
+                None => {}
+            }
+            output.push(c);
+        } else if c == '<' {
+            if !text.trim().is_empty() {
+                output.push_str(&text);
+            }
+            text.clear();
+            in_tag = true;
+            output.push(c);
+        } else {
+            text.push(c);
+        }
+    }
+    if !text.trim().is_empty() {
+        output.push_str(&text);
+    }
+
+    output
+}
+
+/// Génère les lignes de facture du profil BASIC (IncludedSupplyChainTradeLineItem)
+fn generate_line_items_xml(
+    invoice: &InvoiceForm,
+    vat_exempt_franchise: bool,
+    options: &GenerationOptions,
+) -> Result<String, String> {
+    let tax_type_code = escape_xml(invoice.tax_type_code());
+
+    let mut xml_parts = Vec::new();
+    for (index, line) in invoice.lines.iter().filter(|l| l.is_valid()).enumerate() {
+        let category =
+            TaxCategory::for_group(VatGroupKey::of_line(line), vat_exempt_franchise, options);
+        // Détail de la ligne en note (BT-127)
+        let note_xml = match line.detail_text() {
+            Some(detail) => format!(
+                r#"
+                <ram:IncludedNote>
+                    <ram:Content>{}</ram:Content>
+                </ram:IncludedNote>"#,
+                escape_xml(detail)
+            ),
+            None => String::new(),
+        };
+
+        // Rabais de ligne (BG-27), avec le prix brut (BT-148) : le rabais
+        // porte sur le montant de la ligne, le prix brut égale donc le prix net
+        let (gross_price_xml, allowance_xml) = match line.discount_amount {
+            Some(discount) if discount > 0.0 => (
+                format!(
+                    r#"
+                <ram:GrossPriceProductTradePrice>
+                    <ram:ChargeAmount>{:.2}</ram:ChargeAmount>
+                </ram:GrossPriceProductTradePrice>"#,
+                    line.gross_ht_value() / line.quantity
+                ),
+                format!(
+                    r#"
+                <ram:SpecifiedTradeAllowanceCharge>
+                    <ram:ChargeIndicator>
+                        <udt:Indicator>false</udt:Indicator>
+                    </ram:ChargeIndicator>
+                    <ram:ActualAmount>{:.2}</ram:ActualAmount>
+                </ram:SpecifiedTradeAllowanceCharge>"#,
+                    discount
+                ),
+            ),
+            _ => (String::new(), String::new()),
+        };
+
+        // Période de facturation de la ligne (BG-26)
+        let mut period_xml = String::new();
+        if let Some((start, end)) = line.billing_period() {
+            period_xml.push_str(
+                r#"
+                <ram:BillingSpecifiedPeriod>"#,
+            );
+            for (tag, date) in [("StartDateTime", start), ("EndDateTime", end)] {
+                if let Some(date) = date {
+                    period_xml.push_str(&format!(
+                        r#"
+                    <ram:{tag}>
+                        <udt:DateTimeString format="102">{date}</udt:DateTimeString>
+                    </ram:{tag}>"#,
+                        tag = tag,
+                        date = format_date_for_facturx(date)?
+                    ));
+                }
+            }
+            period_xml.push_str(
+                r#"
+                </ram:BillingSpecifiedPeriod>"#,
+            );
+        }
+
+        // Ligne du bon de commande (BT-132), avant le prix brut dans le schéma
+        let order_line_xml = match line.order_line_reference_text() {
+            Some(reference) => format!(
+                r#"
+                <ram:BuyerOrderReferencedDocument>
+                    <ram:LineID>{}</ram:LineID>
+                </ram:BuyerOrderReferencedDocument>"#,
+                escape_xml(reference)
+            ),
+            None => String::new(),
+        };
+
+        // Identifiants article (BT-157 puis BT-155, ordre du schéma CII)
+        let mut product_ids_xml = String::new();
+        if let Some((id, scheme)) = line.standard_item() {
+            product_ids_xml.push_str(&format!(
+                r#"
+                <ram:GlobalID schemeID="{}">{}</ram:GlobalID>"#,
+                escape_xml(scheme),
+                escape_xml(id)
+            ));
+        }
+        if let Some(id) = line.seller_item_id_text() {
+            product_ids_xml.push_str(&format!(
+                r#"
+                <ram:SellerAssignedID>{}</ram:SellerAssignedID>"#,
+                escape_xml(id)
+            ));
+        }
+
+        xml_parts.push(format!(
+            r#"
+        <ram:IncludedSupplyChainTradeLineItem>
+            <ram:AssociatedDocumentLineDocument>
+                <ram:LineID>{line_id}</ram:LineID>{note}
+            </ram:AssociatedDocumentLineDocument>
+            <ram:SpecifiedTradeProduct>{product_ids}
+                <ram:Name>{name}</ram:Name>
+            </ram:SpecifiedTradeProduct>
+            <ram:SpecifiedLineTradeAgreement>{order_line}{gross_price}
+                <ram:NetPriceProductTradePrice>
+                    <ram:ChargeAmount>{price:.2}</ram:ChargeAmount>
+                </ram:NetPriceProductTradePrice>
+            </ram:SpecifiedLineTradeAgreement>
+            <ram:SpecifiedLineTradeDelivery>
+                <ram:BilledQuantity unitCode="{unit_code}">{quantity}</ram:BilledQuantity>
+            </ram:SpecifiedLineTradeDelivery>
+            <ram:SpecifiedLineTradeSettlement>
+                <ram:ApplicableTradeTax>
+                    <ram:TypeCode>{tax_type_code}</ram:TypeCode>
+                    <ram:CategoryCode>{category_code}</ram:CategoryCode>{rate}
+                </ram:ApplicableTradeTax>{period}{allowance}
+                <ram:SpecifiedTradeSettlementLineMonetarySummation>
+                    <ram:LineTotalAmount>{total_ht:.2}</ram:LineTotalAmount>
+                </ram:SpecifiedTradeSettlementLineMonetarySummation>
+            </ram:SpecifiedLineTradeSettlement>
+        </ram:IncludedSupplyChainTradeLineItem>"#,
+            line_id = index + 1,
+            note = note_xml,
+            product_ids = product_ids_xml,
+            name = escape_xml(&line.description),
+            order_line = order_line_xml,
+            gross_price = gross_price_xml,
+            price = line.unit_price_ht,
+            unit_code = escape_xml(line.unit_code()),
+            quantity = format_quantity(line.quantity),
+            tax_type_code = tax_type_code,
+            category_code = category.code,
+            rate = category.rate_xml(line.vat_rate, "                    "),
+            period = period_xml,
+            allowance = allowance_xml,
+            total_ht = line.total_ht_value(),
+        ));
+    }
+
+    Ok(xml_parts.join(""))
+}
+
+/// Identifiant légal du vendeur (schéma 0002) : SIRET par défaut, SIREN sur demande
+pub(super) fn seller_legal_id(emitter: &EmitterConfig) -> &str {
+    match emitter.siren.as_deref() {
+        Some(siren) if emitter.siren_as_legal_id && !siren.trim().is_empty() => siren.trim(),
+        _ => emitter.siret.as_str(),
+    }
+}
 
-    // Regrouper les montants par taux de TVA
-    let mut vat_by_rate: HashMap<String, (f64, f64)> = HashMap::new();
+/// Génère l'identifiant global GLN (schéma 0088) d'une partie, s'il est renseigné
+fn global_id_xml(gln: Option<&str>) -> String {
+    match gln {
+        Some(gln) if !gln.trim().is_empty() => format!(
+            r#"
+                <ram:GlobalID schemeID="0088">{}</ram:GlobalID>"#,
+            escape_xml(gln.trim())
+        ),
+        _ => String::new(),
+    }
+}
 
-    for line in &invoice.lines {
-        if !line.is_valid() {
-            continue;
+/// Génère le groupe livraison (ApplicableHeaderTradeDelivery)
+///
+/// Le lieu d'expédition (ShipFromTradeParty) n'existe qu'en profil EXTENDED ;
+/// sans lui ni fragment `extra_delivery_xml`, le groupe reste vide.
+fn generate_delivery_xml(
+    invoice: &InvoiceForm,
+    profile: FacturXProfile,
+    options: &GenerationOptions,
+) -> String {
+    let ship_from_xml = match &invoice.ship_from_address {
+        Some(ship_from) if profile == FacturXProfile::Extended => {
+            let name_xml = match ship_from
+                .name
+                .as_deref()
+                .filter(|name| !name.trim().is_empty())
+            {
+                Some(name) => format!(
+                    r#"
+                <ram:Name>{}</ram:Name>"#,
+                    escape_xml(name.trim())
+                ),
+                None => String::new(),
+            };
+            format!(
+                r#"
+            <ram:ShipFromTradeParty>{name}
+                <ram:PostalTradeAddress>
+                    <ram:LineOne>{address}</ram:LineOne>
+                    <ram:CountryID>{country}</ram:CountryID>
+                </ram:PostalTradeAddress>
+            </ram:ShipFromTradeParty>"#,
+                name = name_xml,
+                address = escape_xml(ship_from.address.trim()),
+                country = escape_xml(ship_from.country_code.trim()),
+            )
         }
-        let rate_key = format!("{:.2}", line.vat_rate);
-        let base_ht = line.total_ht_value();
-        let vat_amount = line.total_vat_value();
+        _ => String::new(),
+    };
+    let extra_xml = extra_xml(options.extra_delivery_xml.as_deref(), "            ");
 
-        let entry = vat_by_rate.entry(rate_key).or_insert((0.0, 0.0));
-        entry.0 += base_ht;
-        entry.1 += vat_amount;
+    if ship_from_xml.is_empty() && extra_xml.is_empty() {
+        return "<ram:ApplicableHeaderTradeDelivery/>".to_string();
     }
+    format!(
+        r#"<ram:ApplicableHeaderTradeDelivery>{}{}
+        </ram:ApplicableHeaderTradeDelivery>"#,
+        ship_from_xml, extra_xml
+    )
+}
 
-    // Générer le XML pour chaque taux
+/// Fragment XML libre (options `extra_*_xml`) placé en fin de groupe, indenté
+/// de `indent`
+fn extra_xml(fragment: Option<&str>, indent: &str) -> String {
+    match fragment.map(str::trim) {
+        Some(fragment) if !fragment.is_empty() => format!("\n{}{}", indent, fragment),
+        _ => String::new(),
+    }
+}
+
+/// Contrôle un fragment XML libre (options `extra_*_xml`)
+///
+/// Le fragment doit être bien formé une fois placé sous un élément déclarant
+/// les espaces de noms du document (`ram:`, `udt:`, `qdt:`), et ne contenir
+/// au premier niveau que des éléments de l'espace `ram:`, sans texte.
+pub(super) fn validate_xml_fragment(option: &str, fragment: &str) -> Result<(), String> {
+    use xml::reader::{ParserConfig, XmlEvent};
+
+    let invalid = |reason: String| format!("Fragment XML {} invalide: {}", option, reason);
+    let wrapped = format!(
+        r#"<fragment xmlns:ram="{}" xmlns:udt="urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100" xmlns:qdt="urn:un:unece:uncefact:data:standard:QualifiedDataType:100">{}</fragment>"#,
+        RAM_NAMESPACE, fragment
+    );
+    let reader = ParserConfig::new()
+        .trim_whitespace(true)
+        .ignore_comments(true)
+        .create_reader(wrapped.as_bytes());
+
+    let mut depth = 0usize;
+    let mut elements = 0usize;
+    for event in reader {
+        match event.map_err(|e| invalid(e.to_string()))? {
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+                if depth == 2 {
+                    elements += 1;
+                    if name.namespace.as_deref() != Some(RAM_NAMESPACE)
+                        || name.prefix.as_deref() != Some("ram")
+                    {
+                        return Err(invalid(format!(
+                            "l'element '{}' n'est pas dans l'espace de noms ram:",
+                            name.local_name
+                        )));
+                    }
+                }
+            }
+            XmlEvent::EndElement { .. } => depth -= 1,
+            XmlEvent::Characters(_) | XmlEvent::CData(_) if depth == 1 => {
+                return Err(invalid("texte hors element".to_string()));
+            }
+            _ => {}
+        }
+    }
+    if elements == 0 {
+        return Err(invalid("aucun element".to_string()));
+    }
+    Ok(())
+}
+
+/// Génère les documents référencés (AdditionalReferencedDocument)
+///
+/// L'objet facturé (BT-18) est émis à partir du profil EN 16931 ; les autres
+/// références n'existent qu'en profil EXTENDED. Les profils inférieurs n'ont
+/// pas de document référencé.
+fn generate_additional_references_xml(invoice: &InvoiceForm, profile: FacturXProfile) -> String {
+    let mut xml = generate_invoiced_object_xml(invoice, profile);
+    if profile != FacturXProfile::Extended {
+        return xml;
+    }
+
+    for reference in &invoice.additional_references {
+        let uri_xml = match reference
+            .uri
+            .as_deref()
+            .filter(|uri| !uri.trim().is_empty())
+        {
+            Some(uri) => format!(
+                r#"
+                        <ram:URIID>{}</ram:URIID>"#,
+                escape_xml(uri.trim())
+            ),
+            None => String::new(),
+        };
+        let reference_type_xml = match reference
+            .reference_type_code
+            .as_deref()
+            .filter(|code| !code.trim().is_empty())
+        {
+            Some(code) => format!(
+                r#"
+                        <ram:ReferenceTypeCode>{}</ram:ReferenceTypeCode>"#,
+                escape_xml(code.trim())
+            ),
+            None => String::new(),
+        };
+        xml.push_str(&format!(
+            r#"
+                    <ram:AdditionalReferencedDocument>
+                        <ram:IssuerAssignedID>{id}</ram:IssuerAssignedID>{uri}
+                        <ram:TypeCode>{type_code}</ram:TypeCode>{reference_type}
+                    </ram:AdditionalReferencedDocument>"#,
+            id = escape_xml(reference.id.trim()),
+            uri = uri_xml,
+            type_code = escape_xml(reference.type_code.trim()),
+            reference_type = reference_type_xml,
+        ));
+    }
+    xml
+}
+
+/// Génère l'objet facturé (BT-18) : document référencé de type 130 dont le
+/// schéma éventuel (BT-18-1) est porté par ReferenceTypeCode
+fn generate_invoiced_object_xml(invoice: &InvoiceForm, profile: FacturXProfile) -> String {
+    if !matches!(profile, FacturXProfile::EN16931 | FacturXProfile::Extended) {
+        return String::new();
+    }
+    let Some((id, scheme)) = invoice.invoiced_object() else {
+        return String::new();
+    };
+
+    let scheme_xml = match scheme {
+        Some(scheme) => format!(
+            r#"
+                        <ram:ReferenceTypeCode>{}</ram:ReferenceTypeCode>"#,
+            escape_xml(scheme)
+        ),
+        None => String::new(),
+    };
+    format!(
+        r#"
+                    <ram:AdditionalReferencedDocument>
+                        <ram:IssuerAssignedID>{}</ram:IssuerAssignedID>
+                        <ram:TypeCode>130</ram:TypeCode>{}
+                    </ram:AdditionalReferencedDocument>"#,
+        escape_xml(id),
+        scheme_xml
+    )
+}
+
+/// Génère le moyen de paiement (BG-16) : virement SEPA (code 58) vers l'IBAN
+/// de l'émetteur
+///
+/// Le profil MINIMUM ne décrit pas le paiement : rien n'est émis, pas plus
+/// qu'en l'absence d'IBAN.
+fn generate_payment_means_xml(emitter: &EmitterConfig, profile: FacturXProfile) -> String {
+    match emitter.normalized_iban() {
+        Some(iban) if profile != FacturXProfile::Minimum => format!(
+            r#"
+            <ram:SpecifiedTradeSettlementPaymentMeans>
+                <ram:TypeCode>58</ram:TypeCode>
+                <ram:PayeePartyCreditorFinancialAccount>
+                    <ram:IBANID>{}</ram:IBANID>
+                </ram:PayeePartyCreditorFinancialAccount>
+            </ram:SpecifiedTradeSettlementPaymentMeans>"#,
+            escape_xml(&iban)
+        ),
+        _ => String::new(),
+    }
+}
+
+/// Génère la remise globale (BG-20), une remise par taux de TVA des lignes remisables
+fn generate_global_discount_xml(
+    invoice: &InvoiceForm,
+    vat_exempt_franchise: bool,
+    options: &GenerationOptions,
+) -> String {
+    let percent = invoice.global_discount_percent.unwrap_or_default();
+    let tax_type_code = escape_xml(invoice.tax_type_code());
+
+    invoice
+        .global_discount_allowances()
+        .iter()
+        .map(|allowance| {
+            let group = VatGroupKey {
+                rate: VatRateKey::new(allowance.vat_rate),
+                category: allowance.vat_category,
+            };
+            let category = TaxCategory::for_group(group, vat_exempt_franchise, options);
+            format!(
+                r#"
+            <ram:SpecifiedTradeAllowanceCharge>
+                <ram:ChargeIndicator>
+                    <udt:Indicator>false</udt:Indicator>
+                </ram:ChargeIndicator>
+                <ram:CalculationPercent>{percent:.2}</ram:CalculationPercent>
+                <ram:BasisAmount>{basis:.2}</ram:BasisAmount>
+                <ram:ActualAmount>{amount:.2}</ram:ActualAmount>
+                <ram:ReasonCode>95</ram:ReasonCode>
+                <ram:Reason>Remise globale</ram:Reason>
+                <ram:CategoryTradeTax>
+                    <ram:TypeCode>{tax_type_code}</ram:TypeCode>
+                    <ram:CategoryCode>{category_code}</ram:CategoryCode>{rate}
+                </ram:CategoryTradeTax>
+            </ram:SpecifiedTradeAllowanceCharge>"#,
+                percent = percent,
+                basis = allowance.basis,
+                amount = allowance.amount,
+                tax_type_code = tax_type_code,
+                category_code = category.code,
+                rate = category.rate_xml(allowance.vat_rate, "                    "),
+            )
+        })
+        .collect()
+}
+
+/// Génère le récapitulatif TVA pour le XML, un `ApplicableTradeTax` par
+/// catégorie, taux et motif d'exonération (voir [`vat_breakdown`])
+/// En franchise en base, la catégorie est E (exonéré) avec l'article 293 B pour
+/// motif ; le taux de 0 % prend la catégorie `zero_rate_category`
+///
+/// `tax_point_date` (YYYYMMDD) renseigne BT-7 sur chaque taux. BR-CO-3 interdit
+/// de le combiner avec le code de date d'exigibilité (BT-8), qui n'est pas émis.
+fn generate_vat_breakdown_xml(
+    invoice: &InvoiceForm,
+    vat_exempt_franchise: bool,
+    options: &GenerationOptions,
+    tax_point_date: Option<&str>,
+) -> String {
+    let tax_type_code = escape_xml(invoice.tax_type_code());
+    let tax_point_date_xml = match tax_point_date {
+        Some(date) => format!(
+            r#"
+                <ram:TaxPointDate>
+                    <udt:DateString format="102">{}</udt:DateString>
+                </ram:TaxPointDate>"#,
+            date
+        ),
+        None => String::new(),
+    };
+
+    // Générer le XML pour chaque groupe
     let mut xml_parts = Vec::new();
-    for (rate_str, (base_ht, vat_amount)) in vat_by_rate {
-        let rate: f64 = rate_str.parse().unwrap_or(0.0);
+    for group in vat_breakdown(invoice, vat_exempt_franchise, options) {
+        let rate = group.rate.rate();
+        let category = group.tax_category();
+        let exemption_reason_xml = match &category.exemption_reason {
+            Some(reason) => format!(
+                r#"
+                <ram:ExemptionReason>{}</ram:ExemptionReason>"#,
+                escape_xml(reason)
+            ),
+            None => String::new(),
+        };
+        let exemption_code_xml = match category.exemption_reason_code {
+            Some(code) => format!(
+                r#"
+                <ram:ExemptionReasonCode>{}</ram:ExemptionReasonCode>"#,
+                code
+            ),
+            None => String::new(),
+        };
         xml_parts.push(format!(
             r#"
             <ram:ApplicableTradeTax>
                 <ram:CalculatedAmount>{vat_amount:.2}</ram:CalculatedAmount>
-                <ram:TypeCode>VAT</ram:TypeCode>
+                <ram:TypeCode>{tax_type_code}</ram:TypeCode>{exemption_reason}
                 <ram:BasisAmount>{base_ht:.2}</ram:BasisAmount>
-                <ram:CategoryCode>S</ram:CategoryCode>
-                <ram:RateApplicablePercent>{rate:.2}</ram:RateApplicablePercent>
+                <ram:CategoryCode>{category_code}</ram:CategoryCode>{exemption_code}{tax_point_date}{rate}
             </ram:ApplicableTradeTax>"#,
-            vat_amount = vat_amount,
-            base_ht = base_ht,
-            rate = rate,
+            vat_amount = group.vat_amount,
+            tax_type_code = tax_type_code,
+            exemption_reason = exemption_reason_xml,
+            base_ht = group.basis,
+            category_code = category.code,
+            exemption_code = exemption_code_xml,
+            tax_point_date = tax_point_date_xml,
+            rate = category.rate_xml(rate, "                "),
         ));
     }
 
@@ -246,8 +1033,10 @@ fn format_date_for_facturx(date: &str) -> Result<String, String> {
     Ok(date.replace('-', ""))
 }
 
-/// Échappe les caractères spéciaux XML
-fn escape_xml(s: &str) -> String {
+/// Échappe les caractères spéciaux XML et retire les caractères interdits
+pub(super) fn escape_xml(s: &str) -> String {
+    // Les caracteres interdits par XML 1.0 rendraient le document invalide
+    let s: String = s.chars().filter(|&c| is_xml_char(c)).collect();
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -258,6 +1047,1048 @@ fn escape_xml(s: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::facturx::ZeroRateCategory;
+    use crate::models::invoice::{DocumentReference, ShipFromAddress};
+    use crate::models::line::InvoiceLine;
+
+    fn test_emitter() -> EmitterConfig {
+        EmitterConfig {
+            siren: Some("123456789".to_string()),
+            siret: "12345678900012".to_string(),
+            siren_as_legal_id: false,
+            name: "Mon Entreprise SARL".to_string(),
+            address: "12 rue de la Paix, 75001 Paris".to_string(),
+            bic: None,
+            iban: None,
+            num_tva: Some("FR12345678901".to_string()),
+            gln: None,
+            phone: None,
+            email: None,
+            website: None,
+            vat_on_payments: false,
+            vat_exempt_franchise: false,
+            logo: None,
+            xml_storage: None,
+            pdf_storage: None,
+            legal_form: None,
+            capital: None,
+            rcs: None,
+            invoice_number_regex: None,
+        }
+    }
+
+    fn test_invoice() -> InvoiceForm {
+        InvoiceForm::sample(vec![InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            vat_rate: 20.0,
+            ..Default::default()
+        }])
+    }
+
+    fn generate(invoice: &mut InvoiceForm) -> String {
+        let totals = invoice.compute_totals();
+        generate_facturx_xml(invoice, &test_emitter(), totals).unwrap()
+    }
+
+    /// XML complet de la facture dans `profile`, par le générateur public
+    fn generate_in(invoice: &mut InvoiceForm, profile: FacturXProfile) -> String {
+        let totals = invoice.compute_totals();
+        let options = GenerationOptions {
+            profile,
+            ..Default::default()
+        };
+        generate_facturx_xml_with_options(invoice, &test_emitter(), totals, &options).unwrap()
+    }
+
+    #[test]
+    fn test_vat_breakdown_fractional_rates() {
+        let mut invoice = test_invoice();
+        for vat_rate in [2.1, 2.10, 8.25] {
+            invoice.lines.push(InvoiceLine {
+                description: "Produit".to_string(),
+                quantity: 1.0,
+                unit_price_ht: 100.0,
+                vat_rate,
+                ..Default::default()
+            });
+        }
+        let xml = generate(&mut invoice);
+
+        let rates: Vec<&str> = xml
+            .split("<ram:RateApplicablePercent>")
+            .skip(1)
+            .map(|part| part.split('<').next().unwrap())
+            .collect();
+        assert_eq!(rates, vec!["2.10", "8.25", "20.00"]);
+        assert!(xml.contains("<ram:BasisAmount>200.00</ram:BasisAmount>"));
+        assert!(xml.contains("<ram:CalculatedAmount>4.20</ram:CalculatedAmount>"));
+        assert!(xml.contains("<ram:CalculatedAmount>8.25</ram:CalculatedAmount>"));
+    }
+
+    #[test]
+    fn test_vat_breakdown_splits_same_rate_by_category() {
+        let mut invoice = test_invoice();
+        invoice.lines.push(InvoiceLine {
+            description: "Sous-traitance BTP".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 50.0,
+            vat_rate: 0.0,
+            vat_category: Some("AE".to_string()),
+            ..Default::default()
+        });
+        invoice.lines.push(InvoiceLine {
+            description: "Livre scolaire export".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 30.0,
+            vat_rate: 0.0,
+            ..Default::default()
+        });
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+
+        let settlement = &xml[xml.find("<ram:ApplicableHeaderTradeSettlement>").unwrap()..];
+        let blocks: Vec<&str> = settlement
+            .split("<ram:ApplicableTradeTax>")
+            .skip(1)
+            .collect();
+        // Deux groupes à 0 % (autoliquidation, taux zéro) et le taux normal
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[0].contains("<ram:CategoryCode>AE</ram:CategoryCode>"));
+        assert!(blocks[0].contains("<ram:BasisAmount>50.00</ram:BasisAmount>"));
+        assert!(blocks[0].contains("<ram:CalculatedAmount>0.00</ram:CalculatedAmount>"));
+        assert!(
+            blocks[0].contains("<ram:ExemptionReasonCode>VATEX-EU-AE</ram:ExemptionReasonCode>")
+        );
+        assert!(blocks[1].contains("<ram:CategoryCode>Z</ram:CategoryCode>"));
+        assert!(blocks[1].contains("<ram:BasisAmount>30.00</ram:BasisAmount>"));
+        assert!(blocks[2].contains("<ram:CategoryCode>S</ram:CategoryCode>"));
+        assert!(blocks[2].contains("<ram:BasisAmount>100.00</ram:BasisAmount>"));
+        assert!(xml.contains(
+            "<ram:CategoryCode>AE</ram:CategoryCode>\n                    <ram:RateApplicablePercent>0.00"
+        ));
+    }
+
+    #[test]
+    fn test_extra_xml_fragments() {
+        let mut invoice = test_invoice();
+        let options = GenerationOptions {
+            extra_agreement_xml: Some(
+                "<ram:SpecifiedProcuringProject><ram:ID>PRJ-1</ram:ID><ram:Name>Chantier</ram:Name></ram:SpecifiedProcuringProject>"
+                    .to_string(),
+            ),
+            extra_delivery_xml: Some(
+                r#"<ram:ActualDeliverySupplyChainEvent>
+                    <ram:OccurrenceDateTime><udt:DateTimeString format="102">20240110</udt:DateTimeString></ram:OccurrenceDateTime>
+                </ram:ActualDeliverySupplyChainEvent>"#
+                    .to_string(),
+            ),
+            extra_settlement_xml: Some(
+                "<ram:ReceivableSpecifiedTradeAccountingAccount><ram:ID>411000</ram:ID></ram:ReceivableSpecifiedTradeAccountingAccount>"
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+
+        let position = |needle: &str| xml.find(needle).unwrap();
+        assert!(
+            position("<ram:SpecifiedProcuringProject>")
+                < position("</ram:ApplicableHeaderTradeAgreement>")
+        );
+        assert!(
+            position("<ram:ApplicableHeaderTradeDelivery>")
+                < position("<ram:ActualDeliverySupplyChainEvent>")
+        );
+        assert!(!xml.contains("<ram:ApplicableHeaderTradeDelivery/>"));
+        assert!(
+            position("</ram:SpecifiedTradeSettlementHeaderMonetarySummation>")
+                < position("<ram:ReceivableSpecifiedTradeAccountingAccount>")
+        );
+        assert!(
+            position("<ram:ReceivableSpecifiedTradeAccountingAccount>")
+                < position("</ram:ApplicableHeaderTradeSettlement>")
+        );
+    }
+
+    #[test]
+    fn test_extra_xml_fragment_validation() {
+        assert!(validate_xml_fragment(
+            "extra_settlement_xml",
+            "<ram:ID>1</ram:ID><ram:ID>2</ram:ID>"
+        )
+        .is_ok());
+
+        for (fragment, reason) in [
+            ("<ram:ID>1</ram:ID", "invalide"),
+            ("<ram:ID>1</ram:Name>", "invalide"),
+            ("<ID>1</ID>", "n'est pas dans l'espace de noms ram:"),
+            (
+                "<udt:DateTimeString>20240101</udt:DateTimeString>",
+                "n'est pas dans l'espace de noms ram:",
+            ),
+            (
+                r#"<ram:ID xmlns:ram="urn:autre">1</ram:ID>"#,
+                "n'est pas dans l'espace de noms ram:",
+            ),
+            ("texte <ram:ID>1</ram:ID>", "texte hors element"),
+            ("  ", "aucun element"),
+            ("</fragment><fragment>", "invalide"),
+        ] {
+            let error = validate_xml_fragment("extra_settlement_xml", fragment).unwrap_err();
+            assert!(
+                error.starts_with("Fragment XML extra_settlement_xml invalide"),
+                "{}",
+                error
+            );
+            assert!(error.contains(reason), "{}: {}", fragment, error);
+        }
+
+        let options = GenerationOptions {
+            extra_agreement_xml: Some("<ram:ID>".to_string()),
+            ..Default::default()
+        };
+        assert!(options
+            .validate()
+            .unwrap_err()
+            .contains("extra_agreement_xml"));
+    }
+
+    #[test]
+    fn test_vat_breakdown_tax_type_code() {
+        let mut invoice = test_invoice();
+        assert!(generate(&mut invoice).contains("<ram:TypeCode>VAT</ram:TypeCode>"));
+
+        invoice.tax_type_code = Some("GST".to_string());
+        let xml = generate(&mut invoice);
+        assert!(xml.contains("<ram:TypeCode>GST</ram:TypeCode>"));
+        assert!(!xml.contains("<ram:TypeCode>VAT</ram:TypeCode>"));
+    }
+
+    #[test]
+    fn test_installments_payment_terms() {
+        use crate::models::invoice::Installment;
+
+        let mut invoice = test_invoice();
+        invoice.due_date = Some("2024-02-15".to_string());
+        invoice.installments = vec![
+            Installment {
+                amount: 60.0,
+                due_date: "2024-02-15".to_string(),
+                description: Some("Premier versement".to_string()),
+            },
+            Installment {
+                amount: 60.0,
+                due_date: "2024-03-15".to_string(),
+                description: None,
+            },
+        ];
+        let xml = generate_in(&mut invoice, FacturXProfile::Extended);
+
+        assert_eq!(xml.matches("<ram:SpecifiedTradePaymentTerms>").count(), 2);
+        assert!(xml.contains("<ram:Description>Premier versement</ram:Description>"));
+        assert!(xml.contains(r#"<udt:DateTimeString format="102">20240315</udt:DateTimeString>"#));
+        assert!(xml.contains("<ram:PartialPaymentAmount>60.00</ram:PartialPaymentAmount>"));
+
+        // Autres profils : échéance unique, sans montant partiel
+        for profile in [
+            FacturXProfile::Minimum,
+            FacturXProfile::BasicWL,
+            FacturXProfile::Basic,
+            FacturXProfile::EN16931,
+        ] {
+            let xml = generate_in(&mut invoice, profile);
+            assert_eq!(xml.matches("<ram:SpecifiedTradePaymentTerms>").count(), 1);
+            assert!(
+                xml.contains(r#"<udt:DateTimeString format="102">20240215</udt:DateTimeString>"#)
+            );
+            assert!(!xml.contains("PartialPaymentAmount"));
+            assert!(!xml.contains("Premier versement"));
+        }
+
+        // Sans date d'échéance, celle du premier versement
+        invoice.due_date = None;
+        let xml = generate_in(&mut invoice, FacturXProfile::Basic);
+        assert!(xml.contains(r#"<udt:DateTimeString format="102">20240215</udt:DateTimeString>"#));
+        assert!(!xml.contains("20240315"));
+    }
+
+    #[test]
+    fn test_global_id_gln() {
+        let mut invoice = test_invoice();
+        invoice.recipient_gln = Some("4006381333931".to_string());
+        let mut emitter = test_emitter();
+        emitter.gln = Some("3012345000000".to_string());
+
+        let totals = invoice.compute_totals();
+        let xml = generate_facturx_xml(&invoice, &emitter, totals).unwrap();
+
+        assert!(xml.contains(r#"<ram:GlobalID schemeID="0088">3012345000000</ram:GlobalID>"#));
+        assert!(xml.contains(r#"<ram:GlobalID schemeID="0088">4006381333931</ram:GlobalID>"#));
+        assert!(!generate(&mut test_invoice()).contains("GlobalID"));
+    }
+
+    #[test]
+    fn test_vat_on_payments_note() {
+        let mut invoice = test_invoice();
+        assert!(!generate_in(&mut invoice, FacturXProfile::BasicWL).contains("IncludedNote"));
+
+        let mut emitter = test_emitter();
+        emitter.vat_on_payments = true;
+        let totals = invoice.compute_totals();
+        let options = GenerationOptions {
+            profile: FacturXProfile::BasicWL,
+            ..Default::default()
+        };
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        assert!(xml.contains("<ram:Content>TVA acquittée sur les encaissements</ram:Content>"));
+        assert!(xml.contains("<ram:SubjectCode>REG</ram:SubjectCode>"));
+    }
+
+    #[test]
+    fn test_minimum_without_notes() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].discount_value = Some(100.0);
+        invoice.lines[0].discount_type = Some("percent".to_string());
+        invoice.paid = true;
+        invoice.payment_date = Some("2024-01-20".to_string());
+        let totals = invoice.compute_totals();
+        let mut emitter = test_emitter();
+        emitter.vat_on_payments = true;
+        emitter.vat_exempt_franchise = true;
+        emitter.legal_form = Some("SARL".to_string());
+        let mut options = GenerationOptions {
+            legal_mentions_note: true,
+            ..Default::default()
+        };
+
+        // Le schéma MINIMUM n'a pas d'IncludedNote dans ExchangedDocument
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        assert!(!xml.contains("IncludedNote"));
+
+        options.profile = FacturXProfile::BasicWL;
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        let subject_codes: Vec<&str> = xml
+            .split("<ram:SubjectCode>")
+            .skip(1)
+            .filter_map(|rest| rest.split('<').next())
+            .collect();
+        assert_eq!(subject_codes, vec!["REG", "REG", "ABL", "AAI", "PMT"]);
+    }
+
+    #[test]
+    fn test_legal_mentions_note() {
+        let mut invoice = test_invoice();
+        let totals = invoice.compute_totals();
+        let mut emitter = test_emitter();
+        emitter.legal_form = Some("SARL".to_string());
+        emitter.capital = Some("10 000 €".to_string());
+        emitter.rcs = Some("RCS Paris 123 456 789".to_string());
+
+        let mut options = GenerationOptions {
+            profile: FacturXProfile::BasicWL,
+            ..Default::default()
+        };
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        assert!(!xml.contains("IncludedNote"));
+
+        options.legal_mentions_note = true;
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        assert!(xml.contains(
+            "<ram:Content>SARL au capital de 10 000 € - RCS Paris 123 456 789</ram:Content>"
+        ));
+        assert!(xml.contains("<ram:SubjectCode>ABL</ram:SubjectCode>"));
+    }
+
+    #[test]
+    fn test_vat_franchise_exemption_category() {
+        let mut invoice = test_invoice();
+        invoice.apply_vat_franchise();
+        let mut emitter = test_emitter();
+        emitter.num_tva = None;
+        emitter.vat_exempt_franchise = true;
+
+        let totals = invoice.compute_totals();
+        assert_eq!(totals.total_vat, 0.0);
+        let xml = generate_facturx_xml(&invoice, &emitter, totals).unwrap();
+        assert!(xml.contains("<ram:CategoryCode>E</ram:CategoryCode>"));
+        assert!(xml.contains(
+            "<ram:ExemptionReason>TVA non applicable, art. 293 B du CGI</ram:ExemptionReason>"
+        ));
+        assert!(!xml.contains("<ram:CategoryCode>S</ram:CategoryCode>"));
+    }
+
+    #[test]
+    fn test_vat_franchise_without_vat_registration() {
+        let mut invoice = test_invoice();
+        invoice.apply_vat_franchise();
+        let mut emitter = test_emitter();
+        emitter.num_tva = Some("  ".to_string());
+        emitter.vat_exempt_franchise = true;
+
+        let totals = invoice.compute_totals();
+        let options = GenerationOptions {
+            profile: FacturXProfile::BasicWL,
+            ..Default::default()
+        };
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        let seller = &xml[xml.find("<ram:SellerTradeParty>").unwrap()
+            ..xml.find("</ram:SellerTradeParty>").unwrap()];
+        assert!(!seller.contains("SpecifiedTaxRegistration"));
+        assert!(!xml.contains(r#"<ram:ID schemeID="VA"></ram:ID>"#));
+        assert!(xml.contains(
+            "<ram:Content>TVA non applicable, art. 293 B du CGI</ram:Content>\n            <ram:SubjectCode>REG</ram:SubjectCode>"
+        ));
+        assert!(
+            xml.contains("<ram:ExemptionReasonCode>VATEX-FR-FRANCHISE</ram:ExemptionReasonCode>")
+        );
+
+        emitter.vat_exempt_franchise = false;
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        assert!(!xml.contains("293 B"));
+    }
+
+    #[test]
+    fn test_zero_rate_category() {
+        let mut invoice = test_invoice();
+        invoice.lines.push(InvoiceLine {
+            description: "Livraison intracommunautaire".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 50.0,
+            vat_rate: 0.0,
+            ..Default::default()
+        });
+        let totals = invoice.compute_totals();
+        let mut options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+
+        // Par défaut : Z pour 0 %, S pour 20 %, sans motif d'exonération
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert_eq!(
+            xml.matches("<ram:CategoryCode>S</ram:CategoryCode>")
+                .count(),
+            2
+        );
+        assert_eq!(
+            xml.matches("<ram:CategoryCode>Z</ram:CategoryCode>")
+                .count(),
+            2
+        );
+        assert!(!xml.contains("<ram:ExemptionReason>"));
+
+        options.zero_rate_category = ZeroRateCategory::E;
+        options.zero_rate_exemption_reason = Some("Exonération art. 262 ter I du CGI".to_string());
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert_eq!(
+            xml.matches("<ram:CategoryCode>E</ram:CategoryCode>")
+                .count(),
+            2
+        );
+        assert!(xml.contains(
+            "<ram:ExemptionReason>Exonération art. 262 ter I du CGI</ram:ExemptionReason>"
+        ));
+        assert!(xml.contains("<ram:RateApplicablePercent>0.00</ram:RateApplicablePercent>"));
+
+        // Hors champ : motif VATEX-EU-O et aucun taux pour 0 %
+        options.zero_rate_category = ZeroRateCategory::O;
+        options.zero_rate_exemption_reason = None;
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert_eq!(
+            xml.matches("<ram:CategoryCode>O</ram:CategoryCode>")
+                .count(),
+            2
+        );
+        assert!(xml.contains("<ram:ExemptionReason>Non soumis à la TVA</ram:ExemptionReason>"));
+        assert!(xml.contains("<ram:ExemptionReasonCode>VATEX-EU-O</ram:ExemptionReasonCode>"));
+        assert!(!xml.contains("<ram:RateApplicablePercent>0.00</ram:RateApplicablePercent>"));
+        assert_eq!(
+            xml.matches("<ram:RateApplicablePercent>20.00</ram:RateApplicablePercent>")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_seller_legal_id_siren() {
+        let mut invoice = test_invoice();
+        assert!(
+            generate(&mut invoice).contains(r#"<ram:ID schemeID="0002">12345678900012</ram:ID>"#)
+        );
+
+        let mut emitter = test_emitter();
+        emitter.siren_as_legal_id = true;
+        let totals = invoice.compute_totals();
+        let xml = generate_facturx_xml(&invoice, &emitter, totals).unwrap();
+        assert!(xml.contains(r#"<ram:ID schemeID="0002">123456789</ram:ID>"#));
+        assert!(!xml.contains("12345678900012"));
+    }
+
+    #[test]
+    fn test_seller_contact_phone() {
+        let mut invoice = test_invoice();
+        assert!(!generate(&mut invoice).contains("DefinedTradeContact"));
+
+        let mut emitter = test_emitter();
+        emitter.phone = Some("01 23 45 67 89".to_string());
+        let totals = invoice.compute_totals();
+        let xml = generate_facturx_xml(&invoice, &emitter, totals).unwrap();
+        assert!(xml.contains("<ram:CompleteNumber>+33123456789</ram:CompleteNumber>"));
+    }
+
+    #[test]
+    fn test_basic_profile_line_detail_note() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].detail = Some("Audit du 2 au 5 janvier".to_string());
+        assert!(!generate(&mut invoice).contains("IncludedSupplyChainTradeLineItem"));
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains("<ram:ID>urn:factur-x.eu:1p0:basic</ram:ID>"));
+        assert!(xml.contains("<ram:LineID>1</ram:LineID>"));
+        assert!(xml.contains("<ram:Content>Audit du 2 au 5 janvier</ram:Content>"));
+        assert!(xml.contains(r#"<ram:BilledQuantity unitCode="C62">1</ram:BilledQuantity>"#));
+    }
+
+    #[test]
+    fn test_tax_currency_total() {
+        let mut invoice = test_invoice();
+        invoice.currency_code = "USD".to_string();
+        invoice.tax_currency_code = Some("EUR".to_string());
+        invoice.tax_exchange_rate = Some(0.9);
+        let xml = generate(&mut invoice);
+
+        assert!(xml.contains("<ram:TaxCurrencyCode>EUR</ram:TaxCurrencyCode>"));
+        assert!(xml.contains(r#"<ram:TaxTotalAmount currencyID="USD">20.00</ram:TaxTotalAmount>"#));
+        assert!(xml.contains(r#"<ram:TaxTotalAmount currencyID="EUR">18.00</ram:TaxTotalAmount>"#));
+    }
+
+    #[test]
+    fn test_amount_currency_attributes() {
+        let mut invoice = test_invoice();
+        invoice.currency_code = "CHF".to_string();
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+
+        assert!(xml.contains("<ram:InvoiceCurrencyCode>CHF</ram:InvoiceCurrencyCode>"));
+        assert_eq!(xml.matches("currencyID=").count(), 1);
+        assert!(xml.contains(r#"<ram:TaxTotalAmount currencyID="CHF">"#));
+
+        // Montants d'en-tête, de ligne et du récapitulatif TVA : sans attribut
+        let mut amounts = 0;
+        for (start, _) in xml.match_indices("<ram:") {
+            let tag = &xml[start + 5..];
+            let name_len = tag.find([' ', '>', '/']).unwrap();
+            let name = &tag[..name_len];
+            if name.ends_with("Amount") && name != "TaxTotalAmount" {
+                amounts += 1;
+                assert_eq!(&tag[name_len..name_len + 1], ">", "{} avec attribut", name);
+            }
+        }
+        assert!(amounts >= 8);
+    }
+
+    #[test]
+    fn test_global_discount_allowance() {
+        let mut invoice = test_invoice();
+        invoice.lines.push(InvoiceLine {
+            description: "Frais de port".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 30.0,
+            vat_rate: 20.0,
+            discountable: false,
+            ..Default::default()
+        });
+        invoice.global_discount_percent = Some(10.0);
+        let xml = generate(&mut invoice);
+
+        assert_eq!(
+            xml.matches("<ram:Reason>Remise globale</ram:Reason>")
+                .count(),
+            1
+        );
+        assert!(xml.contains("<ram:CalculationPercent>10.00</ram:CalculationPercent>"));
+        assert!(xml.contains("<ram:BasisAmount>100.00</ram:BasisAmount>"));
+        assert!(xml.contains("<ram:ActualAmount>10.00</ram:ActualAmount>"));
+        assert!(xml.contains("<ram:LineTotalAmount>130.00</ram:LineTotalAmount>"));
+        assert!(xml.contains("<ram:AllowanceTotalAmount>10.00</ram:AllowanceTotalAmount>"));
+        assert!(xml.contains("<ram:TaxBasisTotalAmount>120.00</ram:TaxBasisTotalAmount>"));
+        assert!(xml.contains("<ram:BasisAmount>120.00</ram:BasisAmount>"));
+        assert!(xml.contains(r#"<ram:TaxTotalAmount currencyID="EUR">24.00</ram:TaxTotalAmount>"#));
+        assert!(xml.contains("<ram:GrandTotalAmount>144.00</ram:GrandTotalAmount>"));
+
+        invoice.global_discount_percent = None;
+        let xml = generate(&mut invoice);
+        assert!(!xml.contains("AllowanceTotalAmount"));
+        assert!(!xml.contains("Remise globale"));
+    }
+
+    #[test]
+    fn test_ship_from_extended_only() {
+        let mut invoice = test_invoice();
+        invoice.ship_from_address = Some(ShipFromAddress {
+            name: Some("Entrepot Lyon".to_string()),
+            address: "12 rue des Docks, 69007 Lyon".to_string(),
+            country_code: "FR".to_string(),
+        });
+
+        let xml = generate_in(&mut invoice, FacturXProfile::Extended);
+        assert!(xml.contains(
+            "<ram:ApplicableHeaderTradeDelivery>
+            <ram:ShipFromTradeParty>
+                <ram:Name>Entrepot Lyon</ram:Name>
+                <ram:PostalTradeAddress>
+                    <ram:LineOne>12 rue des Docks, 69007 Lyon</ram:LineOne>
+                    <ram:CountryID>FR</ram:CountryID>
+                </ram:PostalTradeAddress>
+            </ram:ShipFromTradeParty>
+        </ram:ApplicableHeaderTradeDelivery>"
+        ));
+
+        // Absent des schémas MINIMUM à EN 16931 : groupe livraison vide
+        for profile in [
+            FacturXProfile::Minimum,
+            FacturXProfile::Basic,
+            FacturXProfile::EN16931,
+        ] {
+            let xml = generate_in(&mut invoice, profile);
+            assert!(xml.contains("<ram:ApplicableHeaderTradeDelivery/>"));
+            assert!(!xml.contains("ShipFromTradeParty"));
+        }
+    }
+
+    #[test]
+    fn test_additional_references_extended_only() {
+        let mut invoice = test_invoice();
+        invoice.additional_references = vec![DocumentReference {
+            type_code: "916".to_string(),
+            id: "CONTRAT-12".to_string(),
+            uri: Some("https://example.com/c?a=1&b=2".to_string()),
+            reference_type_code: Some("CT".to_string()),
+        }];
+
+        let xml = generate_in(&mut invoice, FacturXProfile::Extended);
+        assert!(xml.contains(
+            "<ram:ID>urn:cen.eu:en16931:2017#conformant#urn:factur-x.eu:1p0:extended</ram:ID>"
+        ));
+        assert!(xml.contains(
+            "</ram:BuyerTradeParty>
+                    <ram:AdditionalReferencedDocument>
+                        <ram:IssuerAssignedID>CONTRAT-12</ram:IssuerAssignedID>
+                        <ram:URIID>https://example.com/c?a=1&amp;b=2</ram:URIID>
+                        <ram:TypeCode>916</ram:TypeCode>
+                        <ram:ReferenceTypeCode>CT</ram:ReferenceTypeCode>
+                    </ram:AdditionalReferencedDocument>"
+        ));
+
+        for profile in [
+            FacturXProfile::Minimum,
+            FacturXProfile::Basic,
+            FacturXProfile::EN16931,
+        ] {
+            assert!(!generate_in(&mut invoice, profile).contains("AdditionalReferencedDocument"));
+        }
+    }
+
+    #[test]
+    fn test_invoiced_object_from_en16931() {
+        let mut invoice = test_invoice();
+        invoice.invoiced_object_id = Some(" PDL-14500000000000 ".to_string());
+        invoice.invoiced_object_scheme = Some("AAJ".to_string());
+
+        let xml = generate_in(&mut invoice, FacturXProfile::EN16931);
+        assert!(xml.contains("<ram:ID>urn:cen.eu:en16931:2017</ram:ID>"));
+        assert!(xml.contains(
+            "</ram:BuyerTradeParty>
+                    <ram:AdditionalReferencedDocument>
+                        <ram:IssuerAssignedID>PDL-14500000000000</ram:IssuerAssignedID>
+                        <ram:TypeCode>130</ram:TypeCode>
+                        <ram:ReferenceTypeCode>AAJ</ram:ReferenceTypeCode>
+                    </ram:AdditionalReferencedDocument>"
+        ));
+        assert!(xml.contains("<ram:IncludedSupplyChainTradeLineItem>"));
+
+        // Absent des schémas MINIMUM à BASIC
+        assert!(!generate_in(&mut invoice, FacturXProfile::Basic)
+            .contains("AdditionalReferencedDocument"));
+
+        invoice.invoiced_object_scheme = None;
+        let xml = generate_in(&mut invoice, FacturXProfile::EN16931);
+        assert!(xml.contains("<ram:TypeCode>130</ram:TypeCode>"));
+        assert!(!xml.contains("ReferenceTypeCode"));
+    }
+
+    #[test]
+    fn test_basic_line_gross_price() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].quantity = 4.0;
+        invoice.lines[0].unit_price_ht = 25.0;
+        invoice.lines[0].discount_value = Some(10.0);
+        invoice.lines[0].discount_type = Some("percent".to_string());
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let line = &invoice.lines[0];
+        assert_eq!(
+            line.gross_ht_value() - line.discount_amount.unwrap(),
+            line.total_ht_value()
+        );
+
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains(
+            "<ram:GrossPriceProductTradePrice>\n                    <ram:ChargeAmount>25.00</ram:ChargeAmount>"
+        ));
+        assert!(xml.contains("<ram:ActualAmount>10.00</ram:ActualAmount>"));
+        assert!(xml.contains("<ram:LineTotalAmount>90.00</ram:LineTotalAmount>"));
+
+        // Sans rabais, pas de prix brut
+        invoice.lines[0].discount_value = None;
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(!xml.contains("GrossPriceProductTradePrice"));
+    }
+
+    #[test]
+    fn test_basic_line_item_identifiers() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].seller_item_id = Some("REF-42".to_string());
+        invoice.lines[0].standard_item_id = Some("4006381333931".to_string());
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains(r#"<ram:GlobalID schemeID="0160">4006381333931</ram:GlobalID>"#));
+        assert!(xml.contains("<ram:SellerAssignedID>REF-42</ram:SellerAssignedID>"));
+    }
+
+    #[test]
+    fn test_huge_quantity_rejected_before_xml() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].quantity = 1e308;
+        assert_eq!(invoice.lines[0].validate(0)[0].field, "lines[0][quantity]");
+
+        // Appel direct sans validation : erreur plutot qu'un XML avec "inf"
+        let totals = invoice.compute_totals();
+        assert!(totals.total_ht.is_infinite());
+        let error = generate_facturx_xml(&invoice, &test_emitter(), totals).unwrap_err();
+        assert!(error.contains("nombres finis"));
+    }
+
+    #[test]
+    fn test_basic_line_order_references() {
+        let mut invoice = test_invoice();
+        invoice.purchase_order_reference = Some("PO-2024-17".to_string());
+        let mut second = invoice.lines[0].clone();
+        second.description = "Maintenance".to_string();
+        second.order_line_reference = Some("20".to_string());
+        invoice.lines[0].order_line_reference = Some("10".to_string());
+        invoice.lines.push(second);
+        assert!(invoice.validate_order_line_references().is_empty());
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        let first = xml.find("<ram:LineID>10</ram:LineID>").unwrap();
+        let second = xml.find("<ram:LineID>20</ram:LineID>").unwrap();
+        assert!(first < second);
+        assert_eq!(xml.matches("<ram:BuyerOrderReferencedDocument>").count(), 3);
+        let agreement = xml.find("<ram:SpecifiedLineTradeAgreement>").unwrap();
+        assert!(agreement < first && first < xml.find("<ram:NetPriceProductTradePrice>").unwrap());
+
+        // BASIC WL : pas de lignes, donc pas de BT-132
+        let options = GenerationOptions {
+            profile: FacturXProfile::BasicWL,
+            ..Default::default()
+        };
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(!xml.contains("<ram:LineID>10</ram:LineID>"));
+    }
+
+    #[test]
+    fn test_basic_line_billing_period() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].period_start = Some("2024-01-01".to_string());
+        invoice.lines[0].period_end = Some("2024-01-31".to_string());
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains("<ram:BillingSpecifiedPeriod>"));
+        assert!(xml.contains(
+            r#"<ram:StartDateTime>
+                        <udt:DateTimeString format="102">20240101</udt:DateTimeString>"#
+        ));
+        assert!(xml.contains(r#"<udt:DateTimeString format="102">20240131</udt:DateTimeString>"#));
+    }
+
+    #[test]
+    fn test_control_characters_stripped_from_xml() {
+        let mut invoice = test_invoice();
+        invoice.lines[0].description = "Audit\tcomplet\u{c}".to_string();
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains("<ram:Name>Audit\tcomplet</ram:Name>"));
+        assert!(!xml.contains('\u{c}'));
+    }
+
+    #[test]
+    fn test_public_buyer_reference_fallback() {
+        let mut invoice = test_invoice();
+        invoice.recipient_public_sector = true;
+        let options = GenerationOptions {
+            default_buyer_reference: Some("ACHATS".to_string()),
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains("<ram:BuyerReference>ACHATS</ram:BuyerReference>"));
+
+        invoice.recipient_service_code = Some("SCE-001".to_string());
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains("<ram:BuyerReference>SCE-001</ram:BuyerReference>"));
+
+        invoice.recipient_public_sector = false;
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(!xml.contains("<ram:BuyerReference>"));
+    }
+
+    #[test]
+    fn test_free_of_charge_note() {
+        let mut invoice = test_invoice();
+        assert!(
+            !generate_in(&mut invoice, FacturXProfile::BasicWL).contains(FREE_OF_CHARGE_MENTION)
+        );
+
+        invoice.lines[0].discount_value = Some(100.0);
+        invoice.lines[0].discount_type = Some("percent".to_string());
+        let xml = generate_in(&mut invoice, FacturXProfile::BasicWL);
+        assert!(xml.contains("<ram:Content>Facture à titre gracieux</ram:Content>"));
+        assert!(xml.contains("<ram:SubjectCode>AAI</ram:SubjectCode>"));
+        assert!(xml.contains(r#"<ram:DuePayableAmount>0.00</ram:DuePayableAmount>"#));
+    }
+
+    #[test]
+    fn test_paid_invoice_amounts() {
+        let mut invoice = test_invoice();
+        let xml = generate(&mut invoice);
+        assert!(!xml.contains("<ram:TotalPrepaidAmount>"));
+        assert!(!xml.contains(PAID_MENTION));
+
+        invoice.paid = true;
+        invoice.payment_date = Some("2024-01-20".to_string());
+        let xml = generate_in(&mut invoice, FacturXProfile::BasicWL);
+        assert!(xml.contains("<ram:TotalPrepaidAmount>120.00</ram:TotalPrepaidAmount>"));
+        assert!(xml.contains("<ram:DuePayableAmount>0.00</ram:DuePayableAmount>"));
+        assert!(xml.contains("<ram:Content>Facture acquittée le 20/01/2024</ram:Content>"));
+        assert!(xml.contains("<ram:SubjectCode>PMT</ram:SubjectCode>"));
+    }
+
+    #[test]
+    fn test_basic_wl_header_without_lines() {
+        let mut invoice = test_invoice();
+        invoice.global_discount_percent = Some(10.0);
+        invoice.due_date = Some("2024-02-15".to_string());
+        let mut emitter = test_emitter();
+        emitter.iban = Some("FR76 3000 6000 0112 3456 7890 189".to_string());
+        let totals = invoice.compute_totals();
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::BasicWL,
+            ..Default::default()
+        };
+        let xml = generate_facturx_xml_with_options(&invoice, &emitter, totals, &options).unwrap();
+        assert!(xml.contains("<ram:ID>urn:factur-x.eu:1p0:basicwl</ram:ID>"));
+        assert!(!xml.contains("<ram:IncludedSupplyChainTradeLineItem>"));
+        assert!(xml.contains("<ram:IBANID>FR7630006000011234567890189</ram:IBANID>"));
+
+        // Ordre du schéma : paiement, TVA, remises, conditions, totaux
+        let positions: Vec<usize> = [
+            "<ram:SpecifiedTradeSettlementPaymentMeans>",
+            "<ram:ApplicableTradeTax>",
+            "<ram:SpecifiedTradeAllowanceCharge>",
+            "<ram:SpecifiedTradePaymentTerms>",
+            "<ram:SpecifiedTradeSettlementHeaderMonetarySummation>",
+        ]
+        .iter()
+        .map(|tag| xml.find(tag).unwrap_or_else(|| panic!("{} absent", tag)))
+        .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Profil MINIMUM : pas de moyen de paiement
+        let xml = generate_facturx_xml_with_options(
+            &invoice,
+            &emitter,
+            totals,
+            &GenerationOptions::default(),
+        )
+        .unwrap();
+        assert!(!xml.contains("SpecifiedTradeSettlementPaymentMeans"));
+    }
+
+    #[test]
+    fn test_tax_point_date_placement() {
+        let mut invoice = test_invoice();
+        invoice.tax_point_date = Some("2024-01-10".to_string());
+        let totals = invoice.compute_totals();
+
+        // Profil MINIMUM (défaut) : BT-7 absent
+        assert!(!generate(&mut invoice).contains("<ram:TaxPointDate>"));
+
+        let options = GenerationOptions {
+            profile: FacturXProfile::BasicWL,
+            ..Default::default()
+        };
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        let tax_point = xml.find("<ram:TaxPointDate>").expect("TaxPointDate absent");
+        assert!(xml.contains(r#"<udt:DateString format="102">20240110</udt:DateString>"#));
+        assert!(xml.find("<ram:ApplicableTradeTax>").unwrap() < tax_point);
+        assert!(xml.find("<ram:CategoryCode>S</ram:CategoryCode>").unwrap() < tax_point);
+        assert!(tax_point < xml.find("<ram:RateApplicablePercent>").unwrap());
+
+        invoice.tax_point_date = None;
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(!xml.contains("<ram:TaxPointDate>"));
+    }
+
+    #[test]
+    fn test_compact_xml_output() {
+        let mut invoice = test_invoice();
+        invoice.invoice_number = "FA  2024 & 001".to_string();
+        let pretty = generate(&mut invoice);
+
+        let options = GenerationOptions {
+            compact_xml: true,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let compact =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+
+        assert!(compact.len() < pretty.len());
+        assert!(!compact.contains('\n'));
+        assert!(compact.starts_with(
+            r#"<?xml version="1.0" encoding="UTF-8"?><rsm:CrossIndustryInvoice xmlns:rsm="#
+        ));
+        assert!(compact.contains("<ram:ID>FA  2024 &amp; 001</ram:ID>"));
+        assert!(compact.contains(r#"<udt:DateTimeString format="102">"#));
+        assert_eq!(compact_xml(&pretty), compact);
+    }
+
+    #[test]
+    fn test_schema_location_option() {
+        let mut invoice = test_invoice();
+        let default_xml = generate(&mut invoice);
+        assert!(!default_xml.contains("xsi:"));
+
+        let options = GenerationOptions {
+            schema_location: true,
+            ..Default::default()
+        };
+        let totals = invoice.compute_totals();
+        let xml =
+            generate_facturx_xml_with_options(&invoice, &test_emitter(), totals, &options).unwrap();
+        assert!(xml.contains(r#"xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance""#));
+        assert!(xml.contains(&format!(r#"xsi:schemaLocation="{}">"#, CII_SCHEMA_LOCATION)));
+
+        // Seul l'élément racine change
+        let body =
+            |xml: &str| xml[xml.find("<rsm:ExchangedDocumentContext>").unwrap()..].to_string();
+        assert_eq!(body(&xml), body(&default_xml));
+    }
+
+    #[test]
+    fn test_compact_xml_keeps_text_content() {
+        assert_eq!(
+            compact_xml("<a>\n    <b x=\"1  2\">  texte  </b>\n</a>\n"),
+            r#"<a><b x="1  2">  texte  </b></a>"#
+        );
+    }
+
+    /// Bloc BuyerTradeParty du XML
+    fn buyer_party(xml: &str) -> &str {
+        let start = xml.find("<ram:BuyerTradeParty>").unwrap();
+        let end = xml.find("</ram:BuyerTradeParty>").unwrap();
+        &xml[start..end]
+    }
+
+    #[test]
+    fn test_foreign_buyer_without_siret_scheme() {
+        for (country, vat_number) in [("DE", "DE123456789"), ("ES", "ESB12345678")] {
+            let mut invoice = test_invoice();
+            invoice.recipient_country_code = country.to_string();
+            invoice.recipient_siret = String::new();
+            invoice.recipient_vat_number = Some(vat_number.to_string());
+            let xml = generate(&mut invoice);
+            let buyer = buyer_party(&xml);
+
+            assert!(!buyer.contains("SpecifiedLegalOrganization"));
+            assert!(!buyer.contains(r#"schemeID="0002""#));
+            assert!(buyer.contains(&format!(r#"<ram:ID schemeID="VA">{}</ram:ID>"#, vat_number)));
+            assert!(buyer.contains(&format!("<ram:CountryID>{}</ram:CountryID>", country)));
+        }
+    }
+
+    #[test]
+    fn test_french_buyer_siret_scheme() {
+        let mut invoice = test_invoice();
+        let xml = generate(&mut invoice);
+        assert!(buyer_party(&xml).contains(&format!(
+            r#"<ram:ID schemeID="0002">{}</ram:ID>"#,
+            invoice.recipient_siret
+        )));
+    }
 
     #[test]
     fn test_format_date_for_facturx() {
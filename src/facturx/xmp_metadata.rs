@@ -4,10 +4,13 @@
 //! - La génération des métadonnées XMP conformes au standard Factur-X
 //! - La validation des métadonnées avant création du PDF
 
+use crate::models::validation::is_xml_char;
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 
 /// Profil Factur-X utilisé
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 #[allow(dead_code)]
 pub enum FacturXProfile {
     Minimum,
@@ -18,18 +21,33 @@ pub enum FacturXProfile {
 }
 
 impl FacturXProfile {
+    /// Tous les profils, du plus simple au plus complet
+    pub const ALL: [FacturXProfile; 5] = [
+        FacturXProfile::Minimum,
+        FacturXProfile::BasicWL,
+        FacturXProfile::Basic,
+        FacturXProfile::EN16931,
+        FacturXProfile::Extended,
+    ];
+
     /// Retourne l'identifiant URN du profil
-    #[allow(dead_code)]
     pub fn urn(&self) -> &'static str {
         match self {
             FacturXProfile::Minimum => "urn:factur-x.eu:1p0:minimum",
             FacturXProfile::BasicWL => "urn:factur-x.eu:1p0:basicwl",
             FacturXProfile::Basic => "urn:factur-x.eu:1p0:basic",
-            FacturXProfile::EN16931 => "urn:factur-x.eu:1p0:en16931",
-            FacturXProfile::Extended => "urn:factur-x.eu:1p0:extended",
+            FacturXProfile::EN16931 => "urn:cen.eu:en16931:2017",
+            FacturXProfile::Extended => {
+                "urn:cen.eu:en16931:2017#conformant#urn:factur-x.eu:1p0:extended"
+            }
         }
     }
 
+    /// Indique si le XML du profil décrit les lignes de facture (BASIC et au-delà)
+    pub fn has_line_items(&self) -> bool {
+        !matches!(self, FacturXProfile::Minimum | FacturXProfile::BasicWL)
+    }
+
     /// Retourne le nom du profil pour les métadonnées XMP
     pub fn name(&self) -> &'static str {
         match self {
@@ -42,6 +60,35 @@ impl FacturXProfile {
     }
 }
 
+/// Niveau de conformité PDF/A-3 déclaré et validé
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PdfaConformance {
+    /// PDF/A-3b : reproduction visuelle fiable (défaut)
+    #[default]
+    A3B,
+    /// PDF/A-3u : A-3b et correspondance Unicode (`ToUnicode`) de tout le
+    /// texte, pour l'extraction et la recherche exigées par certains
+    /// archivages
+    A3U,
+}
+
+impl PdfaConformance {
+    /// Lettre de `pdfaid:conformance`
+    pub fn letter(self) -> &'static str {
+        match self {
+            PdfaConformance::A3B => "B",
+            PdfaConformance::A3U => "U",
+        }
+    }
+}
+
+/// Nom standard du fichier XML Factur-X embarqué
+pub const FACTURX_XML_FILENAME: &str = "factur-x.xml";
+
+/// Outil producteur déclaré par défaut (`pdf:Producer` et `/Producer`)
+pub const DEFAULT_PRODUCER: &str = "Generate-Factur-X (krilla + lopdf)";
+
 /// Structure contenant les informations nécessaires pour les métadonnées XMP
 #[derive(Debug, Clone)]
 pub struct XmpMetadata {
@@ -57,6 +104,13 @@ pub struct XmpMetadata {
     pub xml_filename: String,
     /// Version Factur-X
     pub facturx_version: String,
+    /// Outil producteur du PDF (`pdf:Producer`)
+    pub producer: String,
+    /// Déclare la conformité PDF/A-3 (`pdfaid`) ; désactivé pour un PDF
+    /// produit sans validation PDF/A
+    pub pdfa_identification: bool,
+    /// Niveau déclaré dans `pdfaid:conformance`
+    pub pdfa_conformance: PdfaConformance,
 }
 
 impl Default for XmpMetadata {
@@ -66,8 +120,11 @@ impl Default for XmpMetadata {
             author: String::new(),
             subject: "Facture électronique Factur-X".to_string(),
             profile: FacturXProfile::Minimum,
-            xml_filename: "factur-x.xml".to_string(),
+            xml_filename: FACTURX_XML_FILENAME.to_string(),
             facturx_version: "1.0".to_string(),
+            producer: DEFAULT_PRODUCER.to_string(),
+            pdfa_identification: true,
+            pdfa_conformance: PdfaConformance::default(),
         }
     }
 }
@@ -154,10 +211,10 @@ pub fn validate_xmp_metadata(metadata: &XmpMetadata) -> XmpValidationResult {
     }
 
     // Vérification du nom de fichier standard Factur-X
-    if metadata.xml_filename != "factur-x.xml" {
+    if metadata.xml_filename != FACTURX_XML_FILENAME {
         warnings.push(format!(
-            "Le nom de fichier XML '{}' n'est pas le nom standard 'factur-x.xml'",
-            metadata.xml_filename
+            "Le nom de fichier XML '{}' n'est pas le nom standard '{}'",
+            metadata.xml_filename, FACTURX_XML_FILENAME
         ));
     }
 
@@ -198,6 +255,22 @@ pub fn generate_xmp_metadata(metadata: &XmpMetadata) -> Result<String, String> {
     let now = Utc::now();
     let timestamp = now.format("%Y-%m-%dT%H:%M:%S+00:00").to_string();
 
+    let pdfa_identification = if metadata.pdfa_identification {
+        format!(
+            r#"
+
+    <!-- PDF/A Identification -->
+    <rdf:Description rdf:about=""
+        xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/">
+      <pdfaid:part>3</pdfaid:part>
+      <pdfaid:conformance>{}</pdfaid:conformance>
+    </rdf:Description>"#,
+            metadata.pdfa_conformance.letter()
+        )
+    } else {
+        String::new()
+    };
+
     let xmp = format!(
         r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
 <x:xmpmeta xmlns:x="adobe:ns:meta/">
@@ -236,15 +309,8 @@ pub fn generate_xmp_metadata(metadata: &XmpMetadata) -> Result<String, String> {
     <!-- PDF Properties -->
     <rdf:Description rdf:about=""
         xmlns:pdf="http://ns.adobe.com/pdf/1.3/">
-      <pdf:Producer>Generate-Factur-X (printpdf + lopdf)</pdf:Producer>
-    </rdf:Description>
-
-    <!-- PDF/A Identification -->
-    <rdf:Description rdf:about=""
-        xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/">
-      <pdfaid:part>3</pdfaid:part>
-      <pdfaid:conformance>B</pdfaid:conformance>
-    </rdf:Description>
+      <pdf:Producer>{producer}</pdf:Producer>
+    </rdf:Description>{pdfa_identification}
 
     <!-- PDF/A Extension Schema for Factur-X -->
     <rdf:Description rdf:about=""
@@ -306,6 +372,8 @@ pub fn generate_xmp_metadata(metadata: &XmpMetadata) -> Result<String, String> {
         author = escape_xml(&metadata.author),
         subject = escape_xml(&metadata.subject),
         timestamp = timestamp,
+        producer = escape_xml(&metadata.producer),
+        pdfa_identification = pdfa_identification,
         xml_filename = escape_xml(&metadata.xml_filename),
         facturx_version = escape_xml(&metadata.facturx_version),
         profile_name = metadata.profile.name(),
@@ -314,8 +382,10 @@ pub fn generate_xmp_metadata(metadata: &XmpMetadata) -> Result<String, String> {
     Ok(xmp)
 }
 
-/// Échappe les caractères spéciaux XML
+/// Échappe les caractères spéciaux XML et retire les caractères interdits
 fn escape_xml(s: &str) -> String {
+    // Les caractères interdits par XML 1.0 rendraient le XMP invalide
+    let s: String = s.chars().filter(|&c| is_xml_char(c)).collect();
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -336,6 +406,9 @@ mod tests {
             profile: FacturXProfile::Minimum,
             xml_filename: "factur-x.xml".to_string(),
             facturx_version: "1.0".to_string(),
+            producer: DEFAULT_PRODUCER.to_string(),
+            pdfa_identification: true,
+            pdfa_conformance: PdfaConformance::A3B,
         };
         let result = validate_xmp_metadata(&metadata);
         assert!(result.is_valid);
@@ -389,6 +462,9 @@ mod tests {
             profile: FacturXProfile::Minimum,
             xml_filename: "factur-x.xml".to_string(),
             facturx_version: "1.0".to_string(),
+            producer: DEFAULT_PRODUCER.to_string(),
+            pdfa_identification: true,
+            pdfa_conformance: PdfaConformance::A3B,
         };
         let xmp = generate_xmp_metadata(&metadata).unwrap();
 
@@ -396,11 +472,62 @@ mod tests {
         assert!(xmp.contains("pdfaid:conformance>B</pdfaid:conformance"));
         assert!(xmp.contains("fx:DocumentFileName>factur-x.xml</fx:DocumentFileName"));
         assert!(xmp.contains("fx:ConformanceLevel>MINIMUM</fx:ConformanceLevel"));
+        assert!(xmp.contains("<pdf:Producer>Generate-Factur-X (krilla + lopdf)</pdf:Producer>"));
+        assert!(!xmp.contains("printpdf"));
+    }
+
+    #[test]
+    fn test_generate_xmp_pdfa_conformance() {
+        for (conformance, letter) in [(PdfaConformance::A3B, "B"), (PdfaConformance::A3U, "U")] {
+            let metadata = XmpMetadata {
+                title: "Facture".to_string(),
+                author: "Ma Société".to_string(),
+                pdfa_conformance: conformance,
+                ..Default::default()
+            };
+            let xmp = generate_xmp_metadata(&metadata).unwrap();
+
+            assert!(xmp.contains(&format!(
+                "<pdfaid:conformance>{}</pdfaid:conformance>",
+                letter
+            )));
+        }
+        assert_eq!(PdfaConformance::default(), PdfaConformance::A3B);
+    }
+
+    #[test]
+    fn test_generate_xmp_custom_producer() {
+        let metadata = XmpMetadata {
+            title: "Facture".to_string(),
+            author: "Ma Société".to_string(),
+            producer: "ERP Dupont & Fils".to_string(),
+            ..Default::default()
+        };
+        let xmp = generate_xmp_metadata(&metadata).unwrap();
+
+        assert!(xmp.contains("<pdf:Producer>ERP Dupont &amp; Fils</pdf:Producer>"));
     }
 
     #[test]
     fn test_facturx_profile_urn() {
         assert_eq!(FacturXProfile::Minimum.urn(), "urn:factur-x.eu:1p0:minimum");
         assert_eq!(FacturXProfile::Basic.urn(), "urn:factur-x.eu:1p0:basic");
+        assert_eq!(FacturXProfile::BasicWL.urn(), "urn:factur-x.eu:1p0:basicwl");
+        assert_eq!(FacturXProfile::BasicWL.name(), "BASIC WL");
+        assert_eq!(FacturXProfile::EN16931.urn(), "urn:cen.eu:en16931:2017");
+    }
+
+    #[test]
+    fn test_generate_xmp_without_pdfa_identification() {
+        let metadata = XmpMetadata {
+            title: "Facture FA-2024-001".to_string(),
+            author: "Ma Société".to_string(),
+            pdfa_identification: false,
+            ..Default::default()
+        };
+        let xmp = generate_xmp_metadata(&metadata).unwrap();
+
+        assert!(!xmp.contains("pdfaid"));
+        assert!(xmp.contains("fx:ConformanceLevel>MINIMUM</fx:ConformanceLevel"));
     }
 }
@@ -0,0 +1,57 @@
+//! Empreintes SHA-256 des factures pour l'archivage
+//!
+//! L'empreinte du PDF final est consignée dans un registre pour prouver
+//! l'inaltérabilité de la facture archivée. Chaîner chaque empreinte avec la
+//! précédente forme une séquence dont toute modification ou suppression est
+//! détectable.
+
+use sha2::{Digest, Sha256};
+
+/// Empreinte SHA-256 (hexadécimal minuscule) d'un PDF de facture
+pub fn invoice_fingerprint(pdf_bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(pdf_bytes))
+}
+
+/// Empreinte chaînée : SHA-256 de l'empreinte chaînée précédente suivie de
+/// l'empreinte de la facture (les deux en hexadécimal)
+pub fn chain_fingerprint(previous: &str, fingerprint: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(previous.trim().to_ascii_lowercase().as_bytes());
+    hasher.update(fingerprint.as_bytes());
+    to_hex(&hasher.finalize())
+}
+
+/// Vérifie qu'une chaîne est une empreinte SHA-256 hexadécimale
+pub fn is_valid_fingerprint(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invoice_fingerprint() {
+        assert_eq!(
+            invoice_fingerprint(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(is_valid_fingerprint(&invoice_fingerprint(b"%PDF-1.7")));
+    }
+
+    #[test]
+    fn test_chain_fingerprint() {
+        let first = invoice_fingerprint(b"facture 1");
+        let second = invoice_fingerprint(b"facture 2");
+
+        let chained = chain_fingerprint(&first, &second);
+        assert!(is_valid_fingerprint(&chained));
+        assert_eq!(chained, chain_fingerprint(&first.to_uppercase(), &second));
+        assert_ne!(chained, chain_fingerprint(&second, &first));
+        assert_ne!(chained, second);
+    }
+}
@@ -1,20 +1,518 @@
 //! Bibliothèque Factur-X pour la génération de factures PDF/A-3
 
+pub mod batch;
 pub mod facturx;
+pub mod fingerprint;
 pub mod models;
+pub mod storage;
 
+use models::error::FieldError;
+use models::validation::{
+    is_valid_gln, is_valid_iban, is_valid_siren, is_valid_siret, is_valid_vat_number,
+    normalize_phone,
+};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+/// Mention obligatoire pour les prestataires ayant opté pour la TVA sur les encaissements
+pub const VAT_ON_PAYMENTS_MENTION: &str = "TVA acquittée sur les encaissements";
+
+/// Mention obligatoire des entreprises en franchise en base de TVA
+pub const VAT_FRANCHISE_MENTION: &str = "TVA non applicable, art. 293 B du CGI";
+
+/// Mention des factures dont le montant à payer est nul (gratuité, remise totale)
+pub const FREE_OF_CHARGE_MENTION: &str = "Facture à titre gracieux";
+
+/// Mention des factures réglées à l'émission, suivie de la date de paiement
+pub const PAID_MENTION: &str = "Facture acquittée le";
+
 /// Configuration de l'émetteur de factures
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct EmitterConfig {
     pub siren: Option<String>,
+    #[serde(default)]
     pub siret: String,
+    /// Émet le SIREN (au lieu du SIRET) comme identifiant légal du vendeur dans le XML
+    #[serde(default)]
+    pub siren_as_legal_id: bool,
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub address: String,
     pub bic: Option<String>,
+    /// IBAN du compte à créditer, émis comme moyen de paiement par virement
+    /// (BG-16) à partir du profil BASIC WL
+    pub iban: Option<String>,
     pub num_tva: Option<String>,
+    /// GLN de l'émetteur (Global Location Number, schéma 0088)
+    pub gln: Option<String>,
+    /// Téléphone de contact (normalisé au format international à l'émission)
+    pub phone: Option<String>,
+    /// Adresse e-mail de contact, imprimée dans l'en-tête du PDF (lien `mailto:`)
+    pub email: Option<String>,
+    /// Site web, imprimé dans l'en-tête du PDF (lien `https://` si aucun
+    /// schéma n'est précisé)
+    pub website: Option<String>,
+    /// TVA sur les encaissements : ajoute la mention obligatoire sur les factures
+    #[serde(default)]
+    pub vat_on_payments: bool,
+    /// Franchise en base de TVA (art. 293 B du CGI) : factures sans TVA
+    #[serde(default)]
+    pub vat_exempt_franchise: bool,
     pub logo: Option<String>,
     pub xml_storage: Option<String>,
     pub pdf_storage: Option<String>,
+    /// Forme juridique (ex. "SARL"), imprimée avec le capital et le RCS
+    pub legal_form: Option<String>,
+    /// Capital social (ex. "10 000 €")
+    pub capital: Option<String>,
+    /// Immatriculation au RCS (ex. "RCS Paris 123 456 789")
+    pub rcs: Option<String>,
+    /// Format imposé aux numéros de facture (expression régulière, ex. `^FA-\d{4}-\d{4}$`)
+    #[serde(default)]
+    pub invoice_number_regex: Option<String>,
+}
+
+impl EmitterConfig {
+    /// Téléphone de contact au format international (émetteur établi en France)
+    pub fn normalized_phone(&self) -> Option<String> {
+        self.phone
+            .as_deref()
+            .and_then(|phone| normalize_phone(phone, "FR"))
+    }
+
+    /// Liens de contact de l'en-tête du PDF : texte imprimé et URI cible
+    /// (`mailto:` pour l'e-mail, `https://` ajouté à un site sans schéma)
+    pub fn contact_links(&self) -> Vec<(String, String)> {
+        let mut links = Vec::new();
+        if let Some(email) = non_empty(&self.email) {
+            links.push((format!("Email: {}", email), format!("mailto:{}", email)));
+        }
+        if let Some(website) = non_empty(&self.website) {
+            let uri = if website.starts_with("http://") || website.starts_with("https://") {
+                website.to_string()
+            } else {
+                format!("https://{}", website)
+            };
+            links.push((format!("Web: {}", website), uri));
+        }
+        links
+    }
+
+    /// IBAN sans espaces, en majuscules (`None` si absent ou vide)
+    pub fn normalized_iban(&self) -> Option<String> {
+        let iban: String = self
+            .iban
+            .as_deref()?
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        (!iban.is_empty()).then_some(iban)
+    }
+
+    /// Mentions légales de la société : "SARL au capital de 10 000 € - RCS
+    /// Paris 123 456 789" (`None` si ni forme, ni capital, ni RCS)
+    pub fn legal_mentions(&self) -> Option<String> {
+        let company = match (non_empty(&self.legal_form), non_empty(&self.capital)) {
+            (Some(form), Some(capital)) => Some(format!("{} au capital de {}", form, capital)),
+            (Some(form), None) => Some(form.to_string()),
+            (None, Some(capital)) => Some(format!("Capital de {}", capital)),
+            (None, None) => None,
+        };
+        let mentions: Vec<String> = company
+            .into_iter()
+            .chain(non_empty(&self.rcs).map(str::to_string))
+            .collect();
+        (!mentions.is_empty()).then(|| mentions.join(" - "))
+    }
+
+    /// Expression régulière compilée des numéros de facture (`None` si non configurée)
+    pub fn invoice_number_pattern(&self) -> Result<Option<Regex>, FieldError> {
+        let Some(pattern) = self.invoice_number_regex.as_deref() else {
+            return Ok(None);
+        };
+        Regex::new(pattern).map(Some).map_err(|e| {
+            FieldError::new(
+                "invoice_number_regex",
+                format!(
+                    "Le format des numeros de facture ({}) est invalide: {}",
+                    pattern, e
+                ),
+            )
+        })
+    }
+
+    /// Valide la configuration de l'émetteur au chargement
+    ///
+    /// Une configuration invalide produirait des factures non conformes :
+    /// le serveur refuse de démarrer plutôt que de les générer.
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let siret: String = self.siret.chars().filter(|c| !c.is_whitespace()).collect();
+        if siret.is_empty() {
+            errors.push(FieldError::new(
+                "siret",
+                "Le SIRET de l'emetteur est obligatoire",
+            ));
+        } else if !is_valid_siret(&siret) {
+            errors.push(FieldError::new(
+                "siret",
+                format!(
+                    "Le SIRET de l'emetteur ({}) doit contenir 14 chiffres avec une cle valide",
+                    self.siret
+                ),
+            ));
+        }
+
+        let siren = self.siren.as_deref().map(str::trim).unwrap_or_default();
+        if siren.is_empty() {
+            if self.siren_as_legal_id {
+                errors.push(FieldError::new(
+                    "siren",
+                    "Le SIREN de l'emetteur est obligatoire avec siren_as_legal_id",
+                ));
+            }
+        } else if !is_valid_siren(siren) {
+            errors.push(FieldError::new(
+                "siren",
+                format!(
+                    "Le SIREN de l'emetteur ({}) doit contenir 9 chiffres avec une cle valide",
+                    siren
+                ),
+            ));
+        }
+
+        if self.name.trim().is_empty() {
+            errors.push(FieldError::new(
+                "name",
+                "Le nom de l'emetteur est obligatoire",
+            ));
+        }
+
+        if self.address.trim().is_empty() {
+            errors.push(FieldError::new(
+                "address",
+                "L'adresse de l'emetteur est obligatoire",
+            ));
+        }
+
+        if let Some(ref num_tva) = self.num_tva {
+            if !num_tva.trim().is_empty() && !is_valid_vat_number(num_tva.trim()) {
+                errors.push(FieldError::new(
+                    "num_tva",
+                    format!(
+                        "Le numero de TVA de l'emetteur ({}) est invalide (format ou cle de controle)",
+                        num_tva
+                    ),
+                ));
+            }
+        }
+
+        if self.vat_exempt_franchise {
+            if self
+                .num_tva
+                .as_deref()
+                .is_some_and(|num_tva| !num_tva.trim().is_empty())
+            {
+                errors.push(FieldError::new(
+                    "num_tva",
+                    "Le numero de TVA doit etre absent en franchise en base de TVA",
+                ));
+            }
+            if self.vat_on_payments {
+                errors.push(FieldError::new(
+                    "vat_on_payments",
+                    "La TVA sur les encaissements est incompatible avec la franchise en base de TVA",
+                ));
+            }
+        }
+
+        if let Some(ref phone) = self.phone {
+            if !phone.trim().is_empty() && self.normalized_phone().is_none() {
+                errors.push(FieldError::new(
+                    "phone",
+                    format!("Le telephone de l'emetteur ({}) est invalide", phone),
+                ));
+            }
+        }
+
+        if let Some(email) = self.email.as_deref().map(str::trim) {
+            let valid = email.split_once('@').is_some_and(|(user, domain)| {
+                !user.is_empty() && domain.contains('.') && !domain.contains('@')
+            });
+            if !email.is_empty() && (!valid || email.contains(char::is_whitespace)) {
+                errors.push(FieldError::new(
+                    "email",
+                    format!("L'e-mail de l'emetteur ({}) est invalide", email),
+                ));
+            }
+        }
+
+        if let Some(website) = self.website.as_deref().map(str::trim) {
+            let scheme = website.split_once("://").map(|(scheme, _)| scheme);
+            if website.contains(char::is_whitespace)
+                || scheme.is_some_and(|scheme| !matches!(scheme, "http" | "https"))
+            {
+                errors.push(FieldError::new(
+                    "website",
+                    format!(
+                        "Le site web de l'emetteur ({}) est invalide (adresse http ou https attendue)",
+                        website
+                    ),
+                ));
+            }
+        }
+
+        if let Some(iban) = self.normalized_iban() {
+            if !is_valid_iban(&iban) {
+                errors.push(FieldError::new(
+                    "iban",
+                    format!(
+                        "L'IBAN de l'emetteur ({}) est invalide (format ou cle de controle)",
+                        iban
+                    ),
+                ));
+            }
+        }
+
+        // GLN vide ignoré, comme dans le XML
+        if let Some(gln) = non_empty(&self.gln) {
+            if !is_valid_gln(gln) {
+                errors.push(FieldError::new(
+                    "gln",
+                    format!("GLN de l'emetteur invalide ({})", gln),
+                ));
+            }
+        }
+
+        if let Err(error) = self.invoice_number_pattern() {
+            errors.push(error);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Valeur de configuration renseignée (hors espaces)
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml_content: &str) -> EmitterConfig {
+        toml::from_str(toml_content).expect("configuration de test invalide")
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            num_tva = "FR40303265045"
+            "#,
+        );
+        assert!(emitter.validate().is_ok());
+    }
+
+    #[test]
+    fn test_missing_siret() {
+        let emitter = parse(
+            r#"
+            name = "Mon Entreprise SARL"
+            address = "   "
+            "#,
+        );
+        let errors = emitter.validate().unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["siret", "address"]);
+    }
+
+    #[test]
+    fn test_siren_as_legal_id_requires_siren() {
+        let emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            siren_as_legal_id = true
+            "#,
+        );
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors[0].field, "siren");
+    }
+
+    #[test]
+    fn test_invalid_siret_key() {
+        let emitter = parse(
+            r#"
+            siret = "73282932000075"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            "#,
+        );
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors[0].field, "siret");
+    }
+
+    #[test]
+    fn test_franchise_rejects_vat_number() {
+        let emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Jean Dupont EI"
+            address = "3 place du Marche, 69001 Lyon"
+            num_tva = "FR40303265045"
+            vat_exempt_franchise = true
+            "#,
+        );
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "num_tva");
+    }
+
+    #[test]
+    fn test_gln_trimmed_and_blank_ignored() {
+        let config = |gln: &str| {
+            parse(&format!(
+                r#"
+                siret = "73282932000074"
+                name = "Mon Entreprise SARL"
+                address = "12 rue de la Paix, 75001 Paris"
+                gln = "{}"
+                "#,
+                gln
+            ))
+        };
+        assert!(config(" 3012345000000 ").validate().is_ok());
+        assert!(config("").validate().is_ok());
+        assert!(config("   ").validate().is_ok());
+
+        let errors = config("3012345000001").validate().unwrap_err();
+        assert_eq!(errors[0].field, "gln");
+    }
+
+    #[test]
+    fn test_invalid_iban() {
+        let mut emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            iban = "fr76 3000 6000 0112 3456 7890 189"
+            "#,
+        );
+        assert!(emitter.validate().is_ok());
+        assert_eq!(
+            emitter.normalized_iban().as_deref(),
+            Some("FR7630006000011234567890189")
+        );
+
+        emitter.iban = Some("FR76 3000 6000 0112 3456 7890 188".to_string());
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors[0].field, "iban");
+    }
+
+    #[test]
+    fn test_contact_links() {
+        let mut emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            email = "contact@entreprise.fr"
+            website = "www.entreprise.fr"
+            "#,
+        );
+        assert!(emitter.validate().is_ok());
+        assert_eq!(
+            emitter.contact_links(),
+            vec![
+                (
+                    "Email: contact@entreprise.fr".to_string(),
+                    "mailto:contact@entreprise.fr".to_string()
+                ),
+                (
+                    "Web: www.entreprise.fr".to_string(),
+                    "https://www.entreprise.fr".to_string()
+                ),
+            ]
+        );
+
+        emitter.email = Some("contact at entreprise.fr".to_string());
+        emitter.website = Some("ftp://entreprise.fr".to_string());
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors[0].field, "email");
+        assert_eq!(errors[1].field, "website");
+    }
+
+    #[test]
+    fn test_invalid_french_vat_key() {
+        let emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            num_tva = "FR41303265045"
+            "#,
+        );
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "num_tva");
+        assert!(errors[0].message.contains("cle de controle"));
+    }
+
+    #[test]
+    fn test_invalid_invoice_number_regex() {
+        let emitter = parse(
+            r#"
+            siret = "73282932000074"
+            name = "Mon Entreprise SARL"
+            address = "12 rue de la Paix, 75001 Paris"
+            invoice_number_regex = "^FA-(\\d{4}$"
+            "#,
+        );
+        let errors = emitter.validate().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "invoice_number_regex");
+    }
+
+    #[test]
+    fn test_legal_mentions() {
+        let mut emitter = parse(
+            r#"
+            siret = "73282932000074"
+            legal_form = "SARL"
+            capital = "10 000 €"
+            rcs = "RCS Paris 123 456 789"
+            "#,
+        );
+        assert_eq!(
+            emitter.legal_mentions().as_deref(),
+            Some("SARL au capital de 10 000 € - RCS Paris 123 456 789")
+        );
+
+        emitter.legal_form = None;
+        emitter.rcs = Some("  ".to_string());
+        assert_eq!(
+            emitter.legal_mentions().as_deref(),
+            Some("Capital de 10 000 €")
+        );
+
+        emitter.capital = None;
+        assert_eq!(emitter.legal_mentions(), None);
+    }
 }
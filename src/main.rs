@@ -1,25 +1,43 @@
+use facturx_create::batch::BatchArchive;
 use facturx_create::facturx;
+use facturx_create::fingerprint;
 use facturx_create::models;
+use facturx_create::storage::{safe_invoice_filename, FsStorage, InvoiceFileKind, Storage};
 use facturx_create::EmitterConfig;
 
 use axum::body::Body;
-use axum::extract::Multipart;
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::{DefaultBodyLimit, Multipart, Path, Query};
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{Html, IntoResponse, Json, Redirect, Response},
     routing::{get, post},
     Router,
 };
-use serde::Serialize;
-use std::collections::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Seek, Write};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tera::{Context, Tera};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
 use tower_http::services::ServeDir;
 
+use models::business_rules::{check_amount_limits, validate_business_rules};
+use models::catalog::Catalog;
 use models::error::{FieldError, ValidationResponse};
-use models::invoice::{InvoiceForm, InvoiceTypeCode};
+use models::invoice::{
+    check_totals_consistency, Installment, InvoiceForm, InvoiceTotals, InvoiceTypeCode,
+};
 use models::line::InvoiceLine;
+use models::rules::{
+    failed_errors, header_rules, invoice_number_rule, line_rules, max_lines_rule, HeaderFields,
+    RuleResult,
+};
+use models::validation::parse_decimal;
 
 /// Retourne le chemin URL du logo pour les templates HTML
 /// Transforme un chemin relatif (./assets/logo.jpeg) en URL web (/assets/logo.jpeg)
@@ -38,58 +56,80 @@ fn get_logo_path(emitter: &EmitterConfig) -> String {
     }
 }
 
-/// Retourne le chemin fichier du logo pour la génération PDF
+/// Retourne le logo fichier pour la génération PDF
 /// Garde le chemin relatif à la racine du projet
-fn get_logo_file_path(emitter: &EmitterConfig) -> Option<String> {
+fn get_logo_source(emitter: &EmitterConfig) -> Option<facturx::LogoSource> {
     match &emitter.logo {
         Some(logo) if !logo.trim().is_empty() => {
             // Nettoyer le chemin: ./assets/x -> assets/x
             let path = logo.trim_start_matches("./");
-            Some(path.to_string())
+            Some(facturx::LogoSource::Path(path.to_string()))
         }
         _ => None,
     }
 }
 
-/// Nettoie un chemin de stockage (supprime ./ au début)
-fn clean_storage_path(path: &str) -> String {
-    path.trim_start_matches("./").to_string()
-}
-
-/// Sauvegarde un fichier dans le répertoire spécifié
-/// Retourne une erreur si le fichier existe déjà (numéro de facture dupliqué)
-fn save_invoice_file(
-    storage_path: &str,
-    invoice_number: &str,
-    extension: &str,
-    content: &[u8],
-) -> Result<(), String> {
-    let dir_path = std::path::Path::new(storage_path);
+/// Construit l'en-tête Content-Disposition d'un téléchargement
+/// `filename` porte un repli ASCII, `filename*` le nom exact encodé en UTF-8 (RFC 5987)
+fn content_disposition_for(name: &str) -> String {
+    let ascii_fallback: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
 
-    // Créer le répertoire si nécessaire
-    if !dir_path.exists() {
-        std::fs::create_dir_all(dir_path)
-            .map_err(|e| format!("Impossible de créer le répertoire {}: {}", storage_path, e))?;
+    let mut encoded = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
     }
 
-    // Nettoyer le numéro de facture pour le nom de fichier
-    let safe_filename = invoice_number.replace(['/', '\\', ' ', ':'], "_");
-    let filename = format!("{}.{}", safe_filename, extension);
-    let file_path = dir_path.join(&filename);
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii_fallback, encoded
+    )
+}
 
-    // Vérifier si le fichier existe déjà
-    if file_path.exists() {
-        return Err(format!(
-            "Une facture avec le numéro '{}' existe déjà. Le numéro de facture doit être unique.",
-            invoice_number
-        ));
-    }
+/// En-tête listant les écarts PDF/A-3 d'un PDF non conforme toléré
+const PDFA_WARNINGS_HEADER: &str = "X-Pdfa-Warnings";
 
-    // Sauvegarder le fichier
-    std::fs::write(&file_path, content)
-        .map_err(|e| format!("Impossible de sauvegarder {}: {}", file_path.display(), e))?;
+/// En-tête listant les avertissements de vraisemblance des montants
+const AMOUNT_WARNINGS_HEADER: &str = "X-Amount-Warnings";
 
-    Ok(())
+/// Joint des avertissements en une valeur d'en-tête HTTP (ASCII visible)
+fn warnings_header(warnings: &[String]) -> String {
+    warnings
+        .join("; ")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Valide un numéro de facture reçu dans l'URL avant de l'utiliser comme nom de fichier
+/// Rejette tout segment permettant de sortir du répertoire de stockage
+fn sanitize_invoice_number_segment(number: &str) -> Option<String> {
+    if number.trim().is_empty()
+        || number.contains("..")
+        || number.contains(['/', '\\'])
+        || number.chars().any(|c| c.is_control())
+    {
+        return None;
+    }
+    Some(safe_invoice_filename(number))
 }
 
 // Données de session pour l'étape 1
@@ -98,19 +138,82 @@ struct InvoiceSession {
     invoice_number: String,
     issue_date: String,
     issue_date_display: String, // Format DD/MM/YYYY pour affichage
-    type_code: u16,
+    type_code: InvoiceTypeCode,
     type_label: String,
+    /// Type de document saisi mais non reconnu, signalé par `validate_step1`
+    #[serde(skip)]
+    invalid_type_code: Option<String>,
     currency_code: String,
     due_date: Option<String>,
     due_date_display: Option<String>, // Format DD/MM/YYYY pour affichage
     payment_terms: Option<String>,
     buyer_reference: Option<String>,
     purchase_order_reference: Option<String>,
+    tax_type_code: Option<String>,
+    tax_currency_code: Option<String>,
+    tax_exchange_rate: Option<f64>,
     recipient_name: String,
     recipient_siret: String,
     recipient_vat_number: Option<String>,
+    recipient_gln: Option<String>,
     recipient_address: String,
     recipient_country_code: String,
+    recipient_public_sector: bool,
+    recipient_service_code: Option<String>,
+}
+
+impl From<&InvoiceForm> for InvoiceSession {
+    fn from(form: &InvoiceForm) -> Self {
+        Self {
+            invoice_number: form.invoice_number.clone(),
+            issue_date: form.issue_date.clone(),
+            issue_date_display: format_date_display(&form.issue_date),
+            type_code: form.type_code,
+            type_label: form.type_code.label().to_string(),
+            invalid_type_code: None,
+            currency_code: form.currency_code.clone(),
+            due_date: form.due_date.clone(),
+            due_date_display: form.due_date.as_deref().map(format_date_display),
+            payment_terms: form.payment_terms.clone(),
+            buyer_reference: form.buyer_reference.clone(),
+            purchase_order_reference: form.purchase_order_reference.clone(),
+            tax_type_code: form.tax_type_code.clone(),
+            tax_currency_code: form.tax_currency_code.clone(),
+            tax_exchange_rate: form.tax_exchange_rate,
+            recipient_name: form.recipient_name.clone(),
+            recipient_siret: form.recipient_siret.clone(),
+            recipient_vat_number: form.recipient_vat_number.clone(),
+            recipient_gln: form.recipient_gln.clone(),
+            recipient_address: form.recipient_address.clone(),
+            recipient_country_code: form.recipient_country_code.clone(),
+            recipient_public_sector: form.recipient_public_sector,
+            recipient_service_code: form.recipient_service_code.clone(),
+        }
+    }
+}
+
+impl InvoiceSession {
+    /// Champs contrôlés par les règles d'en-tête
+    fn header_fields(&self) -> HeaderFields<'_> {
+        HeaderFields {
+            invoice_number: &self.invoice_number,
+            issue_date: &self.issue_date,
+            currency_code: &self.currency_code,
+            payment_terms: self.payment_terms.as_deref(),
+            buyer_reference: self.buyer_reference.as_deref(),
+            purchase_order_reference: self.purchase_order_reference.as_deref(),
+            tax_type_code: self.tax_type_code.as_deref(),
+            tax_currency_code: self.tax_currency_code.as_deref(),
+            tax_exchange_rate: self.tax_exchange_rate,
+            recipient_name: &self.recipient_name,
+            recipient_siret: &self.recipient_siret,
+            recipient_vat_number: self.recipient_vat_number.as_deref(),
+            recipient_gln: self.recipient_gln.as_deref(),
+            recipient_address: &self.recipient_address,
+            recipient_country_code: &self.recipient_country_code,
+            recipient_service_code: self.recipient_service_code.as_deref(),
+        }
+    }
 }
 
 /// Convertit une date YYYY-MM-DD en DD/MM/YYYY
@@ -124,11 +227,217 @@ fn format_date_display(date: &str) -> String {
     date.to_string()
 }
 
+/// Paramètres du serveur (section `[server]` optionnelle de config/emitter.toml)
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+struct ServerConfig {
+    /// Taille maximale d'un champ de formulaire multipart (octets)
+    max_field_bytes: usize,
+    /// Taille maximale du corps complet d'une requête (octets)
+    max_body_bytes: usize,
+    /// Nombre maximal de générations PDF simultanées (facture unique ou lot)
+    max_concurrent_generations: usize,
+    /// Délai maximal de génération d'une facture (secondes)
+    generation_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_field_bytes: 64 * 1024,
+            max_body_bytes: 2 * 1024 * 1024,
+            max_concurrent_generations: 4,
+            generation_timeout_secs: 30,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Valide les paramètres du serveur au chargement
+    fn validate(&self) -> Result<(), String> {
+        if self.max_concurrent_generations == 0 {
+            return Err("max_concurrent_generations doit etre superieur a 0".to_string());
+        }
+        if self.generation_timeout_secs == 0 {
+            return Err("generation_timeout_secs doit etre superieur a 0".to_string());
+        }
+        Ok(())
+    }
+
+    fn generation_timeout(&self) -> Duration {
+        Duration::from_secs(self.generation_timeout_secs)
+    }
+}
+
+/// Sections du fichier de configuration propres au serveur
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default)]
+    server: ServerConfig,
+    /// Options de génération Factur-X (section `[generation]`)
+    #[serde(default)]
+    generation: facturx::GenerationOptions,
+}
+
 #[derive(Clone)]
 struct AppState {
     emitter: EmitterConfig,
+    /// `invoice_number_regex` de l'émetteur, compilée au démarrage
+    invoice_number_pattern: Option<Regex>,
+    server: ServerConfig,
+    generation: facturx::GenerationOptions,
     tera: Tera,
     session: Arc<RwLock<Option<InvoiceSession>>>,
+    /// Places de génération PDF (`max_concurrent_generations`)
+    generation_permits: Arc<Semaphore>,
+    /// Stockage des factures générées (`xml_storage` et `pdf_storage`)
+    storage: Arc<dyn Storage>,
+}
+
+/// Délai conseillé (secondes) avant de réessayer une génération refusée
+const GENERATION_RETRY_AFTER_SECS: u64 = 5;
+
+/// Réserve une place de génération, libérée quand la permission est relâchée
+/// (fin de la requête, ou du flux d'un lot)
+///
+/// Chaque génération charge les polices et valide le PDF/A : sans limite, un
+/// afflux de requêtes peut épuiser la mémoire. `None` si toutes les places
+/// sont occupées (voir `generation_busy_response`).
+fn acquire_generation_permit(permits: &Arc<Semaphore>) -> Option<OwnedSemaphorePermit> {
+    permits.clone().try_acquire_owned().ok()
+}
+
+/// Réponse 503 d'une génération refusée faute de place, avec `Retry-After`
+fn generation_busy_response() -> Response {
+    let response = ValidationResponse::with_errors(vec![FieldError::new(
+        "_form",
+        "Trop de generations en cours, veuillez reessayer dans quelques secondes",
+    )]);
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, GENERATION_RETRY_AFTER_SECS.to_string())],
+        Json(response),
+    )
+        .into_response()
+}
+
+/// Erreur d'une génération : statut HTTP et erreurs à renvoyer
+type GenerationError = (StatusCode, Vec<FieldError>);
+
+/// Exécute une génération (XML, PDF) ou la sauvegarde de ses fichiers hors
+/// du runtime async, dans la limite de `timeout`
+///
+/// Le rendu krilla, la validation lopdf et le chargement des polices sont
+/// bloquants : ils occuperaient sinon un thread du runtime. Au-delà du délai
+/// la requête échoue en 504 ; la tâche bloquante ne peut pas être
+/// interrompue et se termine en arrière-plan, en gardant ce qu'elle possède
+/// (la place de génération notamment) jusqu'à sa fin.
+async fn run_generation<T, F>(timeout: Duration, generate: F) -> Result<T, GenerationError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, GenerationError> + Send + 'static,
+{
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(generate)).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            vec![FieldError::new(
+                "_form",
+                format!("Erreur generation: tache interrompue ({})", e),
+            )],
+        )),
+        Err(_) => Err((
+            StatusCode::GATEWAY_TIMEOUT,
+            vec![FieldError::new(
+                "_form",
+                format!(
+                    "La generation a depasse le delai de {} s",
+                    timeout.as_secs_f64()
+                ),
+            )],
+        )),
+    }
+}
+
+/// Erreur de lecture d'un formulaire multipart
+#[derive(Debug)]
+enum FormError {
+    /// Données mal formées (400)
+    Parse(String),
+    /// Champ ou requête trop volumineux (413)
+    TooLarge(FieldError),
+    /// Valeurs illisibles, signalées par champ (400)
+    Invalid(Vec<FieldError>),
+}
+
+impl From<MultipartError> for FormError {
+    fn from(e: MultipartError) -> Self {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            FormError::TooLarge(FieldError::new(
+                "_form",
+                "La requete depasse la taille maximale autorisee",
+            ))
+        } else {
+            FormError::Parse(e.body_text())
+        }
+    }
+}
+
+impl IntoResponse for FormError {
+    fn into_response(self) -> Response {
+        match self {
+            FormError::Parse(e) => {
+                let response = ValidationResponse::with_errors(vec![FieldError::new(
+                    "_form",
+                    format!("Erreur de parsing: {}", e),
+                )]);
+                (StatusCode::BAD_REQUEST, Json(response)).into_response()
+            }
+            FormError::TooLarge(error) => {
+                let response = ValidationResponse::with_errors(vec![error]);
+                (StatusCode::PAYLOAD_TOO_LARGE, Json(response)).into_response()
+            }
+            FormError::Invalid(errors) => {
+                let response = ValidationResponse::with_errors(errors);
+                (StatusCode::BAD_REQUEST, Json(response)).into_response()
+            }
+        }
+    }
+}
+
+/// Lit le texte d'un champ multipart en refusant les champs de plus de `max_bytes` octets
+async fn read_field_text(
+    field: Field<'_>,
+    name: &str,
+    max_bytes: usize,
+) -> Result<String, FormError> {
+    let bytes = read_field_bytes(field, name, max_bytes).await?;
+    String::from_utf8(bytes)
+        .map_err(|_| FormError::Parse(format!("le champ {} n'est pas en UTF-8", name)))
+}
+
+/// Lit le contenu brut d'un champ multipart (fichier), limité à `max_bytes` octets
+async fn read_field_bytes(
+    mut field: Field<'_>,
+    name: &str,
+    max_bytes: usize,
+) -> Result<Vec<u8>, FormError> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = field.chunk().await? {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(FormError::TooLarge(FieldError::new(
+                name,
+                format!(
+                    "Le champ depasse la taille maximale autorisee ({} octets)",
+                    max_bytes
+                ),
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
 }
 
 #[tokio::main]
@@ -137,11 +446,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config_path = "config/emitter.toml";
     let config_content = tokio::fs::read_to_string(config_path).await?;
     let emitter: EmitterConfig = toml::from_str(&config_content)?;
-
+    if let Err(errors) = emitter.validate() {
+        let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+        return Err(format!("{}: {}", config_path, messages.join("; ")).into());
+    }
+    let invoice_number_pattern = emitter
+        .invoice_number_pattern()
+        .map_err(|e| format!("{}: {}", config_path, e.message))?;
+    let ConfigFile { server, generation } = toml::from_str(&config_content)?;
+    server
+        .validate()
+        .map_err(|e| format!("{}: {}", config_path, e))?;
+    generation
+        .validate()
+        .map_err(|e| format!("{}: {}", config_path, e))?;
+    // Polices : echec immediat plutot qu'a la premiere facture generee
+    facturx::FontSet::verify()?;
+    let max_body_bytes = server.max_body_bytes;
+
+    let generation_permits = Arc::new(Semaphore::new(server.max_concurrent_generations));
+    let storage = Arc::new(FsStorage::from_config(&emitter));
     let app_state = Arc::new(AppState {
         emitter,
+        invoice_number_pattern,
+        server,
+        generation,
         tera: Tera::new("templates/**/*")?,
         session: Arc::new(RwLock::new(None)),
+        generation_permits,
+        storage,
     });
 
     let app = Router::new()
@@ -149,7 +482,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/invoice/step1", post(step1_submit))
         .route("/invoice/step2", get(step2_page))
         .route("/invoice", post(create_invoice))
+        .route("/invoice/:file", get(download_invoice_file))
+        .route("/api/invoices/batch", post(create_invoice_batch))
+        .route("/api/invoice/:number/hash", get(invoice_hash))
+        .route("/api/profiles", get(list_profiles))
+        .route("/api/validate", post(validate_invoice))
+        .route("/api/pdf/reembed", post(reembed_pdf_xml))
+        .route("/api/debug/dry-run", post(dry_run_invoice))
         .nest_service("/assets", ServeDir::new("assets"))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
         .with_state(app_state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
@@ -168,19 +509,13 @@ async fn step1_page(State(state): State<Arc<AppState>>) -> Html<String> {
 
 // Soumission étape 1
 async fn step1_submit(State(state): State<Arc<AppState>>, multipart: Multipart) -> Response {
-    let data = match parse_step1_data(multipart).await {
+    let data = match parse_step1_data(multipart, state.server.max_field_bytes).await {
         Ok(data) => data,
-        Err(e) => {
-            let response = ValidationResponse::with_errors(vec![FieldError::new(
-                "_form",
-                format!("Erreur de parsing: {}", e),
-            )]);
-            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
-        }
+        Err(e) => return e.into_response(),
     };
 
     // Validation des champs de l'étape 1
-    let errors = validate_step1(&data);
+    let errors = validate_step1(&data, state.invoice_number_pattern.as_ref());
     if !errors.is_empty() {
         let response = ValidationResponse::with_errors(errors);
         return (StatusCode::BAD_REQUEST, Json(response)).into_response();
@@ -217,25 +552,38 @@ async fn step2_page(State(state): State<Arc<AppState>>) -> Response {
 }
 
 /// Parse les données de l'étape 1
-async fn parse_step1_data(mut multipart: Multipart) -> Result<InvoiceSession, String> {
+///
+/// Le type de document vaut 380 (facture) si le champ est absent ; une valeur
+/// non reconnue est conservée pour être refusée par `validate_step1`, plutôt
+/// que de transformer silencieusement un avoir en facture.
+async fn parse_step1_data(
+    mut multipart: Multipart,
+    max_field_bytes: usize,
+) -> Result<InvoiceSession, FormError> {
     let mut data = InvoiceSession::default();
-    data.type_code = 380;
+    data.type_code = InvoiceTypeCode::Invoice;
     data.currency_code = String::from("EUR");
     data.recipient_country_code = String::from("FR");
 
-    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+    while let Some(field) = multipart.next_field().await? {
         let name = field.name().unwrap_or_default().to_string();
-        let value = field.text().await.map_err(|e| e.to_string())?;
+        let value = read_field_text(field, &name, max_field_bytes).await?;
 
         match name.as_str() {
             "invoice_number" => data.invoice_number = value,
             "issue_date" => data.issue_date = value,
-            "type_code" => {
-                data.type_code = value.parse().unwrap_or(380);
-                data.type_label = InvoiceTypeCode::from_code(data.type_code)
-                    .map(|t| t.label().to_string())
-                    .unwrap_or_else(|| "Facture".to_string());
-            }
+            "type_code" => match value
+                .trim()
+                .parse()
+                .ok()
+                .and_then(InvoiceTypeCode::from_code)
+            {
+                Some(type_code) => {
+                    data.type_code = type_code;
+                    data.invalid_type_code = None;
+                }
+                None => data.invalid_type_code = Some(value),
+            },
             "currency_code" => data.currency_code = value,
             "due_date" => {
                 data.due_date = if value.trim().is_empty() {
@@ -265,6 +613,28 @@ async fn parse_step1_data(mut multipart: Multipart) -> Result<InvoiceSession, St
                     Some(value)
                 }
             }
+            "tax_type_code" => {
+                data.tax_type_code = if value.trim().is_empty() {
+                    None
+                } else {
+                    Some(value.trim().to_uppercase())
+                }
+            }
+            "tax_currency_code" => {
+                data.tax_currency_code = if value.trim().is_empty() {
+                    None
+                } else {
+                    Some(value.trim().to_uppercase())
+                }
+            }
+            "tax_exchange_rate" => {
+                // Un taux illisible est conservé à 0 pour être signalé par la validation
+                data.tax_exchange_rate = if value.trim().is_empty() {
+                    None
+                } else {
+                    Some(parse_decimal(&value).unwrap_or(0.0))
+                }
+            }
             "recipient_name" => data.recipient_name = value,
             "recipient_siret" => data.recipient_siret = value,
             "recipient_vat_number" => {
@@ -274,12 +644,31 @@ async fn parse_step1_data(mut multipart: Multipart) -> Result<InvoiceSession, St
                     Some(value)
                 }
             }
+            "recipient_gln" => {
+                data.recipient_gln = if value.trim().is_empty() {
+                    None
+                } else {
+                    Some(value.trim().to_string())
+                }
+            }
             "recipient_address" => data.recipient_address = value,
             "recipient_country_code" => data.recipient_country_code = value,
+            "recipient_public_sector" => {
+                data.recipient_public_sector = matches!(value.trim(), "on" | "true" | "1")
+            }
+            "recipient_service_code" => {
+                data.recipient_service_code = if value.trim().is_empty() {
+                    None
+                } else {
+                    Some(value.trim().to_string())
+                }
+            }
             _ => {}
         }
     }
 
+    data.type_label = data.type_code.label().to_string();
+
     // Formatage des dates pour affichage (DD/MM/YYYY)
     data.issue_date_display = format_date_display(&data.issue_date);
     data.due_date_display = data.due_date.as_ref().map(|d| format_date_display(d));
@@ -288,89 +677,111 @@ async fn parse_step1_data(mut multipart: Multipart) -> Result<InvoiceSession, St
 }
 
 /// Validation de l'étape 1
-fn validate_step1(data: &InvoiceSession) -> Vec<FieldError> {
-    let mut errors = Vec::new();
-
-    if data.invoice_number.trim().is_empty() {
-        errors.push(FieldError::new(
-            "invoice_number",
-            "Le numero de facture est obligatoire",
-        ));
-    }
-
-    if data.issue_date.trim().is_empty() {
-        errors.push(FieldError::new(
-            "issue_date",
-            "La date d'emission est obligatoire",
-        ));
-    }
-
-    if data.recipient_name.trim().is_empty() {
-        errors.push(FieldError::new(
-            "recipient_name",
-            "Le nom du client est obligatoire",
-        ));
-    }
-
-    if data.recipient_siret.trim().is_empty() {
-        errors.push(FieldError::new(
-            "recipient_siret",
-            "Le SIRET du client est obligatoire",
-        ));
-    } else {
-        let cleaned: String = data
-            .recipient_siret
-            .chars()
-            .filter(|c| c.is_ascii_digit())
-            .collect();
-        if cleaned.len() != 14 {
-            errors.push(FieldError::new(
-                "recipient_siret",
-                "Le SIRET doit contenir 14 chiffres",
-            ));
-        }
-    }
-
-    if data.recipient_country_code.trim().is_empty() {
-        errors.push(FieldError::new(
-            "recipient_country_code",
-            "Le pays est obligatoire",
-        ));
-    }
-
-    errors
+fn validate_step1(
+    data: &InvoiceSession,
+    invoice_number_pattern: Option<&Regex>,
+) -> Vec<FieldError> {
+    let mut rules = vec![RuleResult::new(
+        "BT-3-type-code",
+        "Type de document reconnu (380, 381, 384 ou 389)",
+        match &data.invalid_type_code {
+            Some(code) => vec![FieldError::new(
+                "type_code",
+                format!(
+                    "Le type de document '{}' n'est pas reconnu (380, 381, 384 ou 389)",
+                    code.trim()
+                ),
+            )],
+            None => Vec::new(),
+        },
+    )];
+    rules.extend(header_rules(&data.header_fields()));
+    rules.push(invoice_number_rule(
+        &data.invoice_number,
+        invoice_number_pattern,
+    ));
+    failed_errors(rules)
 }
 
 /// Parse les données du formulaire multipart/form-data (étape 2 + données session)
 async fn parse_form_data(
     mut multipart: Multipart,
     session: &InvoiceSession,
-) -> Result<InvoiceForm, String> {
+    max_field_bytes: usize,
+) -> Result<InvoiceForm, FormError> {
     let mut lines_data: HashMap<usize, HashMap<String, String>> = HashMap::new();
+    let mut installments_data: HashMap<usize, HashMap<String, String>> = HashMap::new();
+    let mut invoice_fields: HashMap<String, String> = HashMap::new();
 
-    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+    while let Some(field) = multipart.next_field().await? {
         let name = field.name().unwrap_or_default().to_string();
-        let value = field.text().await.map_err(|e| e.to_string())?;
+        let value = read_field_text(field, &name, max_field_bytes).await?;
 
         if name.starts_with("lines[") {
-            if let Some((index, field_name)) = parse_line_field(&name) {
+            if let Some((index, field_name)) = parse_indexed_field(&name, "lines") {
                 lines_data
                     .entry(index)
                     .or_insert_with(HashMap::new)
                     .insert(field_name, value);
             }
+        } else if name.starts_with("installments[") {
+            if let Some((index, field_name)) = parse_indexed_field(&name, "installments") {
+                installments_data
+                    .entry(index)
+                    .or_default()
+                    .insert(field_name, value);
+            }
+        } else if matches!(
+            name.as_str(),
+            "global_discount_percent"
+                | "invoiced_object_id"
+                | "invoiced_object_scheme"
+                | "prepaid_amount"
+                | "paid"
+                | "payment_date"
+                | "tax_point_date"
+        ) {
+            invoice_fields.insert(name, value);
         }
     }
 
     // Convertit les données des lignes en Vec<InvoiceLine>
+    let mut errors = Vec::new();
     let mut lines: Vec<(usize, InvoiceLine)> = lines_data
         .into_iter()
         .map(|(index, fields)| {
+            let mut number = |key: &str, label: &str| {
+                decimal_field(
+                    &fields,
+                    key,
+                    &format!("lines[{}][{}]", index, key),
+                    &format!("Ligne {} : {}", index + 1, label),
+                    &mut errors,
+                )
+            };
+
             // Parse le rabais (optionnel)
-            let discount_value = fields
-                .get("discount_value")
-                .and_then(|v| v.parse::<f64>().ok())
-                .filter(|&v| v > 0.0);
+            let discount_value = number("discount_value", "le rabais").filter(|&v| v > 0.0);
+            let quantity = number("quantity", "la quantite").unwrap_or(0.0);
+            let unit_price_ht = number("unit_price_ht", "le prix unitaire").unwrap_or(0.0);
+            let vat_rate = number("vat_rate", "le taux de TVA").unwrap_or(20.0);
+            let position = fields
+                .get("position")
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .and_then(|v| match v.parse::<u32>() {
+                    Ok(position) => Some(position),
+                    Err(_) => {
+                        errors.push(FieldError::new(
+                            format!("lines[{}][position]", index),
+                            format!(
+                                "Ligne {} : la position n'est pas un entier valide",
+                                index + 1
+                            ),
+                        ));
+                        None
+                    }
+                });
             let discount_type = fields
                 .get("discount_type")
                 .cloned()
@@ -378,21 +789,61 @@ async fn parse_form_data(
 
             let line = InvoiceLine {
                 description: fields.get("description").cloned().unwrap_or_default(),
-                quantity: fields
-                    .get("quantity")
-                    .and_then(|v| v.parse().ok())
-                    .unwrap_or(0.0),
-                unit_price_ht: fields
-                    .get("unit_price_ht")
-                    .and_then(|v| v.parse().ok())
-                    .unwrap_or(0.0),
-                vat_rate: fields
-                    .get("vat_rate")
-                    .and_then(|v| v.parse().ok())
-                    .unwrap_or(20.0),
+                detail: fields
+                    .get("detail")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                seller_item_id: fields
+                    .get("seller_item_id")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                product_code: fields
+                    .get("product_code")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                standard_item_id: fields
+                    .get("standard_item_id")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                standard_item_scheme: fields
+                    .get("standard_item_scheme")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                order_line_reference: fields
+                    .get("order_line_reference")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                section: fields
+                    .get("section")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                period_start: fields
+                    .get("period_start")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                period_end: fields
+                    .get("period_end")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                quantity,
+                unit_code: fields
+                    .get("unit_code")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+                unit_price_ht,
+                vat_rate,
+                vat_category: fields
+                    .get("vat_category")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
                 discount_value,
                 discount_type,
+                discountable: fields
+                    .get("discountable")
+                    .is_none_or(|v| !matches!(v.trim(), "false" | "0" | "off")),
+                position,
                 total_ht: None,
+                gross_ht: None,
                 total_vat: None,
                 total_ttc: None,
                 discount_amount: None,
@@ -404,27 +855,125 @@ async fn parse_form_data(
     lines.sort_by_key(|(index, _)| *index);
     let lines: Vec<InvoiceLine> = lines.into_iter().map(|(_, line)| line).collect();
 
+    // Échéancier (optionnel) : installments[i][amount|due_date|description]
+    let mut installments: Vec<(usize, Installment)> = installments_data
+        .into_iter()
+        .map(|(index, fields)| {
+            let installment = Installment {
+                amount: decimal_field(
+                    &fields,
+                    "amount",
+                    &format!("installments[{}][amount]", index),
+                    &format!("Echeance {} : le montant", index + 1),
+                    &mut errors,
+                )
+                .unwrap_or(0.0),
+                due_date: fields.get("due_date").cloned().unwrap_or_default(),
+                description: fields
+                    .get("description")
+                    .cloned()
+                    .filter(|v| !v.trim().is_empty()),
+            };
+            (index, installment)
+        })
+        .collect();
+
+    let global_discount_percent = decimal_field(
+        &invoice_fields,
+        "global_discount_percent",
+        "global_discount_percent",
+        "La remise globale",
+        &mut errors,
+    );
+    let prepaid_amount = decimal_field(
+        &invoice_fields,
+        "prepaid_amount",
+        "prepaid_amount",
+        "L'acompte",
+        &mut errors,
+    );
+
+    if !errors.is_empty() {
+        errors.sort_by(|a, b| a.field.cmp(&b.field));
+        return Err(FormError::Invalid(errors));
+    }
+
+    installments.sort_by_key(|(index, _)| *index);
+    let installments: Vec<Installment> = installments.into_iter().map(|(_, i)| i).collect();
+
     Ok(InvoiceForm {
         invoice_number: session.invoice_number.clone(),
         issue_date: session.issue_date.clone(),
         type_code: session.type_code,
         currency_code: session.currency_code.clone(),
         due_date: session.due_date.clone(),
+        tax_point_date: invoice_fields
+            .get("tax_point_date")
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()),
         payment_terms: session.payment_terms.clone(),
         buyer_reference: session.buyer_reference.clone(),
         purchase_order_reference: session.purchase_order_reference.clone(),
+        tax_type_code: session.tax_type_code.clone(),
+        tax_currency_code: session.tax_currency_code.clone(),
+        tax_exchange_rate: session.tax_exchange_rate,
         recipient_name: session.recipient_name.clone(),
         recipient_siret: session.recipient_siret.clone(),
         recipient_vat_number: session.recipient_vat_number.clone(),
+        recipient_gln: session.recipient_gln.clone(),
         recipient_address: session.recipient_address.clone(),
         recipient_country_code: session.recipient_country_code.clone(),
+        recipient_public_sector: session.recipient_public_sector,
+        recipient_service_code: session.recipient_service_code.clone(),
         lines,
+        installments,
+        global_discount_percent,
+        additional_references: Vec::new(),
+        invoiced_object_id: invoice_fields
+            .get("invoiced_object_id")
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()),
+        invoiced_object_scheme: invoice_fields
+            .get("invoiced_object_scheme")
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()),
+        ship_from_address: None,
+        prepaid_amount,
+        paid: invoice_fields
+            .get("paid")
+            .is_some_and(|v| matches!(v.trim(), "true" | "1" | "on")),
+        payment_date: invoice_fields
+            .get("payment_date")
+            .cloned()
+            .filter(|v| !v.trim().is_empty()),
     })
 }
 
-/// Parse un nom de champ de type "lines[0][description]"
-fn parse_line_field(name: &str) -> Option<(usize, String)> {
-    let rest = name.strip_prefix("lines[")?;
+/// Lit un champ numérique saisi au format français ou anglais
+///
+/// Retourne `None` si le champ est absent ou vide ; une saisie illisible est
+/// signalée dans `errors` (`label` désigne la valeur dans le message).
+fn decimal_field(
+    fields: &HashMap<String, String>,
+    key: &str,
+    field: &str,
+    label: &str,
+    errors: &mut Vec<FieldError>,
+) -> Option<f64> {
+    let value = fields.get(key).filter(|v| !v.trim().is_empty())?;
+    let parsed = parse_decimal(value);
+    if parsed.is_none() {
+        errors.push(FieldError::new(
+            field,
+            format!("{} n'est pas un nombre valide", label),
+        ));
+    }
+    parsed
+}
+
+/// Parse un nom de champ indexé de type "lines[0][description]" (préfixe "lines")
+fn parse_indexed_field(name: &str, prefix: &str) -> Option<(usize, String)> {
+    let rest = name.strip_prefix(prefix)?.strip_prefix('[')?;
     let bracket_pos = rest.find(']')?;
     let index: usize = rest[..bracket_pos].parse().ok()?;
 
@@ -436,6 +985,10 @@ fn parse_line_field(name: &str) -> Option<(usize, String)> {
 
 /// Endpoint de création de facture (étape finale)
 async fn create_invoice(State(state): State<Arc<AppState>>, multipart: Multipart) -> Response {
+    let Some(permit) = acquire_generation_permit(&state.generation_permits) else {
+        return generation_busy_response();
+    };
+
     // Récupère la session
     let session_data = {
         let session = state.session.read().unwrap();
@@ -454,142 +1007,1143 @@ async fn create_invoice(State(state): State<Arc<AppState>>, multipart: Multipart
     };
 
     // Parse le formulaire avec les données de session
-    let form = match parse_form_data(multipart, &session).await {
+    let mut form = match parse_form_data(multipart, &session, state.server.max_field_bytes).await {
         Ok(form) => form,
-        Err(e) => {
-            let response = ValidationResponse::with_errors(vec![FieldError::new(
-                "_form",
-                format!("Erreur de parsing: {}", e),
-            )]);
-            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
-        }
+        Err(e) => return e.into_response(),
     };
 
-    // Valide les lignes uniquement (l'étape 1 est déjà validée)
-    let errors = validate_lines(&form);
+    // Valide les lignes uniquement (l'étape 1 est déjà validée), après
+    // complétion depuis le catalogue produits
+    let mut errors = apply_catalog(&mut form, &state.generation);
+    errors.extend(validate_lines(&form, state.generation.max_lines));
     if !errors.is_empty() {
         let response = ValidationResponse::with_errors(errors);
         return (StatusCode::BAD_REQUEST, Json(response)).into_response();
     }
 
-    // Calcul des totaux
-    let mut form = form;
-    let totals = form.compute_totals();
+    // Calcul des totaux, génération du XML puis du PDF, hors du runtime async
+    // et dans la limite de `generation_timeout_secs` ; la place de génération
+    // suit la tâche bloquante jusqu'à sa fin
+    let generation_state = state.clone();
+    let generated = run_generation(state.server.generation_timeout(), move || {
+        let _permit = permit;
+        let documents = build_invoice_documents(
+            &mut form,
+            &generation_state.emitter,
+            &generation_state.generation,
+        )?;
+        Ok((form, documents))
+    })
+    .await;
+    let (
+        form,
+        InvoiceDocuments {
+            xml: xml_content,
+            pdf,
+            amount_warnings,
+        },
+    ) = match generated {
+        Ok(generated) => generated,
+        Err((status, errors)) => {
+            let response = ValidationResponse::with_errors(errors);
+            return (status, Json(response)).into_response();
+        }
+    };
 
-    // Génération du XML Factur-X
-    let xml_content = match facturx::generate_facturx_xml(&form, &state.emitter, totals) {
-        Ok(xml) => xml,
-        Err(e) => {
-            let response = ValidationResponse::with_errors(vec![FieldError::new(
-                "_form",
-                format!("Erreur génération XML: {}", e),
-            )]);
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response();
+    // Sauvegarde (écritures fichier) hors du runtime async, sans délai : une
+    // sauvegarde interrompue laisserait une facture partiellement enregistrée
+    let storage = state.storage.clone();
+    let saved = run_generation(Duration::MAX, move || {
+        save_invoice_documents(storage.as_ref(), &form, &xml_content, &pdf.bytes).map_err(|e| {
+            (
+                StatusCode::CONFLICT,
+                vec![FieldError::new("invoice_number", e)],
+            )
+        })?;
+        Ok((form, pdf))
+    })
+    .await;
+    let (form, pdf) = match saved {
+        Ok(saved) => saved,
+        Err((status, errors)) => {
+            let response = ValidationResponse::with_errors(errors);
+            return (status, Json(response)).into_response();
         }
     };
 
+    // Nom du fichier PDF
+    let filename = format!(
+        "facture_{}.pdf",
+        form.invoice_number.replace(['/', '\\', ' '], "_")
+    );
+
+    // Retourner le PDF en téléchargement
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/pdf")
+        .header("Content-Disposition", content_disposition_for(&filename));
+    // PDF non conforme toléré (allow_non_conformant) : écarts signalés en en-tête
+    if !pdf.warnings.is_empty() {
+        response = response.header(PDFA_WARNINGS_HEADER, warnings_header(&pdf.warnings));
+    }
+    // Montants suspects (plafond, décimales) : signalés sans bloquer
+    if !amount_warnings.is_empty() {
+        let messages: Vec<String> = amount_warnings.into_iter().map(|w| w.message).collect();
+        response = response.header(AMOUNT_WARNINGS_HEADER, warnings_header(&messages));
+    }
+    response.body(Body::from(pdf.bytes)).unwrap()
+}
+
+/// Enregistre le XML, le formulaire JSON puis le PDF d'une facture (sans
+/// effet si le stockage n'est pas configuré)
+fn save_invoice_documents(
+    storage: &dyn Storage,
+    form: &InvoiceForm,
+    xml: &str,
+    pdf: &[u8],
+) -> Result<(), String> {
+    storage.write_xml(&form.invoice_number, xml.as_bytes())?;
+    let json = form.to_json()?;
+    storage.write_json(&form.invoice_number, json.as_bytes())?;
+    storage.write_pdf(&form.invoice_number, pdf)
+}
+
+/// XML et PDF générés pour une facture
+struct InvoiceDocuments {
+    xml: String,
+    pdf: facturx::GeneratedPdf,
+    /// Avertissements non bloquants sur les montants (plafond, décimales)
+    amount_warnings: Vec<FieldError>,
+}
+
+/// Calcule les totaux puis génère le XML et le PDF d'une facture aux lignes validées
+/// Retourne le statut HTTP et les erreurs à renvoyer en cas d'échec
+fn build_invoice_documents(
+    form: &mut InvoiceForm,
+    emitter: &EmitterConfig,
+    options: &facturx::GenerationOptions,
+) -> Result<InvoiceDocuments, GenerationError> {
+    form.sort_lines();
+    if emitter.vat_exempt_franchise {
+        form.apply_vat_franchise();
+    }
+    let totals = form.compute_totals();
+    if let Err(error) = check_totals_consistency(totals) {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, vec![error]));
+    }
+
+    // L'acompte ne peut dépasser le TTC, et l'échéancier doit couvrir
+    // exactement le montant à payer
+    let mut errors = form.validate_prepaid_total(totals);
+    errors.extend(form.validate_installments(totals.due_payable()));
+    if !errors.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, errors));
+    }
+
+    // Mode strict : règles métier EN 16931 appliquées par les plateformes
+    if options.strict {
+        let errors = validate_business_rules(form, totals, emitter.vat_exempt_franchise);
+        if !errors.is_empty() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, errors));
+        }
+    }
+
+    // Génération du XML Factur-X
+    let xml_content = facturx::generate_facturx_xml_with_options(form, emitter, totals, options)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                vec![FieldError::new(
+                    "_form",
+                    format!("Erreur génération XML: {}", e),
+                )],
+            )
+        })?;
+
     // Chemin du logo pour le PDF (chemin fichier relatif à la racine du projet)
-    let logo_file_path = get_logo_file_path(&state.emitter);
-    let logo_path_ref = logo_file_path.as_deref();
+    let logo = get_logo_source(emitter);
 
     // Génération du PDF avec XML embarqué
-    let pdf_bytes = match facturx::generate_invoice_pdf(
-        &form,
-        &state.emitter,
+    let pdf = facturx::generate_invoice_pdf_with_warnings(
+        form,
+        emitter,
         totals,
         &xml_content,
-        logo_path_ref,
-    ) {
-        Ok(pdf) => pdf,
-        Err(e) => {
-            let response = ValidationResponse::with_errors(vec![FieldError::new(
+        logo.as_ref(),
+        options,
+    )
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            vec![FieldError::new(
                 "_form",
                 format!("Erreur génération PDF: {}", e),
+            )],
+        )
+    })?;
+
+    let amount_warnings = check_amount_limits(form, totals, options.max_amount);
+
+    Ok(InvoiceDocuments {
+        xml: xml_content,
+        pdf,
+        amount_warnings,
+    })
+}
+
+/// Nombre d'entrées ZIP en attente d'envoi au client (au-delà, la génération
+/// attend : la mémoire reste bornée même si le client lit lentement)
+const BATCH_STREAM_BUFFER: usize = 2;
+
+/// Corps de réponse alimenté par la génération du lot (tâche bloquante)
+struct BodyWriter(mpsc::Sender<std::io::Result<Vec<u8>>>);
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.blocking_send(Ok(buf.to_vec())).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client deconnecte")
+        })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Génère un lot de factures (JSON) et retourne une archive ZIP
+///
+/// L'archive est envoyée au fil de la génération, entrée par entrée : la
+/// mémoire utilisée ne dépend pas de la taille du lot. Les factures invalides
+/// sont consignées dans le manifeste sans bloquer le lot ; une erreur
+/// d'écriture de l'archive interrompt la réponse (archive tronquée).
+async fn create_invoice_batch(
+    State(state): State<Arc<AppState>>,
+    Json(forms): Json<Vec<InvoiceForm>>,
+) -> Response {
+    if forms.is_empty() {
+        let response = ValidationResponse::with_errors(vec![FieldError::new(
+            "_form",
+            "Le lot doit contenir au moins une facture",
+        )]);
+        return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+    }
+
+    // La place de génération est conservée jusqu'à la fin du flux
+    let Some(permit) = acquire_generation_permit(&state.generation_permits) else {
+        return generation_busy_response();
+    };
+
+    // La génération PDF est coûteuse en CPU : hors du runtime async
+    let emitter = state.emitter.clone();
+    let options = state.generation.clone();
+    let pattern = state.invoice_number_pattern.clone();
+    let (sender, receiver) = mpsc::channel(BATCH_STREAM_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let archive = BatchArchive::streaming(BodyWriter(sender.clone()));
+        if let Err(e) = build_batch_archive(archive, forms, &emitter, &options, pattern.as_ref()) {
+            // Client déconnecté ou écriture impossible : le corps s'interrompt
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
+
+    let filename = format!("factures_{}.zip", chrono::Local::now().format("%Y-%m-%d"));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/zip")
+        .header("Content-Disposition", content_disposition_for(&filename))
+        .body(Body::from_stream(ReceiverStream::new(receiver)))
+        .unwrap()
+}
+
+/// Valide et génère chaque facture du lot, en écrivant les PDF au fil de l'eau
+/// Retourne la sortie de l'archive terminée
+fn build_batch_archive<W: Write + Seek>(
+    mut archive: BatchArchive<W>,
+    forms: Vec<InvoiceForm>,
+    emitter: &EmitterConfig,
+    options: &facturx::GenerationOptions,
+    invoice_number_pattern: Option<&Regex>,
+) -> std::io::Result<W> {
+    let mut filenames = HashSet::new();
+
+    for (index, mut form) in forms.into_iter().enumerate() {
+        let mut errors = apply_catalog(&mut form, options);
+        errors.extend(validate_step1(
+            &InvoiceSession::from(&form),
+            invoice_number_pattern,
+        ));
+        errors.extend(validate_lines(&form, options.max_lines));
+
+        let filename = format!(
+            "facture_{}.pdf",
+            safe_invoice_filename(&form.invoice_number)
+        );
+        if errors.is_empty() && !filenames.insert(filename.clone()) {
+            errors.push(FieldError::new(
+                "invoice_number",
+                format!(
+                    "Le numéro de facture '{}' apparaît plusieurs fois dans le lot",
+                    form.invoice_number
+                ),
+            ));
+        }
+
+        if !errors.is_empty() {
+            archive.add_failure(index, &form.invoice_number, errors);
+            continue;
+        }
+
+        match build_invoice_documents(&mut form, emitter, options) {
+            Ok(documents) => {
+                let mut warnings = documents.pdf.warnings;
+                warnings.extend(
+                    documents
+                        .amount_warnings
+                        .into_iter()
+                        .map(|w| format!("{} : {}", w.field, w.message)),
+                );
+                archive.add_invoice(
+                    index,
+                    &form.invoice_number,
+                    &filename,
+                    &documents.pdf.bytes,
+                    warnings,
+                )?
+            }
+            Err((_, errors)) => archive.add_failure(index, &form.invoice_number, errors),
+        }
+    }
+
+    archive.finish()
+}
+
+/// Télécharge une facture sauvegardée : /invoice/{numero}.pdf, .xml ou .json
+/// Retourne 404 si le stockage n'est pas configuré ou si le fichier n'existe pas
+async fn download_invoice_file(
+    State(state): State<Arc<AppState>>,
+    Path(file): Path<String>,
+) -> Response {
+    let Some((number, extension)) = file.rsplit_once('.') else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let Some(kind) = InvoiceFileKind::from_extension(extension) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let Some(safe_number) = sanitize_invoice_number_segment(number) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let Some(content) = state.storage.read(&safe_number, kind) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", kind.content_type())
+        .header(
+            "Content-Disposition",
+            content_disposition_for(&format!("facture_{}.{}", safe_number, extension)),
+        )
+        .body(Body::from(content))
+        .unwrap()
+}
+
+/// Paramètres de `GET /api/invoice/:number/hash`
+#[derive(Deserialize)]
+struct InvoiceHashQuery {
+    /// Empreinte chaînée de la facture précédente du registre
+    previous: Option<String>,
+}
+
+/// Empreinte d'archivage d'une facture
+#[derive(Serialize)]
+struct InvoiceHashResponse {
+    invoice_number: String,
+    sha256: String,
+    /// Empreinte chaînée avec `previous`, si fourni
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chained_sha256: Option<String>,
+}
+
+/// Endpoint d'empreinte SHA-256 du PDF sauvegardé, pour le registre d'archivage
+async fn invoice_hash(
+    State(state): State<Arc<AppState>>,
+    Path(number): Path<String>,
+    Query(query): Query<InvoiceHashQuery>,
+) -> Response {
+    let Some(safe_number) = sanitize_invoice_number_segment(&number) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let previous = query.previous.filter(|p| !p.trim().is_empty());
+    if let Some(ref previous) = previous {
+        if !fingerprint::is_valid_fingerprint(previous.trim()) {
+            let response = ValidationResponse::with_errors(vec![FieldError::new(
+                "previous",
+                "L'empreinte precedente doit etre un SHA-256 hexadecimal (64 caracteres)",
             )]);
-            return (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response();
+            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
         }
+    }
+
+    let Some(content) = state.storage.read(&safe_number, InvoiceFileKind::Pdf) else {
+        return StatusCode::NOT_FOUND.into_response();
     };
 
-    // Sauvegarde du XML si le chemin est configuré
-    if let Some(ref xml_storage) = state.emitter.xml_storage {
-        let xml_path = clean_storage_path(xml_storage);
-        if let Err(e) = save_invoice_file(
-            &xml_path,
-            &form.invoice_number,
-            "xml",
-            xml_content.as_bytes(),
-        ) {
-            let response =
-                ValidationResponse::with_errors(vec![FieldError::new("invoice_number", e)]);
-            return (StatusCode::CONFLICT, Json(response)).into_response();
+    let sha256 = fingerprint::invoice_fingerprint(&content);
+    let chained_sha256 =
+        previous.map(|previous| fingerprint::chain_fingerprint(&previous, &sha256));
+
+    Json(InvoiceHashResponse {
+        invoice_number: number,
+        sha256,
+        chained_sha256,
+    })
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct ValidateQuery {
+    #[serde(default)]
+    verbose: bool,
+}
+
+/// Détail des règles appliquées (`?verbose=true`)
+#[derive(Serialize)]
+struct VerboseValidationResponse {
+    success: bool,
+    rules: Vec<RuleResult>,
+}
+
+/// Valide une facture JSON sans la générer
+///
+/// Répond toujours 200 : `success` indique si la facture est valide. Avec
+/// `?verbose=true`, chaque règle appliquée est rapportée, respectée ou non,
+/// plutôt que les seules erreurs.
+async fn validate_invoice(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ValidateQuery>,
+    Json(mut form): Json<InvoiceForm>,
+) -> Response {
+    let rules = validation_report(
+        &mut form,
+        &state.generation,
+        state.invoice_number_pattern.as_ref(),
+    );
+    let success = rules.iter().all(|rule| rule.passed);
+    if query.verbose {
+        return Json(VerboseValidationResponse { success, rules }).into_response();
+    }
+    Json(ValidationResponse {
+        success,
+        errors: failed_errors(rules),
+    })
+    .into_response()
+}
+
+/// Règles appliquées à une facture avant génération : catalogue produits
+/// (qui complète les lignes), en-tête et lignes, puis format du numéro de facture
+fn validation_report(
+    form: &mut InvoiceForm,
+    options: &facturx::GenerationOptions,
+    invoice_number_pattern: Option<&Regex>,
+) -> Vec<RuleResult> {
+    let mut rules = vec![RuleResult::new(
+        "catalog",
+        "Codes produit presents dans le catalogue",
+        apply_catalog(form, options),
+    )];
+    rules.push(max_lines_rule(form.lines.len(), options.max_lines));
+    rules.extend(form.validate_verbose());
+    rules.push(invoice_number_rule(
+        &form.invoice_number,
+        invoice_number_pattern,
+    ));
+    rules
+}
+
+/// Paramètres de la génération à blanc
+#[derive(Deserialize, Default)]
+struct DryRunQuery {
+    /// Profil Factur-X du XML (profil de `[generation]` si absent)
+    profile: Option<facturx::xmp_metadata::FacturXProfile>,
+}
+
+/// Groupe de la ventilation TVA (BG-23) renvoyé au client, qui peut ainsi
+/// afficher le récapitulatif TVA sans relire le XML
+#[derive(Serialize)]
+struct VatLine {
+    /// BT-118 : code de catégorie (S, Z, E, AE...)
+    category: String,
+    /// BT-119 : taux en pourcentage
+    rate: f64,
+    /// BT-116 : base imposable, remise globale déduite
+    base: f64,
+    /// BT-117 : montant de TVA
+    amount: f64,
+    /// BT-120 : motif d'exonération
+    exemption_reason: Option<String>,
+}
+
+impl From<facturx::VatBreakdown> for VatLine {
+    /// Montants arrondis au centime, comme dans le XML
+    fn from(group: facturx::VatBreakdown) -> Self {
+        let cents = |amount: f64| (amount * 100.0).round() / 100.0;
+        VatLine {
+            category: group.category.to_string(),
+            rate: group.rate.rate(),
+            base: cents(group.basis),
+            amount: cents(group.vat_amount),
+            exemption_reason: group.exemption_reason,
         }
     }
+}
+
+/// Résultat de la génération à blanc
+#[derive(Serialize)]
+struct DryRunResponse {
+    xml: String,
+    totals: InvoiceTotals,
+    vat_breakdown: Vec<VatLine>,
+}
+
+/// Valide une facture JSON et retourne son XML et ses totaux, sans PDF
+async fn dry_run_invoice(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DryRunQuery>,
+    Json(mut form): Json<InvoiceForm>,
+) -> Response {
+    let mut errors = apply_catalog(&mut form, &state.generation);
+    errors.extend(max_lines_rule(form.lines.len(), state.generation.max_lines).errors);
+    if !errors.is_empty() {
+        let response = ValidationResponse::with_errors(errors);
+        return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+    }
 
-    // Sauvegarde du PDF si le chemin est configuré
-    if let Some(ref pdf_storage) = state.emitter.pdf_storage {
-        let pdf_path = clean_storage_path(pdf_storage);
-        if let Err(e) = save_invoice_file(&pdf_path, &form.invoice_number, "pdf", &pdf_bytes) {
+    // Options de `[generation]`, profil éventuellement remplacé par `?profile=`
+    let options = facturx::GenerationOptions {
+        profile: query.profile.unwrap_or(state.generation.profile),
+        ..state.generation.clone()
+    };
+    match facturx::dry_run_with_options(&mut form, &state.emitter, &options) {
+        Ok((xml, totals)) => {
+            // Mêmes options que le XML
+            let vat_breakdown =
+                facturx::vat_breakdown(&form, state.emitter.vat_exempt_franchise, &options)
+                    .into_iter()
+                    .map(VatLine::from)
+                    .collect();
+            Json(DryRunResponse {
+                xml,
+                totals,
+                vat_breakdown,
+            })
+            .into_response()
+        }
+        Err(facturx::FacturXError::Validation(errors)) => {
+            let response = ValidationResponse::with_errors(errors);
+            (StatusCode::BAD_REQUEST, Json(response)).into_response()
+        }
+        Err(e) => {
             let response =
-                ValidationResponse::with_errors(vec![FieldError::new("invoice_number", e)]);
-            return (StatusCode::CONFLICT, Json(response)).into_response();
+                ValidationResponse::with_errors(vec![FieldError::new("_form", e.to_string())]);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response()
         }
     }
+}
 
-    // Nom du fichier PDF
-    let filename = format!(
-        "facture_{}.pdf",
-        form.invoice_number.replace(['/', '\\', ' '], "_")
-    );
+/// Endpoint de découverte des profils Factur-X et de leurs termes obligatoires
+async fn list_profiles() -> Json<Vec<facturx::ProfileDescription>> {
+    Json(facturx::describe_profiles())
+}
 
-    // Retourner le PDF en téléchargement
+/// Remplace le XML Factur-X d'un PDF déjà produit, sans redessiner la page
+/// Champs multipart : `pdf` (le PDF existant) et `xml` (le XML corrigé)
+async fn reembed_pdf_xml(State(state): State<Arc<AppState>>, mut multipart: Multipart) -> Response {
+    let max_bytes = state.server.max_body_bytes;
+    let mut pdf = None;
+    let mut xml = None;
+    let mut filename = None;
+
+    let fields = async {
+        while let Some(field) = multipart.next_field().await? {
+            match field.name().unwrap_or_default() {
+                "pdf" => {
+                    filename = field.file_name().map(str::to_string);
+                    pdf = Some(read_field_bytes(field, "pdf", max_bytes).await?);
+                }
+                "xml" => xml = Some(read_field_text(field, "xml", max_bytes).await?),
+                _ => {}
+            }
+        }
+        Ok::<_, FormError>(())
+    };
+    if let Err(e) = fields.await {
+        return e.into_response();
+    }
+
+    let (Some(pdf), Some(xml)) = (pdf, xml) else {
+        let response = ValidationResponse::with_errors(vec![FieldError::new(
+            "_form",
+            "Le PDF (champ pdf) et le XML (champ xml) sont obligatoires",
+        )]);
+        return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+    };
+
+    let bytes = match facturx::reembed_facturx_xml(&pdf, &xml) {
+        Ok(bytes) => bytes,
+        Err(facturx::FacturXError::Validation(errors)) => {
+            let response = ValidationResponse::with_errors(errors);
+            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+        }
+        Err(e) => {
+            let response =
+                ValidationResponse::with_errors(vec![FieldError::new("_form", e.to_string())]);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(response)).into_response();
+        }
+    };
+
+    let filename = filename
+        .filter(|name| name.ends_with(".pdf") && !name.contains(['/', '\\']))
+        .unwrap_or_else(|| "facture.pdf".to_string());
     Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/pdf")
-        .header(
-            "Content-Disposition",
-            format!("attachment; filename=\"{}\"", filename),
-        )
-        .body(Body::from(pdf_bytes))
+        .header("Content-Disposition", content_disposition_for(&filename))
+        .body(Body::from(bytes))
         .unwrap()
 }
 
-/// Validation des lignes de facturation
-fn validate_lines(form: &InvoiceForm) -> Vec<FieldError> {
-    let mut errors = Vec::new();
+/// Complète les lignes depuis le catalogue produits de `[generation]` ; sans
+/// catalogue configuré, tout code produit est inconnu
+fn apply_catalog(form: &mut InvoiceForm, options: &facturx::GenerationOptions) -> Vec<FieldError> {
+    form.apply_catalog(options.catalog.as_ref().unwrap_or(&Catalog::default()))
+}
 
+/// Validation des lignes de facturation
+fn validate_lines(form: &InvoiceForm, max_lines: usize) -> Vec<FieldError> {
+    // Sans ligne, les autres règles (remise globale, ...) n'ont pas de sens
     if form.lines.is_empty() {
-        errors.push(FieldError::new(
+        return vec![FieldError::new(
             "lines",
             "La facture doit contenir au moins une ligne",
-        ));
-        return errors;
+        )];
+    }
+    // Facture démesurée : refusée avant de contrôler chaque ligne
+    let max_lines = max_lines_rule(form.lines.len(), max_lines);
+    if !max_lines.passed {
+        return max_lines.errors;
+    }
+    failed_errors(line_rules(form))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use facturx_create::storage::MemoryStorage;
+
+    #[test]
+    fn test_form_error_status() {
+        let too_large = FormError::TooLarge(FieldError::new("recipient_name", "trop long"));
+        assert_eq!(
+            too_large.into_response().status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let parse = FormError::Parse("champ invalide".to_string());
+        assert_eq!(parse.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let invalid = FormError::Invalid(vec![FieldError::new("lines[0][quantity]", "illisible")]);
+        assert_eq!(invalid.into_response().status(), StatusCode::BAD_REQUEST);
     }
 
-    for (index, line) in form.lines.iter().enumerate() {
-        if line.description.trim().is_empty() {
-            errors.push(FieldError::new(
-                format!("lines[{}][description]", index),
-                format!("Ligne {} : la description est obligatoire", index + 1),
-            ));
-        }
+    #[test]
+    fn test_generation_permit_limit() {
+        let permits = Arc::new(Semaphore::new(1));
+        let permit = acquire_generation_permit(&permits).unwrap();
+        assert!(acquire_generation_permit(&permits).is_none());
+        drop(permit);
+        assert!(acquire_generation_permit(&permits).is_some());
+
+        let busy = generation_busy_response();
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(busy.headers()[header::RETRY_AFTER], "5");
+    }
 
-        if line.quantity <= 0.0 {
-            errors.push(FieldError::new(
-                format!("lines[{}][quantity]", index),
-                format!("Ligne {} : la quantite doit etre superieure a 0", index + 1),
+    #[tokio::test]
+    async fn test_generation_timeout() {
+        let done = run_generation(Duration::from_secs(5), || Ok(42)).await;
+        assert_eq!(done.unwrap(), 42);
+
+        let slow = run_generation(Duration::from_millis(10), || {
+            std::thread::sleep(Duration::from_millis(300));
+            Ok(())
+        })
+        .await;
+        let (status, errors) = slow.unwrap_err();
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(errors[0].field, "_form");
+        assert!(errors[0].message.contains("delai"));
+
+        // Les erreurs de la génération sont renvoyées telles quelles, une
+        // tâche interrompue (panique) devient une erreur 500
+        let invalid = run_generation(Duration::from_secs(5), || -> Result<(), _> {
+            Err((
+                StatusCode::BAD_REQUEST,
+                vec![FieldError::new("installments", "echeancier incomplet")],
+            ))
+        })
+        .await;
+        assert_eq!(invalid.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let panicked = run_generation(Duration::from_secs(5), || -> Result<(), _> {
+            panic!("krilla")
+        })
+        .await;
+        assert_eq!(panicked.unwrap_err().0, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let config = ServerConfig {
+            generation_timeout_secs: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_decimal_field() {
+        let fields: HashMap<String, String> = [
+            ("unit_price_ht", "1 234,56"),
+            ("quantity", "abc"),
+            ("discount_value", ""),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let mut errors = Vec::new();
+
+        let price = decimal_field(&fields, "unit_price_ht", "price", "le prix", &mut errors);
+        assert_eq!(price, Some(1234.56));
+        assert_eq!(
+            decimal_field(
+                &fields,
+                "discount_value",
+                "discount",
+                "le rabais",
+                &mut errors
+            ),
+            None
+        );
+        assert_eq!(
+            decimal_field(&fields, "vat_rate", "vat", "le taux", &mut errors),
+            None
+        );
+        assert!(errors.is_empty());
+
+        assert_eq!(
+            decimal_field(
+                &fields,
+                "quantity",
+                "lines[0][quantity]",
+                "la quantite",
+                &mut errors
+            ),
+            None
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][quantity]");
+    }
+
+    #[test]
+    fn test_sanitize_invoice_number_segment() {
+        assert_eq!(
+            sanitize_invoice_number_segment("FA 2024:001").as_deref(),
+            Some("FA_2024_001")
+        );
+        assert!(sanitize_invoice_number_segment("../secret").is_none());
+        assert!(sanitize_invoice_number_segment("..").is_none());
+        assert!(sanitize_invoice_number_segment("a/b").is_none());
+        assert!(sanitize_invoice_number_segment("a\\b").is_none());
+        assert!(sanitize_invoice_number_segment("").is_none());
+    }
+
+    #[test]
+    fn test_warnings_header() {
+        let warnings = vec![
+            "MissingAltText".to_string(),
+            "ContainsNotDefGlyph(é)\n".to_string(),
+        ];
+        assert_eq!(
+            warnings_header(&warnings),
+            "MissingAltText; ContainsNotDefGlyph(_)_"
+        );
+        assert!(axum::http::HeaderValue::from_str(&warnings_header(&warnings)).is_ok());
+    }
+
+    #[test]
+    fn test_content_disposition_for_accented_number() {
+        assert_eq!(
+            content_disposition_for("facture_FA-2024-é01.pdf"),
+            "attachment; filename=\"facture_FA-2024-_01.pdf\"; \
+             filename*=UTF-8''facture_FA-2024-%C3%A901.pdf"
+        );
+        assert_eq!(
+            content_disposition_for("facture_F1.pdf"),
+            "attachment; filename=\"facture_F1.pdf\"; filename*=UTF-8''facture_F1.pdf"
+        );
+    }
+
+    /// État du serveur avec un stockage en mémoire
+    fn test_state(storage: Arc<MemoryStorage>) -> Arc<AppState> {
+        let emitter: EmitterConfig = toml::from_str(
+            r#"
+            siren = "123456789"
+            siret = "12345678900012"
+            name = "Emetteur"
+            address = "1 rue du Test"
+            "#,
+        )
+        .unwrap();
+        Arc::new(AppState {
+            emitter,
+            invoice_number_pattern: None,
+            server: ServerConfig::default(),
+            generation: facturx::GenerationOptions::default(),
+            tera: Tera::default(),
+            session: Arc::new(RwLock::new(None)),
+            generation_permits: Arc::new(Semaphore::new(1)),
+            storage,
+        })
+    }
+
+    /// Facture de test dont les lignes sont données en JSON
+    fn test_form(lines: &str) -> InvoiceForm {
+        InvoiceForm::from_json(&format!(
+            r#"{{
+                "invoice_number": "FA-001",
+                "issue_date": "2024-01-15",
+                "type_code": 380,
+                "currency_code": "EUR",
+                "recipient_name": "Client",
+                "recipient_siret": "98765432100019",
+                "recipient_address": "1 rue du Test",
+                "recipient_country_code": "FR",
+                "lines": {}
+            }}"#,
+            lines
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_validate_invoice_verbose() {
+        let state = test_state(Arc::new(MemoryStorage::new()));
+        let form = || {
+            let mut form =
+                test_form(r#"[{"description": "Audit", "quantity": 1, "unit_price_ht": 100}]"#);
+            form.recipient_siret = "123".to_string();
+            form
+        };
+
+        let rules = validation_report(&mut form(), &state.generation, None);
+        assert_eq!(rules[0].id, "catalog");
+        let failed: Vec<&str> = rules.iter().filter(|r| !r.passed).map(|r| r.id).collect();
+        assert_eq!(failed, vec!["BT-47-siret"]);
+
+        let response = validate_invoice(
+            State(state),
+            Query(ValidateQuery { verbose: true }),
+            Json(form()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["success"], false);
+        assert!(body["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|rule| rule["id"] == "BT-1-present" && rule["passed"] == true));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_vat_breakdown() {
+        let state = test_state(Arc::new(MemoryStorage::new()));
+        let form = test_form(
+            r#"[
+                {"description": "Audit", "quantity": 3, "unit_price_ht": 33.33, "vat_rate": 20},
+                {"description": "Livre", "quantity": 1, "unit_price_ht": 10, "vat_rate": 5.5},
+                {"description": "Formation", "quantity": 1, "unit_price_ht": 50, "vat_rate": 0}
+            ]"#,
+        );
+
+        let response =
+            dry_run_invoice(State(state), Query(DryRunQuery::default()), Json(form)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["vat_breakdown"],
+            serde_json::json!([
+                {"category": "Z", "rate": 0.0, "base": 50.0, "amount": 0.0, "exemption_reason": null},
+                {"category": "S", "rate": 5.5, "base": 10.0, "amount": 0.55, "exemption_reason": null},
+                {"category": "S", "rate": 20.0, "base": 99.99, "amount": 20.0, "exemption_reason": null},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_vat_breakdown_follows_generation_options() {
+        let mut state = test_state(Arc::new(MemoryStorage::new()));
+        Arc::get_mut(&mut state)
+            .unwrap()
+            .generation
+            .zero_rate_category = facturx::ZeroRateCategory::E;
+        let form = test_form(
+            r#"[
+                {"description": "Formation", "quantity": 1, "unit_price_ht": 50, "vat_rate": 0}
+            ]"#,
+        );
+
+        let response =
+            dry_run_invoice(State(state), Query(DryRunQuery::default()), Json(form)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["vat_breakdown"][0]["category"], "E");
+        assert!(body["xml"]
+            .as_str()
+            .unwrap()
+            .contains("<ram:CategoryCode>E</ram:CategoryCode>"));
+    }
+
+    #[test]
+    fn test_validate_lines_max_lines() {
+        let line = InvoiceLine {
+            description: "Abonnement".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 10.0,
+            vat_rate: 20.0,
+            ..Default::default()
+        };
+        let mut form = test_form("[]");
+        form.lines = vec![line; 3];
+        assert!(validate_lines(&form, 3).is_empty());
+
+        let errors = validate_lines(&form, 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines");
+    }
+
+    #[test]
+    fn test_save_invoice_documents_rejects_duplicate() {
+        let form = test_form("[]");
+        let storage = MemoryStorage::new();
+        save_invoice_documents(&storage, &form, "<xml/>", b"%PDF-1.7").unwrap();
+        assert_eq!(storage.len(), 3);
+
+        let duplicate = save_invoice_documents(&storage, &form, "<xml/>", b"%PDF-1.7");
+        assert!(duplicate.unwrap_err().contains("existe déjà"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_batch_streams_archive() {
+        let state = test_state(Arc::new(MemoryStorage::new()));
+        let forms: Vec<InvoiceForm> = (0..3)
+            .map(|i| {
+                let mut form = test_form("[]");
+                form.invoice_number = format!("FA-{}", i);
+                form
+            })
+            .collect();
+
+        let response = create_invoice_batch(State(state.clone()), Json(forms)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+        let mut manifest = String::new();
+        std::io::Read::read_to_string(
+            &mut zip
+                .by_name(facturx_create::batch::MANIFEST_FILENAME)
+                .unwrap(),
+            &mut manifest,
+        )
+        .unwrap();
+        assert!(manifest.contains("\"failed\": 3"));
+
+        // Flux terminé : la place de génération est rendue
+        assert!(acquire_generation_permit(&state.generation_permits).is_some());
+    }
+
+    /// Formulaire multipart de l'étape 1 contenant les champs donnés
+    async fn step1_multipart(fields: &[(&str, &str)]) -> Multipart {
+        use axum::extract::FromRequest;
+
+        let boundary = "facturx-test";
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
             ));
         }
+        body.push_str(&format!("--{}--\r\n", boundary));
+
+        let request = axum::http::Request::builder()
+            .method("POST")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
 
-        if line.unit_price_ht <= 0.0 {
-            errors.push(FieldError::new(
-                format!("lines[{}][unit_price_ht]", index),
-                format!(
-                    "Ligne {} : le prix unitaire doit etre superieur a 0",
-                    index + 1
-                ),
-            ));
+    #[tokio::test]
+    async fn test_line_order_follows_positions() {
+        let form = test_form("[]");
+        let session = InvoiceSession::from(&form);
+        // Indices non contigus (lignes supprimées) envoyés dans le désordre ;
+        // la ligne 7 a été remontée en tête par l'utilisateur
+        let fields = [
+            ("lines[4][description]", "Deuxieme"),
+            ("lines[4][quantity]", "1"),
+            ("lines[4][unit_price_ht]", "20"),
+            ("lines[7][description]", "Premiere"),
+            ("lines[7][quantity]", "1"),
+            ("lines[7][unit_price_ht]", "10"),
+            ("lines[7][position]", "0"),
+            ("lines[1][description]", "Troisieme"),
+            ("lines[1][quantity]", "1"),
+            ("lines[1][unit_price_ht]", "30"),
+            ("lines[1][position]", "5"),
+        ];
+        let mut form = parse_form_data(step1_multipart(&fields).await, &session, 1024)
+            .await
+            .unwrap();
+        form.sort_lines();
+        let descriptions: Vec<&str> = form.lines.iter().map(|l| l.description.as_str()).collect();
+        assert_eq!(descriptions, ["Premiere", "Deuxieme", "Troisieme"]);
+
+        let totals = form.compute_totals();
+        let options = facturx::GenerationOptions {
+            profile: facturx::xmp_metadata::FacturXProfile::Basic,
+            ..Default::default()
+        };
+        let emitter: EmitterConfig = toml::from_str(
+            r#"
+            siren = "123456789"
+            siret = "12345678900012"
+            name = "Emetteur"
+            address = "1 rue du Test"
+            "#,
+        )
+        .unwrap();
+        let xml =
+            facturx::generate_facturx_xml_with_options(&form, &emitter, totals, &options).unwrap();
+        let positions: Vec<usize> = ["Premiere", "Deuxieme", "Troisieme"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let line = xml
+                    .find(&format!("<ram:LineID>{}</ram:LineID>", i + 1))
+                    .unwrap();
+                let product = xml.find(&format!("<ram:Name>{}</ram:Name>", name)).unwrap();
+                assert!(line < product);
+                product
+            })
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+        let invalid = [
+            ("lines[0][description]", "Audit"),
+            ("lines[0][position]", "-1"),
+        ];
+        match parse_form_data(step1_multipart(&invalid).await, &session, 1024).await {
+            Err(FormError::Invalid(errors)) => assert_eq!(errors[0].field, "lines[0][position]"),
+            other => panic!(
+                "position invalide attendue: {:?}",
+                other.map(|f| f.lines.len())
+            ),
         }
     }
 
-    errors
+    #[tokio::test]
+    async fn test_step1_unknown_type_code() {
+        let fields = [
+            ("invoice_number", "FA-001"),
+            ("issue_date", "2024-01-15"),
+            ("type_code", "999"),
+            ("recipient_name", "Client"),
+            ("recipient_siret", "12345678900012"),
+        ];
+        let data = parse_step1_data(step1_multipart(&fields).await, 1024)
+            .await
+            .unwrap();
+
+        let errors = validate_step1(&data, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "type_code");
+        assert!(errors[0].message.contains("'999'"));
+    }
+
+    #[tokio::test]
+    async fn test_step1_missing_type_code_defaults_to_invoice() {
+        let fields = [
+            ("invoice_number", "FA-001"),
+            ("issue_date", "2024-01-15"),
+            ("recipient_name", "Client"),
+            ("recipient_siret", "12345678900012"),
+        ];
+        let data = parse_step1_data(step1_multipart(&fields).await, 1024)
+            .await
+            .unwrap();
+        assert_eq!(data.type_code, InvoiceTypeCode::Invoice);
+        assert_eq!(data.type_label, "Facture");
+        assert!(validate_step1(&data, None).is_empty());
+
+        let fields = [("type_code", "381")];
+        let data = parse_step1_data(step1_multipart(&fields).await, 1024)
+            .await
+            .unwrap();
+        assert_eq!(data.type_code, InvoiceTypeCode::CreditNote);
+        assert_eq!(data.type_label, "Avoir");
+    }
+
+    #[tokio::test]
+    async fn test_download_and_hash_from_storage() {
+        let storage = Arc::new(MemoryStorage::new());
+        storage.write_pdf("FA 001", b"%PDF-1.7").unwrap();
+        let state = test_state(storage);
+
+        let response =
+            download_invoice_file(State(state.clone()), Path("FA 001.pdf".to_string())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["Content-Type"], "application/pdf");
+
+        let missing =
+            download_invoice_file(State(state.clone()), Path("FA 001.xml".to_string())).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let hash = invoice_hash(
+            State(state),
+            Path("FA 001".to_string()),
+            Query(InvoiceHashQuery { previous: None }),
+        )
+        .await;
+        assert_eq!(hash.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,323 @@
+//! Règles métier EN 16931 vérifiées avant génération (mode strict)
+//!
+//! Les montants sont comparés au centime, tels qu'ils sont écrits dans le XML
+//! (arrondis à 2 décimales) : c'est sur ces valeurs que les plateformes
+//! appliquent les règles BR-*.
+
+use super::error::FieldError;
+use super::invoice::{InvoiceForm, InvoiceTotals, VatGroupKey, VatRateKey};
+use std::collections::BTreeMap;
+
+/// Montant en centimes, arrondi comme dans le XML
+fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+/// Formate un montant en centimes pour les messages d'erreur
+fn format_cents(amount: i64) -> String {
+    format!("{:.2}", amount as f64 / 100.0)
+}
+
+/// Vrai si le montant a plus de 2 décimales avant arrondi
+fn has_sub_cent_digits(amount: f64) -> bool {
+    ((amount * 100.0) - (amount * 100.0).round()).abs() > 1e-6
+}
+
+/// Contrôle de vraisemblance des montants (avertissements non bloquants)
+///
+/// Signale :
+/// - un total (HT, base imposable, TVA, TTC) supérieur à `max_amount`, refusé
+///   par certaines plateformes de réception
+/// - un montant net de ligne à plus de 2 décimales avant arrondi : le XML
+///   l'arrondit au centime, ce qui trahit le plus souvent une erreur de
+///   saisie ou d'import (prix ou quantité mal convertis)
+pub fn check_amount_limits(
+    invoice: &InvoiceForm,
+    totals: InvoiceTotals,
+    max_amount: f64,
+) -> Vec<FieldError> {
+    let mut warnings = Vec::new();
+
+    for (label, amount) in [
+        ("total HT", totals.total_ht),
+        ("base imposable", totals.tax_basis_total()),
+        ("total TVA", totals.total_vat),
+        ("total TTC", totals.total_ttc),
+    ] {
+        if amount.abs() > max_amount {
+            warnings.push(FieldError::new(
+                "_form",
+                format!(
+                    "Le {} ({:.2}) depasse le plafond de {:.2}",
+                    label, amount, max_amount
+                ),
+            ));
+        }
+    }
+
+    for (index, line) in invoice.lines.iter().enumerate() {
+        if line.is_valid() && has_sub_cent_digits(line.unrounded_net_ht()) {
+            warnings.push(FieldError::new(
+                format!("lines[{}]", index),
+                format!(
+                    "Ligne {} : le montant HT ({}) a plus de 2 decimales avant arrondi",
+                    index + 1,
+                    line.unrounded_net_ht()
+                ),
+            ));
+        }
+    }
+
+    warnings
+}
+
+/// Vérifie les règles métier EN 16931 sur la facture et ses totaux
+///
+/// Règles contrôlées :
+/// - BR-CO-10 : somme des montants nets des lignes = total HT des lignes
+/// - BR-CO-13 : base imposable totale = total des lignes - remises + charges
+///   (remise globale : somme des remises au centime, comme dans le XML)
+/// - BR-CO-14 : total TVA = somme des montants de TVA par taux
+/// - BR-CO-15 : total TTC = base imposable + total TVA
+/// - BR-CO-25 : un montant à payer positif exige une date d'échéance (ou un
+///   échéancier) ; une facture à titre gracieux ou acquittée (montant à payer
+///   nul) en est dispensée
+/// - BR-S-08 : base de chaque taux = somme des montants nets des lignes à ce taux
+///
+/// En franchise en base (`vat_exempt_franchise`), les lignes sont en catégorie E
+/// et les règles BR-S ne s'appliquent pas. Hors franchise, les lignes à 0 % sont
+/// en catégorie Z, E ou O (`zero_rate_category`) : seules les lignes à taux
+/// positif sont en catégorie S, ce qui garantit BR-S-05. Une catégorie imposée
+/// par la ligne (BT-151) forme un groupe distinct de même taux ; la catégorie S
+/// imposée exige un taux positif (contrôlé par `InvoiceLine::validate`).
+pub fn validate_business_rules(
+    invoice: &InvoiceForm,
+    totals: InvoiceTotals,
+    vat_exempt_franchise: bool,
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    let line_total: i64 = invoice
+        .lines
+        .iter()
+        .filter(|l| l.is_valid())
+        .map(|l| cents(l.total_ht_value()))
+        .sum();
+    let header_line_total = cents(totals.total_ht);
+    let tax_basis_total = cents(totals.tax_basis_total());
+    let tax_total = cents(totals.total_vat);
+    let grand_total = cents(totals.total_ttc);
+
+    // BR-CO-10
+    if line_total != header_line_total {
+        errors.push(FieldError::new(
+            "_form",
+            format!(
+                "BR-CO-10 : la somme des montants nets des lignes ({}) differe du total HT ({})",
+                format_cents(line_total),
+                format_cents(header_line_total)
+            ),
+        ));
+    }
+
+    // BR-CO-13 (remise globale, aucune charge au niveau document)
+    let allowances: i64 = invoice
+        .global_discount_allowances()
+        .iter()
+        .map(|a| cents(a.amount))
+        .sum();
+    let charges = 0;
+    if tax_basis_total != header_line_total - allowances + charges {
+        errors.push(FieldError::new(
+            "_form",
+            format!(
+                "BR-CO-13 : la base imposable ({}) differe du total des lignes corrige des remises et charges ({})",
+                format_cents(tax_basis_total),
+                format_cents(header_line_total - allowances + charges)
+            ),
+        ));
+    }
+
+    // Récapitulatif par groupe de TVA (taux et catégorie), regroupé comme dans
+    // le XML : la base d'un groupe est celle de ses lignes moins la remise
+    // globale qui lui est imputée
+    let mut breakdown: BTreeMap<VatGroupKey, (f64, f64, i64)> = BTreeMap::new();
+    for line in invoice.lines.iter().filter(|l| l.is_valid()) {
+        let entry = breakdown
+            .entry(VatGroupKey::of_line(line))
+            .or_insert((0.0, 0.0, 0));
+        entry.0 += line.total_ht_value();
+        entry.1 += line.total_vat_value();
+        entry.2 += cents(line.total_ht_value());
+    }
+    for allowance in invoice.global_discount_allowances() {
+        let group = VatGroupKey {
+            rate: VatRateKey::new(allowance.vat_rate),
+            category: allowance.vat_category,
+        };
+        if let Some(entry) = breakdown.get_mut(&group) {
+            entry.0 -= allowance.amount;
+            entry.1 -= allowance.vat_amount();
+            entry.2 -= cents(allowance.amount);
+        }
+    }
+
+    // BR-CO-14
+    let breakdown_tax: i64 = breakdown.values().map(|(_, vat, _)| cents(*vat)).sum();
+    if breakdown_tax != tax_total {
+        errors.push(FieldError::new(
+            "_form",
+            format!(
+                "BR-CO-14 : le total TVA ({}) differe de la somme des TVA par taux ({})",
+                format_cents(tax_total),
+                format_cents(breakdown_tax)
+            ),
+        ));
+    }
+
+    // BR-CO-15
+    if grand_total != tax_basis_total + tax_total {
+        errors.push(FieldError::new(
+            "_form",
+            format!(
+                "BR-CO-15 : le total TTC ({}) differe de la base imposable plus la TVA ({})",
+                format_cents(grand_total),
+                format_cents(tax_basis_total + tax_total)
+            ),
+        ));
+    }
+
+    // BR-CO-25 (les conditions de paiement en texte libre ne sont pas émises dans le XML)
+    let has_due_date = invoice
+        .due_date
+        .as_deref()
+        .is_some_and(|date| !date.trim().is_empty());
+    let due_payable = cents(totals.due_payable());
+    if due_payable > 0 && !has_due_date && invoice.installments.is_empty() {
+        errors.push(FieldError::new(
+            "due_date",
+            format!(
+                "BR-CO-25 : le montant a payer ({}) est positif, la date d'echeance est obligatoire",
+                format_cents(due_payable)
+            ),
+        ));
+    }
+
+    if vat_exempt_franchise {
+        return errors;
+    }
+
+    // BR-S-08
+    for (group, (basis, _, lines_basis)) in &breakdown {
+        if cents(*basis) != *lines_basis {
+            errors.push(FieldError::new(
+                "_form",
+                format!(
+                    "BR-S-08 : la base du taux {} % ({}) differe de la somme des lignes a ce taux, remises deduites ({})",
+                    group.rate,
+                    format_cents(cents(*basis)),
+                    format_cents(*lines_basis)
+                ),
+            ));
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::line::InvoiceLine;
+
+    fn invoice_with_lines(lines: Vec<InvoiceLine>) -> InvoiceForm {
+        let mut invoice = InvoiceForm::sample(lines);
+        invoice.due_date = Some("2024-02-15".to_string());
+        invoice
+    }
+
+    fn line(unit_price_ht: f64, vat_rate: f64) -> InvoiceLine {
+        InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 1.0,
+            unit_price_ht,
+            vat_rate,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_consistent_invoice_passes() {
+        let mut invoice = invoice_with_lines(vec![line(100.0, 20.0), line(50.0, 5.5)]);
+        let totals = invoice.compute_totals();
+        assert!(validate_business_rules(&invoice, totals, false).is_empty());
+    }
+
+    #[test]
+    fn test_global_discount_passes() {
+        let mut excluded = line(40.0, 20.0);
+        excluded.discountable = false;
+        let mut invoice = invoice_with_lines(vec![line(100.0, 20.0), excluded, line(60.0, 5.5)]);
+        invoice.global_discount_percent = Some(10.0);
+        let totals = invoice.compute_totals();
+        assert!(validate_business_rules(&invoice, totals, false).is_empty());
+    }
+
+    #[test]
+    fn test_amount_limits() {
+        let mut invoice = invoice_with_lines(vec![line(100.0, 20.0), line(0.333, 20.0)]);
+        let totals = invoice.compute_totals();
+
+        let warnings = check_amount_limits(&invoice, totals, 1_000_000.0);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].field, "lines[1]");
+
+        let warnings = check_amount_limits(&invoice, totals, 110.0);
+        let messages: Vec<&str> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert!(messages.iter().any(|m| m.starts_with("Le total TTC")));
+        assert!(!messages.iter().any(|m| m.starts_with("Le total HT")));
+    }
+
+    #[test]
+    fn test_rounded_lines_keep_br_co_10() {
+        // 3 x 0.335 : chaque ligne vaut 0.34 dans le XML, le total des lignes 1.02
+        let mut invoice = invoice_with_lines(vec![
+            line(0.335, 20.0),
+            line(0.335, 20.0),
+            line(0.335, 20.0),
+        ]);
+        let totals = invoice.compute_totals();
+        assert_eq!(invoice.lines[0].total_ht_value(), 0.34);
+        assert_eq!(totals.total_ht, 1.02);
+        assert_eq!(totals.total_vat, 0.21);
+        assert_eq!(totals.total_ttc, 1.23);
+        assert!(validate_business_rules(&invoice, totals, false).is_empty());
+    }
+
+    #[test]
+    fn test_zero_rate_line_outside_standard_category() {
+        // Ligne à 0 % : catégorie Z, E ou O, donc hors BR-S-05
+        let mut invoice = invoice_with_lines(vec![line(100.0, 0.0), line(50.0, 20.0)]);
+        let totals = invoice.compute_totals();
+
+        assert!(validate_business_rules(&invoice, totals, false).is_empty());
+        assert!(validate_business_rules(&invoice, totals, true).is_empty());
+    }
+
+    #[test]
+    fn test_br_co_25_due_date_unless_free_of_charge() {
+        let mut invoice = invoice_with_lines(vec![line(100.0, 20.0)]);
+        invoice.due_date = None;
+        let totals = invoice.compute_totals();
+        let errors = validate_business_rules(&invoice, totals, false);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "due_date");
+        assert!(errors[0].message.starts_with("BR-CO-25"));
+
+        invoice.lines[0].discount_value = Some(100.0);
+        invoice.lines[0].discount_type = Some("percent".to_string());
+        let totals = invoice.compute_totals();
+        assert!(totals.is_free_of_charge());
+        assert!(validate_business_rules(&invoice, totals, false).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+//! Catalogue produits : désignation, prix, TVA et unité par code article
+//!
+//! Une ligne qui porte un `product_code` sans prix saisi est complétée depuis
+//! le catalogue (voir `InvoiceForm::apply_catalog`). Le catalogue se lit
+//! depuis la section `[generation.catalog]` de la configuration.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Article du catalogue
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CatalogItem {
+    pub description: String,
+    pub unit_price_ht: f64,
+    pub vat_rate: f64,
+    /// BT-130 : Code unité UN/ECE Rec 20 (C62 "unité" si absent)
+    #[serde(default)]
+    pub unit_code: Option<String>,
+}
+
+/// Catalogue produits indexé par code article
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Catalog {
+    items: HashMap<String, CatalogItem>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ajoute ou remplace un article
+    pub fn insert(&mut self, code: impl Into<String>, item: CatalogItem) {
+        self.items.insert(code.into(), item);
+    }
+
+    /// Article d'un code (espaces de début et de fin ignorés)
+    pub fn get(&self, code: &str) -> Option<&CatalogItem> {
+        self.items.get(code.trim())
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::facturx::GenerationOptions;
+
+    #[test]
+    fn test_catalog_from_generation_config() {
+        let options: GenerationOptions = toml::from_str(
+            r#"
+            [catalog.ABO-M]
+            description = "Abonnement mensuel"
+            unit_price_ht = 49.9
+            vat_rate = 20.0
+            unit_code = "MON"
+
+            [catalog."LIVRE 01"]
+            description = "Livre"
+            unit_price_ht = 18.0
+            vat_rate = 5.5
+            "#,
+        )
+        .unwrap();
+
+        let catalog = options.catalog.unwrap();
+        assert_eq!(catalog.len(), 2);
+        assert_eq!(
+            catalog.get("ABO-M").unwrap().unit_code.as_deref(),
+            Some("MON")
+        );
+        assert_eq!(catalog.get(" LIVRE 01 ").unwrap().vat_rate, 5.5);
+        assert!(catalog.get("INCONNU").is_none());
+    }
+}
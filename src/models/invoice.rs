@@ -1,10 +1,191 @@
-use super::line::InvoiceLine;
-use serde::{Deserialize, Serialize};
+use super::catalog::Catalog;
+use super::error::FieldError;
+use super::line::{round_cents, InvoiceLine};
+use super::rules::{header_rules, line_rules, HeaderFields, RuleResult};
+use super::validation::is_valid_country_code;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Code de type de taxe par défaut (UNTDID 5153)
+pub const DEFAULT_TAX_TYPE_CODE: &str = "VAT";
+
+/// Codes de type de taxe autorisés (UNTDID 5153)
+pub const TAX_TYPE_CODES: &[&str] = &[
+    "AAA", "AAB", "AAC", "AAD", "AAE", "AAF", "AAG", "AAH", "AAI", "AAJ", "AAK", "AAL", "AAM",
+    "ADD", "BOL", "CAP", "CAR", "COC", "CST", "CUD", "CVD", "ENV", "EXC", "EXP", "FET", "FRE",
+    "GCN", "GST", "ILL", "IMP", "IND", "LAC", "LCN", "LDP", "LOC", "LST", "MCA", "MCD", "OTH",
+    "PDB", "PDC", "PRF", "SCN", "SSS", "STT", "SUP", "SUR", "SWT", "TAC", "TOT", "TOX", "TTA",
+    "VAD", "VAT",
+];
+
+/// Vérifie qu'un code de type de taxe appartient à la liste UNTDID 5153
+pub fn is_valid_tax_type_code(code: &str) -> bool {
+    TAX_TYPE_CODES.contains(&code)
+}
+
+/// Devises proposées à la saisie (ISO 4217)
+pub const CURRENCY_CODES: &[&str] = &[
+    "EUR", "GBP", "CHF", "DKK", "SEK", "NOK", "PLN", "CZK", "USD",
+];
+
+/// Vérifie qu'un code devise fait partie des devises prises en charge
+pub fn is_known_currency_code(code: &str) -> bool {
+    CURRENCY_CODES.contains(&code)
+}
+
+/// Types de document référencé (UNTDID 1001) admis en AdditionalReferencedDocument :
+/// 50 = appel d'offres ou lot (BT-17), 130 = objet facturé (BT-18),
+/// 916 = justificatif additionnel (BG-24)
+pub const REFERENCED_DOCUMENT_TYPE_CODES: &[&str] = &["50", "130", "916"];
+
+/// Qualifiants de référence (UNTDID 1153) pris en charge
+pub const REFERENCE_TYPE_CODES: &[&str] = &[
+    "AAA", "AAG", "AAJ", "AAK", "ABT", "ACD", "CT", "DQ", "IV", "ON", "PL", "TN", "VN",
+];
+
+/// Document référencé par la facture (AdditionalReferencedDocument, profil EXTENDED)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DocumentReference {
+    /// Type de document (UNTDID 1001) : 50, 130 ou 916
+    pub type_code: String,
+    /// Identifiant du document référencé
+    pub id: String,
+    /// Adresse du document externe (URI)
+    #[serde(default)]
+    pub uri: Option<String>,
+    /// Qualifiant de l'identifiant (UNTDID 1153), ex. "CT" pour un contrat
+    #[serde(default)]
+    pub reference_type_code: Option<String>,
+}
+
+/// Lieu d'expédition distinct de l'adresse du vendeur (entrepôt en
+/// livraison directe), émis en ShipFromTradeParty en profil EXTENDED
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ShipFromAddress {
+    /// Nom du lieu d'expédition (ex: "Entrepot Lyon")
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Adresse (rue, code postal, ville)
+    pub address: String,
+    /// Code pays ISO 3166-1 alpha-2
+    pub country_code: String,
+}
+
+/// Valide la devise de comptabilisation de la TVA (BT-6) et son taux de change
+///
+/// Les deux champs vont ensemble : devise connue, différente de la devise de
+/// facturation, et taux strictement positif.
+pub fn validate_tax_currency(
+    currency_code: &str,
+    tax_currency_code: Option<&str>,
+    exchange_rate: Option<f64>,
+) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    match tax_currency_code {
+        Some(tax_currency) => {
+            if !is_known_currency_code(tax_currency) {
+                errors.push(FieldError::new(
+                    "tax_currency_code",
+                    format!(
+                        "La devise de TVA '{}' n'est pas prise en charge",
+                        tax_currency
+                    ),
+                ));
+            } else if tax_currency == currency_code {
+                errors.push(FieldError::new(
+                    "tax_currency_code",
+                    "La devise de TVA doit differer de la devise de facturation",
+                ));
+            }
+
+            match exchange_rate {
+                Some(rate) if rate > 0.0 && rate.is_finite() => {}
+                Some(_) => errors.push(FieldError::new(
+                    "tax_exchange_rate",
+                    "Le taux de change doit etre superieur a 0",
+                )),
+                None => errors.push(FieldError::new(
+                    "tax_exchange_rate",
+                    "Le taux de change est obligatoire avec une devise de TVA",
+                )),
+            }
+        }
+        None if exchange_rate.is_some() => errors.push(FieldError::new(
+            "tax_currency_code",
+            "La devise de TVA est obligatoire avec un taux de change",
+        )),
+        None => {}
+    }
+
+    errors
+}
+
+/// Clé de regroupement d'un taux de TVA : le taux en millièmes de point
+///
+/// Le PDF, le XML et les règles métier regroupent les lignes sur cette clé :
+/// 2.1 et 2.10 donnent la même clé, 8.25 n'est pas confondu avec 8.2 ou 8.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VatRateKey(i64);
+
+impl VatRateKey {
+    pub fn new(rate: f64) -> Self {
+        Self((rate * 1000.0).round() as i64)
+    }
+
+    /// Taux normalisé (en pourcentage)
+    pub fn rate(&self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+}
+
+/// Taux affiché avec au moins une décimale : 20.0, 5.5, 8.25
+impl fmt::Display for VatRateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rate = format!("{:.3}", self.rate());
+        let rate = rate.trim_end_matches('0');
+        if rate.ends_with('.') {
+            write!(f, "{}0", rate)
+        } else {
+            write!(f, "{}", rate)
+        }
+    }
+}
+
+/// Groupe de la ventilation TVA d'une ligne : taux et catégorie imposée
+///
+/// Deux lignes au même taux mais de catégories différentes (taux normal et
+/// autoliquidation) ne sont pas regroupées. Sans catégorie imposée, la
+/// catégorie se déduit du taux à la génération.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VatGroupKey {
+    pub rate: VatRateKey,
+    /// BT-151 : Catégorie imposée par la ligne
+    pub category: Option<&'static str>,
+}
+
+impl VatGroupKey {
+    pub fn of_line(line: &InvoiceLine) -> Self {
+        Self {
+            rate: VatRateKey::new(line.vat_rate),
+            category: line.vat_category_code(),
+        }
+    }
+}
+
+/// Écart maximal toléré entre le TTC et HT + TVA (arrondis)
+pub const TOTALS_TOLERANCE: f64 = 0.01;
 
 /// Types de document Factur-X (UNTDID 1001)
-#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+///
+/// (Dé)sérialisé sous forme de code numérique (380, 381, 384, 389) :
+/// un code inconnu est rejeté dès le parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum InvoiceTypeCode {
     /// 380 = Facture commerciale
+    #[default]
     Invoice = 380,
     /// 381 = Avoir / Note de crédit
     CreditNote = 381,
@@ -14,13 +195,14 @@ pub enum InvoiceTypeCode {
     PrepaymentInvoice = 389,
 }
 
-impl Default for InvoiceTypeCode {
-    fn default() -> Self {
-        InvoiceTypeCode::Invoice
+impl InvoiceTypeCode {
+    /// Code numérique UNTDID 1001
+    pub fn code(&self) -> u16 {
+        *self as u16
     }
-}
 
-impl InvoiceTypeCode {
+    /// Libellé du type de document (titre et sujet des métadonnées du PDF,
+    /// session du formulaire)
     pub fn label(&self) -> &'static str {
         match self {
             InvoiceTypeCode::Invoice => "Facture",
@@ -30,6 +212,16 @@ impl InvoiceTypeCode {
         }
     }
 
+    /// Titre en capitales imprimé en tête du PDF
+    pub fn title_uppercase(&self) -> &'static str {
+        match self {
+            InvoiceTypeCode::Invoice => "FACTURE",
+            InvoiceTypeCode::CreditNote => "AVOIR",
+            InvoiceTypeCode::CorrectedInvoice => "FACTURE RECTIFICATIVE",
+            InvoiceTypeCode::PrepaymentInvoice => "FACTURE D'ACOMPTE",
+        }
+    }
+
     pub fn from_code(code: u16) -> Option<Self> {
         match code {
             380 => Some(InvoiceTypeCode::Invoice),
@@ -41,7 +233,183 @@ impl InvoiceTypeCode {
     }
 }
 
-#[derive(Deserialize)]
+impl Serialize for InvoiceTypeCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for InvoiceTypeCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Valeur brute : nombre (JSON) ou texte (formulaire)
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawCode {
+            Number(u16),
+            Text(String),
+        }
+
+        let code = match RawCode::deserialize(deserializer)? {
+            RawCode::Number(code) => code,
+            RawCode::Text(text) => text.trim().parse().map_err(|_| {
+                D::Error::custom(format!("code type de document invalide: '{}'", text))
+            })?,
+        };
+
+        InvoiceTypeCode::from_code(code).ok_or_else(|| {
+            D::Error::custom(format!(
+                "code type de document inconnu: {} (attendu 380, 381, 384 ou 389)",
+                code
+            ))
+        })
+    }
+}
+
+/// Échéance d'un paiement fractionné (BG-20 étendu : SpecifiedTradePaymentTerms)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Installment {
+    /// Montant de l'échéance (TTC)
+    pub amount: f64,
+    /// Date d'échéance (YYYY-MM-DD)
+    pub due_date: String,
+    /// Libellé de l'échéance (ex: "Acompte 30%")
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Totaux agrégés d'une facture
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct InvoiceTotals {
+    /// BT-106 : Somme des montants nets des lignes (HT)
+    pub total_ht: f64,
+    /// BT-110 : Montant total de TVA
+    pub total_vat: f64,
+    /// BT-112 : Montant total TTC
+    pub total_ttc: f64,
+    /// BT-107 : Total des remises au niveau document (remise globale)
+    pub allowance_total: f64,
+    /// BT-113 : Montant déjà payé (TTC d'une facture acquittée à l'émission)
+    pub prepaid_total: f64,
+}
+
+/// Montant déjà payé (BT-113) à retrancher du TTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prepayment {
+    /// Aucun paiement avant la facture
+    Unpaid,
+    /// Acompte versé (TTC)
+    Deposit(f64),
+    /// Facture acquittée à l'émission : tout le TTC est payé
+    Paid,
+}
+
+impl InvoiceTotals {
+    /// Calcule les totaux d'une facture : source unique des montants du PDF
+    /// et du XML
+    ///
+    /// Les montants des lignes (`InvoiceLine::compute_totals`) doivent être
+    /// calculés. Ordre d'application :
+    /// 1. remises globales (BT-107) retranchées du total HT des lignes ;
+    /// 2. TVA des lignes moins celle des remises (BT-110), puis TTC (BT-112) ;
+    /// 3. montant déjà payé (BT-113) : acompte, ou tout le TTC si acquittée ;
+    /// 4. montant à payer (BT-115, [`due_payable`](Self::due_payable)), que
+    ///    l'échéancier doit couvrir exactement.
+    pub fn compute(
+        lines: &[InvoiceLine],
+        allowances: &[GlobalDiscountAllowance],
+        prepayment: Prepayment,
+    ) -> Self {
+        let valid_lines = || lines.iter().filter(|l| l.is_valid());
+        // Montants de ligne déjà arrondis au centime : la somme est ré-arrondie
+        // pour effacer les écarts de représentation flottante
+        let total_ht = round_cents(valid_lines().map(|l| l.total_ht_value()).sum());
+        let total_vat = round_cents(valid_lines().map(|l| l.total_vat_value()).sum());
+        let total_ttc = round_cents(valid_lines().map(|l| l.total_ttc_value()).sum());
+
+        let allowance_total: f64 = allowances.iter().map(|a| a.amount).sum();
+        let allowance_vat: f64 = allowances.iter().map(|a| a.vat_amount()).sum();
+        let total_ttc = total_ttc - allowance_total - allowance_vat;
+
+        InvoiceTotals {
+            total_ht,
+            total_vat: total_vat - allowance_vat,
+            total_ttc,
+            allowance_total,
+            prepaid_total: match prepayment {
+                Prepayment::Unpaid => 0.0,
+                Prepayment::Deposit(amount) => amount,
+                Prepayment::Paid => total_ttc,
+            },
+        }
+    }
+
+    /// BT-109 : Base imposable totale (total des lignes moins les remises)
+    ///
+    /// BR-CO-13 ajoute les charges de document (BT-108) : aucune n'est
+    /// gérée, la base est donc `LineTotalAmount - AllowanceTotalAmount`.
+    pub fn tax_basis_total(&self) -> f64 {
+        self.total_ht - self.allowance_total
+    }
+
+    /// BT-115 : Montant restant à payer (TTC moins le montant déjà payé)
+    pub fn due_payable(&self) -> f64 {
+        self.total_ttc - self.prepaid_total
+    }
+
+    /// Vérifie que tous les montants sont finis : un total infini ou NaN
+    /// s'écrirait "inf" / "NaN" dans le XML et le PDF
+    pub fn ensure_finite(&self) -> Result<(), String> {
+        let finite = [
+            self.total_ht,
+            self.total_vat,
+            self.total_ttc,
+            self.allowance_total,
+            self.prepaid_total,
+        ]
+        .iter()
+        .all(|amount| amount.is_finite());
+        if finite {
+            Ok(())
+        } else {
+            Err("Les totaux de la facture ne sont pas des nombres finis : verifiez les quantites et prix des lignes".to_string())
+        }
+    }
+
+    /// Total TTC nul au centime (BT-112 = 0.00), ex. remise de 100 %
+    pub fn is_free_of_charge(&self) -> bool {
+        (self.total_ttc * 100.0).round() == 0.0
+    }
+}
+
+/// Remise globale (BG-20) imputée à un taux de TVA
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobalDiscountAllowance {
+    /// BT-96 : Taux de TVA des lignes remisées
+    pub vat_rate: f64,
+    /// BT-95 : Catégorie imposée des lignes remisées (déduite du taux si absente)
+    pub vat_category: Option<&'static str>,
+    /// BT-93 : Base remisable (somme HT des lignes remisables à ce taux)
+    pub basis: f64,
+    /// BT-92 : Montant de la remise, arrondi au centime
+    pub amount: f64,
+}
+
+impl GlobalDiscountAllowance {
+    /// TVA retranchée par la remise
+    pub fn vat_amount(&self) -> f64 {
+        self.amount * self.vat_rate / 100.0
+    }
+}
+
+/// Schéma ISO 6523 du SIRET (identifiant légal français)
+pub const SIRET_SCHEME_ID: &str = "0002";
+
+/// Formulaire de facture soumis
+///
+/// Sérialisable en JSON (`to_json` / `from_json`) pour l'audit et la
+/// réimpression : les montants calculés des lignes n'y figurent pas, ils sont
+/// recalculés par `compute_totals` au rechargement.
+#[derive(Deserialize, Serialize)]
 pub struct InvoiceForm {
     // Champs obligatoires Factur-X MINIMUM
     /// BT-1 : Numéro de facture (obligatoire)
@@ -49,63 +417,1362 @@ pub struct InvoiceForm {
     /// BT-2 : Date d'émission de la facture (obligatoire)
     pub issue_date: String,
     /// BT-3 : Code type de document (obligatoire) - 380=facture, 381=avoir
-    pub type_code: u16,
+    pub type_code: InvoiceTypeCode,
     /// BT-5 : Code devise (obligatoire) - défaut EUR
     pub currency_code: String,
 
     // Champs conditionnellement obligatoires
     /// BT-9 : Date d'échéance du paiement
     pub due_date: Option<String>,
+    /// BT-7 : Date d'exigibilité de la TVA (date de livraison ou de
+    /// prestation), si différente de la date d'émission
+    #[serde(default)]
+    pub tax_point_date: Option<String>,
     /// BT-20 : Conditions de paiement en texte libre
     pub payment_terms: Option<String>,
     /// BT-10 : Référence de la commande acheteur
     pub buyer_reference: Option<String>,
     /// BT-13 : Référence du bon de commande
     pub purchase_order_reference: Option<String>,
+    /// Code type de taxe du récapitulatif (UNTDID 5153) - défaut VAT
+    pub tax_type_code: Option<String>,
+    /// BT-6 : Devise de comptabilisation de la TVA, si différente de BT-5
+    pub tax_currency_code: Option<String>,
+    /// Taux de change : 1 unité de la devise de facturation = `tax_exchange_rate`
+    /// unités de la devise de TVA
+    pub tax_exchange_rate: Option<f64>,
 
     // Destinataire (acheteur)
     /// BT-44 : Nom du destinataire (obligatoire)
     pub recipient_name: String,
-    /// BT-47 : SIRET du destinataire
+    /// BT-47 : SIRET du destinataire (destinataire français uniquement)
     pub recipient_siret: String,
     /// BT-48 : Numéro TVA intracommunautaire du destinataire
     pub recipient_vat_number: Option<String>,
+    /// BT-46 : GLN du destinataire (Global Location Number, schéma 0088)
+    pub recipient_gln: Option<String>,
     /// BT-50 à BT-55 : Adresse du destinataire
     pub recipient_address: String,
     /// BT-55 : Code pays du destinataire (obligatoire pour le profil BASIC)
     pub recipient_country_code: String,
+    /// Destinataire du secteur public (facturation via Chorus Pro)
+    #[serde(default)]
+    pub recipient_public_sector: bool,
+    /// Code service Chorus Pro du destinataire public
+    #[serde(default)]
+    pub recipient_service_code: Option<String>,
 
     // Lignes de facturation
     pub lines: Vec<InvoiceLine>,
+
+    /// Échéancier de paiement (remplace la date d'échéance unique si non vide)
+    #[serde(default)]
+    pub installments: Vec<Installment>,
+
+    /// BT-94 : Remise globale en pourcentage de la base remisable
+    /// (lignes `discountable`)
+    #[serde(default)]
+    pub global_discount_percent: Option<f64>,
+
+    /// Documents référencés (contrats, appels d'offres, justificatifs),
+    /// émis uniquement en profil EXTENDED
+    #[serde(default)]
+    pub additional_references: Vec<DocumentReference>,
+
+    /// BT-18 : Identifiant de l'objet facturé (contrat, compteur, point de
+    /// livraison), émis en profils EN 16931 et EXTENDED
+    #[serde(default)]
+    pub invoiced_object_id: Option<String>,
+    /// BT-18-1 : Schéma de l'identifiant de l'objet facturé (UNTDID 1153)
+    #[serde(default)]
+    pub invoiced_object_scheme: Option<String>,
+
+    /// Lieu d'expédition des marchandises s'il diffère de l'adresse du
+    /// vendeur, émis uniquement en profil EXTENDED
+    #[serde(default)]
+    pub ship_from_address: Option<ShipFromAddress>,
+
+    /// BT-113 : Acompte déjà versé (TTC), déduit du montant à payer
+    #[serde(default)]
+    pub prepaid_amount: Option<f64>,
+
+    /// Facture réglée à l'émission (espèces, carte) : tampon "PAYÉ" sur le
+    /// PDF et montant à payer nul dans le XML
+    #[serde(default)]
+    pub paid: bool,
+    /// Date du paiement (AAAA-MM-JJ), obligatoire si `paid`
+    #[serde(default)]
+    pub payment_date: Option<String>,
 }
 
 impl InvoiceForm {
-    /// Agrège les totaux pour XML Factur-X
-    pub fn compute_totals(&mut self) -> (f64, f64, f64) {
-        let total_ht: f64 = self
-            .lines
-            .iter_mut()
-            .filter(|l| l.is_valid())
-            .map(|l| {
-                l.compute_totals();
-                l.total_ht_value()
-            })
-            .sum();
+    /// Sérialise le formulaire en JSON
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Impossible de serialiser la facture: {}", e))
+    }
+
+    /// Relit un formulaire sérialisé par `to_json`
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("JSON de facture invalide: {}", e))
+    }
+
+    /// Valide l'échéancier : dates valides, montants positifs et somme égale au montant dû
+    pub fn validate_installments(&self, due_payable: f64) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if self.installments.is_empty() {
+            return errors;
+        }
+
+        for (index, installment) in self.installments.iter().enumerate() {
+            if chrono::NaiveDate::parse_from_str(&installment.due_date, "%Y-%m-%d").is_err() {
+                errors.push(FieldError::new(
+                    format!("installments[{}][due_date]", index),
+                    format!(
+                        "Echeance {} : la date doit etre au format AAAA-MM-JJ",
+                        index + 1
+                    ),
+                ));
+            }
+
+            if installment.amount <= 0.0 {
+                errors.push(FieldError::new(
+                    format!("installments[{}][amount]", index),
+                    format!(
+                        "Echeance {} : le montant doit etre superieur a 0",
+                        index + 1
+                    ),
+                ));
+            }
+        }
+
+        let total: f64 = self.installments.iter().map(|i| i.amount).sum();
+        if (total - due_payable).abs() >= TOTALS_TOLERANCE {
+            errors.push(FieldError::new(
+                "installments",
+                format!(
+                    "La somme des echeances ({:.2}) doit etre egale au montant a payer ({:.2})",
+                    total, due_payable
+                ),
+            ));
+        }
+
+        errors
+    }
+
+    /// Date de paiement d'une facture acquittée (`None` si non payée)
+    pub fn paid_on(&self) -> Option<&str> {
+        if !self.paid {
+            return None;
+        }
+        self.payment_date
+            .as_deref()
+            .map(str::trim)
+            .filter(|date| !date.is_empty())
+    }
+
+    /// Valide la date d'exigibilité de la TVA (BT-7) : format AAAA-MM-JJ
+    pub fn validate_tax_point_date(&self) -> Vec<FieldError> {
+        match self.tax_point_date.as_deref().map(str::trim) {
+            Some(date)
+                if !date.is_empty()
+                    && chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() =>
+            {
+                vec![FieldError::new(
+                    "tax_point_date",
+                    "La date d'exigibilite de la TVA doit etre au format AAAA-MM-JJ",
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Valide le paiement à l'émission : date obligatoire au format AAAA-MM-JJ
+    pub fn validate_payment(&self) -> Vec<FieldError> {
+        if !self.paid {
+            return Vec::new();
+        }
+        match self.paid_on() {
+            None => vec![FieldError::new(
+                "payment_date",
+                "La date de paiement est obligatoire pour une facture acquittee",
+            )],
+            Some(date) if chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() => {
+                vec![FieldError::new(
+                    "payment_date",
+                    "La date de paiement doit etre au format AAAA-MM-JJ",
+                )]
+            }
+            Some(_) => Vec::new(),
+        }
+    }
+
+    /// Valide l'acompte (BT-113) : montant fini et positif, sans objet pour
+    /// une facture acquittée (tout le TTC est déjà payé)
+    pub fn validate_prepaid_amount(&self) -> Vec<FieldError> {
+        let Some(amount) = self.prepaid_amount else {
+            return Vec::new();
+        };
+        if !amount.is_finite() || amount < 0.0 {
+            return vec![FieldError::new(
+                "prepaid_amount",
+                "L'acompte doit etre un montant positif",
+            )];
+        }
+        if self.paid && amount > 0.0 {
+            return vec![FieldError::new(
+                "prepaid_amount",
+                "Une facture acquittee ne peut pas porter d'acompte",
+            )];
+        }
+        Vec::new()
+    }
 
-        let total_vat: f64 = self
-            .lines
+    /// Valide l'acompte face aux totaux calculés : il ne peut dépasser le
+    /// total TTC, sans quoi le montant à payer (BT-115) serait négatif
+    pub fn validate_prepaid_total(&self, totals: InvoiceTotals) -> Vec<FieldError> {
+        if self.prepaid_amount.is_none()
+            || totals.prepaid_total - totals.total_ttc < TOTALS_TOLERANCE
+        {
+            return Vec::new();
+        }
+        vec![FieldError::new(
+            "prepaid_amount",
+            format!(
+                "L'acompte ({:.2}) depasse le total TTC de la facture ({:.2})",
+                totals.prepaid_total, totals.total_ttc
+            ),
+        )]
+    }
+
+    /// Valide la remise globale : pourcentage entre 0 et 100, appliqué à au
+    /// moins une ligne remisable
+    pub fn validate_global_discount(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        let Some(percent) = self.global_discount_percent else {
+            return errors;
+        };
+
+        if !(0.0..=100.0).contains(&percent) {
+            errors.push(FieldError::new(
+                "global_discount_percent",
+                "La remise globale doit etre comprise entre 0 et 100 %",
+            ));
+        } else if percent > 0.0 && !self.lines.iter().any(|l| l.discountable) {
+            errors.push(FieldError::new(
+                "global_discount_percent",
+                "La remise globale ne s'applique a aucune ligne (toutes les lignes en sont exclues)",
+            ));
+        }
+
+        errors
+    }
+
+    /// Champs d'en-tête contrôlés par les règles de l'étape 1
+    pub fn header_fields(&self) -> HeaderFields<'_> {
+        HeaderFields {
+            invoice_number: &self.invoice_number,
+            issue_date: &self.issue_date,
+            currency_code: &self.currency_code,
+            payment_terms: self.payment_terms.as_deref(),
+            buyer_reference: self.buyer_reference.as_deref(),
+            purchase_order_reference: self.purchase_order_reference.as_deref(),
+            tax_type_code: self.tax_type_code.as_deref(),
+            tax_currency_code: self.tax_currency_code.as_deref(),
+            tax_exchange_rate: self.tax_exchange_rate,
+            recipient_name: &self.recipient_name,
+            recipient_siret: &self.recipient_siret,
+            recipient_vat_number: self.recipient_vat_number.as_deref(),
+            recipient_gln: self.recipient_gln.as_deref(),
+            recipient_address: &self.recipient_address,
+            recipient_country_code: &self.recipient_country_code,
+            recipient_service_code: self.recipient_service_code.as_deref(),
+        }
+    }
+
+    /// Applique toutes les règles de validation (en-tête puis lignes) et
+    /// retourne le résultat de chacune, respectée ou non
+    ///
+    /// Le catalogue produits et les règles métier EN 16931 du mode strict,
+    /// qui dépendent des options de génération, ne sont pas inclus.
+    pub fn validate_verbose(&self) -> Vec<RuleResult> {
+        let mut results = header_rules(&self.header_fields());
+        results.extend(line_rules(self));
+        results
+    }
+
+    /// Valide les références de ligne de commande (BT-132) : elles renvoient
+    /// au bon de commande de l'en-tête (BT-13), qui doit donc être renseigné
+    pub fn validate_order_line_references(&self) -> Vec<FieldError> {
+        let has_order = self
+            .purchase_order_reference
+            .as_deref()
+            .is_some_and(|reference| !reference.trim().is_empty());
+        if has_order {
+            return Vec::new();
+        }
+        self.lines
             .iter()
-            .filter(|l| l.is_valid())
-            .map(|l| l.total_vat_value())
-            .sum();
+            .enumerate()
+            .filter(|(_, line)| line.order_line_reference_text().is_some())
+            .map(|(index, _)| {
+                FieldError::new(
+                    format!("lines[{}][order_line_reference]", index),
+                    format!(
+                        "Ligne {} : la reference de ligne de commande exige la reference du bon de commande",
+                        index + 1
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    /// Valide les documents référencés : type UNTDID 1001 admis, identifiant
+    /// renseigné et qualifiant UNTDID 1153 pris en charge
+    pub fn validate_additional_references(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        for (index, reference) in self.additional_references.iter().enumerate() {
+            if !REFERENCED_DOCUMENT_TYPE_CODES.contains(&reference.type_code.trim()) {
+                errors.push(FieldError::new(
+                    format!("additional_references[{}][type_code]", index),
+                    format!(
+                        "Reference {} : le type de document '{}' est inconnu (50, 130 ou 916)",
+                        index + 1,
+                        reference.type_code
+                    ),
+                ));
+            }
+
+            if reference.id.trim().is_empty() {
+                errors.push(FieldError::new(
+                    format!("additional_references[{}][id]", index),
+                    format!("Reference {} : l'identifiant est obligatoire", index + 1),
+                ));
+            }
+
+            if let Some(code) = reference
+                .reference_type_code
+                .as_deref()
+                .filter(|code| !code.trim().is_empty())
+            {
+                if !REFERENCE_TYPE_CODES.contains(&code.trim()) {
+                    errors.push(FieldError::new(
+                        format!("additional_references[{}][reference_type_code]", index),
+                        format!(
+                            "Reference {} : le qualifiant '{}' n'est pas un code UNTDID 1153 pris en charge",
+                            index + 1,
+                            code
+                        ),
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Valide l'objet facturé : schéma UNTDID 1153 pris en charge, et
+    /// identifiant renseigné dès qu'un schéma est indiqué
+    pub fn validate_invoiced_object(&self) -> Vec<FieldError> {
+        let Some(scheme) = self
+            .invoiced_object_scheme
+            .as_deref()
+            .map(str::trim)
+            .filter(|scheme| !scheme.is_empty())
+        else {
+            return Vec::new();
+        };
+
+        let mut errors = Vec::new();
+        if !REFERENCE_TYPE_CODES.contains(&scheme) {
+            errors.push(FieldError::new(
+                "invoiced_object_scheme",
+                format!(
+                    "Le schema '{}' de l'objet facture n'est pas un code UNTDID 1153 pris en charge",
+                    scheme
+                ),
+            ));
+        }
+        if self.invoiced_object().is_none() {
+            errors.push(FieldError::new(
+                "invoiced_object_id",
+                "L'identifiant de l'objet facture est obligatoire avec un schema",
+            ));
+        }
+        errors
+    }
+
+    /// Objet facturé (BT-18) et son schéma éventuel, valeurs vides ignorées
+    pub fn invoiced_object(&self) -> Option<(&str, Option<&str>)> {
+        let id = self
+            .invoiced_object_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())?;
+        let scheme = self
+            .invoiced_object_scheme
+            .as_deref()
+            .map(str::trim)
+            .filter(|scheme| !scheme.is_empty());
+        Some((id, scheme))
+    }
+
+    /// Valide le lieu d'expédition : adresse renseignée et code pays ISO 3166-1
+    pub fn validate_ship_from_address(&self) -> Vec<FieldError> {
+        let Some(ship_from) = &self.ship_from_address else {
+            return Vec::new();
+        };
+        let mut errors = Vec::new();
+
+        if ship_from.address.trim().is_empty() {
+            errors.push(FieldError::new(
+                "ship_from_address[address]",
+                "L'adresse d'expedition est obligatoire",
+            ));
+        }
+
+        if !is_valid_country_code(ship_from.country_code.trim()) {
+            errors.push(FieldError::new(
+                "ship_from_address[country_code]",
+                format!(
+                    "Le pays d'expedition '{}' n'est pas un code ISO 3166-1 alpha-2 (ex: FR)",
+                    ship_from.country_code
+                ),
+            ));
+        }
+
+        errors
+    }
+
+    /// Code type de taxe à émettre dans ApplicableTradeTax (VAT par défaut)
+    pub fn tax_type_code(&self) -> &str {
+        self.tax_type_code
+            .as_deref()
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .unwrap_or(DEFAULT_TAX_TYPE_CODE)
+    }
+
+    /// Destinataire établi en France (identifié par son SIRET)
+    pub fn is_french_recipient(&self) -> bool {
+        self.recipient_country_code
+            .trim()
+            .eq_ignore_ascii_case("FR")
+    }
+
+    /// BT-47 : Identifiant légal du destinataire et son schéma ISO 6523
+    ///
+    /// Seul le SIRET d'un destinataire français (schéma 0002) est émis ; un
+    /// destinataire étranger est identifié par son numéro de TVA (BT-48).
+    pub fn buyer_legal_id(&self) -> Option<(&str, &str)> {
+        let siret = self.recipient_siret.trim();
+        if self.is_french_recipient() && !siret.is_empty() {
+            Some((siret, SIRET_SCHEME_ID))
+        } else {
+            None
+        }
+    }
+
+    /// BT-10 : Référence acheteur à émettre dans `BuyerReference`
+    ///
+    /// Ordre de priorité : la référence saisie, puis pour un destinataire public
+    /// le code service, puis `default_reference` (valeur configurée). Un
+    /// destinataire privé sans référence n'en reçoit aucune.
+    pub fn effective_buyer_reference<'a>(
+        &'a self,
+        default_reference: Option<&'a str>,
+    ) -> Option<&'a str> {
+        let non_empty = |value: Option<&'a str>| value.map(str::trim).filter(|v| !v.is_empty());
+
+        if let Some(reference) = non_empty(self.buyer_reference.as_deref()) {
+            return Some(reference);
+        }
+        if !self.recipient_public_sector {
+            return None;
+        }
+        non_empty(self.recipient_service_code.as_deref()).or(non_empty(default_reference))
+    }
+
+    /// Complète les lignes depuis le catalogue produits, avant validation
+    ///
+    /// Une ligne avec un `product_code` et sans prix (prix nul) reçoit le prix,
+    /// le taux de TVA et l'unité du catalogue, ainsi que sa désignation si elle
+    /// est vide ; le code devient la référence article du vendeur (BT-155) si
+    /// aucune n'est saisie. Une ligne avec un prix saisi le conserve. Un code
+    /// absent du catalogue est une erreur.
+    pub fn apply_catalog(&mut self, catalog: &Catalog) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        for (index, line) in self.lines.iter_mut().enumerate() {
+            let Some(code) = line.product_code_text().map(str::to_string) else {
+                continue;
+            };
+            let Some(item) = catalog.get(&code) else {
+                errors.push(FieldError::new(
+                    format!("lines[{}][product_code]", index),
+                    format!(
+                        "Ligne {} : le code produit '{}' est inconnu du catalogue",
+                        index + 1,
+                        code
+                    ),
+                ));
+                continue;
+            };
+
+            if line.unit_price_ht == 0.0 {
+                line.unit_price_ht = item.unit_price_ht;
+                line.vat_rate = item.vat_rate;
+                if line.unit_code.is_none() {
+                    line.unit_code = item.unit_code.clone();
+                }
+                if line.description.trim().is_empty() {
+                    line.description = item.description.clone();
+                }
+            }
+            if line.seller_item_id_text().is_none() {
+                line.seller_item_id = Some(code);
+            }
+        }
+        errors
+    }
+
+    /// Trie les lignes dans l'ordre affiché à l'utilisateur
+    ///
+    /// Une ligne sans `position` prend son rang actuel comme position ; à
+    /// position égale, l'ordre actuel est conservé (tri stable). À appeler
+    /// avant la génération : les `LineID` (BT-126) suivent cet ordre.
+    pub fn sort_lines(&mut self) {
+        let mut lines: Vec<(u32, InvoiceLine)> = std::mem::take(&mut self.lines)
+            .into_iter()
+            .enumerate()
+            .map(|(rank, line)| (line.position.unwrap_or(rank as u32), line))
+            .collect();
+        lines.sort_by_key(|(position, _)| *position);
+        self.lines = lines.into_iter().map(|(_, line)| line).collect();
+    }
+
+    /// Franchise en base de TVA : aucune TVA n'est facturée, tous les taux passent à 0
+    /// À appeler avant `compute_totals`
+    pub fn apply_vat_franchise(&mut self) {
+        for line in &mut self.lines {
+            line.vat_rate = 0.0;
+        }
+    }
 
-        let total_ttc: f64 = self
-            .lines
+    /// BT-111 : Total TVA converti dans la devise de TVA, arrondi au centime
+    ///
+    /// Retourne la devise et le montant si une devise de TVA et un taux sont renseignés.
+    pub fn tax_currency_vat_total(&self, total_vat: f64) -> Option<(&str, f64)> {
+        let tax_currency = self
+            .tax_currency_code
+            .as_deref()
+            .filter(|code| !code.is_empty())?;
+        let rate = self.tax_exchange_rate?;
+        Some((tax_currency, (total_vat * rate * 100.0).round() / 100.0))
+    }
+
+    /// Remise globale répartie par groupe de TVA (BG-20, voir
+    /// [`VatGroupKey`]), par taux croissant
+    ///
+    /// La base remisable d'un groupe est la somme HT des lignes
+    /// `discountable` de ce groupe : les lignes exclues n'y contribuent pas. Chaque montant est
+    /// arrondi au centime, comme dans le XML. À appeler après le calcul des
+    /// totaux de lignes.
+    pub fn global_discount_allowances(&self) -> Vec<GlobalDiscountAllowance> {
+        let Some(percent) = self.global_discount_percent.filter(|p| *p > 0.0) else {
+            return Vec::new();
+        };
+
+        let mut bases: BTreeMap<VatGroupKey, f64> = BTreeMap::new();
+        for line in self.lines.iter().filter(|l| l.is_valid() && l.discountable) {
+            *bases.entry(VatGroupKey::of_line(line)).or_default() += line.total_ht_value();
+        }
+
+        bases
+            .into_iter()
+            .filter(|(_, basis)| *basis > 0.0)
+            .map(|(group, basis)| GlobalDiscountAllowance {
+                vat_rate: group.rate.rate(),
+                vat_category: group.category,
+                basis,
+                amount: (basis * percent / 100.0 * 100.0).round() / 100.0,
+            })
+            .collect()
+    }
+
+    /// Agrège les totaux pour XML Factur-X
+    ///
+    /// La remise globale réduit la base imposable et la TVA de chaque taux.
+    pub fn compute_totals(&mut self) -> InvoiceTotals {
+        for line in self.lines.iter_mut().filter(|l| l.is_valid()) {
+            line.compute_totals();
+        }
+        InvoiceTotals::compute(
+            &self.lines,
+            &self.global_discount_allowances(),
+            self.prepayment(),
+        )
+    }
+
+    /// Montant déjà payé : tout le TTC si la facture est acquittée, sinon
+    /// l'acompte saisi
+    pub fn prepayment(&self) -> Prepayment {
+        if self.paid {
+            return Prepayment::Paid;
+        }
+        match self.prepaid_amount {
+            Some(amount) if amount > 0.0 => Prepayment::Deposit(amount),
+            _ => Prepayment::Unpaid,
+        }
+    }
+
+    /// Ajoute une ligne et retourne les totaux recalculés
+    pub fn add_line(&mut self, line: InvoiceLine) -> InvoiceTotals {
+        self.lines.push(line);
+        self.compute_totals()
+    }
+
+    /// Retire la ligne à l'index donné et retourne la ligne retirée avec les
+    /// totaux recalculés (`None` si l'index est hors bornes)
+    pub fn remove_line(&mut self, index: usize) -> Option<(InvoiceLine, InvoiceTotals)> {
+        if index >= self.lines.len() {
+            return None;
+        }
+        let line = self.lines.remove(index);
+        Some((line, self.compute_totals()))
+    }
+}
+
+/// Vérifie la cohérence des totaux agrégés tels qu'écrits dans le XML :
+/// TTC = base imposable + TVA, chaque montant arrondi au centime (BR-CO-15)
+///
+/// Un écart signale une incohérence de calcul (ex: rabais mal appliqué)
+/// qui produirait un XML Factur-X rejeté par les plateformes.
+pub fn check_totals_consistency(totals: InvoiceTotals) -> Result<(), FieldError> {
+    let cents = |amount: f64| (amount * 100.0).round() as i64;
+    let total_ttc = cents(totals.total_ttc);
+    let total_ht = cents(totals.tax_basis_total());
+    let total_vat = cents(totals.total_vat);
+    let delta = total_ttc - (total_ht + total_vat);
+
+    if delta == 0 {
+        Ok(())
+    } else {
+        Err(FieldError::new(
+            "_form",
+            format!(
+                "Totaux incohérents : TTC {:.2} != HT {:.2} + TVA {:.2} (écart {:.2})",
+                total_ttc as f64 / 100.0,
+                total_ht as f64 / 100.0,
+                total_vat as f64 / 100.0,
+                delta as f64 / 100.0
+            ),
+        ))
+    }
+}
+
+/// Facture de test partagée par les tests des modèles et du générateur
+#[cfg(test)]
+impl InvoiceForm {
+    /// En-tête minimal valide (émise le 15/01/2024, sans échéance) et `lines`
+    pub(crate) fn sample(lines: Vec<InvoiceLine>) -> Self {
+        let mut form = Self::from_json(
+            r#"{
+                "invoice_number": "FA-2024-001",
+                "issue_date": "2024-01-15",
+                "type_code": 380,
+                "currency_code": "EUR",
+                "recipient_name": "Client SA",
+                "recipient_siret": "98765432100019",
+                "recipient_address": "1 avenue du Client, 69001 Lyon",
+                "recipient_country_code": "FR",
+                "lines": []
+            }"#,
+        )
+        .unwrap();
+        form.lines = lines;
+        form
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::catalog::CatalogItem;
+
+    fn form_with_lines(lines: Vec<InvoiceLine>) -> InvoiceForm {
+        InvoiceForm::sample(lines)
+    }
+
+    #[test]
+    fn test_validate_verbose_reports_passed_and_failed_rules() {
+        let mut form = form_with_lines(vec![InvoiceLine {
+            description: "Prestation".to_string(),
+            unit_price_ht: 100.0,
+            ..Default::default()
+        }]);
+        let results = form.validate_verbose();
+        assert!(results.iter().all(|r| r.passed));
+        assert!(results.iter().any(|r| r.id == "BT-1-present"));
+
+        form.invoice_number = " ".to_string();
+        form.recipient_siret = "123".to_string();
+        let failed: Vec<&str> = form
+            .validate_verbose()
             .iter()
-            .filter(|l| l.is_valid())
-            .map(|l| l.total_ttc_value())
-            .sum();
+            .filter(|r| !r.passed)
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(failed, vec!["BT-1-present", "BT-47-siret"]);
+
+        let results = form.validate_verbose();
+        let siret = results.iter().find(|r| r.id == "BT-47-siret").unwrap();
+        assert_eq!(
+            siret.errors[0].message,
+            "Le SIRET doit contenir 14 chiffres"
+        );
+    }
+
+    #[test]
+    fn test_paid_invoice() {
+        let mut form = form_with_lines(vec![InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            vat_rate: 20.0,
+            ..Default::default()
+        }]);
+        assert_eq!(form.compute_totals().due_payable(), 120.0);
+
+        form.paid = true;
+        let fields: Vec<String> = form
+            .validate_payment()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, vec!["payment_date"]);
+
+        form.payment_date = Some("15/01/2024".to_string());
+        assert_eq!(form.validate_payment().len(), 1);
+
+        form.payment_date = Some("2024-01-15".to_string());
+        assert!(form.validate_payment().is_empty());
+        let totals = form.compute_totals();
+        assert_eq!(totals.prepaid_total, 120.0);
+        assert_eq!(totals.due_payable(), 0.0);
+        assert!(!totals.is_free_of_charge());
+    }
+
+    #[test]
+    fn test_apply_catalog() {
+        let mut catalog = Catalog::new();
+        catalog.insert(
+            "ABO-M",
+            CatalogItem {
+                description: "Abonnement mensuel".to_string(),
+                unit_price_ht: 49.9,
+                vat_rate: 20.0,
+                unit_code: Some("MON".to_string()),
+            },
+        );
+        catalog.insert(
+            "LIVRE",
+            CatalogItem {
+                description: "Livre".to_string(),
+                unit_price_ht: 18.0,
+                vat_rate: 5.5,
+                unit_code: None,
+            },
+        );
+
+        let mut form = form_with_lines(vec![
+            InvoiceLine {
+                product_code: Some(" ABO-M ".to_string()),
+                quantity: 2.0,
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Livre dedicace".to_string(),
+                product_code: Some("LIVRE".to_string()),
+                seller_item_id: Some("REF-42".to_string()),
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Livre a prix negocie".to_string(),
+                product_code: Some("LIVRE".to_string()),
+                unit_price_ht: 15.0,
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Inconnu".to_string(),
+                product_code: Some("XYZ".to_string()),
+                unit_price_ht: 10.0,
+                ..Default::default()
+            },
+        ]);
+
+        let errors = form.apply_catalog(&catalog);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[3][product_code]");
+        assert!(errors[0].message.contains("'XYZ' est inconnu"));
+
+        let lines = &form.lines;
+        assert_eq!(lines[0].description, "Abonnement mensuel");
+        assert_eq!(lines[0].unit_price_ht, 49.9);
+        assert_eq!(lines[0].unit_code(), "MON");
+        assert_eq!(lines[0].seller_item_id.as_deref(), Some("ABO-M"));
+
+        assert_eq!(lines[1].description, "Livre dedicace");
+        assert_eq!((lines[1].unit_price_ht, lines[1].vat_rate), (18.0, 5.5));
+        assert_eq!(lines[1].unit_code(), "C62");
+        assert_eq!(lines[1].seller_item_id.as_deref(), Some("REF-42"));
+
+        // Prix saisi conservé, TVA de la ligne inchangée
+        assert_eq!((lines[2].unit_price_ht, lines[2].vat_rate), (15.0, 20.0));
+        assert!(lines.iter().take(3).all(|line| line.validate(0).is_empty()));
+    }
+
+    #[test]
+    fn test_order_line_references_require_order() {
+        let mut form = form_with_lines(vec![
+            InvoiceLine {
+                order_line_reference: Some("10".to_string()),
+                ..Default::default()
+            },
+            InvoiceLine::default(),
+        ]);
+        let errors = form.validate_order_line_references();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][order_line_reference]");
+
+        form.purchase_order_reference = Some("PO-2024-17".to_string());
+        assert!(form.validate_order_line_references().is_empty());
+    }
+
+    #[test]
+    fn test_tax_point_date_format() {
+        let mut form = form_with_lines(Vec::new());
+        assert!(form.validate_tax_point_date().is_empty());
+
+        form.tax_point_date = Some("2024-01-10".to_string());
+        assert!(form.validate_tax_point_date().is_empty());
+
+        for invalid in ["10/01/2024", "2024-02-30", "20240110"] {
+            form.tax_point_date = Some(invalid.to_string());
+            let errors = form.validate_tax_point_date();
+            assert_eq!(errors.len(), 1, "{}", invalid);
+            assert_eq!(errors[0].field, "tax_point_date");
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut form = form_with_lines(vec![
+            InvoiceLine {
+                description: "Prestation".to_string(),
+                quantity: 3.0,
+                unit_price_ht: 33.33,
+                vat_rate: 20.0,
+                discount_value: Some(10.0),
+                discount_type: Some("percent".to_string()),
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Debours".to_string(),
+                quantity: 1.0,
+                unit_price_ht: 12.5,
+                vat_rate: 5.5,
+                discountable: false,
+                ..Default::default()
+            },
+        ]);
+        form.type_code = InvoiceTypeCode::CreditNote;
+        form.global_discount_percent = Some(5.0);
+        form.paid = true;
+        form.payment_date = Some("2024-01-20".to_string());
+        let totals = form.compute_totals();
+
+        let json = form.to_json().unwrap();
+        assert!(json.contains("\"type_code\": 381"));
+        // Montants calculés des lignes non sérialisés
+        assert!(!json.contains("total_ht"));
+
+        let mut reloaded = InvoiceForm::from_json(&json).unwrap();
+        assert!(reloaded.lines.iter().all(|line| line.total_ht.is_none()));
+        assert_eq!(reloaded.compute_totals(), totals);
+        assert_eq!(reloaded.to_json().unwrap(), json);
+
+        assert!(InvoiceForm::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_vat_rate_key() {
+        assert_eq!(VatRateKey::new(2.1), VatRateKey::new(2.10));
+        assert_eq!(VatRateKey::new(8.25).rate(), 8.25);
+        assert_ne!(VatRateKey::new(8.25), VatRateKey::new(8.2));
+        assert!(VatRateKey::new(5.5) < VatRateKey::new(20.0));
+
+        assert_eq!(VatRateKey::new(20.0).to_string(), "20.0");
+        assert_eq!(VatRateKey::new(2.1).to_string(), "2.1");
+        assert_eq!(VatRateKey::new(8.25).to_string(), "8.25");
+    }
+
+    #[test]
+    fn test_totals_consistent_with_discounts() {
+        let mut form = form_with_lines(vec![
+            InvoiceLine {
+                description: "Licence".to_string(),
+                quantity: 3.0,
+                unit_price_ht: 19.99,
+                vat_rate: 5.5,
+                discount_value: Some(33.33),
+                discount_type: Some("percent".to_string()),
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Support".to_string(),
+                quantity: 7.0,
+                unit_price_ht: 13.37,
+                vat_rate: 20.0,
+                discount_value: Some(12.34),
+                discount_type: Some("amount".to_string()),
+                ..Default::default()
+            },
+        ]);
+
+        let totals = form.compute_totals();
+        assert!(check_totals_consistency(totals).is_ok());
+
+        // 11.138 remisé de 10 % : HT 10.0242 et TVA 2.00484 non arrondis
+        // donnent un TTC de 12.03 au centime, contre 10.02 + 2.00 dans le XML
+        let mut form = form_with_lines(vec![InvoiceLine {
+            description: "Licence".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 11.138,
+            vat_rate: 20.0,
+            discount_value: Some(10.0),
+            discount_type: Some("percent".to_string()),
+            ..Default::default()
+        }]);
+        let totals = form.compute_totals();
+        assert_eq!(
+            (totals.total_ht, totals.total_vat, totals.total_ttc),
+            (10.02, 2.0, 12.02)
+        );
+        assert!(check_totals_consistency(totals).is_ok());
+
+        let unrounded = InvoiceTotals {
+            total_ht: 10.0242,
+            total_vat: 2.00484,
+            total_ttc: 12.02904,
+            allowance_total: 0.0,
+            prepaid_total: 0.0,
+        };
+        assert!(check_totals_consistency(unrounded).is_err());
+    }
+
+    #[test]
+    fn test_invoice_type_code_deserialize() {
+        use serde::de::value::{Error, StrDeserializer, U16Deserializer};
+
+        let code = InvoiceTypeCode::deserialize(U16Deserializer::<Error>::new(381)).unwrap();
+        assert_eq!(code, InvoiceTypeCode::CreditNote);
+
+        let code = InvoiceTypeCode::deserialize(StrDeserializer::<Error>::new("389")).unwrap();
+        assert_eq!(code, InvoiceTypeCode::PrepaymentInvoice);
+
+        assert!(InvoiceTypeCode::deserialize(U16Deserializer::<Error>::new(999)).is_err());
+        assert!(InvoiceTypeCode::deserialize(StrDeserializer::<Error>::new("abc")).is_err());
+    }
+
+    #[test]
+    fn test_invoice_type_code_labels() {
+        let expected = [
+            (380, "Facture", "FACTURE"),
+            (381, "Avoir", "AVOIR"),
+            (384, "Facture rectificative", "FACTURE RECTIFICATIVE"),
+            (389, "Facture d'acompte", "FACTURE D'ACOMPTE"),
+        ];
+        for (code, label, title) in expected {
+            let type_code = InvoiceTypeCode::from_code(code).unwrap();
+            assert_eq!(type_code.code(), code);
+            assert_eq!(type_code.label(), label);
+            assert_eq!(type_code.title_uppercase(), title);
+            assert_eq!(type_code.label().to_uppercase(), title);
+        }
+    }
+
+    #[test]
+    fn test_tax_type_code_default_and_validation() {
+        let mut form = form_with_lines(Vec::new());
+        assert_eq!(form.tax_type_code(), "VAT");
+
+        form.tax_type_code = Some(" ".to_string());
+        assert_eq!(form.tax_type_code(), "VAT");
+
+        form.tax_type_code = Some("GST".to_string());
+        assert_eq!(form.tax_type_code(), "GST");
+
+        assert!(is_valid_tax_type_code("VAT"));
+        assert!(is_valid_tax_type_code("GST"));
+        assert!(!is_valid_tax_type_code("TVA"));
+    }
+
+    #[test]
+    fn test_validate_installments_sum() {
+        let mut form = form_with_lines(Vec::new());
+        form.installments = vec![
+            Installment {
+                amount: 400.0,
+                due_date: "2024-02-15".to_string(),
+                description: Some("Acompte".to_string()),
+            },
+            Installment {
+                amount: 800.0,
+                due_date: "2024-03-15".to_string(),
+                description: None,
+            },
+        ];
+        assert!(form.validate_installments(1200.0).is_empty());
+
+        let errors = form.validate_installments(1000.0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "installments");
+    }
+
+    #[test]
+    fn test_due_payable_with_discount_prepayment_and_installments() {
+        let mut form = form_with_lines(vec![InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 10.0,
+            unit_price_ht: 100.0,
+            vat_rate: 20.0,
+            ..Default::default()
+        }]);
+        // 1000 HT - 10 % = 900 HT, 180 de TVA, 1080 TTC, moins 300 d'acompte
+        form.global_discount_percent = Some(10.0);
+        form.prepaid_amount = Some(300.0);
+        form.installments = vec![
+            Installment {
+                amount: 480.0,
+                due_date: "2024-02-15".to_string(),
+                description: None,
+            },
+            Installment {
+                amount: 300.0,
+                due_date: "2024-03-15".to_string(),
+                description: None,
+            },
+        ];
+        assert!(form.validate_prepaid_amount().is_empty());
+
+        let totals = form.compute_totals();
+        assert!((totals.tax_basis_total() - 900.0).abs() < 0.001);
+        assert!((totals.total_vat - 180.0).abs() < 0.001);
+        assert!((totals.total_ttc - 1080.0).abs() < 0.001);
+        assert_eq!(totals.prepaid_total, 300.0);
+        assert!((totals.due_payable() - 780.0).abs() < 0.001);
+        assert!(form.validate_installments(totals.due_payable()).is_empty());
+
+        // Même calcul depuis les lignes, remises et acompte
+        let allowances = form.global_discount_allowances();
+        assert_eq!(
+            InvoiceTotals::compute(&form.lines, &allowances, Prepayment::Deposit(300.0)),
+            totals
+        );
+
+        assert!(form.validate_prepaid_total(totals).is_empty());
+
+        // Acompte supérieur au TTC : montant à payer négatif refusé
+        form.prepaid_amount = Some(1080.0);
+        let totals = form.compute_totals();
+        assert!(form.validate_prepaid_total(totals).is_empty());
+        form.prepaid_amount = Some(1100.0);
+        let totals = form.compute_totals();
+        let errors = form.validate_prepaid_total(totals);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "L'acompte (1100.00) depasse le total TTC de la facture (1080.00)"
+        );
+
+        form.paid = true;
+        assert_eq!(form.validate_prepaid_amount().len(), 1);
+        form.prepaid_amount = Some(-1.0);
+        assert_eq!(form.validate_prepaid_amount()[0].field, "prepaid_amount");
+    }
+
+    #[test]
+    fn test_validate_installments_invalid_date() {
+        let mut form = form_with_lines(Vec::new());
+        form.installments = vec![Installment {
+            amount: 100.0,
+            due_date: "15/02/2024".to_string(),
+            description: None,
+        }];
+        let errors = form.validate_installments(100.0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "installments[0][due_date]");
+    }
+
+    #[test]
+    fn test_totals_inconsistent_reports_delta() {
+        let err = check_totals_consistency(InvoiceTotals {
+            total_ht: 100.0,
+            total_vat: 20.0,
+            total_ttc: 125.0,
+            allowance_total: 0.0,
+            prepaid_total: 0.0,
+        })
+        .unwrap_err();
+        assert_eq!(err.field, "_form");
+        assert!(err.message.contains("(écart 5.00)"));
+    }
+
+    #[test]
+    fn test_validate_tax_currency() {
+        assert!(validate_tax_currency("USD", Some("EUR"), Some(0.92)).is_empty());
+        assert!(validate_tax_currency("EUR", None, None).is_empty());
+
+        let errors = validate_tax_currency("USD", Some("EUR"), Some(0.0));
+        assert_eq!(errors[0].field, "tax_exchange_rate");
+
+        let errors = validate_tax_currency("USD", Some("XYZ"), Some(1.1));
+        assert_eq!(errors[0].field, "tax_currency_code");
+
+        let errors = validate_tax_currency("EUR", Some("EUR"), Some(1.0));
+        assert_eq!(errors[0].field, "tax_currency_code");
+    }
+
+    #[test]
+    fn test_tax_currency_vat_total() {
+        let mut form = form_with_lines(Vec::new());
+        assert_eq!(form.tax_currency_vat_total(20.0), None);
+
+        form.tax_currency_code = Some("EUR".to_string());
+        form.tax_exchange_rate = Some(0.9234);
+        assert_eq!(form.tax_currency_vat_total(20.0), Some(("EUR", 18.47)));
+    }
+
+    #[test]
+    fn test_effective_buyer_reference() {
+        let mut form = form_with_lines(Vec::new());
+        let default = Some("DEFAUT");
+
+        // Destinataire privé : seule la référence saisie est émise
+        assert_eq!(form.effective_buyer_reference(default), None);
+        form.recipient_service_code = Some("SERVICE-01".to_string());
+        assert_eq!(form.effective_buyer_reference(default), None);
+        form.buyer_reference = Some("REF-42".to_string());
+        assert_eq!(form.effective_buyer_reference(default), Some("REF-42"));
+
+        // Destinataire public : référence, puis code service, puis défaut
+        form.recipient_public_sector = true;
+        assert_eq!(form.effective_buyer_reference(default), Some("REF-42"));
+        form.buyer_reference = Some("  ".to_string());
+        assert_eq!(form.effective_buyer_reference(default), Some("SERVICE-01"));
+        form.recipient_service_code = None;
+        assert_eq!(form.effective_buyer_reference(default), Some("DEFAUT"));
+        assert_eq!(form.effective_buyer_reference(None), None);
+    }
+
+    #[test]
+    fn test_free_of_charge_totals() {
+        let mut form = form_with_lines(vec![InvoiceLine {
+            description: "Licence offerte".to_string(),
+            quantity: 1.0,
+            unit_price_ht: 100.0,
+            vat_rate: 20.0,
+            discount_value: Some(100.0),
+            discount_type: Some("percent".to_string()),
+            ..Default::default()
+        }]);
+        assert!(form.compute_totals().is_free_of_charge());
+
+        form.lines[0].discount_value = Some(50.0);
+        assert!(!form.compute_totals().is_free_of_charge());
+    }
+
+    #[test]
+    fn test_buyer_legal_id_by_country() {
+        let mut form = form_with_lines(Vec::new());
+        assert_eq!(form.buyer_legal_id(), Some(("98765432100019", "0002")));
+
+        form.recipient_country_code = "DE".to_string();
+        assert_eq!(form.buyer_legal_id(), None);
+
+        form.recipient_country_code = "fr".to_string();
+        form.recipient_siret = String::new();
+        assert_eq!(form.buyer_legal_id(), None);
+    }
+
+    #[test]
+    fn test_global_discount_skips_excluded_lines() {
+        let mut form = form_with_lines(vec![
+            InvoiceLine {
+                description: "Prestation".to_string(),
+                quantity: 2.0,
+                unit_price_ht: 100.0,
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Debours".to_string(),
+                quantity: 1.0,
+                unit_price_ht: 50.0,
+                discountable: false,
+                ..Default::default()
+            },
+            InvoiceLine {
+                description: "Livre".to_string(),
+                quantity: 1.0,
+                unit_price_ht: 40.0,
+                vat_rate: 5.5,
+                ..Default::default()
+            },
+        ]);
+        form.global_discount_percent = Some(10.0);
+
+        let totals = form.compute_totals();
+        let allowances = form.global_discount_allowances();
+        assert_eq!(allowances.len(), 2);
+        assert_eq!(allowances[0].vat_rate, 5.5);
+        assert!((allowances[0].amount - 4.0).abs() < 0.001);
+        // Base remisable à 20 % : 200 (la ligne de débours de 50 est exclue)
+        assert!((allowances[1].basis - 200.0).abs() < 0.001);
+        assert!((allowances[1].amount - 20.0).abs() < 0.001);
+
+        assert!((totals.total_ht - 290.0).abs() < 0.001);
+        assert!((totals.allowance_total - 24.0).abs() < 0.001);
+        assert!((totals.tax_basis_total() - 266.0).abs() < 0.001);
+        // TVA : (250 - 20) x 20 % + (40 - 4) x 5,5 %
+        assert!((totals.total_vat - 47.98).abs() < 0.001);
+        assert!(check_totals_consistency(totals).is_ok());
+        assert!(form.validate_global_discount().is_empty());
+
+        form.global_discount_percent = Some(120.0);
+        assert_eq!(
+            form.validate_global_discount()[0].field,
+            "global_discount_percent"
+        );
+        form.global_discount_percent = Some(5.0);
+        for line in &mut form.lines {
+            line.discountable = false;
+        }
+        assert_eq!(form.validate_global_discount().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_ship_from_address() {
+        let mut form = form_with_lines(vec![InvoiceLine::default()]);
+        assert!(form.validate_ship_from_address().is_empty());
+
+        form.ship_from_address = Some(ShipFromAddress {
+            name: Some("Entrepot Lyon".to_string()),
+            address: "12 rue des Docks, 69007 Lyon".to_string(),
+            country_code: "FR".to_string(),
+        });
+        assert!(form.validate_ship_from_address().is_empty());
+
+        form.ship_from_address = Some(ShipFromAddress {
+            name: None,
+            address: " ".to_string(),
+            country_code: "France".to_string(),
+        });
+        let fields: Vec<String> = form
+            .validate_ship_from_address()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "ship_from_address[address]",
+                "ship_from_address[country_code]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_invoiced_object() {
+        let mut form = form_with_lines(Vec::new());
+        assert!(form.validate_invoiced_object().is_empty());
+        assert_eq!(form.invoiced_object(), None);
+
+        form.invoiced_object_id = Some("CTR-2024-881".to_string());
+        assert!(form.validate_invoiced_object().is_empty());
+        form.invoiced_object_scheme = Some(" CT ".to_string());
+        assert!(form.validate_invoiced_object().is_empty());
+        assert_eq!(form.invoiced_object(), Some(("CTR-2024-881", Some("CT"))));
+
+        form.invoiced_object_scheme = Some("XYZ".to_string());
+        form.invoiced_object_id = Some("  ".to_string());
+        let fields: Vec<String> = form
+            .validate_invoiced_object()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, vec!["invoiced_object_scheme", "invoiced_object_id"]);
+    }
+
+    #[test]
+    fn test_validate_additional_references() {
+        let mut form = form_with_lines(Vec::new());
+        form.additional_references = vec![
+            DocumentReference {
+                type_code: "916".to_string(),
+                id: "CONTRAT-2024-12".to_string(),
+                uri: Some("https://example.com/contrat.pdf".to_string()),
+                reference_type_code: Some("CT".to_string()),
+            },
+            DocumentReference {
+                type_code: "130".to_string(),
+                id: "ABC-1".to_string(),
+                uri: None,
+                reference_type_code: None,
+            },
+        ];
+        assert!(form.validate_additional_references().is_empty());
+
+        form.additional_references[1].type_code = "380".to_string();
+        form.additional_references[1].id = " ".to_string();
+        form.additional_references[1].reference_type_code = Some("ZZZ".to_string());
+        let fields: Vec<String> = form
+            .validate_additional_references()
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "additional_references[1][type_code]",
+                "additional_references[1][id]",
+                "additional_references[1][reference_type_code]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_and_remove_line() {
+        let mut form = form_with_lines(Vec::new());
+        let line = InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 2.0,
+            unit_price_ht: 50.0,
+            vat_rate: 20.0,
+            ..Default::default()
+        };
+
+        let totals = form.add_line(line.clone());
+        assert!((totals.total_ht - 100.0).abs() < 0.001);
+        let totals = form.add_line(line);
+        assert!((totals.total_ttc - 240.0).abs() < 0.001);
+        assert_eq!(form.lines.len(), 2);
 
-        (total_ht, total_vat, total_ttc)
+        let (removed, totals) = form.remove_line(0).unwrap();
+        assert_eq!(removed.description, "Prestation");
+        assert!((totals.total_ht - 100.0).abs() < 0.001);
+        assert!(form.remove_line(1).is_none());
+        assert_eq!(form.lines.len(), 1);
     }
 }
@@ -1,20 +1,102 @@
+use super::error::FieldError;
+use super::validation::{has_invalid_xml_chars, is_valid_gtin};
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// Type de rabais d'une ligne : pourcentage du montant brut ou montant fixe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscountType {
+    /// "percent" (défaut)
+    #[default]
+    Percent,
+    /// "amount" : montant HT retranché du montant brut
+    Amount,
+}
+
+impl DiscountType {
+    /// Code saisi dans le formulaire
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiscountType::Percent => "percent",
+            DiscountType::Amount => "amount",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.trim() {
+            "percent" => Some(DiscountType::Percent),
+            "amount" => Some(DiscountType::Amount),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct InvoiceLine {
     pub description: String,
+    /// Détail complémentaire (optionnel), affiché sous la description
+    #[serde(default)]
+    pub detail: Option<String>,
+    /// BT-155 : Référence article du vendeur
+    #[serde(default)]
+    pub seller_item_id: Option<String>,
+    /// Code article du catalogue produits : complète une ligne sans prix
+    /// (voir `InvoiceForm::apply_catalog`)
+    #[serde(default)]
+    pub product_code: Option<String>,
+    /// BT-157 : Identifiant standard de l'article (GTIN par défaut)
+    #[serde(default)]
+    pub standard_item_id: Option<String>,
+    /// Schéma de l'identifiant standard (ISO 6523, "0160" = GTIN par défaut)
+    #[serde(default)]
+    pub standard_item_scheme: Option<String>,
+    /// BT-132 : Référence de la ligne du bon de commande acheteur (exige la
+    /// référence du bon de commande BT-13 en en-tête)
+    #[serde(default)]
+    pub order_line_reference: Option<String>,
+    /// Section de regroupement dans le PDF (ex: "Prestations"), sans effet sur le XML
+    #[serde(default)]
+    pub section: Option<String>,
+    /// BT-134 : Début de la période de facturation de la ligne (AAAA-MM-JJ)
+    #[serde(default)]
+    pub period_start: Option<String>,
+    /// BT-135 : Fin de la période de facturation de la ligne (AAAA-MM-JJ)
+    #[serde(default)]
+    pub period_end: Option<String>,
     pub quantity: f64,
+    /// BT-130 : Code unité UN/ECE Rec 20 de la quantité (C62 "unité" si absent)
+    #[serde(default)]
+    pub unit_code: Option<String>,
+    /// Prix unitaire HT ; absent ou nul, il vient du catalogue si la ligne
+    /// porte un `product_code`
+    #[serde(default)]
     pub unit_price_ht: f64,
+    #[serde(default = "default_vat_rate")]
     pub vat_rate: f64,
+    /// BT-151 : Catégorie de TVA imposée (code UNTDID 5305, ex. "AE" pour
+    /// l'autoliquidation) ; déduite du taux si absente
+    #[serde(default)]
+    pub vat_category: Option<String>,
     /// Valeur du rabais (optionnel)
     #[serde(default)]
     pub discount_value: Option<f64>,
     /// Type de rabais : "percent" ou "amount"
     #[serde(default)]
     pub discount_type: Option<String>,
+    /// Ligne soumise à la remise globale de la facture (défaut : oui)
+    /// Les articles déjà remisés ou les débours en sont exclus avec `false`.
+    #[serde(default = "default_discountable")]
+    pub discountable: bool,
+    /// Rang d'affichage de la ligne (optionnel) : les lignes sont triées par
+    /// position avant la génération, qui numérote les `LineID` dans cet ordre
+    #[serde(default)]
+    pub position: Option<u32>,
     #[serde(skip_serializing)]
     pub total_ht: Option<f64>,
+    /// Montant brut (quantité × prix unitaire), avant rabais
+    #[serde(skip_serializing)]
+    pub gross_ht: Option<f64>,
     #[serde(skip_serializing)]
     pub total_ttc: Option<f64>,
     #[serde(skip_serializing)]
@@ -23,18 +105,61 @@ pub struct InvoiceLine {
     pub discount_amount: Option<f64>,
 }
 
+fn default_discountable() -> bool {
+    true
+}
+
+fn default_vat_rate() -> f64 {
+    20.0
+}
+
+/// Catégories de TVA UNTDID 5305 acceptées pour une ligne (BT-151)
+pub const VAT_CATEGORY_CODES: [&str; 9] = ["S", "Z", "E", "AE", "K", "G", "O", "L", "M"];
+
+/// Code unité par défaut (UN/ECE Rec 20 : "unité")
+pub const DEFAULT_UNIT_CODE: &str = "C62";
+
+/// Montant brut maximal d'une ligne (quantité × prix unitaire) : au-delà, un
+/// `f64` ne garantit plus le centime et les totaux peuvent devenir infinis
+pub const MAX_LINE_AMOUNT: f64 = 10_000_000_000_000.0;
+
 impl InvoiceLine {
+    /// Type de rabais saisi ; pourcentage si absent ou vide, `None` si inconnu
+    /// (rejeté par `validate`)
+    pub fn discount_type_parsed(&self) -> Option<DiscountType> {
+        match self.discount_type.as_deref() {
+            Some(code) if !code.trim().is_empty() => DiscountType::from_code(code),
+            _ => Some(DiscountType::default()),
+        }
+    }
+
+    /// Catégorie de TVA imposée, normalisée (`None` si absente ou inconnue,
+    /// rejetée par `validate`)
+    pub fn vat_category_code(&self) -> Option<&'static str> {
+        let code = self.vat_category.as_deref()?.trim().to_ascii_uppercase();
+        VAT_CATEGORY_CODES.into_iter().find(|known| *known == code)
+    }
+
+    /// Calcule le montant brut = quantité × prix unitaire
+    pub fn compute_gross_ht(&mut self) {
+        self.gross_ht = Some(self.quantity * self.unit_price_ht);
+    }
+
     /// Calcule le montant du rabais
+    ///
+    /// Un type de rabais inconnu (ex. "pct") n'est interprété ni comme un
+    /// pourcentage ni comme un montant : aucun rabais n'est appliqué, la ligne
+    /// étant refusée par `validate`.
     pub fn compute_discount(&mut self) {
-        let gross_ht = self.quantity * self.unit_price_ht;
+        let gross_ht = self.gross_ht_value();
 
-        if let Some(discount_val) = self.discount_value {
+        if let (Some(discount_val), Some(discount_type)) =
+            (self.discount_value, self.discount_type_parsed())
+        {
             if discount_val > 0.0 {
-                let discount_type = self.discount_type.as_deref().unwrap_or("percent");
-                self.discount_amount = Some(if discount_type == "percent" {
-                    gross_ht * (discount_val / 100.0)
-                } else {
-                    discount_val
+                self.discount_amount = Some(match discount_type {
+                    DiscountType::Percent => gross_ht * (discount_val / 100.0),
+                    DiscountType::Amount => discount_val,
                 });
                 return;
             }
@@ -42,31 +167,166 @@ impl InvoiceLine {
         self.discount_amount = Some(0.0);
     }
 
-    /// Calcule HT = (quantité × prix unitaire) - rabais
+    /// Montant net avant arrondi : montant brut - rabais
+    pub fn unrounded_net_ht(&self) -> f64 {
+        (self.gross_ht_value() - self.discount_amount.unwrap_or(0.0)).max(0.0)
+    }
+
+    /// Calcule HT = montant brut - rabais, arrondi au centime comme le montant
+    /// net de ligne (BT-131) écrit dans le XML
     pub fn compute_total_ht(&mut self) {
-        let gross_ht = self.quantity * self.unit_price_ht;
-        let discount = self.discount_amount.unwrap_or(0.0);
-        self.total_ht = Some((gross_ht - discount).max(0.0));
+        self.total_ht = Some(round_cents(self.unrounded_net_ht()));
     }
 
-    /// Calcule TVA = HT × taux TVA
+    /// Calcule TVA = HT × taux TVA, arrondie au centime
     pub fn compute_total_vat(&mut self) {
-        self.total_vat = self.total_ht.map(|ht| ht * (self.vat_rate / 100.0));
+        self.total_vat = self
+            .total_ht
+            .map(|ht| round_cents(ht * (self.vat_rate / 100.0)));
     }
 
     /// Calcule TTC = HT + TVA
     pub fn compute_total_ttc(&mut self) {
-        self.total_ttc = self.total_ht.map(|ht| ht * (1.0 + self.vat_rate / 100.0));
+        self.total_ttc = self
+            .total_ht
+            .map(|ht| round_cents(ht + self.total_vat_value()));
     }
 
     /// Recalcule tous les totaux (incluant le rabais)
     pub fn compute_totals(&mut self) {
+        self.compute_gross_ht();
         self.compute_discount();
         self.compute_total_ht();
         self.compute_total_vat();
         self.compute_total_ttc();
     }
 
+    /// Détail de la ligne s'il est renseigné (hors espaces)
+    pub fn detail_text(&self) -> Option<&str> {
+        self.detail
+            .as_deref()
+            .map(str::trim)
+            .filter(|detail| !detail.is_empty())
+    }
+
+    /// Référence article du vendeur si renseignée
+    pub fn seller_item_id_text(&self) -> Option<&str> {
+        self.seller_item_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Référence de ligne du bon de commande si renseignée
+    pub fn order_line_reference_text(&self) -> Option<&str> {
+        self.order_line_reference
+            .as_deref()
+            .map(str::trim)
+            .filter(|reference| !reference.is_empty())
+    }
+
+    /// Code article du catalogue si renseigné
+    pub fn product_code_text(&self) -> Option<&str> {
+        self.product_code
+            .as_deref()
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+    }
+
+    /// Code unité de la quantité (BT-130), C62 par défaut
+    pub fn unit_code(&self) -> &str {
+        self.unit_code
+            .as_deref()
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .unwrap_or(DEFAULT_UNIT_CODE)
+    }
+
+    /// Identifiant standard de l'article et son schéma, si renseigné
+    pub fn standard_item(&self) -> Option<(&str, &str)> {
+        let id = self
+            .standard_item_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())?;
+        let scheme = self
+            .standard_item_scheme
+            .as_deref()
+            .map(str::trim)
+            .filter(|scheme| !scheme.is_empty())
+            .unwrap_or(GTIN_SCHEME_ID);
+        Some((id, scheme))
+    }
+
+    /// Section de la ligne si renseignée (hors espaces)
+    pub fn section_text(&self) -> Option<&str> {
+        self.section
+            .as_deref()
+            .map(str::trim)
+            .filter(|section| !section.is_empty())
+    }
+
+    /// BG-26 : Période de facturation (début, fin), si au moins une date est renseignée
+    pub fn billing_period(&self) -> Option<(Option<&str>, Option<&str>)> {
+        fn non_empty(date: &Option<String>) -> Option<&str> {
+            date.as_deref()
+                .map(str::trim)
+                .filter(|date| !date.is_empty())
+        }
+        match (non_empty(&self.period_start), non_empty(&self.period_end)) {
+            (None, None) => None,
+            period => Some(period),
+        }
+    }
+
+    /// Ligne d'abonnement au prorata d'une période entamée en cours de route
+    ///
+    /// La période complète `period_start`..=`period_end` est facturée
+    /// `full_price` HT. Seuls les jours de `billed_from` à `period_end` inclus
+    /// sont facturés : la quantité est le nombre de jours facturés (unité
+    /// `DAY`) au prix journalier arrondi au centime, pour que le montant HT
+    /// tombe juste au centime. Une période complète reste facturée 1 x
+    /// `full_price`. `billed_from` est ramené dans la période ; les totaux
+    /// restent à calculer (`compute_totals`).
+    pub fn prorate(
+        full_price: f64,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        billed_from: NaiveDate,
+    ) -> InvoiceLine {
+        let billed_from = billed_from.max(period_start).min(period_end);
+        let period_days = (period_end - period_start).num_days().max(0) + 1;
+        let billed_days = (period_end - billed_from).num_days().max(0) + 1;
+
+        let (quantity, unit_price_ht, unit_code) = if billed_days == period_days {
+            (1.0, full_price, None)
+        } else {
+            let daily_price = round_cents(full_price / period_days as f64);
+            (billed_days as f64, daily_price, Some("DAY".to_string()))
+        };
+
+        InvoiceLine {
+            description: format!(
+                "Abonnement du {} au {} (prorata {}/{} jours)",
+                billed_from.format("%d/%m/%Y"),
+                period_end.format("%d/%m/%Y"),
+                billed_days,
+                period_days
+            ),
+            period_start: Some(billed_from.format("%Y-%m-%d").to_string()),
+            period_end: Some(period_end.format("%Y-%m-%d").to_string()),
+            quantity,
+            unit_price_ht,
+            unit_code,
+            ..Default::default()
+        }
+    }
+
+    /// Montant brut avant rabais (calculé à la volée si le cache est vide)
+    pub fn gross_ht_value(&self) -> f64 {
+        self.gross_ht.unwrap_or(self.quantity * self.unit_price_ht)
+    }
+
     /// Somme HT pour agrégation
     pub fn total_ht_value(&self) -> f64 {
         self.total_ht.unwrap_or_default()
@@ -82,6 +342,224 @@ impl InvoiceLine {
         self.total_ttc.unwrap_or_default()
     }
 
+    /// Valide la ligne et retourne les erreurs par champ (`index` = position dans le formulaire)
+    pub fn validate(&self, index: usize) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if self.description.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("lines[{}][description]", index),
+                format!("Ligne {} : la description est obligatoire", index + 1),
+            ));
+        }
+
+        // Caractères de contrôle collés depuis un tableur : XML invalide
+        for (key, value) in [
+            ("description", Some(self.description.as_str())),
+            ("detail", self.detail.as_deref()),
+            ("seller_item_id", self.seller_item_id.as_deref()),
+            ("order_line_reference", self.order_line_reference.as_deref()),
+            ("section", self.section.as_deref()),
+        ] {
+            if value.is_some_and(has_invalid_xml_chars) {
+                errors.push(FieldError::new(
+                    format!("lines[{}][{}]", index, key),
+                    format!(
+                        "Ligne {} : le texte contient des caracteres de controle non autorises",
+                        index + 1
+                    ),
+                ));
+            }
+        }
+
+        if self.quantity <= 0.0 {
+            errors.push(FieldError::new(
+                format!("lines[{}][quantity]", index),
+                format!("Ligne {} : la quantite doit etre superieure a 0", index + 1),
+            ));
+        }
+
+        if self.unit_price_ht <= 0.0 {
+            errors.push(FieldError::new(
+                format!("lines[{}][unit_price_ht]", index),
+                format!(
+                    "Ligne {} : le prix unitaire doit etre superieur a 0",
+                    index + 1
+                ),
+            ));
+        }
+
+        // Nombre infini ou NaN (ex. 1e309 lu comme inf) : "inf" dans le XML
+        for (key, label, value) in [
+            ("quantity", "la quantite", Some(self.quantity)),
+            (
+                "unit_price_ht",
+                "le prix unitaire",
+                Some(self.unit_price_ht),
+            ),
+            ("vat_rate", "le taux de TVA", Some(self.vat_rate)),
+            ("discount_value", "le rabais", self.discount_value),
+        ] {
+            if value.is_some_and(|value| !value.is_finite()) {
+                errors.push(FieldError::new(
+                    format!("lines[{}][{}]", index, key),
+                    format!("Ligne {} : {} n'est pas un nombre fini", index + 1, label),
+                ));
+            }
+        }
+
+        let gross_ht = self.quantity * self.unit_price_ht;
+        if self.quantity.is_finite()
+            && self.unit_price_ht.is_finite()
+            && gross_ht.abs() > MAX_LINE_AMOUNT
+        {
+            errors.push(FieldError::new(
+                format!("lines[{}][quantity]", index),
+                format!(
+                    "Ligne {} : le montant de la ligne (quantite x prix unitaire) depasse {:.0}",
+                    index + 1,
+                    MAX_LINE_AMOUNT
+                ),
+            ));
+        }
+
+        let unit_code = self.unit_code();
+        if unit_code.len() > 3 || !unit_code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            errors.push(FieldError::new(
+                format!("lines[{}][unit_code]", index),
+                format!(
+                    "Ligne {} : le code unite '{}' est invalide (code UN/ECE Rec 20, ex. C62, HUR, KGM)",
+                    index + 1,
+                    unit_code
+                ),
+            ));
+        }
+
+        if let Some((id, scheme)) = self.standard_item() {
+            if scheme == GTIN_SCHEME_ID && !is_valid_gtin(id) {
+                errors.push(FieldError::new(
+                    format!("lines[{}][standard_item_id]", index),
+                    format!(
+                        "Ligne {} : le GTIN {} est invalide (8, 12, 13 ou 14 chiffres avec cle de controle)",
+                        index + 1,
+                        id
+                    ),
+                ));
+            }
+        }
+
+        if let Some((start, end)) = self.billing_period() {
+            let mut parse = |date: Option<&str>, key: &str| {
+                let date = date?;
+                let parsed = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+                if parsed.is_none() {
+                    errors.push(FieldError::new(
+                        format!("lines[{}][{}]", index, key),
+                        format!(
+                            "Ligne {} : la date {} doit etre au format AAAA-MM-JJ",
+                            index + 1,
+                            date
+                        ),
+                    ));
+                }
+                parsed
+            };
+            let start = parse(start, "period_start");
+            let end = parse(end, "period_end");
+
+            if let (Some(start), Some(end)) = (start, end) {
+                if end < start {
+                    errors.push(FieldError::new(
+                        format!("lines[{}][period_end]", index),
+                        format!(
+                            "Ligne {} : la fin de periode doit etre posterieure ou egale au debut",
+                            index + 1
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(code) = self.vat_category.as_deref() {
+            if !code.trim().is_empty() && self.vat_category_code().is_none() {
+                errors.push(FieldError::new(
+                    format!("lines[{}][vat_category]", index),
+                    format!(
+                        "Ligne {} : la categorie de TVA '{}' est inconnue ({})",
+                        index + 1,
+                        code,
+                        VAT_CATEGORY_CODES.join(", ")
+                    ),
+                ));
+            }
+        }
+        // BR-S-05 : la catégorie S (taux normal) exige un taux positif ;
+        // BR-Z-05, BR-E-05, BR-AE-05, BR-IC-05, BR-G-05 et BR-O-05 : les
+        // catégories exonérées, autoliquidées ou hors champ un taux nul. Seules
+        // S, L et M (IGIC, IPSI) portent un taux
+        match self.vat_category_code() {
+            Some("S") if self.vat_rate <= 0.0 => errors.push(FieldError::new(
+                format!("lines[{}][vat_category]", index),
+                format!(
+                    "Ligne {} : la categorie de TVA S exige un taux superieur a 0",
+                    index + 1
+                ),
+            )),
+            Some(code) if !matches!(code, "S" | "L" | "M") && self.vat_rate != 0.0 => {
+                errors.push(FieldError::new(
+                    format!("lines[{}][vat_category]", index),
+                    format!(
+                        "Ligne {} : la categorie de TVA {} exige un taux de 0 %",
+                        index + 1,
+                        code
+                    ),
+                ))
+            }
+            _ => {}
+        }
+
+        let discount_type = self.discount_type_parsed();
+        if discount_type.is_none() {
+            errors.push(FieldError::new(
+                format!("lines[{}][discount_type]", index),
+                format!(
+                    "Ligne {} : le type de rabais '{}' est inconnu (percent ou amount)",
+                    index + 1,
+                    self.discount_type.as_deref().unwrap_or_default()
+                ),
+            ));
+        }
+
+        if let (Some(discount_val), Some(discount_type)) = (self.discount_value, discount_type) {
+            if discount_type == DiscountType::Percent {
+                if !(0.0..=100.0).contains(&discount_val) {
+                    errors.push(FieldError::new(
+                        format!("lines[{}][discount_value]", index),
+                        format!(
+                            "Ligne {} : le rabais en pourcentage doit etre compris entre 0 et 100",
+                            index + 1
+                        ),
+                    ));
+                }
+            } else {
+                let gross_ht = self.gross_ht_value();
+                if discount_val < 0.0 || discount_val > gross_ht {
+                    errors.push(FieldError::new(
+                        format!("lines[{}][discount_value]", index),
+                        format!(
+                            "Ligne {} : le rabais ({:.2}) ne peut pas depasser le montant brut de la ligne ({:.2})",
+                            index + 1,
+                            discount_val,
+                            gross_ht
+                        ),
+                    ));
+                }
+            }
+        }
+
+        errors
+    }
+
     /// Validation métier Factur-X
     pub fn is_valid(&self) -> bool {
         !self.description.trim().is_empty()
@@ -95,12 +573,26 @@ impl Default for InvoiceLine {
     fn default() -> Self {
         Self {
             description: String::new(),
+            detail: None,
+            seller_item_id: None,
+            product_code: None,
+            standard_item_id: None,
+            standard_item_scheme: None,
+            order_line_reference: None,
+            section: None,
+            period_start: None,
+            period_end: None,
             quantity: 1.0,
+            unit_code: None,
             unit_price_ht: 0.0,
             vat_rate: 20.0,
+            vat_category: None,
             discount_value: None,
             discount_type: None,
+            discountable: true,
+            position: None,
             total_ht: None,
+            gross_ht: None,
             total_vat: None,
             total_ttc: None,
             discount_amount: None,
@@ -120,3 +612,308 @@ impl fmt::Display for InvoiceLine {
         )
     }
 }
+
+/// Schéma ISO 6523 du GTIN, utilisé par défaut pour l'identifiant standard
+pub const GTIN_SCHEME_ID: &str = "0160";
+
+/// Arrondit un montant au centime, comme les montants écrits dans le XML
+pub fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+/// Nombre maximal de décimales affichées pour une quantité (précision CII)
+const QUANTITY_MAX_DECIMALS: usize = 4;
+
+/// Formate une quantité sans zéros superflus : "3" pour 3.0, "1.5" pour 1.5
+///
+/// Les quantités non décimales finies (1/3, ...) sont arrondies à 4 décimales.
+/// Les prix et montants restent formatés avec 2 décimales fixes.
+pub fn format_quantity(quantity: f64) -> String {
+    let formatted = format!("{:.*}", QUANTITY_MAX_DECIMALS, quantity);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(discount_value: f64, discount_type: &str) -> InvoiceLine {
+        InvoiceLine {
+            description: "Prestation".to_string(),
+            quantity: 2.0,
+            unit_price_ht: 50.0,
+            discount_value: Some(discount_value),
+            discount_type: Some(discount_type.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_gross_minus_discount_is_net() {
+        for (value, kind) in [(10.0, "percent"), (15.5, "amount"), (0.0, "percent")] {
+            let mut line = line(value, kind);
+            line.compute_totals();
+            assert_eq!(line.gross_ht, Some(100.0));
+            let discount = line.discount_amount.unwrap();
+            assert!((line.gross_ht_value() - discount - line.total_ht_value()).abs() < 1e-9);
+        }
+
+        // Cache vide : montant brut calculé à la volée
+        assert_eq!(line(10.0, "percent").gross_ht_value(), 100.0);
+    }
+
+    #[test]
+    fn test_validate_huge_or_non_finite_amounts() {
+        let huge = InvoiceLine {
+            description: "Erreur d'import".to_string(),
+            quantity: 1e308,
+            unit_price_ht: 10.0,
+            ..Default::default()
+        };
+        let errors = huge.validate(0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][quantity]");
+        assert!(errors[0].message.contains("depasse"));
+
+        let nan_price = InvoiceLine {
+            description: "Prestation".to_string(),
+            unit_price_ht: f64::NAN,
+            ..Default::default()
+        };
+        let errors = nan_price.validate(1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[1][unit_price_ht]");
+
+        let at_limit = InvoiceLine {
+            description: "Ouvrage".to_string(),
+            unit_price_ht: MAX_LINE_AMOUNT,
+            ..Default::default()
+        };
+        assert!(at_limit.validate(0).is_empty());
+    }
+
+    #[test]
+    fn test_validate_percent_discount_over_100() {
+        let errors = line(150.0, "percent").validate(0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][discount_value]");
+    }
+
+    #[test]
+    fn test_validate_amount_discount_over_gross() {
+        let errors = line(100.01, "amount").validate(2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[2][discount_value]");
+    }
+
+    #[test]
+    fn test_validate_discount_within_limits() {
+        assert!(line(100.0, "percent").validate(0).is_empty());
+        assert!(line(100.0, "amount").validate(0).is_empty());
+    }
+
+    #[test]
+    fn test_discount_type_parsed() {
+        assert_eq!(
+            line(10.0, "amount").discount_type_parsed(),
+            Some(DiscountType::Amount)
+        );
+        assert_eq!(
+            line(10.0, "percent").discount_type_parsed(),
+            Some(DiscountType::Percent)
+        );
+        assert_eq!(line(10.0, "bogus").discount_type_parsed(), None);
+
+        let mut amount = line(10.0, "amount");
+        amount.compute_discount();
+        assert_eq!(amount.discount_amount, Some(10.0));
+
+        let errors = line(10.0, "bogus").validate(1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[1][discount_type]");
+    }
+
+    #[test]
+    fn test_unknown_discount_type_is_not_guessed() {
+        // Faute de frappe : refusée, et aucun rabais appliqué en attendant
+        let mut typo = line(10.0, "pct");
+        assert_eq!(typo.discount_type_parsed(), None);
+        let errors = typo.validate(0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][discount_type]");
+        assert!(errors[0].message.contains("'pct'"));
+        typo.compute_totals();
+        assert_eq!(typo.discount_amount, Some(0.0));
+        assert_eq!(typo.total_ht, Some(100.0));
+
+        // Chaîne vide : type non renseigné, pourcentage par défaut
+        let mut empty = line(10.0, "");
+        assert_eq!(empty.discount_type_parsed(), Some(DiscountType::Percent));
+        assert!(empty.validate(0).is_empty());
+        empty.compute_totals();
+        assert_eq!(empty.discount_amount, Some(10.0));
+    }
+
+    #[test]
+    fn test_validate_vat_category() {
+        let mut line = line(10.0, "percent");
+        line.vat_rate = 0.0;
+        line.vat_category = Some("ae".to_string());
+        assert_eq!(line.vat_category_code(), Some("AE"));
+        assert!(line.validate(0).is_empty());
+
+        // Seules S, L et M admettent un taux non nul
+        line.vat_rate = 20.0;
+        for code in ["Z", "E", "AE", "K", "G", "O"] {
+            line.vat_category = Some(code.to_string());
+            let errors = line.validate(0);
+            assert_eq!(errors.len(), 1, "{}", code);
+            assert_eq!(
+                errors[0].message,
+                format!(
+                    "Ligne 1 : la categorie de TVA {} exige un taux de 0 %",
+                    code
+                )
+            );
+        }
+        for code in ["S", "L", "M"] {
+            line.vat_category = Some(code.to_string());
+            assert!(line.validate(0).is_empty(), "{}", code);
+        }
+        line.vat_rate = 0.0;
+
+        line.vat_category = Some("X".to_string());
+        let errors = line.validate(0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][vat_category]");
+
+        line.vat_category = Some("S".to_string());
+        line.vat_rate = 0.0;
+        assert_eq!(line.validate(0)[0].field, "lines[0][vat_category]");
+    }
+
+    #[test]
+    fn test_validate_control_characters() {
+        let mut line = line(10.0, "percent");
+        line.description = "Audit\tcomplet\u{c}".to_string();
+        let errors = line.validate(0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][description]");
+
+        line.description = "Audit\tcomplet".to_string();
+        assert!(line.validate(0).is_empty());
+    }
+
+    #[test]
+    fn test_validate_billing_period() {
+        let mut line = line(10.0, "percent");
+        line.period_start = Some("2024-01-01".to_string());
+        line.period_end = Some("2024-01-31".to_string());
+        assert!(line.validate(0).is_empty());
+
+        line.period_end = Some("2023-12-31".to_string());
+        let errors = line.validate(0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[0][period_end]");
+
+        line.period_start = Some("01/01/2024".to_string());
+        line.period_end = None;
+        let errors = line.validate(1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[1][period_start]");
+
+        line.period_start = Some(" ".to_string());
+        assert_eq!(line.billing_period(), None);
+    }
+
+    #[test]
+    fn test_prorate_first_period() {
+        let date = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+
+        let mut line = InvoiceLine::prorate(
+            31.0,
+            date("2025-03-01"),
+            date("2025-03-31"),
+            date("2025-03-15"),
+        );
+        assert_eq!(
+            line.description,
+            "Abonnement du 15/03/2025 au 31/03/2025 (prorata 17/31 jours)"
+        );
+        assert_eq!(
+            line.billing_period(),
+            Some((Some("2025-03-15"), Some("2025-03-31")))
+        );
+        assert_eq!(line.quantity, 17.0);
+        assert_eq!(line.unit_code(), "DAY");
+        assert_eq!(line.unit_price_ht, 1.0);
+        line.compute_totals();
+        assert_eq!(line.total_ht_value(), 17.0);
+        assert!(line.validate(0).is_empty());
+
+        // Prix journalier arrondi au centime : 100 / 31 = 3.23
+        let mut uneven = InvoiceLine::prorate(
+            100.0,
+            date("2025-03-01"),
+            date("2025-03-31"),
+            date("2025-03-15"),
+        );
+        assert_eq!(uneven.unit_price_ht, 3.23);
+        uneven.compute_totals();
+        assert_eq!(format!("{:.2}", uneven.total_ht_value()), "54.91");
+
+        // Facturation depuis le début (ou avant) : période complète
+        let full = InvoiceLine::prorate(
+            120.0,
+            date("2025-02-01"),
+            date("2025-02-28"),
+            date("2025-01-20"),
+        );
+        assert_eq!(full.quantity, 1.0);
+        assert_eq!(full.unit_price_ht, 120.0);
+        assert_eq!(full.unit_code(), "C62");
+        assert!(full.description.contains("(prorata 28/28 jours)"));
+
+        // Dernier jour seulement
+        let last_day = InvoiceLine::prorate(
+            30.0,
+            date("2025-04-01"),
+            date("2025-04-30"),
+            date("2025-05-10"),
+        );
+        assert_eq!(last_day.quantity, 1.0);
+        assert_eq!(last_day.unit_price_ht, 1.0);
+        assert_eq!(last_day.period_start.as_deref(), Some("2025-04-30"));
+    }
+
+    #[test]
+    fn test_format_quantity() {
+        assert_eq!(format_quantity(3.0), "3");
+        assert_eq!(format_quantity(1.5), "1.5");
+        assert_eq!(format_quantity(0.25), "0.25");
+        assert_eq!(format_quantity(0.333), "0.333");
+        assert_eq!(format_quantity(10.0), "10");
+    }
+
+    #[test]
+    fn test_validate_gtin_standard_item() {
+        let mut line = line(10.0, "percent");
+        line.standard_item_id = Some("4006381333932".to_string());
+        let errors = line.validate(1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "lines[1][standard_item_id]");
+
+        line.standard_item_id = Some("4006381333931".to_string());
+        assert!(line.validate(1).is_empty());
+
+        // Hors schéma GTIN, aucune clé n'est contrôlée
+        line.standard_item_id = Some("ABC-123".to_string());
+        line.standard_item_scheme = Some("0088".to_string());
+        assert!(line.validate(1).is_empty());
+    }
+}
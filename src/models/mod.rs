@@ -1,3 +1,7 @@
 pub mod line;
 pub mod invoice;
 pub mod error;
+pub mod validation;
+pub mod business_rules;
+pub mod catalog;
+pub mod rules;
@@ -0,0 +1,411 @@
+//! Règles de validation nommées d'une facture
+//!
+//! Chaque contrôle est une règle identifiée (ex. "BT-1-present") dont le
+//! résultat est rapporté, respecté ou non, par `InvoiceForm::validate_verbose`.
+//! Les validations des formulaires et du lot ne retiennent que les erreurs
+//! des règles en échec (voir [`failed_errors`]).
+
+use super::error::FieldError;
+use super::invoice::{
+    is_known_currency_code, is_valid_tax_type_code, validate_tax_currency, InvoiceForm,
+};
+use super::validation::{has_invalid_xml_chars, is_valid_gln, is_valid_vat_number};
+use regex::Regex;
+use serde::Serialize;
+
+/// Résultat d'une règle de validation
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleResult {
+    /// Identifiant stable de la règle (terme métier EN 16931 si possible)
+    pub id: &'static str,
+    /// Contrôle effectué
+    pub description: &'static str,
+    pub passed: bool,
+    /// Erreurs de la règle (vide si elle est respectée)
+    pub errors: Vec<FieldError>,
+}
+
+impl RuleResult {
+    pub fn new(id: &'static str, description: &'static str, errors: Vec<FieldError>) -> Self {
+        Self {
+            id,
+            description,
+            passed: errors.is_empty(),
+            errors,
+        }
+    }
+}
+
+/// Erreurs des règles en échec, dans l'ordre des règles
+pub fn failed_errors(results: Vec<RuleResult>) -> Vec<FieldError> {
+    results
+        .into_iter()
+        .flat_map(|result| result.errors)
+        .collect()
+}
+
+/// Champs d'en-tête (facture et client) contrôlés dès l'étape 1, avant la
+/// saisie des lignes
+pub struct HeaderFields<'a> {
+    pub invoice_number: &'a str,
+    pub issue_date: &'a str,
+    pub currency_code: &'a str,
+    pub payment_terms: Option<&'a str>,
+    pub buyer_reference: Option<&'a str>,
+    pub purchase_order_reference: Option<&'a str>,
+    pub tax_type_code: Option<&'a str>,
+    pub tax_currency_code: Option<&'a str>,
+    pub tax_exchange_rate: Option<f64>,
+    pub recipient_name: &'a str,
+    pub recipient_siret: &'a str,
+    pub recipient_vat_number: Option<&'a str>,
+    pub recipient_gln: Option<&'a str>,
+    pub recipient_address: &'a str,
+    pub recipient_country_code: &'a str,
+    pub recipient_service_code: Option<&'a str>,
+}
+
+impl HeaderFields<'_> {
+    fn is_french_recipient(&self) -> bool {
+        self.recipient_country_code
+            .trim()
+            .eq_ignore_ascii_case("FR")
+    }
+}
+
+/// Règles d'en-tête, dans l'ordre des messages du formulaire
+pub fn header_rules(header: &HeaderFields) -> Vec<RuleResult> {
+    vec![
+        RuleResult::new(
+            "BT-1-present",
+            "Numero de facture renseigne",
+            required(
+                header.invoice_number,
+                "invoice_number",
+                "Le numero de facture est obligatoire",
+            ),
+        ),
+        RuleResult::new(
+            "BT-2-present",
+            "Date d'emission renseignee",
+            required(
+                header.issue_date,
+                "issue_date",
+                "La date d'emission est obligatoire",
+            ),
+        ),
+        RuleResult::new(
+            "BT-44-present",
+            "Nom du client renseigne",
+            required(
+                header.recipient_name,
+                "recipient_name",
+                "Le nom du client est obligatoire",
+            ),
+        ),
+        RuleResult::new(
+            "BT-47-siret",
+            "SIRET de 14 chiffres pour un client francais",
+            check_siret(header),
+        ),
+        RuleResult::new(
+            "BT-48-vat-number",
+            "Numero de TVA prefixe du code pays pour un client etranger",
+            check_foreign_vat_number(header),
+        ),
+        RuleResult::new(
+            "BT-55-present",
+            "Pays du client renseigne",
+            required(
+                header.recipient_country_code,
+                "recipient_country_code",
+                "Le pays est obligatoire",
+            ),
+        ),
+        RuleResult::new(
+            "BT-46-gln",
+            "GLN du client de 13 chiffres avec cle GS1",
+            match header.recipient_gln {
+                Some(gln) if !is_valid_gln(gln) => vec![FieldError::new(
+                    "recipient_gln",
+                    "Le GLN doit contenir 13 chiffres avec une cle de controle valide",
+                )],
+                _ => Vec::new(),
+            },
+        ),
+        // Devise de facturation (BT-5) : attribut currencyID du total TVA
+        RuleResult::new(
+            "BT-5-currency",
+            "Devise de facturation prise en charge",
+            if is_known_currency_code(header.currency_code) {
+                Vec::new()
+            } else {
+                vec![FieldError::new(
+                    "currency_code",
+                    format!(
+                        "La devise de facturation '{}' n'est pas prise en charge",
+                        header.currency_code
+                    ),
+                )]
+            },
+        ),
+        RuleResult::new(
+            "BT-6-tax-currency",
+            "Devise de TVA connue et differente, avec un taux de change positif",
+            validate_tax_currency(
+                header.currency_code,
+                header.tax_currency_code,
+                header.tax_exchange_rate,
+            ),
+        ),
+        RuleResult::new(
+            "XML-control-chars",
+            "Textes d'en-tete sans caractere de controle",
+            check_header_texts(header),
+        ),
+        RuleResult::new(
+            "BT-118-tax-type",
+            "Code type de taxe UNTDID 5153",
+            match header.tax_type_code {
+                Some(code) if !is_valid_tax_type_code(code) => vec![FieldError::new(
+                    "tax_type_code",
+                    format!(
+                        "Le code type de taxe '{}' n'est pas un code UNTDID 5153 valide",
+                        code
+                    ),
+                )],
+                _ => Vec::new(),
+            },
+        ),
+    ]
+}
+
+/// Format des numéros de facture imposé par l'émetteur (`invoice_number_regex`)
+///
+/// Un numéro vide est laissé à la règle `BT-1-present`.
+pub fn invoice_number_rule(invoice_number: &str, pattern: Option<&Regex>) -> RuleResult {
+    let errors = match pattern {
+        Some(pattern) if !invoice_number.trim().is_empty() && !pattern.is_match(invoice_number) => {
+            vec![FieldError::new(
+                "invoice_number",
+                format!(
+                    "Le numero de facture '{}' ne respecte pas le format attendu ({})",
+                    invoice_number,
+                    pattern.as_str()
+                ),
+            )]
+        }
+        _ => Vec::new(),
+    };
+    RuleResult::new(
+        "BT-1-format",
+        "Numero de facture conforme au format de l'emetteur",
+        errors,
+    )
+}
+
+/// Nombre de lignes limité par `max_lines` (section `[generation]`)
+pub fn max_lines_rule(line_count: usize, max_lines: usize) -> RuleResult {
+    let errors = if line_count > max_lines {
+        vec![FieldError::new(
+            "lines",
+            format!(
+                "La facture contient {} lignes, au-dela du maximum autorise ({})",
+                line_count, max_lines
+            ),
+        )]
+    } else {
+        Vec::new()
+    };
+    RuleResult::new(
+        "BG-25-max-lines",
+        "Nombre de lignes dans la limite de la configuration",
+        errors,
+    )
+}
+
+fn required(value: &str, field: &str, message: &str) -> Vec<FieldError> {
+    if value.trim().is_empty() {
+        vec![FieldError::new(field, message)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Client français : SIRET obligatoire
+fn check_siret(header: &HeaderFields) -> Vec<FieldError> {
+    if !header.is_french_recipient() {
+        return Vec::new();
+    }
+    if header.recipient_siret.trim().is_empty() {
+        return vec![FieldError::new(
+            "recipient_siret",
+            "Le SIRET du client est obligatoire",
+        )];
+    }
+    let digits = header
+        .recipient_siret
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .count();
+    if digits != 14 {
+        return vec![FieldError::new(
+            "recipient_siret",
+            "Le SIRET doit contenir 14 chiffres",
+        )];
+    }
+    Vec::new()
+}
+
+/// Client étranger : numéro de TVA
+fn check_foreign_vat_number(header: &HeaderFields) -> Vec<FieldError> {
+    if header.is_french_recipient() || header.recipient_country_code.trim().is_empty() {
+        return Vec::new();
+    }
+    match header.recipient_vat_number.map(str::trim) {
+        None | Some("") => vec![FieldError::new(
+            "recipient_vat_number",
+            "Le numero de TVA est obligatoire pour un client etranger",
+        )],
+        Some(vat_number) if !is_valid_vat_number(vat_number) => vec![FieldError::new(
+            "recipient_vat_number",
+            "Le numero de TVA doit commencer par le code pays (ex: DE123456789)",
+        )],
+        Some(_) => Vec::new(),
+    }
+}
+
+/// Caractères de contrôle collés depuis un tableur : XML invalide
+fn check_header_texts(header: &HeaderFields) -> Vec<FieldError> {
+    [
+        ("invoice_number", Some(header.invoice_number)),
+        ("recipient_name", Some(header.recipient_name)),
+        ("recipient_address", Some(header.recipient_address)),
+        ("payment_terms", header.payment_terms),
+        ("buyer_reference", header.buyer_reference),
+        ("purchase_order_reference", header.purchase_order_reference),
+        ("recipient_service_code", header.recipient_service_code),
+    ]
+    .into_iter()
+    .filter(|(_, value)| value.is_some_and(has_invalid_xml_chars))
+    .map(|(field, _)| {
+        FieldError::new(
+            field,
+            "Le texte contient des caracteres de controle non autorises",
+        )
+    })
+    .collect()
+}
+
+/// Règles des lignes et des compléments de la facture (étape 2)
+pub fn line_rules(form: &InvoiceForm) -> Vec<RuleResult> {
+    vec![
+        RuleResult::new(
+            "BG-25-present",
+            "Au moins une ligne de facture",
+            if form.lines.is_empty() {
+                vec![FieldError::new(
+                    "lines",
+                    "La facture doit contenir au moins une ligne",
+                )]
+            } else {
+                Vec::new()
+            },
+        ),
+        RuleResult::new(
+            "BG-25-lines",
+            "Lignes valides (description, quantite, prix, unite, GTIN, periode, rabais)",
+            form.lines
+                .iter()
+                .enumerate()
+                .flat_map(|(index, line)| line.validate(index))
+                .collect(),
+        ),
+        RuleResult::new(
+            "BT-94-global-discount",
+            "Remise globale entre 0 et 100 % sur au moins une ligne remisable",
+            form.validate_global_discount(),
+        ),
+        RuleResult::new(
+            "BG-24-additional-references",
+            "Documents references de type et qualifiant pris en charge",
+            form.validate_additional_references(),
+        ),
+        RuleResult::new(
+            "BT-18-invoiced-object",
+            "Objet facture avec un schema UNTDID 1153 pris en charge",
+            form.validate_invoiced_object(),
+        ),
+        RuleResult::new(
+            "ship-from-address",
+            "Lieu d'expedition avec adresse et code pays ISO 3166-1",
+            form.validate_ship_from_address(),
+        ),
+        RuleResult::new(
+            "BT-132-order-line-reference",
+            "Reference de ligne de commande avec un bon de commande en en-tete",
+            form.validate_order_line_references(),
+        ),
+        RuleResult::new(
+            "BT-113-prepaid-amount",
+            "Acompte positif, sans objet pour une facture acquittee",
+            form.validate_prepaid_amount(),
+        ),
+        RuleResult::new(
+            "payment-date",
+            "Date de paiement valide pour une facture acquittee",
+            form.validate_payment(),
+        ),
+        RuleResult::new(
+            "BT-7-tax-point-date",
+            "Date d'exigibilite de la TVA au format AAAA-MM-JJ",
+            form.validate_tax_point_date(),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_errors_keep_rule_order() {
+        let results = vec![
+            RuleResult::new("a", "A", vec![FieldError::new("x", "erreur x")]),
+            RuleResult::new("b", "B", Vec::new()),
+            RuleResult::new("c", "C", vec![FieldError::new("y", "erreur y")]),
+        ];
+        assert!(!results[0].passed);
+        assert!(results[1].passed);
+
+        let fields: Vec<String> = failed_errors(results)
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn test_max_lines_rule_boundary() {
+        assert!(max_lines_rule(0, 1000).passed);
+        assert!(max_lines_rule(1000, 1000).passed);
+
+        let result = max_lines_rule(1001, 1000);
+        assert!(!result.passed);
+        assert_eq!(result.errors[0].field, "lines");
+        assert!(result.errors[0].message.contains("1001 lignes"));
+    }
+
+    #[test]
+    fn test_invoice_number_rule() {
+        let pattern = Regex::new(r"^FA-\d{4}-\d{4}$").unwrap();
+        assert!(invoice_number_rule("FA-2024-0001", Some(&pattern)).passed);
+        assert!(invoice_number_rule("FA-24-1", None).passed);
+        assert!(invoice_number_rule("", Some(&pattern)).passed);
+
+        let result = invoice_number_rule("FA-24-1", Some(&pattern));
+        assert!(!result.passed);
+        assert_eq!(result.errors[0].field, "invoice_number");
+        assert!(result.errors[0].message.contains("^FA-\\d{4}-\\d{4}$"));
+    }
+}
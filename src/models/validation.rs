@@ -0,0 +1,393 @@
+//! Contrôles de format des identifiants (GLN, SIRET, TVA, téléphone, ...)
+
+/// Vérifie la clé de contrôle GS1 (modulo 10) d'un identifiant numérique
+/// (GLN, GTIN-8/12/13/14) : le dernier chiffre est la clé.
+pub fn is_valid_gs1_check_digit(digits: &str) -> bool {
+    if digits.len() < 2 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let (body, check) = digits.split_at(digits.len() - 1);
+    let sum: u32 = body
+        .chars()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| {
+            let digit = c.to_digit(10).unwrap_or(0);
+            if i % 2 == 0 {
+                digit * 3
+            } else {
+                digit
+            }
+        })
+        .sum();
+
+    let expected = (10 - sum % 10) % 10;
+    check.parse::<u32>().ok() == Some(expected)
+}
+
+/// Vérifie un GLN (Global Location Number, schéma 0088) : 13 chiffres avec clé GS1
+pub fn is_valid_gln(gln: &str) -> bool {
+    gln.len() == 13 && is_valid_gs1_check_digit(gln)
+}
+
+/// Vérifie un GTIN (GTIN-8, 12, 13 ou 14) : longueur et clé GS1
+pub fn is_valid_gtin(gtin: &str) -> bool {
+    matches!(gtin.len(), 8 | 12 | 13 | 14) && is_valid_gs1_check_digit(gtin)
+}
+
+/// Vérifie la clé de Luhn d'un identifiant numérique (SIREN, SIRET)
+pub fn is_valid_luhn(digits: &str) -> bool {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .enumerate()
+        .map(|(i, c)| {
+            let digit = c.to_digit(10).unwrap_or(0);
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+/// Vérifie un SIREN : 9 chiffres avec clé de Luhn
+pub fn is_valid_siren(siren: &str) -> bool {
+    siren.len() == 9 && is_valid_luhn(siren)
+}
+
+/// SIREN de La Poste, dont les SIRET ne respectent pas la clé de Luhn
+const LA_POSTE_SIREN: &str = "356000000";
+
+/// Vérifie un SIRET : 14 chiffres avec clé de Luhn
+///
+/// Les établissements de La Poste font exception : la somme de leurs
+/// chiffres doit être un multiple de 5.
+pub fn is_valid_siret(siret: &str) -> bool {
+    if siret.len() != 14 || !siret.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+
+    if siret.starts_with(LA_POSTE_SIREN) {
+        let sum: u32 = siret.chars().filter_map(|c| c.to_digit(10)).sum();
+        return sum.is_multiple_of(5);
+    }
+
+    is_valid_luhn(siret)
+}
+
+/// Vérifie le format d'un numéro de TVA intracommunautaire :
+/// code pays sur 2 lettres suivi de 2 à 13 caractères alphanumériques
+///
+/// Un numéro français est de plus contrôlé par [`is_valid_french_vat_number`].
+pub fn is_valid_vat_number(vat: &str) -> bool {
+    let (country, number) = match (vat.get(..2), vat.get(2..)) {
+        (Some(country), Some(number)) => (country, number),
+        _ => return false,
+    };
+
+    let well_formed = country.chars().all(|c| c.is_ascii_uppercase())
+        && (2..=13).contains(&number.len())
+        && number
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase());
+
+    well_formed && (country != "FR" || is_valid_french_vat_number(vat))
+}
+
+/// Vérifie un IBAN (sans espaces, en majuscules) : code pays, clé sur 2
+/// chiffres et 11 à 30 caractères alphanumériques, clé modulo 97 valide
+pub fn is_valid_iban(iban: &str) -> bool {
+    let well_formed = (15..=34).contains(&iban.len())
+        && iban
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+        && iban[..2].chars().all(|c| c.is_ascii_uppercase())
+        && iban[2..4].chars().all(|c| c.is_ascii_digit());
+    if !well_formed {
+        return false;
+    }
+
+    // Les 4 premiers caractères passent en fin, chaque lettre vaut 10 à 35
+    let remainder = iban[4..]
+        .chars()
+        .chain(iban[..4].chars())
+        .fold(0u32, |remainder, c| {
+            let value = c.to_digit(36).unwrap_or(0);
+            if value < 10 {
+                (remainder * 10 + value) % 97
+            } else {
+                (remainder * 100 + value) % 97
+            }
+        });
+    remainder == 1
+}
+
+/// Clé d'un numéro de TVA français : (12 + 3 × (SIREN mod 97)) mod 97
+pub fn french_vat_key(siren: &str) -> Option<u32> {
+    if siren.len() != 9 || !siren.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let siren: u64 = siren.parse().ok()?;
+    Some(((12 + 3 * (siren % 97)) % 97) as u32)
+}
+
+/// Vérifie un numéro de TVA français : "FR" + clé sur 2 caractères + SIREN
+///
+/// Une clé numérique doit correspondre au SIREN ; les clés alphanumériques
+/// (attribuées à certains assujettis) ne sont pas calculables et sont acceptées.
+pub fn is_valid_french_vat_number(vat: &str) -> bool {
+    let Some(number) = vat.strip_prefix("FR") else {
+        return false;
+    };
+    if number.len() != 11 || !number.is_ascii() {
+        return false;
+    }
+
+    let (key, siren) = number.split_at(2);
+    if !is_valid_siren(siren) {
+        return false;
+    }
+    if key.chars().all(|c| c.is_ascii_digit()) {
+        key.parse().ok() == french_vat_key(siren)
+    } else {
+        key.chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    }
+}
+
+/// Codes pays ISO 3166-1 alpha-2, plus XI (Irlande du Nord) admis par la
+/// liste de codes EN 16931
+const COUNTRY_CODES: &[&str] = &[
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
+    "BA", "BB", "BD", "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS",
+    "BT", "BV", "BW", "BY", "BZ", "CA", "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN",
+    "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE", "DJ", "DK", "DM", "DO", "DZ", "EC", "EE",
+    "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA", "GB", "GD", "GE", "GF",
+    "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK", "HM",
+    "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM",
+    "JO", "JP", "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC",
+    "LI", "LK", "LR", "LS", "LT", "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK",
+    "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS", "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA",
+    "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ", "OM", "PA", "PE", "PF", "PG",
+    "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS", "RU", "RW",
+    "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS",
+    "ST", "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO",
+    "TR", "TT", "TV", "TW", "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI",
+    "VN", "VU", "WF", "WS", "XI", "YE", "YT", "ZA", "ZM", "ZW",
+];
+
+/// Vérifie un code pays ISO 3166-1 alpha-2 (en majuscules, ex. "FR")
+pub fn is_valid_country_code(code: &str) -> bool {
+    COUNTRY_CODES.contains(&code)
+}
+
+/// Normalise un numéro de téléphone au format international (+33123456789)
+///
+/// Les espaces, points, tirets et parenthèses sont ignorés. Un numéro national
+/// commençant par 0 n'est converti que pour la France (`default_country` = "FR").
+/// Retourne `None` si le numéro n'est pas exploitable.
+pub fn normalize_phone(raw: &str, default_country: &str) -> Option<String> {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '.' | '-' | '(' | ')'))
+        .collect();
+
+    let international = if let Some(rest) = cleaned.strip_prefix('+') {
+        rest.to_string()
+    } else if let Some(rest) = cleaned.strip_prefix("00") {
+        rest.to_string()
+    } else if let Some(rest) = cleaned.strip_prefix('0') {
+        if !default_country.eq_ignore_ascii_case("FR") || rest.len() != 9 {
+            return None;
+        }
+        format!("33{}", rest)
+    } else {
+        return None;
+    };
+
+    // E.164 : 15 chiffres au plus, indicatif pays compris
+    if (8..=15).contains(&international.len())
+        && international.chars().all(|c| c.is_ascii_digit())
+        && !international.starts_with('0')
+    {
+        Some(format!("+{}", international))
+    } else {
+        None
+    }
+}
+
+/// Convertit une saisie décimale en nombre ("1 234,56" ou "1234.56")
+///
+/// La virgule et le point sont acceptés comme séparateur décimal, les espaces
+/// (y compris insécables) comme séparateur de milliers. Retourne `None` si la
+/// saisie n'est pas un nombre fini.
+pub fn parse_decimal(raw: &str) -> Option<f64> {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+
+    if cleaned.is_empty()
+        || !cleaned
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+'))
+    {
+        return None;
+    }
+
+    cleaned.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// Caractère autorisé par XML 1.0 (production `Char`) : les contrôles C0
+/// autres que tabulation, saut de ligne et retour chariot sont interdits,
+/// ainsi que U+FFFE et U+FFFF
+pub fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+/// Vrai si le texte contient un caractère interdit en XML (ex. saut de page
+/// ou tabulation verticale collés depuis un tableur)
+pub fn has_invalid_xml_chars(text: &str) -> bool {
+    !text.chars().all(is_xml_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_country_code() {
+        assert!(is_valid_country_code("FR"));
+        assert!(is_valid_country_code("XI"));
+        assert!(!is_valid_country_code("fr"));
+        assert!(!is_valid_country_code("FRA"));
+        assert!(!is_valid_country_code("ZZ"));
+    }
+
+    #[test]
+    fn test_valid_gln() {
+        assert!(is_valid_gln("3012345000000"));
+        assert!(is_valid_gln("4006381333931"));
+    }
+
+    #[test]
+    fn test_invalid_gln() {
+        assert!(!is_valid_gln("4006381333932"));
+        assert!(!is_valid_gln("400638133393"));
+        assert!(!is_valid_gln("40063813339AB"));
+        assert!(!is_valid_gln(""));
+    }
+
+    #[test]
+    fn test_gtin() {
+        assert!(is_valid_gtin("4006381333931"));
+        assert!(is_valid_gtin("96385074"));
+        assert!(!is_valid_gtin("4006381333932"));
+        assert!(!is_valid_gtin("400638133393"));
+    }
+
+    #[test]
+    fn test_siren() {
+        assert!(is_valid_siren("732829320"));
+        assert!(!is_valid_siren("732829321"));
+        assert!(!is_valid_siren("73282932"));
+    }
+
+    #[test]
+    fn test_siret() {
+        assert!(is_valid_siret("73282932000074"));
+        assert!(is_valid_siret("35600000000010"));
+        assert!(!is_valid_siret("73282932000075"));
+        assert!(!is_valid_siret("7328293200007"));
+        assert!(!is_valid_siret("7328293200007A"));
+    }
+
+    #[test]
+    fn test_vat_number() {
+        assert!(is_valid_vat_number("FR40303265045"));
+        assert!(is_valid_vat_number("DE123456789"));
+        assert!(!is_valid_vat_number("fr40303265045"));
+        assert!(!is_valid_vat_number("FR"));
+        assert!(!is_valid_vat_number("FR 40303265045"));
+    }
+
+    #[test]
+    fn test_iban() {
+        assert!(is_valid_iban("FR7630006000011234567890189"));
+        assert!(is_valid_iban("DE89370400440532013000"));
+        assert!(!is_valid_iban("FR7630006000011234567890188"));
+        assert!(!is_valid_iban("fr7630006000011234567890189"));
+        assert!(!is_valid_iban("FR76 3000 6000 0112 3456 7890 189"));
+        assert!(!is_valid_iban("FR76"));
+    }
+
+    #[test]
+    fn test_french_vat_key() {
+        assert_eq!(french_vat_key("303265045"), Some(40));
+        assert_eq!(french_vat_key("123456782"), Some(11));
+        assert_eq!(french_vat_key("12345"), None);
+
+        assert!(is_valid_vat_number("FR11123456782"));
+        assert!(!is_valid_vat_number("FR41303265045"));
+        assert!(!is_valid_vat_number("FR40303265046"));
+        assert!(!is_valid_vat_number("FR403032650"));
+        assert!(is_valid_vat_number("FRA0303265045"));
+    }
+
+    #[test]
+    fn test_normalize_phone() {
+        assert_eq!(
+            normalize_phone("01 23 45 67 89", "FR").as_deref(),
+            Some("+33123456789")
+        );
+        assert_eq!(
+            normalize_phone("+33 1 23 45 67 89", "FR").as_deref(),
+            Some("+33123456789")
+        );
+        assert_eq!(
+            normalize_phone("01.23.45.67.89", "FR").as_deref(),
+            Some("+33123456789")
+        );
+        assert_eq!(normalize_phone("01 23 45 67 89", "DE"), None);
+        assert_eq!(normalize_phone("telephone", "FR"), None);
+        assert_eq!(normalize_phone("", "FR"), None);
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(parse_decimal("1 234,56"), Some(1234.56));
+        assert_eq!(parse_decimal("1234.56"), Some(1234.56));
+        assert_eq!(parse_decimal("1\u{a0}234,5"), Some(1234.5));
+        assert_eq!(parse_decimal(" 150,00 "), Some(150.0));
+        assert_eq!(parse_decimal("20"), Some(20.0));
+        assert_eq!(parse_decimal("1.234,56"), None);
+        assert_eq!(parse_decimal("abc"), None);
+        assert_eq!(parse_decimal("inf"), None);
+        assert_eq!(parse_decimal(""), None);
+    }
+
+    #[test]
+    fn test_xml_chars() {
+        assert!(!has_invalid_xml_chars("Audit\tcomplet\r\nété €"));
+        assert!(has_invalid_xml_chars("Audit\u{c}"));
+        assert!(has_invalid_xml_chars("Audit\u{b}"));
+        assert!(has_invalid_xml_chars("\u{0}"));
+        assert!(has_invalid_xml_chars("\u{FFFE}"));
+        assert!(is_xml_char('\u{1F600}'));
+    }
+}
@@ -0,0 +1,375 @@
+//! Stockage des factures générées (XML, PDF et formulaire JSON)
+//!
+//! Le serveur persiste et relit les factures via le trait `Storage` :
+//! `FsStorage` écrit dans les répertoires `xml_storage` et `pdf_storage` de la
+//! configuration émetteur (le formulaire JSON est rangé à côté du XML),
+//! `MemoryStorage` garde les fichiers en mémoire pour
+//! les tests. Dans les deux cas, un numéro de facture déjà enregistré est
+//! refusé (unicité exigée par le décret de facturation).
+
+use crate::EmitterConfig;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Type de fichier d'une facture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InvoiceFileKind {
+    Xml,
+    Pdf,
+    /// Formulaire soumis, pour l'audit et la réimpression
+    Json,
+}
+
+impl InvoiceFileKind {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            InvoiceFileKind::Xml => "xml",
+            InvoiceFileKind::Pdf => "pdf",
+            InvoiceFileKind::Json => "json",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "xml" => Some(InvoiceFileKind::Xml),
+            "pdf" => Some(InvoiceFileKind::Pdf),
+            "json" => Some(InvoiceFileKind::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            InvoiceFileKind::Xml => "application/xml",
+            InvoiceFileKind::Pdf => "application/pdf",
+            InvoiceFileKind::Json => "application/json",
+        }
+    }
+}
+
+/// Persistance des factures générées
+///
+/// Les écritures échouent si une facture du même numéro existe déjà. Un
+/// stockage non configuré pour un type de fichier ignore l'écriture et ne
+/// retrouve aucun fichier.
+pub trait Storage: Send + Sync {
+    /// Enregistre le XML Factur-X d'une facture
+    fn write_xml(&self, invoice_number: &str, content: &[u8]) -> Result<(), String>;
+
+    /// Enregistre le PDF d'une facture
+    fn write_pdf(&self, invoice_number: &str, content: &[u8]) -> Result<(), String>;
+
+    /// Enregistre le formulaire JSON d'une facture (`InvoiceForm::to_json`)
+    fn write_json(&self, invoice_number: &str, content: &[u8]) -> Result<(), String>;
+
+    /// Relit un fichier enregistré (`None` si absent ou non configuré)
+    fn read(&self, invoice_number: &str, kind: InvoiceFileKind) -> Option<Vec<u8>>;
+}
+
+/// Nettoie le numéro de facture pour l'utiliser comme nom de fichier
+pub fn safe_invoice_filename(invoice_number: &str) -> String {
+    invoice_number.replace(['/', '\\', ' ', ':'], "_")
+}
+
+/// Message d'erreur d'un numéro de facture déjà enregistré
+fn duplicate_error(invoice_number: &str) -> String {
+    format!(
+        "Une facture avec le numéro '{}' existe déjà. Le numéro de facture doit être unique.",
+        invoice_number
+    )
+}
+
+/// Stockage sur disque dans les répertoires de la configuration émetteur
+#[derive(Debug, Clone, Default)]
+pub struct FsStorage {
+    xml_dir: Option<PathBuf>,
+    pdf_dir: Option<PathBuf>,
+}
+
+impl FsStorage {
+    /// Répertoires XML et PDF (`None` ou vide : type de fichier non stocké)
+    pub fn new(xml_dir: Option<&str>, pdf_dir: Option<&str>) -> Self {
+        // Nettoie un chemin de stockage (supprime ./ au début)
+        fn clean(dir: Option<&str>) -> Option<PathBuf> {
+            dir.filter(|d| !d.trim().is_empty())
+                .map(|d| PathBuf::from(d.trim_start_matches("./")))
+        }
+        Self {
+            xml_dir: clean(xml_dir),
+            pdf_dir: clean(pdf_dir),
+        }
+    }
+
+    /// Répertoires `xml_storage` et `pdf_storage` de la configuration
+    pub fn from_config(emitter: &EmitterConfig) -> Self {
+        Self::new(
+            emitter.xml_storage.as_deref(),
+            emitter.pdf_storage.as_deref(),
+        )
+    }
+
+    fn dir(&self, kind: InvoiceFileKind) -> Option<&Path> {
+        match kind {
+            InvoiceFileKind::Xml | InvoiceFileKind::Json => self.xml_dir.as_deref(),
+            InvoiceFileKind::Pdf => self.pdf_dir.as_deref(),
+        }
+    }
+
+    fn file_path(&self, invoice_number: &str, kind: InvoiceFileKind) -> Option<PathBuf> {
+        self.dir(kind).map(|dir| {
+            dir.join(format!(
+                "{}.{}",
+                safe_invoice_filename(invoice_number),
+                kind.extension()
+            ))
+        })
+    }
+
+    /// Sauvegarde un fichier, refusé si le fichier existe déjà (numéro dupliqué)
+    fn write(
+        &self,
+        invoice_number: &str,
+        kind: InvoiceFileKind,
+        content: &[u8],
+    ) -> Result<(), String> {
+        let (Some(dir_path), Some(file_path)) =
+            (self.dir(kind), self.file_path(invoice_number, kind))
+        else {
+            return Ok(());
+        };
+
+        // Créer le répertoire si nécessaire
+        if !dir_path.exists() {
+            std::fs::create_dir_all(dir_path).map_err(|e| {
+                format!(
+                    "Impossible de créer le répertoire {}: {}",
+                    dir_path.display(),
+                    e
+                )
+            })?;
+        }
+
+        if file_path.exists() {
+            return Err(duplicate_error(invoice_number));
+        }
+
+        // `exists` ne protège pas d'une écriture concurrente : la publication
+        // échoue aussi si le fichier apparaît entre-temps
+        atomic_write(&file_path, content).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => duplicate_error(invoice_number),
+            _ => format!("Impossible de sauvegarder {}: {}", file_path.display(), e),
+        })
+    }
+}
+
+impl Storage for FsStorage {
+    fn write_xml(&self, invoice_number: &str, content: &[u8]) -> Result<(), String> {
+        self.write(invoice_number, InvoiceFileKind::Xml, content)
+    }
+
+    fn write_pdf(&self, invoice_number: &str, content: &[u8]) -> Result<(), String> {
+        self.write(invoice_number, InvoiceFileKind::Pdf, content)
+    }
+
+    fn write_json(&self, invoice_number: &str, content: &[u8]) -> Result<(), String> {
+        self.write(invoice_number, InvoiceFileKind::Json, content)
+    }
+
+    fn read(&self, invoice_number: &str, kind: InvoiceFileKind) -> Option<Vec<u8>> {
+        std::fs::read(self.file_path(invoice_number, kind)?).ok()
+    }
+}
+
+/// Compteur des fichiers temporaires, pour que deux écritures simultanées du
+/// même processus n'utilisent jamais le même fichier
+static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Écrit un fichier de façon atomique : fichier temporaire unique dans le même
+/// répertoire, fsync, puis publication par lien physique. Un lecteur ne voit
+/// jamais de fichier tronqué, un arrêt en cours d'écriture ne laisse pas de
+/// facture partielle à l'emplacement final, et un fichier déjà présent n'est
+/// jamais remplacé (erreur `AlreadyExists`).
+fn atomic_write(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other("chemin sans nom de fichier"))?;
+    let tmp_path = dir.join(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = (|| {
+        let mut file = std::fs::File::create_new(&tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        std::fs::hard_link(&tmp_path, path)?;
+
+        // Persiste l'entrée de répertoire (sans effet hors Unix)
+        if let Ok(dir_file) = std::fs::File::open(dir) {
+            let _ = dir_file.sync_all();
+        }
+        Ok(())
+    })();
+
+    let _ = std::fs::remove_file(&tmp_path);
+    result
+}
+
+/// Stockage en mémoire, pour les tests
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<HashMap<(InvoiceFileKind, String), Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Nombre de fichiers enregistrés
+    pub fn len(&self) -> usize {
+        self.files.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write(
+        &self,
+        invoice_number: &str,
+        kind: InvoiceFileKind,
+        content: &[u8],
+    ) -> Result<(), String> {
+        let mut files = self.files.lock().unwrap();
+        let key = (kind, safe_invoice_filename(invoice_number));
+        if files.contains_key(&key) {
+            return Err(duplicate_error(invoice_number));
+        }
+        files.insert(key, content.to_vec());
+        Ok(())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn write_xml(&self, invoice_number: &str, content: &[u8]) -> Result<(), String> {
+        self.write(invoice_number, InvoiceFileKind::Xml, content)
+    }
+
+    fn write_pdf(&self, invoice_number: &str, content: &[u8]) -> Result<(), String> {
+        self.write(invoice_number, InvoiceFileKind::Pdf, content)
+    }
+
+    fn write_json(&self, invoice_number: &str, content: &[u8]) -> Result<(), String> {
+        self.write(invoice_number, InvoiceFileKind::Json, content)
+    }
+
+    fn read(&self, invoice_number: &str, kind: InvoiceFileKind) -> Option<Vec<u8>> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(&(kind, safe_invoice_filename(invoice_number)))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() {
+        let storage = MemoryStorage::new();
+        storage.write_pdf("FA 001", b"%PDF-1.7").unwrap();
+        storage.write_xml("FA 001", b"<xml/>").unwrap();
+
+        assert_eq!(storage.len(), 2);
+        assert_eq!(
+            storage.read("FA_001", InvoiceFileKind::Pdf).unwrap(),
+            b"%PDF-1.7"
+        );
+        assert!(storage.read("FA 002", InvoiceFileKind::Pdf).is_none());
+
+        let err = storage.write_pdf("FA 001", b"%PDF-1.7").unwrap_err();
+        assert!(err.contains("existe déjà"));
+    }
+
+    #[test]
+    fn test_fs_storage() {
+        let dir = std::env::temp_dir().join(format!("facturx-storage-{}", std::process::id()));
+        let pdf_dir = dir.join("pdf");
+        let storage = FsStorage::new(None, pdf_dir.to_str());
+
+        storage.write_pdf("FA/001", b"%PDF-1.7").unwrap();
+        assert_eq!(
+            std::fs::read(pdf_dir.join("FA_001.pdf")).unwrap(),
+            b"%PDF-1.7"
+        );
+        assert_eq!(
+            storage.read("FA/001", InvoiceFileKind::Pdf).unwrap(),
+            b"%PDF-1.7"
+        );
+        assert!(storage.write_pdf("FA/001", b"%PDF-1.7").is_err());
+
+        // Pas de fichier temporaire résiduel
+        assert_eq!(std::fs::read_dir(&pdf_dir).unwrap().count(), 1);
+
+        // XML non configuré : écriture ignorée, aucune lecture
+        storage.write_xml("FA/001", b"<xml/>").unwrap();
+        assert!(storage.read("FA/001", InvoiceFileKind::Xml).is_none());
+        storage.write_json("FA/001", b"{}").unwrap();
+        assert!(storage.read("FA/001", InvoiceFileKind::Json).is_none());
+
+        // Formulaire JSON rangé à côté du XML
+        let xml_dir = dir.join("xml");
+        let storage = FsStorage::new(xml_dir.to_str(), None);
+        storage.write_xml("FA/001", b"<xml/>").unwrap();
+        storage.write_json("FA/001", b"{}").unwrap();
+        assert_eq!(std::fs::read(xml_dir.join("FA_001.json")).unwrap(), b"{}");
+        assert!(storage.write_json("FA/001", b"{}").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fs_storage_concurrent_duplicates() {
+        let dir = std::env::temp_dir().join(format!("facturx-concurrent-{}", std::process::id()));
+        let pdf_dir = dir.join("pdf");
+        let storage = FsStorage::new(None, pdf_dir.to_str());
+        std::fs::create_dir_all(&pdf_dir).unwrap();
+
+        // Fichier apparu après le contrôle d'existence : jamais remplacé
+        let path = pdf_dir.join("FA_002.pdf");
+        std::fs::write(&path, b"original").unwrap();
+        let err = atomic_write(&path, b"%PDF-1.7").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"original");
+
+        // Sauvegardes simultanées du même numéro : une seule réussit, intacte
+        let contents: Vec<Vec<u8>> = (0..8).map(|i| vec![b'0' + i; 4096]).collect();
+        let results: Vec<Result<(), String>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = contents
+                .iter()
+                .map(|content| scope.spawn(|| storage.write_pdf("FA/001", content)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        for err in results.iter().filter_map(|r| r.as_ref().err()) {
+            assert!(err.contains("existe déjà"), "{}", err);
+        }
+        let saved = std::fs::read(pdf_dir.join("FA_001.pdf")).unwrap();
+        assert!(contents.contains(&saved));
+
+        // Pas de fichier temporaire résiduel
+        assert_eq!(std::fs::read_dir(&pdf_dir).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}